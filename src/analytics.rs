@@ -1,17 +1,458 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
-use tokio::{sync::watch, time::{interval, Duration}};
+use std::time::Instant;
+use tokio::{sync::{mpsc, watch}, time::{interval, Duration}};
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
 use serde::Serialize;
-use chrono::Utc;
+use chrono::{DateTime, FixedOffset, Local, Utc};
+use thiserror::Error;
 use crate::{
+    consolidated::ConsolidatedOutput,
+    feed_health::{Feed, FeedHealth},
+    latency::FeedLag,
     orderbook::ConcurrentOrderBook,
     tradeslog::ConcurrentTradesLog,
-    persistence,
+    persistence::{self, PersistenceConfig},
+    session_stats::SessionStats,
 };
 
 const SNAPSHOT_INTERVAL_MS: u64 = 100;
 const BATCH_SIZE: usize = 1000;
+const BOOK_IMBALANCE_ALPHA: Decimal = dec!(0.1);
+/// Share of `net_flow` taken by book order flow; trade flow gets the rest.
+const NET_FLOW_BOOK_WEIGHT: Decimal = dec!(0.5);
+const FEED_STALE_AFTER: Duration = Duration::from_secs(30);
+/// Parquet file names as they were before templates: local date and time of the flush,
+/// then the batch number.
+pub const DEFAULT_FILENAME_TEMPLATE: &str = "features_{date}_{time}_{batch}.parquet";
+const FILENAME_PLACEHOLDERS: [&str; 5] = ["{symbol}", "{date}", "{time}", "{batch}", "{run}"];
+
+/// Destination for feature snapshots; several can be active at once.
+#[derive(Debug, Clone)]
+pub enum OutputSink {
+    /// Multi-line human readable summary.
+    PrettyConsole,
+    /// One compact JSON object per line.
+    JsonConsole,
+    /// Batches written under `output_dir`, one file per batch as `rotation` decides.
+    Parquet,
+    /// Every snapshot is forwarded to the receiver.
+    Channel(mpsc::UnboundedSender<FeaturesSnapshot>),
+}
+
+/// When the Parquet sink closes its batch and writes it out as a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationPolicy {
+    /// Once the batch holds this many snapshots.
+    ByCount(usize),
+    /// Once the batch's first snapshot is this old. Checked as snapshots arrive, so
+    /// files cover at least this long.
+    ByDuration(Duration),
+    /// Once the batch takes up about this many bytes in memory; the compressed file
+    /// ends up smaller.
+    BySize(usize),
+}
+
+/// Why a `filename_template` can't be used.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum FilenameTemplateError {
+    #[error(
+        "Filename template {0:?} needs {{date}} and {{time}}, or {{batch}} and {{run}}, to give every batch its own file"
+    )]
+    NoUniquePart(String),
+    #[error("Unknown placeholder {0} in filename template")]
+    UnknownPlaceholder(String),
+    #[error("Filename template {0:?} uses {{symbol}}, but no symbol is configured")]
+    MissingSymbol(String),
+}
+
+/// Why a lagged feature can't be added to snapshots.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum LaggedFeatureError {
+    #[error("{0:?} is not of the form <feature>_lag<ticks>")]
+    Malformed(String),
+    #[error("{0:?} can't be lagged")]
+    UnknownFeature(String),
+    #[error("Lag of {0:?} must be at least one tick")]
+    ZeroLag(String),
+}
+
+/// A feature that can be lagged, and how to read it off a snapshot.
+type LaggableFeature = (&'static str, fn(&FeaturesSnapshot) -> Option<Decimal>);
+
+/// Features `LaggedFeature` can name.
+pub const LAGGABLE_FEATURES: [LaggableFeature; 16] = [
+    ("mid_price", |s| s.mid_price),
+    ("microprice", |s| s.microprice),
+    ("spread", |s| s.spread),
+    ("imbalance", |s| s.imbalance),
+    ("book_imbalance_ema", |s| s.book_imbalance_ema),
+    ("volume_imbalance_top5", |s| s.volume_imbalance_top5),
+    ("last_trade_price", |s| s.last_trade_price),
+    ("trade_imbalance", |s| s.trade_imbalance),
+    ("trade_imbalance_ema", |s| s.trade_imbalance_ema),
+    ("vwap_total", |s| s.vwap_total),
+    ("price_change", |s| s.price_change),
+    ("order_flow_imbalance", |s| s.order_flow_imbalance),
+    ("flow_price_corr", |s| s.flow_price_corr),
+    ("composite_pressure", |s| s.composite_pressure),
+    ("net_flow", |s| s.net_flow),
+    ("price_dislocation", |s| s.price_dislocation),
+];
+
+/// A feature's value some analytics ticks back, added to every snapshot under a name
+/// like `mid_price_lag1`; see `AnalyticsConfig::lagged_features`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LaggedFeature {
+    /// Index into `LAGGABLE_FEATURES`.
+    feature: usize,
+    lag: usize,
+}
+
+impl LaggedFeature {
+    /// `feature`, one of `LAGGABLE_FEATURES`, as it was `lag` ticks earlier.
+    pub fn new(feature: &str, lag: usize) -> Result<Self, LaggedFeatureError> {
+        let name = format!("{}_lag{}", feature, lag);
+        let feature = LAGGABLE_FEATURES
+            .iter()
+            .position(|(laggable, _)| *laggable == feature)
+            .ok_or_else(|| LaggedFeatureError::UnknownFeature(name.clone()))?;
+        if lag == 0 {
+            return Err(LaggedFeatureError::ZeroLag(name));
+        }
+        Ok(Self { feature, lag })
+    }
+
+    /// Name of the lagged copy, e.g. `mid_price_lag1`.
+    pub fn name(&self) -> String {
+        format!("{}_lag{}", LAGGABLE_FEATURES[self.feature].0, self.lag)
+    }
+
+    pub fn lag(&self) -> usize {
+        self.lag
+    }
+
+    fn value(&self, snapshot: &FeaturesSnapshot) -> Option<Decimal> {
+        (LAGGABLE_FEATURES[self.feature].1)(snapshot)
+    }
+}
+
+impl FromStr for LaggedFeature {
+    type Err = LaggedFeatureError;
+
+    /// Parses a name like `mid_price_lag1` or `imbalance_lag5`.
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let malformed = || LaggedFeatureError::Malformed(name.to_string());
+        let (feature, lag) = name.rsplit_once("_lag").ok_or_else(malformed)?;
+        Self::new(feature, lag.parse().map_err(|_| malformed())?)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AnalyticsConfig {
+    pub sinks: Vec<OutputSink>,
+    pub snapshot_interval: Duration,
+    pub rotation: RotationPolicy,
+    pub output_dir: PathBuf,
+    /// Name of each Parquet file under `output_dir`, resolved when its batch is flushed:
+    /// `{symbol}`, `{date}` (`YYYYMMDD`), `{time}` (`HHMMSSmmm`), `{batch}` (numbered from
+    /// `000` each run) and `{run}` (`YYYYMMDD_HHMMSS` the writer started). Must contain
+    /// `{date}` and `{time}`, or `{batch}` and `{run}`; may contain `/` to write into
+    /// subdirectories. A name that is already taken gets a `_1`, `_2`, ... suffix.
+    pub filename_template: String,
+    /// Symbol the snapshots are of, for `{symbol}`.
+    pub symbol: Option<String>,
+    /// How Parquet batches store their columns, e.g. prices exactly.
+    pub persistence: PersistenceConfig,
+    /// Weight of the newest reading in `book_imbalance_ema`, in (0, 1].
+    pub book_imbalance_alpha: Decimal,
+    /// Console sinks print only every Nth snapshot; other sinks still get every one.
+    pub console_every_n_ticks: usize,
+    /// Feeds whose lag is reported; `feed_lag_ms` is the worst of them.
+    pub feed_lags: Vec<FeedLag>,
+    /// Leaves snapshots without a best bid or ask (book not synced yet) out of parquet
+    /// batches; other sinks still get them.
+    pub skip_empty_book: bool,
+    /// Time from the task's start during which snapshots are computed and sent to every
+    /// other sink but kept out of parquet batches, while windows fill and the book syncs.
+    pub warmup: Duration,
+    /// Size of `price_dislocation`, either way, that sets `price_dislocated`; `None`
+    /// never sets it.
+    pub dislocation_threshold: Option<Decimal>,
+    /// Cross-venue book snapshotted and sent alongside the features every tick, if any.
+    pub consolidated: Option<ConsolidatedOutput>,
+    /// Health both feed managers publish, e.g. `HealthPublisher::subscribe`. Without it
+    /// every snapshot reports both feeds healthy.
+    pub feed_health: Option<watch::Receiver<FeedHealth>>,
+    /// A connected feed that has gone this long without a message is reported unhealthy.
+    pub feed_stale_after: Duration,
+    /// Takes every tick's mid price, for the summary of the run.
+    pub session_stats: Option<SessionStats>,
+    /// Earlier ticks' values added to every snapshot, for models that take them as inputs.
+    pub lagged_features: Vec<LaggedFeature>,
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        Self {
+            sinks: vec![OutputSink::PrettyConsole, OutputSink::Parquet],
+            snapshot_interval: Duration::from_millis(SNAPSHOT_INTERVAL_MS),
+            rotation: RotationPolicy::ByCount(BATCH_SIZE),
+            output_dir: PathBuf::from("data"),
+            filename_template: DEFAULT_FILENAME_TEMPLATE.to_string(),
+            symbol: None,
+            persistence: PersistenceConfig::default(),
+            book_imbalance_alpha: BOOK_IMBALANCE_ALPHA,
+            console_every_n_ticks: 1,
+            feed_lags: Vec::new(),
+            skip_empty_book: false,
+            warmup: Duration::ZERO,
+            dislocation_threshold: None,
+            consolidated: None,
+            feed_health: None,
+            feed_stale_after: FEED_STALE_AFTER,
+            session_stats: None,
+            lagged_features: Vec::new(),
+        }
+    }
+}
+
+impl AnalyticsConfig {
+    /// Checks `filename_template` against the placeholders it may use.
+    pub fn validate(&self) -> Result<(), FilenameTemplateError> {
+        validate_filename_template(&self.filename_template, self.symbol.as_deref())
+    }
+}
+
+/// Checks that `template` only uses known placeholders, has a symbol for `{symbol}`, and
+/// names each batch's file differently, within a run and across runs.
+pub fn validate_filename_template(template: &str, symbol: Option<&str>) -> Result<(), FilenameTemplateError> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').map_or(rest.len(), |end| start + end + 1);
+        let placeholder = &rest[start..end];
+        if !FILENAME_PLACEHOLDERS.contains(&placeholder) {
+            return Err(FilenameTemplateError::UnknownPlaceholder(placeholder.to_string()));
+        }
+        rest = &rest[end..];
+    }
+    let has = |placeholder: &str| template.contains(placeholder);
+    let unique_by_time = has("{date}") && has("{time}");
+    let unique_by_run = has("{batch}") && has("{run}");
+    if !unique_by_time && !unique_by_run {
+        return Err(FilenameTemplateError::NoUniquePart(template.to_string()));
+    }
+    if template.contains("{symbol}") && symbol.is_none() {
+        return Err(FilenameTemplateError::MissingSymbol(template.to_string()));
+    }
+    Ok(())
+}
+
+/// Exponential moving average that skips missing readings.
+#[derive(Debug, Clone)]
+struct Ema {
+    alpha: Decimal,
+    value: Option<Decimal>,
+}
+
+impl Ema {
+    fn new(alpha: Decimal) -> Self {
+        Self { alpha, value: None }
+    }
+
+    /// Folds in `reading`, seeding on the first one; `None` leaves the average as it was.
+    fn update(&mut self, reading: Option<Decimal>) -> Option<Decimal> {
+        if let Some(reading) = reading {
+            self.value = Some(match self.value {
+                Some(ema) => self.alpha * reading + (dec!(1) - self.alpha) * ema,
+                None => reading,
+            });
+        }
+        self.value
+    }
+}
+
+/// Analytics ticks covered by `spread_mean_100` and `spread_std_100`.
+const SPREAD_WINDOW_TICKS: usize = 100;
+
+/// Mean and population standard deviation over the last `len` readings, skipping missing ones.
+#[derive(Debug, Clone)]
+struct RollingStats {
+    len: usize,
+    values: VecDeque<Decimal>,
+    sum: Decimal,
+    sum_sq: Decimal,
+}
+
+impl RollingStats {
+    fn new(len: usize) -> Self {
+        Self {
+            len,
+            values: VecDeque::with_capacity(len),
+            sum: Decimal::ZERO,
+            sum_sq: Decimal::ZERO,
+        }
+    }
+
+    /// Folds in `reading` and returns the window's mean and standard deviation, or
+    /// `None` for both while it is empty.
+    fn update(&mut self, reading: Option<Decimal>) -> (Option<Decimal>, Option<Decimal>) {
+        if let Some(reading) = reading {
+            if self.values.len() == self.len {
+                if let Some(oldest) = self.values.pop_front() {
+                    self.sum -= oldest;
+                    self.sum_sq -= oldest * oldest;
+                }
+            }
+            self.values.push_back(reading);
+            self.sum += reading;
+            self.sum_sq += reading * reading;
+        }
+        if self.values.is_empty() {
+            return (None, None);
+        }
+
+        let n = Decimal::from(self.values.len());
+        let mean = self.sum / n;
+        let variance = (self.sum_sq / n - mean * mean).max(Decimal::ZERO);
+        let std = variance.to_f64().and_then(|variance| Decimal::from_f64(variance.sqrt()));
+        (Some(mean), std)
+    }
+}
+
+/// Analytics ticks covered by `flow_price_corr`; a minute at the default interval.
+const FLOW_CORR_WINDOW_TICKS: usize = 600;
+
+/// Pearson correlation over the last `len` (x, y) pairs, kept as running sums so each
+/// update is O(1).
+#[derive(Debug, Clone)]
+struct RollingCorrelation {
+    len: usize,
+    pairs: VecDeque<(Decimal, Decimal)>,
+    sum_x: Decimal,
+    sum_y: Decimal,
+    sum_xy: Decimal,
+    sum_x2: Decimal,
+    sum_y2: Decimal,
+}
+
+impl RollingCorrelation {
+    fn new(len: usize) -> Self {
+        Self {
+            len,
+            pairs: VecDeque::with_capacity(len),
+            sum_x: Decimal::ZERO,
+            sum_y: Decimal::ZERO,
+            sum_xy: Decimal::ZERO,
+            sum_x2: Decimal::ZERO,
+            sum_y2: Decimal::ZERO,
+        }
+    }
+
+    /// Folds in `pair` and returns the window's correlation, or `None` while it holds
+    /// fewer than two pairs or either series is flat.
+    fn update(&mut self, pair: Option<(Decimal, Decimal)>) -> Option<Decimal> {
+        if let Some((x, y)) = pair {
+            if self.pairs.len() == self.len {
+                if let Some((x, y)) = self.pairs.pop_front() {
+                    self.sum_x -= x;
+                    self.sum_y -= y;
+                    self.sum_xy -= x * y;
+                    self.sum_x2 -= x * x;
+                    self.sum_y2 -= y * y;
+                }
+            }
+            self.pairs.push_back((x, y));
+            self.sum_x += x;
+            self.sum_y += y;
+            self.sum_xy += x * y;
+            self.sum_x2 += x * x;
+            self.sum_y2 += y * y;
+        }
+        if self.pairs.len() < 2 {
+            return None;
+        }
+
+        // n² times the covariance and variances; the n² cancels out of the ratio
+        let n = Decimal::from(self.pairs.len());
+        let covariance = n * self.sum_xy - self.sum_x * self.sum_y;
+        let variance_x = n * self.sum_x2 - self.sum_x * self.sum_x;
+        let variance_y = n * self.sum_y2 - self.sum_y * self.sum_y;
+        if variance_x <= Decimal::ZERO || variance_y <= Decimal::ZERO {
+            return None;
+        }
+        let denominator = variance_x.to_f64()?.sqrt() * variance_y.to_f64()?.sqrt();
+        Decimal::from_f64(covariance.to_f64()? / denominator).map(|corr| corr.clamp(dec!(-1), dec!(1)))
+    }
+}
+
+/// Correlation between the order-flow imbalance at one tick and the mid-price change from
+/// that tick to the next, over the last `len` ticks that had both readings.
+#[derive(Debug, Clone)]
+struct FlowPriceCorrelation {
+    /// The previous tick's imbalance and mid, waiting for the next mid.
+    previous: Option<(Decimal, Decimal)>,
+    correlation: RollingCorrelation,
+}
+
+impl FlowPriceCorrelation {
+    fn new(len: usize) -> Self {
+        Self {
+            previous: None,
+            correlation: RollingCorrelation::new(len),
+        }
+    }
+
+    /// Pairs the previous tick's imbalance with the mid change since, then holds on to
+    /// this tick's readings for the next one.
+    fn update(&mut self, flow_imbalance: Option<Decimal>, mid: Option<Decimal>) -> Option<Decimal> {
+        let pair = match (self.previous, mid) {
+            (Some((flow_imbalance, previous_mid)), Some(mid)) => Some((flow_imbalance, mid - previous_mid)),
+            _ => None,
+        };
+        self.previous = flow_imbalance.zip(mid);
+        self.correlation.update(pair)
+    }
+}
+
+/// The last ticks' values of the lagged features, to fill in `FeaturesSnapshot::lagged`.
+#[derive(Debug, Clone)]
+struct FeatureLags {
+    features: Vec<LaggedFeature>,
+    /// Every lagged feature's value at each of the last ticks, newest first.
+    history: VecDeque<Vec<Option<Decimal>>>,
+    depth: usize,
+}
+
+impl FeatureLags {
+    fn new(features: &[LaggedFeature]) -> Self {
+        Self {
+            features: features.to_vec(),
+            history: VecDeque::new(),
+            depth: features.iter().map(LaggedFeature::lag).max().unwrap_or(0),
+        }
+    }
+
+    /// Adds the lagged values to `snapshot`, null for lags reaching back before the first
+    /// tick, then keeps its own values for the ticks to come.
+    fn apply(&mut self, snapshot: &mut FeaturesSnapshot) {
+        if self.features.is_empty() {
+            return;
+        }
+        let current = self.features.iter().map(|feature| feature.value(snapshot)).collect();
+        for (i, feature) in self.features.iter().enumerate() {
+            let lagged = self.history.get(feature.lag - 1).and_then(|values| values[i]);
+            snapshot.lagged.insert(feature.name(), lagged);
+        }
+        self.history.push_front(current);
+        self.history.truncate(self.depth);
+    }
+}
 
 #[derive(Serialize, Clone)]
 pub struct FeaturesSnapshot {
@@ -22,6 +463,10 @@ pub struct FeaturesSnapshot {
     pub microprice: Option<Decimal>,
     pub spread: Option<Decimal>,
     pub imbalance: Option<Decimal>,
+    pub book_imbalance_ema: Option<Decimal>,
+    /// Mean and standard deviation of `spread` over the last 100 analytics ticks.
+    pub spread_mean_100: Option<Decimal>,
+    pub spread_std_100: Option<Decimal>,
     pub top_bids: Vec<(Decimal, Decimal)>,
     pub top_asks: Vec<(Decimal, Decimal)>,
     pub pwi_1: Option<Decimal>,
@@ -35,18 +480,34 @@ pub struct FeaturesSnapshot {
     pub ask_depth_ratio: Option<Decimal>,
     pub bid_volume_001: Option<Decimal>,
     pub ask_volume_001: Option<Decimal>,
+    pub bid_volume_005: Option<Decimal>,
+    pub ask_volume_005: Option<Decimal>,
+    pub bid_volume_010: Option<Decimal>,
+    pub ask_volume_010: Option<Decimal>,
+    pub bid_volume_050: Option<Decimal>,
+    pub ask_volume_050: Option<Decimal>,
     pub bid_avg_distance: Option<Decimal>,
     pub ask_avg_distance: Option<Decimal>,
     pub last_trade_price: Option<Decimal>,
     pub trade_imbalance: Option<Decimal>,
+    pub trade_imbalance_ema: Option<Decimal>,
     pub vwap_total: Option<Decimal>,
     pub price_change: Option<Decimal>,
     pub avg_trade_size: Option<Decimal>,
     pub signed_count_momentum: i64,
+    pub trade_rate_1s: Option<f64>,
     pub trade_rate_10s: Option<f64>,
+    pub trade_rate_60s: Option<f64>,
     pub order_flow_imbalance: Option<Decimal>,
     pub order_flow_pressure: Decimal,
+    /// The bid and ask shares of `order_flow_pressure`; whichever is larger is driving it.
+    pub bid_flow_pressure: Decimal,
+    pub ask_flow_pressure: Decimal,
     pub order_flow_significance: bool,
+    /// Correlation between `order_flow_imbalance` and the next tick's mid-price change
+    /// over the last 600 analytics ticks; how well the flow signal leads price.
+    pub flow_price_corr: Option<Decimal>,
+    pub composite_pressure: Option<Decimal>,
     pub vwap_10: Option<Decimal>,   
     pub vwap_50: Option<Decimal>,   
     pub vwap_100: Option<Decimal>,
@@ -55,133 +516,83 @@ pub struct FeaturesSnapshot {
     pub aggr_ratio_50: Option<Decimal>, 
     pub aggr_ratio_100: Option<Decimal>,
     pub aggr_ratio_1000: Option<Decimal>,
+    pub aggr_ratio_10s: Option<Decimal>,
+    /// Volume share of trades of at least 1 (base asset) over the last 100 trades.
+    pub large_trade_share_100: Option<Decimal>,
+    /// Book and trade flow combined into one direction in [-1, 1]; see `net_flow`.
+    pub net_flow: Option<Decimal>,
+    /// Microprice minus the last trade price; see `price_dislocation`.
+    pub price_dislocation: Option<Decimal>,
+    /// Whether `price_dislocation` reached `AnalyticsConfig::dislocation_threshold`.
+    pub price_dislocated: bool,
+    /// Smoothed lag of the slowest feed in `AnalyticsConfig::feed_lags`, in ms. Negative
+    /// when the local clock runs behind the exchange's; see `FeedLag`.
+    pub feed_lag_ms: Option<f64>,
+    /// Smoothed time from the exchange stamping a trade to the trade feed receiving it, in
+    /// ms, as published in `AnalyticsConfig::feed_health`; see `IngestLag`.
+    pub trade_ingest_lag_ms: Option<f64>,
+    /// Whether the depth feed was connected and had sent a message within
+    /// `AnalyticsConfig::feed_stale_after`; rows with this unset come from a degraded feed.
+    pub depth_feed_healthy: bool,
+    /// Same for the trade feed.
+    pub trade_feed_healthy: bool,
+    /// Whether the trade feed's current connection skipped over trade ids, so the trade
+    /// features miss trades; see `FeedHealth::trade_feed_gap_detected`.
+    pub trade_feed_gap_detected: bool,
+    /// Milliseconds since the depth feed's last message, `None` before its first or
+    /// without `AnalyticsConfig::feed_health`.
+    pub depth_msg_age_ms: Option<u64>,
+    pub trade_msg_age_ms: Option<u64>,
+    /// False while the book is unsynced or its feed has gone stale; rows with this unset
+    /// describe a book that may not match the exchange.
+    pub book_trusted: bool,
+    /// When the book last took an update (epoch ms); the book's age is the distance from
+    /// `timestamp` to this.
+    pub book_last_update: Option<u64>,
+    /// Mid price 100ms after this snapshot. A training label, only filled in Parquet batches.
+    pub future_mid_100ms: Option<Decimal>,
+    /// Mid price 1s after this snapshot, filled like `future_mid_100ms`.
+    pub future_mid_1s: Option<Decimal>,
+    /// `AnalyticsConfig::lagged_features` by name, e.g. `mid_price_lag1`, each null until
+    /// that many ticks have passed. Stored as columns of their own.
+    #[serde(flatten)]
+    pub lagged: BTreeMap<String, Option<Decimal>>,
 }
 
 pub async fn run_analytics_task(
     order_book: Arc<ConcurrentOrderBook>,
     trades_log: Arc<ConcurrentTradesLog>,
+    config: AnalyticsConfig,
     mut shutdown_rx: watch::Receiver<bool>,
 ) {
-    const SIGNIFICANCE_THRESHOLD: Decimal = dec!(10.0);
-
-    let mut interval = interval(Duration::from_millis(SNAPSHOT_INTERVAL_MS));
-    let mut batch = Vec::with_capacity(BATCH_SIZE);
-    let mut batch_id = 0;
+    let mut interval = interval(config.snapshot_interval);
+    let mut output = SnapshotOutput::new(&config, std::io::stdout());
+    // Smoothed per tick, so the fixed sampling interval sets its time scale
+    let mut book_imbalance_ema = Ema::new(config.book_imbalance_alpha);
+    let mut spread_stats = RollingStats::new(SPREAD_WINDOW_TICKS);
+    let mut flow_price_corr = FlowPriceCorrelation::new(FLOW_CORR_WINDOW_TICKS);
+    let mut feature_lags = FeatureLags::new(&config.lagged_features);
 
     loop {
         tokio::select! {
             _ = interval.tick() => {
-                let (ob_snap, trade_snap) = tokio::join!(
-                    order_book.get_snapshot(),
-                    trades_log.get_snapshot()
-                );
-
-                let (flow_imbalance, flow_pressure) = order_book.get_flow_imbalance().await;
-
-                let snapshot = FeaturesSnapshot {
-                    timestamp: Utc::now().to_rfc3339(),
-                    best_bid: ob_snap.best_bid.map(|(p, _)| p),
-                    best_ask: ob_snap.best_ask.map(|(p, _)| p),
-                    mid_price: ob_snap.mid_price,
-                    microprice: ob_snap.microprice,
-                    spread: ob_snap.spread,
-                    imbalance: ob_snap.imbalance,
-                    top_bids: ob_snap.top_bids,
-                    top_asks: ob_snap.top_asks,
-                    pwi_1: ob_snap.pwi_1,
-                    pwi_5: ob_snap.pwi_5,
-                    pwi_25: ob_snap.pwi_25,
-                    pwi_50: ob_snap.pwi_50,
-                    bid_slope: ob_snap.bid_slope,
-                    ask_slope: ob_snap.ask_slope,
-                    volume_imbalance_top5: ob_snap.volume_imbalance_top5,
-                    bid_depth_ratio: ob_snap.bid_depth_ratio,
-                    ask_depth_ratio: ob_snap.ask_depth_ratio,
-                    bid_volume_001: ob_snap.bid_volume_001,
-                    ask_volume_001: ob_snap.ask_volume_001,
-                    bid_avg_distance: ob_snap.bid_avg_distance,
-                    ask_avg_distance: ob_snap.ask_avg_distance,
-                    last_trade_price: trade_snap.last_price,
-                    vwap_10: trade_snap.vwap_10,
-                    vwap_50: trade_snap.vwap_50,  
-                    vwap_100: trade_snap.vwap_100,
-                    vwap_1000: trade_snap.vwap_1000,
-                    aggr_ratio_10: trade_snap.aggr_ratio_10,  
-                    aggr_ratio_50: trade_snap.aggr_ratio_50,  
-                    aggr_ratio_100: trade_snap.aggr_ratio_100,
-                    aggr_ratio_1000: trade_snap.aggr_ratio_1000,
-                    trade_imbalance: trade_snap.trade_imbalance,
-                    vwap_total: trade_snap.vwap_total,
-                    price_change: trade_snap.price_change,
-                    avg_trade_size: trade_snap.avg_trade_size,
-                    signed_count_momentum: trade_snap.signed_count_momentum,
-                    trade_rate_10s: trade_snap.trade_rate_10s,
-                    order_flow_imbalance: flow_imbalance,
-                    order_flow_pressure: flow_pressure,
-                    order_flow_significance: flow_pressure >= SIGNIFICANCE_THRESHOLD,
-                };
-                
-                // Simple console output
-                println!(
-                    r#"[{}] MID: {:.2} | MICRO: {:.2} (Δ {:.4})
-                    VWAP: 10={:.3} | 50={:.3} | 100={:.3} | 1000={:.3}
-                    AGGR: 10={:.3} | 50={:.3} | 100={:.3} | 1000={:.3}
-                    BID/ASK: {:?}/{:?} | SPRD: {:?} | IMB: {:?}
-                    PWI: 1%={:?} 5%={:?} 25%={:?} 50%={:?}
-                    SLOPE: B{:?}/A{:?} | VOL_IMB: {:?}
-                    DEPTH: B{:?}/A{:?} | VOL(0.01%): B{:?}/A{:?}
-                    TRADES: LAST={:?} IMB={:?}
-                    VWAP_TOT={:?} ΔPRICE={:?} AVG_SIZE={:?}
-                    MOMENTUM: {} TRADE_RATE={:?}
-                    FLWIMB: {:.3}"#,
-                    snapshot.timestamp,
-                    snapshot.mid_price.unwrap_or(dec!(0)),
-                    snapshot.microprice.unwrap_or(dec!(0)),
-                    snapshot.microprice.unwrap_or(dec!(0)) - snapshot.mid_price.unwrap_or(dec!(0)),  
-                    snapshot.vwap_10.unwrap_or(dec!(0)),  
-                    snapshot.vwap_50.unwrap_or(dec!(0)),
-                    snapshot.vwap_100.unwrap_or(dec!(0)),
-                    snapshot.vwap_1000.unwrap_or(dec!(0)),
-                    snapshot.aggr_ratio_10.unwrap_or(dec!(0)),
-                    snapshot.aggr_ratio_50.unwrap_or(dec!(0)),
-                    snapshot.aggr_ratio_100.unwrap_or(dec!(0)),
-                    snapshot.aggr_ratio_1000.unwrap_or(dec!(0)),
-                    snapshot.best_bid,
-                    snapshot.best_ask,
-                    snapshot.spread,
-                    snapshot.imbalance,
-                    snapshot.pwi_1,
-                    snapshot.pwi_5,
-                    snapshot.pwi_25,
-                    snapshot.pwi_50,
-                    snapshot.bid_slope,
-                    snapshot.ask_slope,
-                    snapshot.volume_imbalance_top5,
-                    snapshot.bid_depth_ratio,
-                    snapshot.ask_depth_ratio,
-                    snapshot.bid_volume_001,
-                    snapshot.ask_volume_001,
-                    snapshot.last_trade_price,
-                    snapshot.trade_imbalance,
-                    snapshot.vwap_total,
-                    snapshot.price_change,
-                    snapshot.avg_trade_size,
-                    snapshot.signed_count_momentum,
-                    snapshot.trade_rate_10s,
-                    snapshot.order_flow_imbalance.unwrap_or(dec!(0)),
-                );
-                batch.push(snapshot);
-                if batch.len() >= BATCH_SIZE {
-                    let filename = format!(
-                        "data/features_{}_{:03}.parquet",
-                        chrono::Local::now().format("%Y%m%d_%H%M%S"), 
-                        batch_id
-                    );
-                    if let Err(e) = persistence::save_feature_as_parquet(&batch, &filename) {
-                        eprintln!("Failed to save batch {}: {}", batch_id, e);
-                    }
-                    batch.clear();
-                    batch_id += 1;
+                let mut snapshot = build_snapshot(&order_book, &trades_log).await;
+                snapshot.book_imbalance_ema = book_imbalance_ema.update(snapshot.imbalance);
+                (snapshot.spread_mean_100, snapshot.spread_std_100) = spread_stats.update(snapshot.spread);
+                snapshot.flow_price_corr = flow_price_corr.update(snapshot.order_flow_imbalance, snapshot.mid_price);
+                snapshot.feed_lag_ms = worst_lag(&config.feed_lags);
+                if let Some(feed_health) = &config.feed_health {
+                    let now_ms = Utc::now().timestamp_millis() as u64;
+                    apply_feed_health(&mut snapshot, &feed_health.borrow(), now_ms, config.feed_stale_after);
+                }
+                snapshot.price_dislocated = is_dislocated(snapshot.price_dislocation, config.dislocation_threshold);
+                feature_lags.apply(&mut snapshot);
+                if let (Some(stats), Some(mid)) = (&config.session_stats, snapshot.mid_price) {
+                    stats.record_mid(mid);
+                }
+                output.publish(snapshot);
+                if let Some(consolidated) = &config.consolidated {
+                    let _ = consolidated.tx.send(consolidated.book.snapshot(consolidated.depth).await);
                 }
             }
             _ = shutdown_rx.changed() => {
@@ -192,11 +603,447 @@ pub async fn run_analytics_task(
     }
 }
 
+/// Collects the current order book and trade features into one snapshot.
+async fn build_snapshot(order_book: &ConcurrentOrderBook, trades_log: &ConcurrentTradesLog) -> FeaturesSnapshot {
+    const SIGNIFICANCE_THRESHOLD: Decimal = dec!(10.0);
+
+    let (ob_snap, trade_snap) = tokio::join!(
+        order_book.get_snapshot(),
+        trades_log.get_snapshot()
+    );
+
+    let (flow_imbalance, flow_pressure) = (ob_snap.order_flow_imbalance, ob_snap.order_flow_pressure);
+
+    FeaturesSnapshot {
+        timestamp: Utc::now().to_rfc3339(),
+        best_bid: ob_snap.best_bid.map(|(p, _)| p),
+        best_ask: ob_snap.best_ask.map(|(p, _)| p),
+        mid_price: ob_snap.mid_price,
+        microprice: ob_snap.microprice,
+        spread: ob_snap.spread,
+        imbalance: ob_snap.imbalance,
+        book_imbalance_ema: None,
+        spread_mean_100: None,
+        spread_std_100: None,
+        top_bids: ob_snap.top_bids,
+        top_asks: ob_snap.top_asks,
+        pwi_1: ob_snap.pwi_1,
+        pwi_5: ob_snap.pwi_5,
+        pwi_25: ob_snap.pwi_25,
+        pwi_50: ob_snap.pwi_50,
+        bid_slope: ob_snap.bid_slope,
+        ask_slope: ob_snap.ask_slope,
+        volume_imbalance_top5: ob_snap.volume_imbalance_top5,
+        bid_depth_ratio: ob_snap.bid_depth_ratio,
+        ask_depth_ratio: ob_snap.ask_depth_ratio,
+        bid_volume_001: ob_snap.bid_volume_001,
+        ask_volume_001: ob_snap.ask_volume_001,
+        bid_volume_005: ob_snap.bid_volume_005,
+        ask_volume_005: ob_snap.ask_volume_005,
+        bid_volume_010: ob_snap.bid_volume_010,
+        ask_volume_010: ob_snap.ask_volume_010,
+        bid_volume_050: ob_snap.bid_volume_050,
+        ask_volume_050: ob_snap.ask_volume_050,
+        bid_avg_distance: ob_snap.bid_avg_distance,
+        ask_avg_distance: ob_snap.ask_avg_distance,
+        last_trade_price: trade_snap.last_price,
+        vwap_10: trade_snap.vwap_10,
+        vwap_50: trade_snap.vwap_50,  
+        vwap_100: trade_snap.vwap_100,
+        vwap_1000: trade_snap.vwap_1000,
+        aggr_ratio_10: trade_snap.aggr_ratio_10,  
+        aggr_ratio_50: trade_snap.aggr_ratio_50,  
+        aggr_ratio_100: trade_snap.aggr_ratio_100,
+        aggr_ratio_1000: trade_snap.aggr_ratio_1000,
+        aggr_ratio_10s: trade_snap.aggr_ratio_10s,
+        large_trade_share_100: trade_snap.large_trade_share_100,
+        net_flow: net_flow(flow_imbalance, trade_snap.aggr_ratio_10s),
+        price_dislocation: price_dislocation(ob_snap.microprice, trade_snap.last_price),
+        price_dislocated: false,
+        feed_lag_ms: None,
+        trade_ingest_lag_ms: None,
+        depth_feed_healthy: true,
+        trade_feed_healthy: true,
+        trade_feed_gap_detected: false,
+        depth_msg_age_ms: None,
+        trade_msg_age_ms: None,
+        book_trusted: ob_snap.trusted,
+        book_last_update: ob_snap.last_update,
+        future_mid_100ms: None,
+        future_mid_1s: None,
+        lagged: BTreeMap::new(),
+        trade_imbalance: trade_snap.trade_imbalance,
+        trade_imbalance_ema: trade_snap.trade_imbalance_ema,
+        vwap_total: trade_snap.vwap_total,
+        price_change: trade_snap.price_change,
+        avg_trade_size: trade_snap.avg_trade_size,
+        signed_count_momentum: trade_snap.signed_count_momentum,
+        trade_rate_1s: trade_snap.trade_rate_1s,
+        trade_rate_10s: trade_snap.trade_rate_10s,
+        trade_rate_60s: trade_snap.trade_rate_60s,
+        order_flow_imbalance: flow_imbalance,
+        order_flow_pressure: flow_pressure,
+        bid_flow_pressure: ob_snap.bid_flow_pressure,
+        ask_flow_pressure: ob_snap.ask_flow_pressure,
+        order_flow_significance: flow_pressure >= SIGNIFICANCE_THRESHOLD,
+        flow_price_corr: None,
+        composite_pressure: ob_snap.composite_pressure,
+    }
+}
+
+fn worst_lag(feed_lags: &[FeedLag]) -> Option<f64> {
+    feed_lags.iter().filter_map(FeedLag::lag_ms).reduce(f64::max)
+}
+
+fn apply_feed_health(snapshot: &mut FeaturesSnapshot, health: &FeedHealth, now_ms: u64, stale_after: Duration) {
+    snapshot.depth_feed_healthy = health.is_healthy(Feed::Depth, now_ms, stale_after);
+    snapshot.trade_feed_healthy = health.is_healthy(Feed::Trade, now_ms, stale_after);
+    snapshot.trade_feed_gap_detected = health.trade_feed_gap_detected;
+    snapshot.trade_ingest_lag_ms = health.trade_ingest_lag_ms.map(|lag| lag as f64);
+    snapshot.depth_msg_age_ms = health.message_age_ms(Feed::Depth, now_ms);
+    snapshot.trade_msg_age_ms = health.message_age_ms(Feed::Trade, now_ms);
+}
+
+/// Order-flow imbalance and trade aggressor pressure folded into one directional reading.
+///
+/// Both sides look back 10 seconds. The taker-buy share in [0, 1] is mapped onto [-1, 1] as
+/// `2 * ratio - 1`, then weighted against the book side by `NET_FLOW_BOOK_WEIGHT` (an even
+/// split). Cancel penalties can push the book side a little past ±1, so the blend is clamped.
+/// `None` unless both readings are available.
+pub fn net_flow(order_flow_imbalance: Option<Decimal>, aggr_ratio_10s: Option<Decimal>) -> Option<Decimal> {
+    let book = order_flow_imbalance?;
+    let trades = dec!(2) * aggr_ratio_10s? - dec!(1);
+    let blended = NET_FLOW_BOOK_WEIGHT * book + (dec!(1) - NET_FLOW_BOOK_WEIGHT) * trades;
+    Some(blended.clamp(dec!(-1), dec!(1)))
+}
+
+/// How far the book's fair value has moved from where trades print: microprice minus
+/// the last trade price. Positive when trades are going off below fair value, so the
+/// next ones would be expected higher. `None` unless both prices are known.
+pub fn price_dislocation(microprice: Option<Decimal>, last_trade_price: Option<Decimal>) -> Option<Decimal> {
+    Some(microprice? - last_trade_price?)
+}
+
+fn is_dislocated(dislocation: Option<Decimal>, threshold: Option<Decimal>) -> bool {
+    matches!((dislocation, threshold), (Some(dislocation), Some(threshold)) if dislocation.abs() >= threshold)
+}
+
+/// Routes each snapshot to the configured sinks.
+struct SnapshotOutput<W: Write> {
+    sinks: Vec<OutputSink>,
+    console: W,
+    parquet: BatchWriter,
+    console_every_n_ticks: usize,
+    ticks: usize,
+    skip_empty_book: bool,
+    /// End of the warmup, until it has passed.
+    warmup_until: Option<Instant>,
+}
+
+impl<W: Write> SnapshotOutput<W> {
+    fn new(config: &AnalyticsConfig, console: W) -> Self {
+        Self {
+            sinks: config.sinks.clone(),
+            console,
+            parquet: BatchWriter::new(config.output_dir.clone(), config.rotation)
+                .with_filename_template(&config.filename_template, config.symbol.as_deref())
+                .with_persistence(config.persistence)
+                .with_snapshot_interval(config.snapshot_interval),
+            console_every_n_ticks: config.console_every_n_ticks.max(1),
+            ticks: 0,
+            skip_empty_book: config.skip_empty_book,
+            warmup_until: (!config.warmup.is_zero()).then(|| Instant::now() + config.warmup),
+        }
+    }
+
+    /// Whether the warmup is still running; notes when it ends.
+    fn warming_up(&mut self) -> bool {
+        match self.warmup_until {
+            Some(until) if Instant::now() < until => true,
+            Some(_) => {
+                println!("Analytics warmup over, persisting snapshots");
+                self.warmup_until = None;
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn publish(&mut self, snapshot: FeaturesSnapshot) {
+        self.ticks += 1;
+        let warming_up = self.warming_up();
+        let to_console = self.ticks.is_multiple_of(self.console_every_n_ticks);
+        let mut to_parquet = false;
+        for sink in &self.sinks {
+            match sink {
+                OutputSink::PrettyConsole | OutputSink::JsonConsole if !to_console => {}
+                OutputSink::PrettyConsole => {
+                    let _ = writeln!(self.console, "{}", pretty_summary(&snapshot));
+                }
+                OutputSink::JsonConsole => match serde_json::to_string(&snapshot) {
+                    Ok(line) => {
+                        let _ = writeln!(self.console, "{}", line);
+                    }
+                    Err(e) => eprintln!("Failed to serialize snapshot: {}", e),
+                },
+                OutputSink::Parquet if warming_up => {}
+                OutputSink::Parquet => {
+                    to_parquet = !(self.skip_empty_book && snapshot.best_bid.is_none() && snapshot.best_ask.is_none());
+                }
+                OutputSink::Channel(tx) => {
+                    let _ = tx.send(snapshot.clone());
+                }
+            }
+        }
+
+        if to_parquet {
+            self.parquet.push(snapshot);
+        }
+        // Checked on every tick, so an idle batch still rotates on time
+        self.parquet.rotate_if_due();
+    }
+}
+
+/// Parquet batch of snapshots, written out as one file whenever `rotation` says so.
+///
+/// Rows still pending when the writer is dropped are flushed to a last file, so a batch
+/// isn't lost when the analytics task returns early or panics; `finish` does the same
+/// explicitly.
+pub struct BatchWriter {
+    output_dir: PathBuf,
+    rotation: RotationPolicy,
+    filename_template: String,
+    symbol: Option<String>,
+    persistence: PersistenceConfig,
+    snapshot_interval: Duration,
+    /// When the writer was created, for `{run}`.
+    run_started: DateTime<Local>,
+    batch: Vec<FeaturesSnapshot>,
+    /// When the first snapshot of the current batch arrived.
+    batch_started: Option<Instant>,
+    batch_bytes: usize,
+    batch_id: usize,
+}
+
+impl BatchWriter {
+    pub fn new(output_dir: PathBuf, rotation: RotationPolicy) -> Self {
+        Self {
+            output_dir,
+            rotation,
+            filename_template: DEFAULT_FILENAME_TEMPLATE.to_string(),
+            symbol: None,
+            persistence: PersistenceConfig::default(),
+            snapshot_interval: Duration::from_millis(SNAPSHOT_INTERVAL_MS),
+            run_started: Local::now(),
+            batch: Vec::new(),
+            batch_started: None,
+            batch_bytes: 0,
+            batch_id: 0,
+        }
+    }
+
+    /// Names files after `template`, see `AnalyticsConfig::filename_template`. An invalid
+    /// one is reported and the default kept, so batches still get written somewhere.
+    pub fn with_filename_template(mut self, template: &str, symbol: Option<&str>) -> Self {
+        match validate_filename_template(template, symbol) {
+            Ok(()) => {
+                self.filename_template = template.to_string();
+                self.symbol = symbol.map(str::to_string);
+            }
+            Err(e) => eprintln!("{}; using {:?} instead", e, DEFAULT_FILENAME_TEMPLATE),
+        }
+        self
+    }
+
+    /// Stores batches as `persistence` says.
+    pub fn with_persistence(mut self, persistence: PersistenceConfig) -> Self {
+        self.persistence = persistence;
+        self
+    }
+
+    /// How far apart snapshots are meant to be, which bounds how late a `future_mid_*`
+    /// label may land; see `fill_future_mids`.
+    pub fn with_snapshot_interval(mut self, interval: Duration) -> Self {
+        self.snapshot_interval = interval;
+        self
+    }
+
+    /// Path the current batch is written to if it is flushed at `now`, unless a file is
+    /// already there.
+    pub fn filename(&self, now: DateTime<Local>) -> PathBuf {
+        let name = self
+            .filename_template
+            .replace("{symbol}", self.symbol.as_deref().unwrap_or_default())
+            .replace("{date}", &now.format("%Y%m%d").to_string())
+            .replace("{time}", &now.format("%H%M%S%3f").to_string())
+            .replace("{batch}", &format!("{:03}", self.batch_id))
+            .replace("{run}", &self.run_started.format("%Y%m%d_%H%M%S").to_string());
+        self.output_dir.join(name)
+    }
+
+    /// Adds a row to the current batch; it is written by `rotate_if_due` or on flush.
+    pub fn push(&mut self, snapshot: FeaturesSnapshot) {
+        self.batch_started.get_or_insert_with(Instant::now);
+        self.batch_bytes += snapshot_size(&snapshot);
+        self.batch.push(snapshot);
+    }
+
+    /// Rows waiting to be written.
+    pub fn pending(&self) -> usize {
+        self.batch.len()
+    }
+
+    /// Writes the batch out if the rotation policy says it is complete.
+    pub fn rotate_if_due(&mut self) {
+        if self.rotation_due() {
+            self.flush();
+        }
+    }
+
+    /// Writes whatever is pending and consumes the writer.
+    pub fn finish(mut self) {
+        self.flush();
+    }
+
+    fn rotation_due(&self) -> bool {
+        if self.batch.is_empty() {
+            return false;
+        }
+        match self.rotation {
+            RotationPolicy::ByCount(count) => self.batch.len() >= count,
+            RotationPolicy::ByDuration(age) => self.batch_started.is_some_and(|started| started.elapsed() >= age),
+            RotationPolicy::BySize(bytes) => self.batch_bytes >= bytes,
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        fill_future_mids(&mut self.batch, self.snapshot_interval);
+        let filename = persistence::unused_path(&self.filename(Local::now()));
+        let saved = persistence::save_features_with_config(&self.batch, &filename.to_string_lossy(), &self.persistence);
+        if let Err(e) = saved {
+            eprintln!("Failed to save batch {}: {}", self.batch_id, e);
+        }
+        self.batch.clear();
+        self.batch_started = None;
+        self.batch_bytes = 0;
+        self.batch_id += 1;
+    }
+}
+
+impl Drop for BatchWriter {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Rough in-memory footprint of a snapshot, including its depth levels.
+fn snapshot_size(snapshot: &FeaturesSnapshot) -> usize {
+    std::mem::size_of::<FeaturesSnapshot>()
+        + snapshot.timestamp.len()
+        + (snapshot.top_bids.len() + snapshot.top_asks.len()) * std::mem::size_of::<(Decimal, Decimal)>()
+        + snapshot.lagged.keys().map(|name| name.len() + std::mem::size_of::<Option<Decimal>>()).sum::<usize>()
+}
+
+/// Fills the `future_mid_*` labels of a batch by looking ahead within it.
+///
+/// Each row takes the mid price of the first later row at least the horizon past its own
+/// timestamp, so skipped ticks don't shift the alignment. Rows whose horizon runs past the
+/// end of the batch stay null, and so do rows whose first match lands more than `interval`
+/// past the horizon, where the snapshots have a gap.
+pub fn fill_future_mids(batch: &mut [FeaturesSnapshot], interval: Duration) {
+    let times: Vec<Option<DateTime<FixedOffset>>> = batch
+        .iter()
+        .map(|snapshot| DateTime::parse_from_rfc3339(&snapshot.timestamp).ok())
+        .collect();
+    let tolerance = chrono::Duration::from_std(interval).unwrap_or_else(|_| chrono::Duration::zero());
+    let look_ahead = |horizon: chrono::Duration| -> Vec<Option<Decimal>> {
+        let mut ahead = 0;
+        (0..batch.len())
+            .map(|i| {
+                let target = times[i]? + horizon;
+                ahead = ahead.max(i + 1);
+                while ahead < batch.len() && times[ahead].is_none_or(|t| t < target) {
+                    ahead += 1;
+                }
+                if times.get(ahead).copied().flatten()? - target > tolerance {
+                    return None;
+                }
+                batch[ahead].mid_price
+            })
+            .collect()
+    };
+    let mids_100ms = look_ahead(chrono::Duration::milliseconds(100));
+    let mids_1s = look_ahead(chrono::Duration::seconds(1));
+
+    for ((snapshot, mid_100ms), mid_1s) in batch.iter_mut().zip(mids_100ms).zip(mids_1s) {
+        snapshot.future_mid_100ms = mid_100ms;
+        snapshot.future_mid_1s = mid_1s;
+    }
+}
+
+fn pretty_summary(snapshot: &FeaturesSnapshot) -> String {
+    format!(
+        r#"[{}] MID: {:.2} | MICRO: {:.2} (Δ {:.4})
+        VWAP: 10={:.3} | 50={:.3} | 100={:.3} | 1000={:.3}
+        AGGR: 10={:.3} | 50={:.3} | 100={:.3} | 1000={:.3}
+        BID/ASK: {:?}/{:?} | SPRD: {:?} | IMB: {:?} (EMA {:?})
+        PWI: 1%={:?} 5%={:?} 25%={:?} 50%={:?}
+        SLOPE: B{:?}/A{:?} | VOL_IMB: {:?}
+        DEPTH: B{:?}/A{:?} | VOL(0.01%): B{:?}/A{:?}
+        TRADES: LAST={:?} IMB={:?}
+        VWAP_TOT={:?} ΔPRICE={:?} AVG_SIZE={:?}
+        MOMENTUM: {} TRADE_RATE={:?}
+        FLWIMB: {:.3}"#,
+        snapshot.timestamp,
+        snapshot.mid_price.unwrap_or(dec!(0)),
+        snapshot.microprice.unwrap_or(dec!(0)),
+        snapshot.microprice.unwrap_or(dec!(0)) - snapshot.mid_price.unwrap_or(dec!(0)),
+        snapshot.vwap_10.unwrap_or(dec!(0)),
+        snapshot.vwap_50.unwrap_or(dec!(0)),
+        snapshot.vwap_100.unwrap_or(dec!(0)),
+        snapshot.vwap_1000.unwrap_or(dec!(0)),
+        snapshot.aggr_ratio_10.unwrap_or(dec!(0)),
+        snapshot.aggr_ratio_50.unwrap_or(dec!(0)),
+        snapshot.aggr_ratio_100.unwrap_or(dec!(0)),
+        snapshot.aggr_ratio_1000.unwrap_or(dec!(0)),
+        snapshot.best_bid,
+        snapshot.best_ask,
+        snapshot.spread,
+        snapshot.imbalance,
+        snapshot.book_imbalance_ema,
+        snapshot.pwi_1,
+        snapshot.pwi_5,
+        snapshot.pwi_25,
+        snapshot.pwi_50,
+        snapshot.bid_slope,
+        snapshot.ask_slope,
+        snapshot.volume_imbalance_top5,
+        snapshot.bid_depth_ratio,
+        snapshot.ask_depth_ratio,
+        snapshot.bid_volume_001,
+        snapshot.ask_volume_001,
+        snapshot.last_trade_price,
+        snapshot.trade_imbalance,
+        snapshot.vwap_total,
+        snapshot.price_change,
+        snapshot.avg_trade_size,
+        snapshot.signed_count_momentum,
+        snapshot.trade_rate_10s,
+        snapshot.order_flow_imbalance.unwrap_or(dec!(0)),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
+        consolidated::ConsolidatedBook,
+        feed_health::HealthPublisher,
         orderbook::ConcurrentOrderBook,
+        stream_config::Exchange,
         tradeslog::{ConcurrentTradesLog, Trade},
     };
     use rust_decimal_macros::dec;
@@ -209,10 +1056,16 @@ mod tests {
         let (shutdown_tx, shutdown_rx) = watch::channel(false);
         let order_book = Arc::new(ConcurrentOrderBook::new());
         let trades_log = Arc::new(ConcurrentTradesLog::new(10));
+        let dir = tempfile::tempdir().unwrap();
+        let config = AnalyticsConfig {
+            output_dir: dir.path().to_path_buf(),
+            ..AnalyticsConfig::default()
+        };
 
         let task = tokio::spawn(run_analytics_task(
             order_book,
             trades_log,
+            config,
             shutdown_rx,
         ));
 
@@ -220,6 +1073,102 @@ mod tests {
         task.await.unwrap();
     }
 
+    #[tokio::test]
+    async fn test_consolidated_snapshots_are_sent_each_tick() {
+        let order_book = Arc::new(ConcurrentOrderBook::new());
+        order_book.apply_snapshot(vec![(dec!(100), dec!(1))], vec![(dec!(101), dec!(1))]).await;
+        let other_venue = ConcurrentOrderBook::new();
+        other_venue.apply_snapshot(vec![(dec!(100), dec!(2))], vec![(dec!(100.5), dec!(1))]).await;
+        let book = ConsolidatedBook::new()
+            .with_venue(Exchange::Binance, (*order_book).clone())
+            .with_venue(Exchange::Okx, other_venue);
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let config = AnalyticsConfig {
+            sinks: Vec::new(),
+            snapshot_interval: Duration::from_millis(10),
+            consolidated: Some(ConsolidatedOutput { book, depth: 5, tx }),
+            ..AnalyticsConfig::default()
+        };
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let task = tokio::spawn(run_analytics_task(order_book, Arc::new(ConcurrentTradesLog::new(10)), config, shutdown_rx));
+
+        let snapshot = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(snapshot.best_bid, Some((dec!(100), dec!(3))));
+        assert_eq!(snapshot.best_ask_venue, Some(Exchange::Okx));
+        shutdown_tx.send(true).unwrap();
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_feed_health_flags_follow_the_published_health() {
+        let health = HealthPublisher::new();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let config = AnalyticsConfig {
+            sinks: vec![OutputSink::Channel(tx)],
+            snapshot_interval: Duration::from_millis(10),
+            feed_health: Some(health.subscribe()),
+            ..AnalyticsConfig::default()
+        };
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let order_book = Arc::new(ConcurrentOrderBook::new());
+        let task = tokio::spawn(run_analytics_task(order_book, Arc::new(ConcurrentTradesLog::new(10)), config, shutdown_rx));
+        async fn next_where(
+            rx: &mut mpsc::UnboundedReceiver<FeaturesSnapshot>,
+            wanted: fn(&FeaturesSnapshot) -> bool,
+        ) -> FeaturesSnapshot {
+            loop {
+                let snapshot = tokio::time::timeout(Duration::from_secs(1), rx.recv()).await.unwrap().unwrap();
+                if wanted(&snapshot) {
+                    return snapshot;
+                }
+            }
+        }
+
+        // Neither feed has connected yet
+        let snapshot = next_where(&mut rx, |_| true).await;
+        assert!(!snapshot.depth_feed_healthy && !snapshot.trade_feed_healthy);
+        assert_eq!(snapshot.depth_msg_age_ms, None);
+
+        health.connected(Feed::Depth, false);
+        health.connected(Feed::Trade, false);
+        health.message(Feed::Depth);
+        health.message(Feed::Trade);
+        let snapshot = next_where(&mut rx, |s| s.depth_feed_healthy && s.trade_feed_healthy).await;
+        assert!(snapshot.depth_msg_age_ms.unwrap() < 1_000);
+
+        // The trade feed drops; its last message stays on record
+        health.disconnected(Feed::Trade);
+        let snapshot = next_where(&mut rx, |s| !s.trade_feed_healthy).await;
+        assert!(snapshot.depth_feed_healthy);
+        assert!(snapshot.trade_msg_age_ms.is_some());
+
+        shutdown_tx.send(true).unwrap();
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_a_quiet_feed_is_unhealthy_while_still_connected() {
+        let health = FeedHealth {
+            last_depth_msg_ms: Some(1_000),
+            last_trade_msg_ms: Some(9_000),
+            depth_connected: true,
+            trade_connected: true,
+            trade_feed_gap_detected: true,
+            trade_ingest_lag_ms: Some(35),
+            ..FeedHealth::default()
+        };
+        let mut snapshot = build_snapshot(&ConcurrentOrderBook::new(), &ConcurrentTradesLog::new(10)).await;
+        // Without health to go on, nothing says otherwise
+        assert!(snapshot.depth_feed_healthy && snapshot.trade_feed_healthy);
+
+        apply_feed_health(&mut snapshot, &health, 10_000, Duration::from_secs(5));
+        assert!(!snapshot.depth_feed_healthy);
+        assert!(snapshot.trade_feed_healthy && snapshot.trade_feed_gap_detected);
+        assert_eq!(snapshot.trade_ingest_lag_ms, Some(35.0));
+        assert_eq!((snapshot.depth_msg_age_ms, snapshot.trade_msg_age_ms), (Some(9_000), Some(1_000)));
+    }
+
     #[tokio::test]
     async fn test_trade_processing() {
         let order_book = Arc::new(ConcurrentOrderBook::new());
@@ -230,12 +1179,21 @@ mod tests {
             quantity: dec!(1.0),
             timestamp: Utc::now().timestamp_millis() as u64,
             is_buyer_maker: false,
+            symbol: None,
+            trade_id: None,
+            aggregated_trade_ids: None,
         }).await;
 
+        let dir = tempfile::tempdir().unwrap();
+        let config = AnalyticsConfig {
+            output_dir: dir.path().to_path_buf(),
+            ..AnalyticsConfig::default()
+        };
         let (shutdown_tx, shutdown_rx) = watch::channel(false);
         let task = tokio::spawn(run_analytics_task(
             order_book,
             trades_log.clone(),
+            config,
             shutdown_rx,
         ));
 
@@ -245,5 +1203,619 @@ mod tests {
 
         let snapshot = trades_log.get_snapshot().await;
         assert_eq!(snapshot.last_price, Some(dec!(100.0)));
+        // The partial batch is flushed when the task stops
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_filename_template_resolves_placeholders() {
+        use chrono::TimeZone;
+
+        let now = Local.with_ymd_and_hms(2024, 4, 10, 9, 5, 7).unwrap() + chrono::Duration::milliseconds(42);
+        let writer = BatchWriter::new(PathBuf::from("out"), RotationPolicy::ByCount(BATCH_SIZE));
+        assert_eq!(writer.filename(now), PathBuf::from("out/features_20240410_090507042_000.parquet"));
+
+        let mut writer = BatchWriter::new(PathBuf::from("out"), RotationPolicy::ByCount(BATCH_SIZE))
+            .with_filename_template("binance/{symbol}/{date}/{run}-part-{batch}.parquet", Some("btcusdt"));
+        writer.run_started = Local.with_ymd_and_hms(2024, 4, 10, 8, 0, 0).unwrap();
+        writer.batch_id = 12;
+        assert_eq!(
+            writer.filename(now),
+            PathBuf::from("out/binance/btcusdt/20240410/20240410_080000-part-012.parquet")
+        );
+
+        // Names that repeat within a second, from one day to the next, or from run to run
+        for template in ["features_{date}.parquet", "{time}.parquet", "{date}_{batch}.parquet"] {
+            assert_eq!(
+                validate_filename_template(template, None),
+                Err(FilenameTemplateError::NoUniquePart(template.to_string()))
+            );
+        }
+        assert_eq!(
+            validate_filename_template("{exchange}_{batch}.parquet", None),
+            Err(FilenameTemplateError::UnknownPlaceholder("{exchange}".to_string()))
+        );
+        assert_eq!(
+            validate_filename_template("{symbol}_{date}_{time}.parquet", None),
+            Err(FilenameTemplateError::MissingSymbol("{symbol}_{date}_{time}.parquet".to_string()))
+        );
+        assert!(AnalyticsConfig::default().validate().is_ok());
+
+        // An invalid template leaves the default in place
+        let writer = BatchWriter::new(PathBuf::from("out"), RotationPolicy::ByCount(BATCH_SIZE))
+            .with_filename_template("features.parquet", None);
+        assert_eq!(writer.filename(now), PathBuf::from("out/features_20240410_090507042_000.parquet"));
+    }
+
+    #[tokio::test]
+    async fn test_batch_writer_never_overwrites_a_file() {
+        use chrono::TimeZone;
+
+        let dir = tempfile::tempdir().unwrap();
+        let order_book = ConcurrentOrderBook::new();
+        let trades_log = ConcurrentTradesLog::new(10);
+
+        // Two writers started in the same second name their first batches alike
+        let run_started = Local.with_ymd_and_hms(2024, 4, 10, 8, 0, 0).unwrap();
+        for _ in 0..2 {
+            let mut writer = BatchWriter::new(dir.path().to_path_buf(), RotationPolicy::ByCount(BATCH_SIZE))
+                .with_filename_template("{run}_{batch}.parquet", None);
+            writer.run_started = run_started;
+            writer.push(build_snapshot(&order_book, &trades_log).await);
+            writer.finish();
+        }
+
+        let mut files: Vec<_> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["20240410_080000_000.parquet", "20240410_080000_000_1.parquet"]);
+    }
+
+    #[tokio::test]
+    async fn test_dropping_batch_writer_flushes_pending_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let order_book = ConcurrentOrderBook::new();
+        let trades_log = ConcurrentTradesLog::new(10);
+
+        let mut writer = BatchWriter::new(dir.path().to_path_buf(), RotationPolicy::ByCount(BATCH_SIZE));
+        for _ in 0..3 {
+            writer.push(build_snapshot(&order_book, &trades_log).await);
+        }
+        writer.rotate_if_due();
+        assert_eq!(writer.pending(), 3);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        drop(writer);
+        let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().path()).collect();
+        assert_eq!(files.len(), 1);
+        let features = persistence::load_features_from_parquet(&files[0].to_string_lossy()).unwrap();
+        assert_eq!(features.len(), 3);
+
+        // Nothing pending, nothing written
+        BatchWriter::new(dir.path().to_path_buf(), RotationPolicy::ByCount(BATCH_SIZE)).finish();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_book_imbalance_ema_trajectory() {
+        let mut ema = Ema::new(dec!(0.5));
+        let readings = [None, Some(dec!(0.8)), None, Some(dec!(-0.2)), Some(dec!(0.4)), Some(dec!(0.4))];
+        let trajectory: Vec<_> = readings.into_iter().map(|reading| ema.update(reading)).collect();
+        assert_eq!(
+            trajectory,
+            vec![None, Some(dec!(0.8)), Some(dec!(0.8)), Some(dec!(0.3)), Some(dec!(0.35)), Some(dec!(0.375))]
+        );
+    }
+
+    #[test]
+    fn test_spread_std_rises_as_the_spread_widens() {
+        let mut stats = RollingStats::new(4);
+        assert_eq!(stats.update(None), (None, None));
+
+        // A steady spread has no spread of its own
+        for _ in 0..4 {
+            assert_eq!(stats.update(Some(dec!(0.01))), (Some(dec!(0.01)), Some(dec!(0))));
+        }
+
+        let mut last_std = dec!(0);
+        for spread in [dec!(0.02), dec!(0.04), dec!(0.08)] {
+            let (_, std) = stats.update(Some(spread));
+            let std = std.unwrap();
+            assert!(std > last_std, "{} after {}", std, last_std);
+            last_std = std;
+        }
+        // The window now holds 0.01, 0.02, 0.04 and 0.08
+        let (mean, _) = stats.update(None);
+        assert_eq!(mean, Some(dec!(0.0375)));
+    }
+
+    #[test]
+    fn test_rolling_correlation_approaches_the_true_correlation() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(11);
+        let mut correlation = RollingCorrelation::new(2000);
+        assert_eq!(correlation.update(Some((dec!(1), dec!(1)))), None);
+
+        // y = x + noise with equal variances, so the true correlation is 1/√2
+        let mut estimate = None;
+        for _ in 0..5000 {
+            let x: f64 = rng.gen_range(-1.0..1.0);
+            let noise: f64 = rng.gen_range(-1.0..1.0);
+            let pair = (Decimal::from_f64(x).unwrap(), Decimal::from_f64(x + noise).unwrap());
+            estimate = correlation.update(Some(pair));
+        }
+        let estimate = estimate.unwrap().to_f64().unwrap();
+        assert!((estimate - std::f64::consts::FRAC_1_SQRT_2).abs() < 0.05, "{}", estimate);
+
+        // Anti-correlated readings displace the window and flip the sign
+        for _ in 0..2000 {
+            let x = Decimal::from_f64(rng.gen_range(-1.0..1.0)).unwrap();
+            correlation.update(Some((x, -x)));
+        }
+        let estimate = correlation.update(None).unwrap();
+        assert!(estimate <= dec!(-0.999999), "{}", estimate);
+
+        // A flat series has no correlation to speak of
+        let mut flat = RollingCorrelation::new(10);
+        for x in [dec!(1), dec!(2), dec!(3)] {
+            assert_eq!(flat.update(Some((x, dec!(5)))), None);
+        }
+    }
+
+    #[test]
+    fn test_flow_price_corr_pairs_flow_with_the_next_mid_change() {
+        let mut corr = FlowPriceCorrelation::new(10);
+        // Each tick's imbalance predicts the next tick's move exactly
+        let ticks = [
+            (Some(dec!(0.5)), Some(dec!(100))),
+            (Some(dec!(-0.2)), Some(dec!(100.5))),
+            (None, Some(dec!(100.3))),
+            (Some(dec!(0.4)), Some(dec!(100.1))),
+            (Some(dec!(0.1)), None),
+            (Some(dec!(-0.3)), Some(dec!(100.2))),
+            (Some(dec!(0.2)), Some(dec!(99.9))),
+        ];
+        let estimates: Vec<_> = ticks.into_iter().map(|(flow, mid)| corr.update(flow, mid)).collect();
+        assert_eq!(estimates[..2], [None, None]);
+        assert_eq!(estimates[6], Some(dec!(1)));
+        // A tick missing either reading starts no pair and completes none
+        let pairs: Vec<_> = corr.correlation.pairs.iter().copied().collect();
+        assert_eq!(pairs, vec![(dec!(0.5), dec!(0.5)), (dec!(-0.2), dec!(-0.2)), (dec!(-0.3), dec!(-0.3))]);
+    }
+
+    #[tokio::test]
+    async fn test_json_console_only_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AnalyticsConfig {
+            sinks: vec![OutputSink::JsonConsole],
+            rotation: RotationPolicy::ByCount(1),
+            output_dir: dir.path().to_path_buf(),
+            ..AnalyticsConfig::default()
+        };
+
+        let order_book = ConcurrentOrderBook::new();
+        order_book.apply_snapshot(vec![(dec!(100.0), dec!(2.0))], vec![(dec!(101.0), dec!(1.0))]).await;
+        let trades_log = ConcurrentTradesLog::new(10);
+
+        let mut output = SnapshotOutput::new(&config, Vec::new());
+        output.publish(build_snapshot(&order_book, &trades_log).await);
+        output.publish(build_snapshot(&order_book, &trades_log).await);
+
+        let printed = String::from_utf8(output.console).unwrap();
+        let lines: Vec<&str> = printed.lines().collect();
+        assert_eq!(lines.len(), 2);
+        for line in lines {
+            let json: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert!(json["timestamp"].is_string());
+            assert_eq!(json["best_bid"], "100.0");
+            assert_eq!(json["best_ask"], "101.0");
+        }
+
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_console_prints_every_nth_tick() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let config = AnalyticsConfig {
+            sinks: vec![OutputSink::JsonConsole, OutputSink::Parquet, OutputSink::Channel(tx)],
+            rotation: RotationPolicy::ByCount(12),
+            output_dir: dir.path().to_path_buf(),
+            console_every_n_ticks: 5,
+            ..AnalyticsConfig::default()
+        };
+
+        let order_book = ConcurrentOrderBook::new();
+        let trades_log = ConcurrentTradesLog::new(10);
+        let mut output = SnapshotOutput::new(&config, Vec::new());
+        for tick in 1..=12 {
+            let mut snapshot = build_snapshot(&order_book, &trades_log).await;
+            snapshot.signed_count_momentum = tick;
+            output.publish(snapshot);
+        }
+
+        let printed = String::from_utf8(output.console).unwrap();
+        let ticks: Vec<i64> = printed
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["signed_count_momentum"].as_i64().unwrap())
+            .collect();
+        assert_eq!(ticks, vec![5, 10]);
+
+        // Everything else still sees every tick
+        assert_eq!(std::iter::from_fn(|| rx.try_recv().ok()).count(), 12);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_batches_rotate_by_duration() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = AnalyticsConfig {
+            sinks: vec![OutputSink::Parquet],
+            rotation: RotationPolicy::ByDuration(Duration::from_millis(200)),
+            output_dir: dir.path().to_path_buf(),
+            ..AnalyticsConfig::default()
+        };
+
+        let order_book = ConcurrentOrderBook::new();
+        let trades_log = ConcurrentTradesLog::new(10);
+        let mut output = SnapshotOutput::new(&config, Vec::new());
+        output.publish(build_snapshot(&order_book, &trades_log).await);
+        output.publish(build_snapshot(&order_book, &trades_log).await);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        output.publish(build_snapshot(&order_book, &trades_log).await);
+
+        // Three snapshots, far short of a count-based batch
+        let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().path()).collect();
+        assert_eq!(files.len(), 1);
+        let features = persistence::load_features_from_parquet(&files[0].to_string_lossy()).unwrap();
+        assert_eq!(features.len(), 3);
+        assert_eq!(output.parquet.pending(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_batches_rotate_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let order_book = ConcurrentOrderBook::new();
+        let trades_log = ConcurrentTradesLog::new(10);
+        let snapshot = build_snapshot(&order_book, &trades_log).await;
+        let config = AnalyticsConfig {
+            sinks: vec![OutputSink::Parquet],
+            rotation: RotationPolicy::BySize(snapshot_size(&snapshot) * 5 / 2),
+            output_dir: dir.path().to_path_buf(),
+            ..AnalyticsConfig::default()
+        };
+
+        let mut output = SnapshotOutput::new(&config, Vec::new());
+        for _ in 0..6 {
+            output.publish(snapshot.clone());
+        }
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_lagged_features_carry_earlier_ticks() {
+        let order_book = ConcurrentOrderBook::new();
+        let trades_log = ConcurrentTradesLog::new(10);
+        let lagged = ["mid_price_lag1", "imbalance_lag5"].map(|name| name.parse::<LaggedFeature>().unwrap());
+        let mut lags = FeatureLags::new(&lagged);
+
+        let mut rows = Vec::new();
+        for tick in 0..8 {
+            let mut snapshot = build_snapshot(&order_book, &trades_log).await;
+            snapshot.mid_price = Some(Decimal::from(100 + tick));
+            snapshot.imbalance = Some(Decimal::new(tick, 1));
+            lags.apply(&mut snapshot);
+            rows.push(snapshot);
+        }
+
+        assert_eq!(rows[0].lagged.get("mid_price_lag1"), Some(&None));
+        for n in 1..rows.len() {
+            assert_eq!(rows[n].lagged["mid_price_lag1"], rows[n - 1].mid_price, "row {}", n);
+        }
+        let imbalance_lag5: Vec<_> = rows.iter().map(|row| row.lagged["imbalance_lag5"]).collect();
+        assert_eq!(imbalance_lag5, vec![None, None, None, None, None, Some(dec!(0)), Some(dec!(0.1)), Some(dec!(0.2))]);
+
+        // Flattened into the JSON output as columns of their own
+        let json = serde_json::to_value(&rows[1]).unwrap();
+        assert_eq!(json["mid_price_lag1"], serde_json::json!("100"));
+    }
+
+    #[test]
+    fn test_lagged_feature_names() {
+        let feature: LaggedFeature = "order_flow_imbalance_lag10".parse().unwrap();
+        assert_eq!((feature.name(), feature.lag()), ("order_flow_imbalance_lag10".to_string(), 10));
+        assert_eq!("mid_price".parse::<LaggedFeature>(), Err(LaggedFeatureError::Malformed("mid_price".to_string())));
+        assert_eq!(
+            "top_bids_lag1".parse::<LaggedFeature>(),
+            Err(LaggedFeatureError::UnknownFeature("top_bids_lag1".to_string()))
+        );
+        assert_eq!("spread_lag0".parse::<LaggedFeature>(), Err(LaggedFeatureError::ZeroLag("spread_lag0".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_future_mids_look_ahead_within_batch() {
+        let order_book = ConcurrentOrderBook::new();
+        let trades_log = ConcurrentTradesLog::new(10);
+        let start = Utc::now();
+
+        // One snapshot every 100ms with mid = tick, except that tick 3 was skipped
+        let mut batch = Vec::new();
+        for tick in (0..15).filter(|&tick| tick != 3) {
+            let mut snapshot = build_snapshot(&order_book, &trades_log).await;
+            snapshot.timestamp = (start + chrono::Duration::milliseconds(100 * tick)).to_rfc3339();
+            snapshot.mid_price = Some(Decimal::from(tick));
+            batch.push(snapshot);
+        }
+        fill_future_mids(&mut batch, Duration::from_millis(100));
+
+        let mids = |ticks: Vec<Option<i64>>| -> Vec<Option<Decimal>> {
+            ticks.into_iter().map(|tick| tick.map(Decimal::from)).collect()
+        };
+        let future_100ms: Vec<_> = batch.iter().map(|s| s.future_mid_100ms).collect();
+        let future_1s: Vec<_> = batch.iter().map(|s| s.future_mid_1s).collect();
+
+        // Tick 2 looks past the gap to tick 4
+        let mut expected: Vec<_> = (1..15).filter(|&tick| tick != 3).map(Some).collect();
+        expected.push(None);
+        assert_eq!(future_100ms, mids(expected));
+
+        // Only ticks 0..=4 have a row a full second ahead; the rest of the batch stays null
+        let mut expected = vec![Some(10), Some(11), Some(12), Some(14)];
+        expected.resize(batch.len(), None);
+        assert_eq!(future_1s, mids(expected));
+    }
+
+    #[tokio::test]
+    async fn test_future_mids_stay_null_across_a_gap() {
+        let order_book = ConcurrentOrderBook::new();
+        let trades_log = ConcurrentTradesLog::new(10);
+        let start = Utc::now();
+
+        // One snapshot every 100ms with mid = tick, but nothing from tick 5 to tick 19
+        let mut batch = Vec::new();
+        for tick in (0..5).chain(20..32) {
+            let mut snapshot = build_snapshot(&order_book, &trades_log).await;
+            snapshot.timestamp = (start + chrono::Duration::milliseconds(100 * tick)).to_rfc3339();
+            snapshot.mid_price = Some(Decimal::from(tick));
+            batch.push(snapshot);
+        }
+        fill_future_mids(&mut batch, Duration::from_millis(100));
+
+        let future_100ms: Vec<_> = batch.iter().map(|s| s.future_mid_100ms).collect();
+        let future_1s: Vec<_> = batch.iter().map(|s| s.future_mid_1s).collect();
+
+        // Tick 4's next row is 1.5s later, far past either horizon
+        let mut expected: Vec<_> = (1..5).map(|tick| Some(Decimal::from(tick))).collect();
+        expected.push(None);
+        expected.extend((21..32).map(|tick| Some(Decimal::from(tick))));
+        expected.push(None);
+        assert_eq!(future_100ms, expected);
+
+        let mut expected = vec![None; 5];
+        expected.push(Some(dec!(30)));
+        expected.push(Some(dec!(31)));
+        expected.resize(batch.len(), None);
+        assert_eq!(future_1s, expected);
+    }
+
+    #[tokio::test]
+    async fn test_net_flow_when_book_and_trades_agree() {
+        let order_book = ConcurrentOrderBook::new();
+        let trades_log = ConcurrentTradesLog::new(100);
+
+        // Heavy bid adds against one small ask
+        order_book.apply_deltas(
+            vec![(dec!(100.0), dec!(4)), (dec!(99.9), dec!(3)), (dec!(99.8), dec!(3))],
+            vec![(dec!(100.1), dec!(1))],
+        ).await;
+        // Mostly taker buys
+        let now = Utc::now().timestamp_millis() as u64;
+        for (i, (quantity, is_buyer_maker)) in [(dec!(2), false), (dec!(1), true), (dec!(2), false)].into_iter().enumerate() {
+            trades_log.insert_trade(Trade {
+                price: dec!(100.1),
+                quantity,
+                timestamp: now + i as u64,
+                is_buyer_maker,
+                symbol: None,
+                trade_id: None,
+                aggregated_trade_ids: None,
+            }).await;
+        }
+
+        let snapshot = build_snapshot(&order_book, &trades_log).await;
+        assert_eq!(snapshot.aggr_ratio_10s, Some(dec!(0.8)));
+        let book_flow = snapshot.order_flow_imbalance.unwrap();
+        assert!(book_flow > dec!(0.8), "{}", book_flow);
+        let combined = snapshot.net_flow.unwrap();
+        assert!(combined > dec!(0.7) && combined <= dec!(1), "{}", combined);
+
+        // Opposing flows cancel out, and either side missing leaves no reading
+        assert_eq!(net_flow(Some(dec!(0.6)), Some(dec!(0.2))), Some(dec!(0)));
+        assert_eq!(net_flow(Some(dec!(1.2)), Some(dec!(1))), Some(dec!(1)));
+        assert_eq!(net_flow(None, Some(dec!(1))), None);
+        assert_eq!(net_flow(Some(dec!(1)), None), None);
+    }
+
+    #[tokio::test]
+    async fn test_price_dislocation_sign_follows_the_trades() {
+        let order_book = ConcurrentOrderBook::new();
+        let trades_log = ConcurrentTradesLog::new(10);
+        assert_eq!(build_snapshot(&order_book, &trades_log).await.price_dislocation, None);
+
+        // Bid-heavy touch puts the microprice near the ask, at 100.09
+        order_book.apply_snapshot(vec![(dec!(100.0), dec!(9))], vec![(dec!(100.1), dec!(1))]).await;
+        let snapshot = build_snapshot(&order_book, &trades_log).await;
+        assert_eq!(snapshot.microprice, Some(dec!(100.09)));
+        assert_eq!(snapshot.price_dislocation, None);
+
+        let trade = |price| Trade {
+            price,
+            quantity: dec!(1),
+            timestamp: Utc::now().timestamp_millis() as u64,
+            is_buyer_maker: true,
+            symbol: None,
+            trade_id: None,
+            aggregated_trade_ids: None,
+        };
+        // Trades hitting the bid print below fair value
+        trades_log.insert_trade(trade(dec!(100.0))).await;
+        let snapshot = build_snapshot(&order_book, &trades_log).await;
+        assert_eq!(snapshot.price_dislocation, Some(dec!(0.09)));
+        assert!(is_dislocated(snapshot.price_dislocation, Some(dec!(0.05))));
+        assert!(!is_dislocated(snapshot.price_dislocation, Some(dec!(0.1))));
+        assert!(!is_dislocated(snapshot.price_dislocation, None));
+
+        // ...and a print through the ask above it
+        trades_log.insert_trade(trade(dec!(100.2))).await;
+        let snapshot = build_snapshot(&order_book, &trades_log).await;
+        assert_eq!(snapshot.price_dislocation, Some(dec!(-0.11)));
+        assert!(is_dislocated(snapshot.price_dislocation, Some(dec!(0.1))));
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_report_the_slowest_feed() {
+        use crate::clock::ManualClock;
+
+        let clock = Arc::new(ManualClock::new(1_000_000));
+        let depth_lag = FeedLag::with_clock("test_lob_feed_lag_ms", clock.clone());
+        let trade_lag = FeedLag::with_clock("test_trade_feed_lag_ms", clock);
+        depth_lag.record(999_990);
+        trade_lag.record(999_875);
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let config = AnalyticsConfig {
+            sinks: vec![OutputSink::Channel(tx)],
+            snapshot_interval: Duration::from_millis(10),
+            feed_lags: vec![depth_lag, trade_lag],
+            ..AnalyticsConfig::default()
+        };
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let task = tokio::spawn(run_analytics_task(
+            Arc::new(ConcurrentOrderBook::new()),
+            Arc::new(ConcurrentTradesLog::new(10)),
+            config,
+            shutdown_rx,
+        ));
+
+        let snapshot = rx.recv().await.unwrap();
+        shutdown_tx.send(true).unwrap();
+        task.await.unwrap();
+        assert_eq!(snapshot.feed_lag_ms, Some(125.0));
+        assert_eq!(worst_lag(&[]), None);
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_carry_book_trust() {
+        let order_book = ConcurrentOrderBook::new();
+        let trades_log = ConcurrentTradesLog::new(10);
+        assert!(!build_snapshot(&order_book, &trades_log).await.book_trusted);
+
+        order_book.apply_snapshot(vec![(dec!(100), dec!(1))], vec![(dec!(101), dec!(1))]).await;
+        assert!(build_snapshot(&order_book, &trades_log).await.book_trusted);
+
+        order_book.mark_untrusted().await;
+        let snapshot = build_snapshot(&order_book, &trades_log).await;
+        assert!(!snapshot.book_trusted);
+        assert_eq!(snapshot.best_bid, Some(dec!(100)));
+    }
+
+    #[tokio::test]
+    async fn test_channel_and_parquet_sinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let config = AnalyticsConfig {
+            sinks: vec![OutputSink::Parquet, OutputSink::Channel(tx)],
+            rotation: RotationPolicy::ByCount(2),
+            output_dir: dir.path().to_path_buf(),
+            ..AnalyticsConfig::default()
+        };
+
+        let order_book = ConcurrentOrderBook::new();
+        let trades_log = ConcurrentTradesLog::new(10);
+        let mut output = SnapshotOutput::new(&config, Vec::new());
+        for _ in 0..2 {
+            output.publish(build_snapshot(&order_book, &trades_log).await);
+        }
+
+        assert!(output.console.is_empty());
+        assert!(rx.try_recv().is_ok());
+        assert!(rx.try_recv().is_ok());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_empty_book_is_not_persisted_when_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let config = AnalyticsConfig {
+            sinks: vec![OutputSink::Parquet, OutputSink::Channel(tx)],
+            rotation: RotationPolicy::ByCount(2),
+            output_dir: dir.path().to_path_buf(),
+            skip_empty_book: true,
+            ..AnalyticsConfig::default()
+        };
+
+        let order_book = ConcurrentOrderBook::new();
+        let trades_log = ConcurrentTradesLog::new(10);
+        let mut output = SnapshotOutput::new(&config, Vec::new());
+        for _ in 0..3 {
+            output.publish(build_snapshot(&order_book, &trades_log).await);
+        }
+        assert_eq!(output.parquet.pending(), 0);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        order_book.apply_snapshot(vec![(dec!(100), dec!(1))], vec![(dec!(101), dec!(1))]).await;
+        for _ in 0..2 {
+            output.publish(build_snapshot(&order_book, &trades_log).await);
+        }
+        let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|f| f.unwrap().path()).collect();
+        assert_eq!(files.len(), 1);
+        let rows = persistence::load_features_from_parquet(&files[0].to_string_lossy()).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert!(rows.iter().all(|row| row.best_bid == Some(dec!(100))));
+
+        // The channel still saw every tick
+        assert_eq!(std::iter::from_fn(|| rx.try_recv().ok()).count(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_nothing_is_persisted_during_warmup() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let config = AnalyticsConfig {
+            sinks: vec![OutputSink::Parquet, OutputSink::Channel(tx)],
+            rotation: RotationPolicy::ByCount(2),
+            output_dir: dir.path().to_path_buf(),
+            warmup: Duration::from_millis(100),
+            ..AnalyticsConfig::default()
+        };
+
+        let order_book = ConcurrentOrderBook::new();
+        order_book.apply_snapshot(vec![(dec!(100), dec!(1))], vec![(dec!(101), dec!(1))]).await;
+        let trades_log = ConcurrentTradesLog::new(10);
+        let mut output = SnapshotOutput::new(&config, Vec::new());
+        for _ in 0..3 {
+            output.publish(build_snapshot(&order_book, &trades_log).await);
+        }
+        assert_eq!(output.parquet.pending(), 0);
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        output.publish(build_snapshot(&order_book, &trades_log).await);
+        assert_eq!(output.parquet.pending(), 1);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+        output.publish(build_snapshot(&order_book, &trades_log).await);
+        let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|f| f.unwrap().path()).collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(persistence::load_features_from_parquet(&files[0].to_string_lossy()).unwrap().len(), 2);
+
+        // Warmup only holds back persistence
+        assert_eq!(std::iter::from_fn(|| rx.try_recv().ok()).count(), 5);
     }
 }
@@ -0,0 +1,259 @@
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+const DEFAULT_AUDIT_INTERVAL: Duration = Duration::from_secs(300);
+const DEFAULT_AUDIT_DEPTH: usize = 20;
+const DEFAULT_MAX_DIVERGENT_LEVELS: usize = 2;
+
+/// How often and how strictly a live book is checked against a fresh REST depth
+/// snapshot; see `LobFeedManager::with_audit`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditConfig {
+    pub interval: Duration,
+    /// Levels compared on each side, from the best price.
+    pub depth: usize,
+    /// Largest relative difference between the live and snapshot quantity at a price
+    /// that still counts as a match, e.g. 0.01 for 1%.
+    pub tolerance: Decimal,
+    /// Divergent levels an audit puts up with. The book and the snapshot are taken
+    /// moments apart, so a busy book rarely matches level for level.
+    pub max_divergent_levels: usize,
+    /// Resyncs a book from a new snapshot when its audit diverges.
+    pub resync: bool,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            interval: DEFAULT_AUDIT_INTERVAL,
+            depth: DEFAULT_AUDIT_DEPTH,
+            tolerance: Decimal::new(1, 2),
+            max_divergent_levels: DEFAULT_MAX_DIVERGENT_LEVELS,
+            resync: false,
+        }
+    }
+}
+
+impl AuditConfig {
+    pub fn new(interval: Duration) -> Self {
+        Self { interval, ..Self::default() }
+    }
+
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    pub fn with_tolerance(mut self, tolerance: Decimal) -> Self {
+        self.tolerance = tolerance;
+        self
+    }
+
+    pub fn with_max_divergent_levels(mut self, levels: usize) -> Self {
+        self.max_divergent_levels = levels;
+        self
+    }
+
+    pub fn with_resync(mut self, resync: bool) -> Self {
+        self.resync = resync;
+        self
+    }
+}
+
+/// A price where the live book and the snapshot disagree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LevelDivergence {
+    /// The snapshot has a level the live book lacks.
+    Missing { price: Decimal, quantity: Decimal },
+    /// The live book has a level the snapshot lacks.
+    Extra { price: Decimal, quantity: Decimal },
+    /// Both have the level, with quantities further apart than the tolerance.
+    Quantity { price: Decimal, live: Decimal, snapshot: Decimal },
+}
+
+/// Outcome of comparing a live book with a snapshot, divergences best price first.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AuditReport {
+    pub bids: Vec<LevelDivergence>,
+    pub asks: Vec<LevelDivergence>,
+    /// Distinct prices compared across both sides.
+    pub levels_compared: usize,
+}
+
+impl AuditReport {
+    pub fn divergent_levels(&self) -> usize {
+        self.bids.len() + self.asks.len()
+    }
+
+    /// Whether more levels diverge than `config` puts up with.
+    pub fn diverged(&self, config: &AuditConfig) -> bool {
+        self.divergent_levels() > config.max_divergent_levels
+    }
+}
+
+/// Compares the top `config.depth` levels a side of a live book with a snapshot's.
+///
+/// Levels may come in any order. A side cut at `depth` says nothing about prices past
+/// its last one, so only prices within both sides' reach are compared; otherwise a
+/// level inserted near the top would push the last one out and show up as missing.
+pub fn diff_books(
+    live_bids: &[(Decimal, Decimal)],
+    live_asks: &[(Decimal, Decimal)],
+    snapshot_bids: &[(Decimal, Decimal)],
+    snapshot_asks: &[(Decimal, Decimal)],
+    config: &AuditConfig,
+) -> AuditReport {
+    let (bids, bid_levels) = diff_side(live_bids, snapshot_bids, true, config);
+    let (asks, ask_levels) = diff_side(live_asks, snapshot_asks, false, config);
+    AuditReport { bids, asks, levels_compared: bid_levels + ask_levels }
+}
+
+fn diff_side(
+    live: &[(Decimal, Decimal)],
+    snapshot: &[(Decimal, Decimal)],
+    is_bid: bool,
+    config: &AuditConfig,
+) -> (Vec<LevelDivergence>, usize) {
+    let live = top_levels(live, is_bid, config.depth);
+    let snapshot = top_levels(snapshot, is_bid, config.depth);
+    let worse = |a: Decimal, b: Decimal| if is_bid { a.min(b) } else { a.max(b) };
+    let better = |a: Decimal, b: Decimal| if is_bid { a.max(b) } else { a.min(b) };
+    // The shallower of the cut sides bounds what both can speak for
+    let reach = [&live, &snapshot]
+        .into_iter()
+        .filter(|levels| levels.len() == config.depth)
+        .filter_map(|levels| levels.keys().copied().reduce(worse))
+        .reduce(better);
+    let within_reach = |price: &Decimal| match reach {
+        Some(reach) => worse(*price, reach) == reach,
+        None => true,
+    };
+
+    let mut prices: Vec<Decimal> = live.keys().chain(snapshot.keys()).copied().filter(within_reach).collect();
+    prices.sort_unstable();
+    prices.dedup();
+    if is_bid {
+        prices.reverse();
+    }
+
+    let divergences = prices
+        .iter()
+        .filter_map(|&price| match (live.get(&price), snapshot.get(&price)) {
+            (None, Some(&quantity)) => Some(LevelDivergence::Missing { price, quantity }),
+            (Some(&quantity), None) => Some(LevelDivergence::Extra { price, quantity }),
+            (Some(&live), Some(&snapshot)) if (live - snapshot).abs() > config.tolerance * snapshot => {
+                Some(LevelDivergence::Quantity { price, live, snapshot })
+            }
+            _ => None,
+        })
+        .collect();
+    (divergences, prices.len())
+}
+
+/// The best `depth` levels with a quantity.
+fn top_levels(levels: &[(Decimal, Decimal)], is_bid: bool, depth: usize) -> BTreeMap<Decimal, Decimal> {
+    let book: BTreeMap<Decimal, Decimal> = levels.iter().copied().filter(|(_, quantity)| !quantity.is_zero()).collect();
+    if is_bid {
+        book.into_iter().rev().take(depth).collect()
+    } else {
+        book.into_iter().take(depth).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::OrderBook;
+    use rust_decimal_macros::dec;
+
+    fn book(bids: Vec<(Decimal, Decimal)>, asks: Vec<(Decimal, Decimal)>) -> OrderBook {
+        let mut book = OrderBook::new();
+        book.apply_snapshot(bids, asks);
+        book
+    }
+
+    fn audit(live: &OrderBook, snapshot: &OrderBook, config: &AuditConfig) -> AuditReport {
+        let depth = config.depth;
+        diff_books(
+            &live.top_bids(depth),
+            &live.top_asks(depth),
+            &snapshot.top_bids(depth),
+            &snapshot.top_asks(depth),
+            config,
+        )
+    }
+
+    #[test]
+    fn test_known_discrepancies_are_reported_best_price_first() {
+        // The live book missed the removal of 100.2, never saw 99.9, and drifted at 100.0
+        let snapshot = book(
+            vec![(dec!(100.0), dec!(1.0)), (dec!(99.9), dec!(2.0)), (dec!(99.8), dec!(3.0))],
+            vec![(dec!(100.1), dec!(1.0)), (dec!(100.3), dec!(3.0))],
+        );
+        let live = book(
+            vec![(dec!(100.0), dec!(1.5)), (dec!(99.8), dec!(3.0)), (dec!(99.7), dec!(1.0))],
+            vec![
+                (dec!(100.1), dec!(1.005)),
+                (dec!(100.2), dec!(2.0)),
+                (dec!(100.25), dec!(0.4)),
+                (dec!(100.3), dec!(3.0)),
+            ],
+        );
+        let config = AuditConfig::default().with_depth(5);
+
+        let report = audit(&live, &snapshot, &config);
+        assert_eq!(
+            report.bids,
+            vec![
+                LevelDivergence::Quantity { price: dec!(100.0), live: dec!(1.5), snapshot: dec!(1.0) },
+                LevelDivergence::Missing { price: dec!(99.9), quantity: dec!(2.0) },
+                LevelDivergence::Extra { price: dec!(99.7), quantity: dec!(1.0) },
+            ]
+        );
+        // 100.1 is off by half a percent, inside the 1% tolerance
+        assert_eq!(
+            report.asks,
+            vec![
+                LevelDivergence::Extra { price: dec!(100.2), quantity: dec!(2.0) },
+                LevelDivergence::Extra { price: dec!(100.25), quantity: dec!(0.4) },
+            ]
+        );
+        assert_eq!(report.levels_compared, 8);
+        assert_eq!(report.divergent_levels(), 5);
+        assert!(report.diverged(&config));
+        assert!(!report.diverged(&config.with_max_divergent_levels(5)));
+
+        let json = serde_json::to_string(&report.bids[1]).unwrap();
+        assert_eq!(json, r#"{"kind":"missing","price":"99.9","quantity":"2.0"}"#);
+    }
+
+    #[test]
+    fn test_matching_books_agree_within_tolerance() {
+        let snapshot = book(vec![(dec!(50), dec!(10))], vec![(dec!(51), dec!(4))]);
+        let live = book(vec![(dec!(50), dec!(10.05))], vec![(dec!(51), dec!(4))]);
+
+        let report = audit(&live, &snapshot, &AuditConfig::default());
+        assert_eq!(report, AuditReport { levels_compared: 2, ..AuditReport::default() });
+
+        let strict = AuditConfig::default().with_tolerance(Decimal::ZERO).with_max_divergent_levels(0);
+        assert!(audit(&live, &snapshot, &strict).diverged(&strict));
+    }
+
+    #[test]
+    fn test_levels_past_either_sides_depth_are_not_compared() {
+        // A new best bid pushed 98 out of the live top 3; it is still in the snapshot's
+        let snapshot = book(vec![(dec!(100), dec!(1)), (dec!(99), dec!(1)), (dec!(98), dec!(1))], vec![]);
+        let live = book(
+            vec![(dec!(100.5), dec!(1)), (dec!(100), dec!(1)), (dec!(99), dec!(1)), (dec!(98), dec!(1))],
+            vec![],
+        );
+        let config = AuditConfig::default().with_depth(3);
+
+        let report = audit(&live, &snapshot, &config);
+        assert_eq!(report.bids, vec![LevelDivergence::Extra { price: dec!(100.5), quantity: dec!(1) }]);
+        assert_eq!(report.levels_compared, 3);
+    }
+}
@@ -0,0 +1,112 @@
+use log::{debug, warn};
+use std::sync::Arc;
+use tokio::sync::watch;
+
+/// Where a feed connection is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConnectorState {
+    /// Not connected and not about to be: before the first attempt, between a drop and
+    /// the reconnect backoff, and after shutdown.
+    #[default]
+    Idle,
+    Connecting,
+    Connected,
+    /// Waiting out the reconnect backoff.
+    Backoff,
+    /// Gave up reconnecting; the feed has stopped. Only a new attempt leaves it.
+    Error,
+}
+
+impl ConnectorState {
+    fn can_become(self, next: ConnectorState) -> bool {
+        use ConnectorState::*;
+        matches!(
+            (self, next),
+            (Idle, Connecting) | (Idle, Backoff) | (Connecting, Connected) | (Connecting, Idle) | (Connected, Idle)
+                | (Backoff, Connecting) | (Backoff, Idle)
+                | (Idle, Error) | (Backoff, Error) | (Error, Connecting)
+        )
+    }
+}
+
+/// Connection state of one feed, published to any number of watchers.
+///
+/// The transport drives it; transitions the lifecycle doesn't allow (say, `Connected`
+/// straight to `Backoff`) are logged and ignored. Clones share the state.
+#[derive(Debug, Clone)]
+pub struct ConnectorFsm {
+    tx: Arc<watch::Sender<ConnectorState>>,
+}
+
+impl ConnectorFsm {
+    pub fn new() -> Self {
+        let (tx, _) = watch::channel(ConnectorState::Idle);
+        Self { tx: Arc::new(tx) }
+    }
+
+    pub fn state(&self) -> ConnectorState {
+        *self.tx.borrow()
+    }
+
+    /// Receiver that sees every state from now on; fast transitions may be coalesced.
+    pub fn subscribe(&self) -> watch::Receiver<ConnectorState> {
+        self.tx.subscribe()
+    }
+
+    /// Moves to `next`, returning whether the lifecycle allowed it. Staying put is a no-op.
+    pub fn transition(&self, next: ConnectorState) -> bool {
+        let current = self.state();
+        if current == next {
+            return true;
+        }
+        if !current.can_become(next) {
+            warn!("Ignoring connector transition {:?} -> {:?}", current, next);
+            return false;
+        }
+        debug!("Connector {:?} -> {:?}", current, next);
+        self.tx.send_replace(next);
+        true
+    }
+}
+
+impl Default for ConnectorFsm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ConnectorState::*;
+
+    #[test]
+    fn test_lifecycle_transitions() {
+        let fsm = ConnectorFsm::new();
+        let rx = fsm.subscribe();
+        assert_eq!(fsm.state(), Idle);
+
+        for next in [Connecting, Connected, Idle, Backoff, Connecting, Idle] {
+            assert!(fsm.transition(next), "{:?}", next);
+            assert_eq!(*rx.borrow(), next);
+        }
+
+        // A live connection has to drop before backing off
+        assert!(fsm.transition(Connecting));
+        assert!(fsm.transition(Connected));
+        assert!(!fsm.transition(Backoff));
+        assert!(!fsm.transition(Connecting));
+        assert_eq!(fsm.state(), Connected);
+
+        // Giving up sticks until the next attempt
+        assert!(fsm.transition(Idle));
+        assert!(fsm.transition(Error));
+        assert!(!fsm.transition(Idle));
+        assert!(!fsm.transition(Backoff));
+        assert!(fsm.transition(Connecting));
+        assert!(fsm.transition(Connected));
+
+        // Clones share the state
+        assert_eq!(fsm.clone().state(), Connected);
+    }
+}
@@ -0,0 +1,286 @@
+use crate::orderbook::ConcurrentOrderBook;
+use crate::stream_config::Exchange;
+use chrono::Utc;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tokio::sync::mpsc;
+
+/// One instrument's order books on several venues, read as a single book.
+///
+/// Levels at the same price on different venues add up. Each venue's book is read on its
+/// own, so a snapshot is consistent per venue but not across them; with feeds lagging
+/// differently the merged book can cross, which the snapshot reports as `crossed`.
+#[derive(Debug, Clone, Default)]
+pub struct ConsolidatedBook {
+    venues: Vec<(Exchange, ConcurrentOrderBook)>,
+}
+
+/// A price in the merged ladder, with what each venue has there.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConsolidatedLevel {
+    pub price: Decimal,
+    /// Sum over `venues`.
+    pub quantity: Decimal,
+    /// Quantity per venue quoting this price, in the order the venues were added.
+    pub venues: Vec<(Exchange, Decimal)>,
+}
+
+/// How much of the merged ladder a venue accounts for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VenueDepth {
+    pub venue: Exchange,
+    pub bid_quantity: Decimal,
+    pub ask_quantity: Decimal,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConsolidatedSnapshot {
+    pub timestamp: String,
+    /// Best price across venues, with the quantity summed over every venue quoting it.
+    pub best_bid: Option<(Decimal, Decimal)>,
+    pub best_ask: Option<(Decimal, Decimal)>,
+    /// Venue with the most quantity at `best_bid`; see `ConsolidatedBook::best_bid_venue`.
+    pub best_bid_venue: Option<Exchange>,
+    pub best_ask_venue: Option<Exchange>,
+    /// Merged ladders, best price first.
+    pub bids: Vec<ConsolidatedLevel>,
+    pub asks: Vec<ConsolidatedLevel>,
+    /// Each venue's share of `bids` and `asks`, in the order the venues were added.
+    pub venue_depth: Vec<VenueDepth>,
+    /// Whether the best bid is at or above the best ask. Only possible across venues, and
+    /// a sign one of them is stale or an arbitrage is open.
+    pub crossed: bool,
+}
+
+/// Analytics sends a consolidated snapshot of `book`, `depth` levels a side, on `tx`
+/// every tick; see `AnalyticsConfig::consolidated`.
+#[derive(Debug, Clone)]
+pub struct ConsolidatedOutput {
+    pub book: ConsolidatedBook,
+    pub depth: usize,
+    pub tx: mpsc::UnboundedSender<ConsolidatedSnapshot>,
+}
+
+impl ConsolidatedBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `venue`'s book. A venue added twice is counted twice.
+    pub fn with_venue(mut self, venue: Exchange, book: ConcurrentOrderBook) -> Self {
+        self.venues.push((venue, book));
+        self
+    }
+
+    pub fn venues(&self) -> impl Iterator<Item = Exchange> + '_ {
+        self.venues.iter().map(|(venue, _)| *venue)
+    }
+
+    /// Venue quoting the highest bid. When several share it, the one with the most
+    /// quantity there, then the one added first.
+    pub async fn best_bid_venue(&self) -> Option<Exchange> {
+        let mut touches = Vec::with_capacity(self.venues.len());
+        for (venue, book) in &self.venues {
+            touches.push((*venue, book.best_bid().await));
+        }
+        touch_venue(touches, true)
+    }
+
+    /// Venue quoting the lowest ask, ties broken like `best_bid_venue`.
+    pub async fn best_ask_venue(&self) -> Option<Exchange> {
+        let mut touches = Vec::with_capacity(self.venues.len());
+        for (venue, book) in &self.venues {
+            touches.push((*venue, book.best_ask().await));
+        }
+        touch_venue(touches, false)
+    }
+
+    /// Merges the top `depth` levels a side of every venue.
+    pub async fn snapshot(&self, depth: usize) -> ConsolidatedSnapshot {
+        // A venue level outside its own top `depth` can't make the merged top `depth`
+        let mut bid_levels = Vec::with_capacity(self.venues.len());
+        let mut ask_levels = Vec::with_capacity(self.venues.len());
+        for (venue, book) in &self.venues {
+            bid_levels.push((*venue, book.top_bids(depth).await));
+            ask_levels.push((*venue, book.top_asks(depth).await));
+        }
+        let bids = merge(&bid_levels, depth, true);
+        let asks = merge(&ask_levels, depth, false);
+
+        let venue_depth = self
+            .venues()
+            .map(|venue| VenueDepth {
+                venue,
+                bid_quantity: venue_quantity(&bids, venue),
+                ask_quantity: venue_quantity(&asks, venue),
+            })
+            .collect();
+        let crossed = matches!((bids.first(), asks.first()), (Some(bid), Some(ask)) if bid.price >= ask.price);
+
+        ConsolidatedSnapshot {
+            timestamp: Utc::now().to_rfc3339(),
+            best_bid: bids.first().map(|level| (level.price, level.quantity)),
+            best_ask: asks.first().map(|level| (level.price, level.quantity)),
+            best_bid_venue: bids.first().and_then(largest_venue),
+            best_ask_venue: asks.first().and_then(largest_venue),
+            bids,
+            asks,
+            venue_depth,
+            crossed,
+        }
+    }
+}
+
+/// Sums `levels` by price into the best `depth` merged levels; bids best-first from
+/// the top, asks from the bottom.
+fn merge(levels: &[(Exchange, Vec<(Decimal, Decimal)>)], depth: usize, is_bid: bool) -> Vec<ConsolidatedLevel> {
+    let mut ladder: BTreeMap<Decimal, ConsolidatedLevel> = BTreeMap::new();
+    for (venue, venue_levels) in levels {
+        for &(price, quantity) in venue_levels {
+            let level = ladder.entry(price).or_insert_with(|| ConsolidatedLevel {
+                price,
+                quantity: Decimal::ZERO,
+                venues: Vec::new(),
+            });
+            level.quantity += quantity;
+            level.venues.push((*venue, quantity));
+        }
+    }
+    if is_bid {
+        ladder.into_values().rev().take(depth).collect()
+    } else {
+        ladder.into_values().take(depth).collect()
+    }
+}
+
+fn venue_quantity(ladder: &[ConsolidatedLevel], venue: Exchange) -> Decimal {
+    ladder
+        .iter()
+        .flat_map(|level| &level.venues)
+        .filter(|(v, _)| *v == venue)
+        .map(|(_, quantity)| *quantity)
+        .sum()
+}
+
+/// The venue with the most quantity at `level`, the first added on a tie.
+fn largest_venue(level: &ConsolidatedLevel) -> Option<Exchange> {
+    level
+        .venues
+        .iter()
+        .rev()
+        .max_by_key(|(_, quantity)| *quantity)
+        .map(|(venue, _)| *venue)
+}
+
+fn touch_venue(touches: Vec<(Exchange, Option<(Decimal, Decimal)>)>, is_bid: bool) -> Option<Exchange> {
+    let levels: Vec<_> = touches
+        .into_iter()
+        .filter_map(|(venue, touch)| touch.map(|level| (venue, vec![level])))
+        .collect();
+    merge(&levels, 1, is_bid).first().and_then(largest_venue)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    async fn book(bids: Vec<(Decimal, Decimal)>, asks: Vec<(Decimal, Decimal)>) -> ConcurrentOrderBook {
+        let book = ConcurrentOrderBook::new();
+        book.apply_snapshot(bids, asks).await;
+        book
+    }
+
+    fn level(price: Decimal, venues: Vec<(Exchange, Decimal)>) -> ConsolidatedLevel {
+        ConsolidatedLevel {
+            price,
+            quantity: venues.iter().map(|(_, quantity)| *quantity).sum(),
+            venues,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_two_venues_merge_into_one_ladder() {
+        use Exchange::{Binance, Coinbase};
+        let binance = book(
+            vec![(dec!(100.0), dec!(1)), (dec!(99.5), dec!(2)), (dec!(99.0), dec!(3))],
+            vec![(dec!(100.5), dec!(1)), (dec!(101.0), dec!(2))],
+        )
+        .await;
+        let coinbase = book(
+            vec![(dec!(100.0), dec!(4)), (dec!(99.8), dec!(1))],
+            vec![(dec!(100.4), dec!(0.5)), (dec!(100.5), dec!(2)), (dec!(102.0), dec!(1))],
+        )
+        .await;
+        let consolidated = ConsolidatedBook::new().with_venue(Binance, binance).with_venue(Coinbase, coinbase);
+
+        let snapshot = consolidated.snapshot(3).await;
+        assert_eq!(
+            snapshot.bids,
+            vec![
+                level(dec!(100.0), vec![(Binance, dec!(1)), (Coinbase, dec!(4))]),
+                level(dec!(99.8), vec![(Coinbase, dec!(1))]),
+                level(dec!(99.5), vec![(Binance, dec!(2))]),
+            ]
+        );
+        assert_eq!(
+            snapshot.asks,
+            vec![
+                level(dec!(100.4), vec![(Coinbase, dec!(0.5))]),
+                level(dec!(100.5), vec![(Binance, dec!(1)), (Coinbase, dec!(2))]),
+                level(dec!(101.0), vec![(Binance, dec!(2))]),
+            ]
+        );
+        assert_eq!(snapshot.best_bid, Some((dec!(100.0), dec!(5))));
+        assert_eq!(snapshot.best_ask, Some((dec!(100.4), dec!(0.5))));
+        // Both venues bid 100.0; Coinbase has more there
+        assert_eq!(snapshot.best_bid_venue, Some(Coinbase));
+        assert_eq!(consolidated.best_bid_venue().await, Some(Coinbase));
+        assert_eq!(snapshot.best_ask_venue, Some(Coinbase));
+        assert_eq!(consolidated.best_ask_venue().await, Some(Coinbase));
+        assert_eq!(
+            snapshot.venue_depth,
+            vec![
+                VenueDepth { venue: Binance, bid_quantity: dec!(3), ask_quantity: dec!(3) },
+                VenueDepth { venue: Coinbase, bid_quantity: dec!(5), ask_quantity: dec!(2.5) },
+            ]
+        );
+        assert!(!snapshot.crossed);
+    }
+
+    #[tokio::test]
+    async fn test_books_crossed_across_venues_are_reported() {
+        use Exchange::{Binance, Kraken};
+        // Each book is fine on its own, but Kraken bids above Binance's ask
+        let binance = book(vec![(dec!(99.0), dec!(1))], vec![(dec!(100.0), dec!(1))]).await;
+        let kraken = book(vec![(dec!(100.5), dec!(2))], vec![(dec!(101.0), dec!(1))]).await;
+        let consolidated = ConsolidatedBook::new().with_venue(Binance, binance).with_venue(Kraken, kraken);
+
+        let snapshot = consolidated.snapshot(5).await;
+        assert!(snapshot.crossed);
+        assert_eq!(snapshot.best_bid, Some((dec!(100.5), dec!(2))));
+        assert_eq!(snapshot.best_ask, Some((dec!(100.0), dec!(1))));
+        assert_eq!(snapshot.best_bid_venue, Some(Kraken));
+        assert_eq!(snapshot.best_ask_venue, Some(Binance));
+        assert_eq!(snapshot.bids.len(), 2);
+        assert_eq!(snapshot.asks.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_empty_venues_leave_the_touch_to_the_others() {
+        let consolidated = ConsolidatedBook::new()
+            .with_venue(Exchange::Okx, ConcurrentOrderBook::new())
+            .with_venue(Exchange::Bybit, book(vec![(dec!(50), dec!(1))], vec![]).await);
+
+        assert_eq!(consolidated.best_bid_venue().await, Some(Exchange::Bybit));
+        assert_eq!(consolidated.best_ask_venue().await, None);
+        let snapshot = consolidated.snapshot(5).await;
+        assert_eq!(snapshot.best_ask, None);
+        assert!(!snapshot.crossed);
+        assert_eq!(snapshot.venue_depth[0].bid_quantity, Decimal::ZERO);
+
+        let nothing = ConsolidatedBook::new().snapshot(5).await;
+        assert!(nothing.bids.is_empty() && nothing.venue_depth.is_empty());
+    }
+}
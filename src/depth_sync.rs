@@ -0,0 +1,496 @@
+use crate::clock::Clock;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+const MAX_BUFFERED_DIFFS: usize = 10_000;
+
+/// A diff-depth event reduced to its update id range and parsed levels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthDiff {
+    pub first_update_id: u64,
+    pub final_update_id: u64,
+    pub bids: Vec<(Decimal, Decimal)>,
+    pub asks: Vec<(Decimal, Decimal)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncState {
+    /// Diffs are buffered until a REST snapshot is applied.
+    AwaitingSnapshot,
+    /// Diffs are applied while their update ids stay contiguous.
+    Live { last_update_id: u64 },
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SyncOutcome {
+    /// Held back until the next snapshot arrives, or in the reorder buffer until the
+    /// diffs before it do.
+    Buffered,
+    /// Already covered by the snapshot.
+    Discarded,
+    /// Arrived after later diffs had already been applied past it, so it was dropped.
+    Late,
+    /// Diffs to apply to the book, in order.
+    Apply(Vec<DepthDiff>),
+    /// Continuity was broken; a fresh snapshot is required.
+    Gap { expected: u64, received: u64 },
+}
+
+/// Sequencing state machine for Binance's diff-depth stream.
+///
+/// Follows the documented procedure: buffer diffs, fetch a snapshot, drop
+/// diffs with `u <= lastUpdateId`, then apply from the first event where
+/// `U <= lastUpdateId + 1 <= u`, each later event continuing from the
+/// previous `u`.
+///
+/// With a reorder window, a live diff that skips ahead is held instead of breaking the
+/// sequence, and released in order once the diffs before it arrive. Only if they
+/// haven't within the window is it a gap.
+#[derive(Debug, Clone)]
+pub struct DepthSynchronizer {
+    state: SyncState,
+    buffer: VecDeque<DepthDiff>,
+    /// Update id of the last snapshot, telling diffs it covered from late ones.
+    snapshot_id: u64,
+    reorder: Option<ReorderBuffer>,
+}
+
+/// Live diffs that arrived ahead of their turn, by first update id.
+#[derive(Debug, Clone)]
+struct ReorderBuffer {
+    window: Duration,
+    clock: Arc<dyn Clock>,
+    /// Each diff with the time (ms since epoch) it arrived.
+    held: BTreeMap<u64, (u64, DepthDiff)>,
+}
+
+impl ReorderBuffer {
+    /// When the oldest held diff stops waiting, in ms since epoch.
+    fn deadline_ms(&self) -> Option<u64> {
+        let arrived = self.held.values().map(|(arrived, _)| *arrived).min()?;
+        Some(arrived + self.window.as_millis() as u64)
+    }
+}
+
+impl DepthSynchronizer {
+    pub fn new() -> Self {
+        Self {
+            state: SyncState::AwaitingSnapshot,
+            buffer: VecDeque::new(),
+            snapshot_id: 0,
+            reorder: None,
+        }
+    }
+
+    /// Holds out-of-order diffs for up to `window`, timed by `clock`, before calling the
+    /// missing ones a gap. Off by default: Binance delivers each stream in order.
+    pub fn with_reorder_window(mut self, window: Duration, clock: Arc<dyn Clock>) -> Self {
+        self.reorder = Some(ReorderBuffer {
+            window,
+            clock,
+            held: BTreeMap::new(),
+        });
+        self
+    }
+
+    pub fn state(&self) -> SyncState {
+        self.state
+    }
+
+    /// Feeds one diff event through the state machine.
+    pub fn on_diff(&mut self, diff: DepthDiff) -> SyncOutcome {
+        match self.state {
+            SyncState::AwaitingSnapshot => {
+                self.buffer_diff(diff);
+                SyncOutcome::Buffered
+            }
+            SyncState::Live { last_update_id } => {
+                if diff.final_update_id <= last_update_id {
+                    if diff.final_update_id > self.snapshot_id {
+                        return SyncOutcome::Late;
+                    }
+                    return SyncOutcome::Discarded;
+                }
+                if diff.first_update_id > last_update_id + 1 {
+                    if let Some(reorder) = &mut self.reorder {
+                        let arrived = reorder.clock.now_ms();
+                        reorder.held.insert(diff.first_update_id, (arrived, diff));
+                        return self.expire_held().unwrap_or(SyncOutcome::Buffered);
+                    }
+                    let received = diff.first_update_id;
+                    // Keep the event (and anything still buffered behind it) for the next snapshot
+                    self.state = SyncState::AwaitingSnapshot;
+                    self.buffer.push_front(diff);
+                    return SyncOutcome::Gap {
+                        expected: last_update_id + 1,
+                        received,
+                    };
+                }
+                self.state = SyncState::Live {
+                    last_update_id: diff.final_update_id,
+                };
+                let mut to_apply = vec![diff];
+                self.release_held(&mut to_apply);
+                SyncOutcome::Apply(to_apply)
+            }
+        }
+    }
+
+    /// How long until the oldest held diff has waited out the reorder window; `None`
+    /// with nothing held.
+    pub fn held_expires_in(&self) -> Option<Duration> {
+        let reorder = self.reorder.as_ref()?;
+        let deadline_ms = reorder.deadline_ms()?;
+        Some(Duration::from_millis(deadline_ms.saturating_sub(reorder.clock.now_ms())))
+    }
+
+    /// Gives up on the diffs missing in front of the held ones once the oldest has waited
+    /// out the reorder window: returns the gap, keeping the held diffs for the next
+    /// snapshot. `None` while they may still arrive.
+    pub fn expire_held(&mut self) -> Option<SyncOutcome> {
+        let last_update_id = match self.state {
+            SyncState::Live { last_update_id } => last_update_id,
+            SyncState::AwaitingSnapshot => return None,
+        };
+        let reorder = self.reorder.as_mut()?;
+        if reorder.clock.now_ms() < reorder.deadline_ms()? {
+            return None;
+        }
+        let held = std::mem::take(&mut reorder.held);
+        let received = *held.keys().next()?;
+        self.state = SyncState::AwaitingSnapshot;
+        for (_, diff) in held.into_values().rev() {
+            self.buffer.push_front(diff);
+        }
+        Some(SyncOutcome::Gap {
+            expected: last_update_id + 1,
+            received,
+        })
+    }
+
+    /// Moves held diffs that now continue the sequence onto `to_apply`, dropping the
+    /// ones it has already passed.
+    fn release_held(&mut self, to_apply: &mut Vec<DepthDiff>) {
+        let reorder = match &mut self.reorder {
+            Some(reorder) => reorder,
+            None => return,
+        };
+        while let (Some(entry), SyncState::Live { last_update_id }) = (reorder.held.first_entry(), self.state) {
+            if entry.get().1.final_update_id <= last_update_id {
+                entry.remove();
+            } else if *entry.key() <= last_update_id + 1 {
+                let (_, diff) = entry.remove();
+                self.state = SyncState::Live {
+                    last_update_id: diff.final_update_id,
+                };
+                to_apply.push(diff);
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Marks a snapshot as applied and releases any buffered diffs that follow it.
+    pub fn on_snapshot(&mut self, last_update_id: u64) -> SyncOutcome {
+        self.state = SyncState::Live { last_update_id };
+        self.snapshot_id = last_update_id;
+        if let Some(reorder) = &mut self.reorder {
+            // Sequence whatever arrived out of order before the snapshot too
+            self.buffer.extend(std::mem::take(&mut reorder.held).into_values().map(|(_, diff)| diff));
+            self.buffer.make_contiguous().sort_by_key(|diff| diff.first_update_id);
+        }
+
+        let mut to_apply = Vec::new();
+        while let Some(diff) = self.buffer.pop_front() {
+            match self.on_diff(diff) {
+                SyncOutcome::Apply(mut diffs) => to_apply.append(&mut diffs),
+                SyncOutcome::Gap { expected, received } => {
+                    return SyncOutcome::Gap { expected, received };
+                }
+                SyncOutcome::Discarded | SyncOutcome::Late | SyncOutcome::Buffered => {}
+            }
+        }
+
+        SyncOutcome::Apply(to_apply)
+    }
+
+    /// Drops all state, e.g. after a reconnect.
+    pub fn reset(&mut self) {
+        self.state = SyncState::AwaitingSnapshot;
+        self.buffer.clear();
+        if let Some(reorder) = &mut self.reorder {
+            reorder.held.clear();
+        }
+    }
+
+    fn buffer_diff(&mut self, diff: DepthDiff) {
+        if self.buffer.len() == MAX_BUFFERED_DIFFS {
+            self.buffer.pop_front();
+        }
+        self.buffer.push_back(diff);
+    }
+}
+
+impl Default for DepthSynchronizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::orderbook::OrderBook;
+    use rust_decimal_macros::dec;
+    use std::collections::HashMap;
+
+    fn diff(first: u64, last: u64) -> DepthDiff {
+        DepthDiff {
+            first_update_id: first,
+            final_update_id: last,
+            bids: vec![(dec!(100), Decimal::from(last))],
+            asks: vec![],
+        }
+    }
+
+    fn applied_ids(outcome: SyncOutcome) -> Vec<u64> {
+        match outcome {
+            SyncOutcome::Apply(diffs) => diffs.iter().map(|d| d.final_update_id).collect(),
+            other => panic!("Expected Apply, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_buffers_until_snapshot() {
+        let mut sync = DepthSynchronizer::new();
+        assert_eq!(sync.on_diff(diff(1, 5)), SyncOutcome::Buffered);
+        assert_eq!(sync.on_diff(diff(6, 8)), SyncOutcome::Buffered);
+        assert_eq!(sync.state(), SyncState::AwaitingSnapshot);
+    }
+
+    #[test]
+    fn test_snapshot_discards_stale_and_applies_from_straddling_event() {
+        let mut sync = DepthSynchronizer::new();
+        sync.on_diff(diff(1, 5));    // u <= lastUpdateId: discarded
+        sync.on_diff(diff(6, 10));   // straddles lastUpdateId + 1 = 8
+        sync.on_diff(diff(11, 12));  // contiguous
+
+        assert_eq!(applied_ids(sync.on_snapshot(7)), vec![10, 12]);
+        assert_eq!(sync.state(), SyncState::Live { last_update_id: 12 });
+    }
+
+    #[test]
+    fn test_live_discards_already_applied_events() {
+        let mut sync = DepthSynchronizer::new();
+        sync.on_snapshot(20);
+
+        assert_eq!(sync.on_diff(diff(15, 20)), SyncOutcome::Discarded);
+        assert_eq!(applied_ids(sync.on_diff(diff(18, 22))), vec![22]);
+        assert_eq!(applied_ids(sync.on_diff(diff(23, 23))), vec![23]);
+    }
+
+    #[test]
+    fn test_gap_in_live_stream_requires_resync() {
+        let mut sync = DepthSynchronizer::new();
+        sync.on_snapshot(10);
+        sync.on_diff(diff(11, 12));
+
+        assert_eq!(
+            sync.on_diff(diff(15, 16)),
+            SyncOutcome::Gap { expected: 13, received: 15 }
+        );
+        assert_eq!(sync.state(), SyncState::AwaitingSnapshot);
+
+        // The event that exposed the gap is kept for the next snapshot
+        assert_eq!(applied_ids(sync.on_snapshot(14)), vec![16]);
+    }
+
+    #[test]
+    fn test_snapshot_older_than_buffer_is_a_gap() {
+        let mut sync = DepthSynchronizer::new();
+        sync.on_diff(diff(50, 60));
+
+        assert_eq!(
+            sync.on_snapshot(40),
+            SyncOutcome::Gap { expected: 41, received: 50 }
+        );
+        assert_eq!(sync.state(), SyncState::AwaitingSnapshot);
+    }
+
+    fn reordering(window_ms: u64) -> (DepthSynchronizer, ManualClock) {
+        let clock = ManualClock::new(1_000_000);
+        let sync = DepthSynchronizer::new().with_reorder_window(Duration::from_millis(window_ms), Arc::new(clock.clone()));
+        (sync, clock)
+    }
+
+    #[test]
+    fn test_reorder_window_restores_a_shuffled_stream() {
+        use rand::rngs::StdRng;
+        use rand::seq::SliceRandom;
+        use rand::SeedableRng;
+
+        // Each event moves the top bid up and removes the level from two events before,
+        // so applying any two of them out of order leaves a different book
+        let events: Vec<DepthDiff> = (11..=60u64)
+            .map(|id| DepthDiff {
+                first_update_id: id,
+                final_update_id: id,
+                bids: vec![(Decimal::from(id), Decimal::from(id)), (Decimal::from(id - 2), dec!(0))],
+                asks: vec![(Decimal::from(1000 + id % 7), Decimal::from(id))],
+            })
+            .collect();
+        let snapshot = || (vec![(dec!(9), dec!(1)), (dec!(10), dec!(1))], vec![(dec!(1010), dec!(1))]);
+
+        let mut reference = OrderBook::new();
+        let (bids, asks) = snapshot();
+        reference.apply_snapshot(bids, asks);
+        for event in &events {
+            reference.apply_deltas(event.bids.clone(), event.asks.clone());
+        }
+
+        // Every event lands within a few places of its turn
+        let mut shuffled = events.clone();
+        let mut rng = StdRng::seed_from_u64(7);
+        for chunk in shuffled.chunks_mut(5) {
+            chunk.shuffle(&mut rng);
+        }
+        assert_ne!(shuffled, events);
+
+        let (mut sync, _clock) = reordering(50);
+        let mut book = OrderBook::new();
+        let (bids, asks) = snapshot();
+        book.apply_snapshot(bids, asks);
+        assert_eq!(sync.on_snapshot(10), SyncOutcome::Apply(vec![]));
+        for event in shuffled {
+            match sync.on_diff(event) {
+                SyncOutcome::Apply(diffs) => {
+                    for diff in diffs {
+                        book.apply_deltas(diff.bids, diff.asks);
+                    }
+                }
+                SyncOutcome::Buffered => {}
+                other => panic!("Expected the event to be applied or held, got {:?}", other),
+            }
+        }
+
+        assert_eq!(sync.state(), SyncState::Live { last_update_id: 60 });
+        assert_eq!(sync.held_expires_in(), None);
+        assert_eq!(book.top_bids(100), reference.top_bids(100));
+        assert_eq!(book.top_asks(100), reference.top_asks(100));
+    }
+
+    #[test]
+    fn test_held_diffs_become_a_gap_after_the_window() {
+        let (mut sync, clock) = reordering(50);
+        sync.on_snapshot(10);
+
+        assert_eq!(sync.on_diff(diff(12, 13)), SyncOutcome::Buffered);
+        clock.advance(Duration::from_millis(20));
+        assert_eq!(sync.on_diff(diff(14, 14)), SyncOutcome::Buffered);
+        // The window runs from the oldest held diff
+        assert_eq!(sync.held_expires_in(), Some(Duration::from_millis(30)));
+        assert_eq!(sync.expire_held(), None);
+
+        clock.advance(Duration::from_millis(30));
+        assert_eq!(sync.expire_held(), Some(SyncOutcome::Gap { expected: 11, received: 12 }));
+        assert_eq!(sync.state(), SyncState::AwaitingSnapshot);
+        assert_eq!(sync.held_expires_in(), None);
+
+        // The held diffs wait for the next snapshot like any other
+        assert_eq!(applied_ids(sync.on_snapshot(11)), vec![13, 14]);
+    }
+
+    #[test]
+    fn test_late_diffs_are_told_from_covered_ones() {
+        let (mut sync, _clock) = reordering(50);
+        sync.on_snapshot(10);
+
+        assert_eq!(sync.on_diff(diff(8, 10)), SyncOutcome::Discarded);
+        assert_eq!(applied_ids(sync.on_diff(diff(11, 11))), vec![11]);
+        assert_eq!(sync.on_diff(diff(13, 13)), SyncOutcome::Buffered);
+        assert_eq!(applied_ids(sync.on_diff(diff(12, 12))), vec![12, 13]);
+        assert_eq!(sync.on_diff(diff(12, 12)), SyncOutcome::Late);
+
+        // Without a window, skipping ahead is still a gap straight away
+        let mut strict = DepthSynchronizer::new();
+        strict.on_snapshot(10);
+        assert_eq!(strict.on_diff(diff(12, 12)), SyncOutcome::Gap { expected: 11, received: 12 });
+    }
+
+    #[test]
+    fn test_dropped_event_forces_single_resync() {
+        type Levels = (Vec<(Decimal, Decimal)>, Vec<(Decimal, Decimal)>);
+
+        let events: Vec<DepthDiff> = (11..=20u64)
+            .map(|id| DepthDiff {
+                first_update_id: id,
+                final_update_id: id,
+                // Each event adds a level and removes the one added two events earlier
+                bids: vec![
+                    (Decimal::from(id), Decimal::from(id)),
+                    (Decimal::from(id - 2), dec!(0)),
+                ],
+                asks: vec![(Decimal::from(1000 + id), dec!(1))],
+            })
+            .collect();
+
+        // Reference book built from the full stream, remembering each state so the
+        // "REST endpoint" can serve a snapshot at any update id
+        let mut reference = OrderBook::new();
+        reference.apply_snapshot(
+            vec![(dec!(9), dec!(1)), (dec!(10), dec!(1))],
+            vec![(dec!(1010), dec!(1))],
+        );
+        let mut states: HashMap<u64, Levels> = HashMap::new();
+        states.insert(10, (reference.top_bids(100), reference.top_asks(100)));
+        for event in &events {
+            reference.apply_deltas(event.bids.clone(), event.asks.clone());
+            states.insert(event.final_update_id, (reference.top_bids(100), reference.top_asks(100)));
+        }
+
+        let mut book = OrderBook::new();
+        let mut sync = DepthSynchronizer::new();
+        let mut resyncs = 0;
+        let mut resync_pending = false;
+
+        let apply = |book: &mut OrderBook, outcome: SyncOutcome| {
+            if let SyncOutcome::Apply(diffs) = outcome {
+                for diff in diffs {
+                    book.apply_deltas(diff.bids, diff.asks);
+                }
+            }
+        };
+
+        let (bids, asks) = states[&10].clone();
+        book.apply_snapshot(bids, asks);
+        let outcome = sync.on_snapshot(10);
+        apply(&mut book, outcome);
+
+        for event in events.iter().filter(|e| e.final_update_id != 14) {
+            match sync.on_diff(event.clone()) {
+                SyncOutcome::Gap { .. } => {
+                    resyncs += 1;
+                    book.clear();
+                    resync_pending = true;
+                }
+                outcome => apply(&mut book, outcome),
+            }
+
+            // The refetched snapshot (taken at update 15) lands once 16 is buffered
+            if resync_pending && event.final_update_id == 16 {
+                let (bids, asks) = states[&15].clone();
+                book.apply_snapshot(bids, asks);
+                let outcome = sync.on_snapshot(15);
+                apply(&mut book, outcome);
+                resync_pending = false;
+            }
+        }
+
+        assert_eq!(resyncs, 1);
+        assert_eq!(book.top_bids(100), reference.top_bids(100));
+        assert_eq!(book.top_asks(100), reference.top_asks(100));
+    }
+}
@@ -0,0 +1,706 @@
+use super::{parse_levels, AdapterError, BestQuotes, DepthEvent, ExchangeAdapter};
+use crate::depth_sync::DepthDiff;
+use crate::stream_config::{DepthSpeed, Environment, Exchange, StreamConfig, TradeStreamKind};
+use crate::tradeslog::Trade;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const BINANCE_REST_BASE: &str = "https://api.binance.com";
+const SNAPSHOT_DEPTH_LIMIT: u32 = 1000;
+/// Most trades one `/api/v3/trades` or `/api/v3/aggTrades` request returns.
+const RECENT_TRADES_LIMIT: usize = 1000;
+
+/// `(price, quantity)` levels of one side of a book.
+type Levels = Vec<(Decimal, Decimal)>;
+
+/// Event from the diff depth stream (`<symbol>@depth`, `<symbol>@depth@100ms`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DepthUpdateEvent {
+    #[serde(rename = "e")]
+    pub event_type: String,
+    #[serde(rename = "E")]
+    pub event_time: u64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "U")]
+    pub first_update_id: u64,
+    #[serde(rename = "u")]
+    pub final_update_id: u64,
+    #[serde(rename = "b")]
+    pub bids: Vec<(String, String)>,
+    #[serde(rename = "a")]
+    pub asks: Vec<(String, String)>,
+}
+
+/// Message from the partial depth stream (`<symbol>@depth<levels>`).
+#[derive(Debug, Deserialize)]
+pub struct PartialDepthSnapshot {
+    #[serde(rename = "lastUpdateId")]
+    pub last_update_id: u64,
+    pub bids: Vec<(String, String)>,
+    pub asks: Vec<(String, String)>,
+}
+
+/// Body of `GET /api/v3/depth`, used to bootstrap a diff-synced book.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BinanceRestSnapshot {
+    #[serde(rename = "lastUpdateId")]
+    pub last_update_id: u64,
+    pub bids: Vec<(String, String)>,
+    pub asks: Vec<(String, String)>,
+}
+
+impl BinanceRestSnapshot {
+    /// `(lastUpdateId, bids, asks)` with malformed levels dropped.
+    pub fn into_levels(self) -> (u64, Levels, Levels) {
+        (self.last_update_id, parse_levels(self.bids), parse_levels(self.asks))
+    }
+}
+
+/// Parses a depth REST response body.
+pub fn from_binance_rest_snapshot(body: &str) -> Result<(u64, Levels, Levels), AdapterError> {
+    let snapshot: BinanceRestSnapshot = serde_json::from_str(body)?;
+    Ok(snapshot.into_levels())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum DepthMessage {
+    Update(DepthUpdateEvent),
+    Partial(PartialDepthSnapshot),
+}
+
+/// Envelope Binance puts around every payload on a combined stream.
+#[derive(Debug, Deserialize)]
+pub struct CombinedStreamMessage {
+    pub stream: String,
+    pub data: DepthMessage,
+}
+
+/// Combined-stream envelope with `data` left unparsed until the stream name says what it is.
+#[derive(Debug, Deserialize)]
+pub struct CombinedStreamEnvelope {
+    pub stream: String,
+    pub data: serde_json::Value,
+}
+
+/// Payload of a combined stream, dispatched on the stream name.
+#[derive(Debug, Clone)]
+pub enum CombinedPayload {
+    Depth(DepthEvent),
+    Trade(Trade),
+    BookTicker(BestQuotes),
+}
+
+/// Event from the best bid/offer stream (`<symbol>@bookTicker`).
+#[derive(Debug, Deserialize)]
+pub struct BookTickerEvent {
+    #[serde(rename = "u")]
+    pub update_id: u64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "b")]
+    pub bid_price: String,
+    #[serde(rename = "B")]
+    pub bid_qty: String,
+    #[serde(rename = "a")]
+    pub ask_price: String,
+    #[serde(rename = "A")]
+    pub ask_qty: String,
+}
+
+impl TryFrom<BookTickerEvent> for BestQuotes {
+    type Error = AdapterError;
+
+    fn try_from(event: BookTickerEvent) -> Result<Self, Self::Error> {
+        let decimal = |value: &str| Decimal::from_str(value).map_err(|_| AdapterError::DecimalConversion);
+        Ok(Self {
+            symbol: Some(event.symbol.to_lowercase()),
+            bid: (decimal(&event.bid_price)?, decimal(&event.bid_qty)?),
+            ask: (decimal(&event.ask_price)?, decimal(&event.ask_qty)?),
+        })
+    }
+}
+
+/// Just the event time `E` of a stream payload, bare or inside a combined envelope.
+#[derive(Debug, Deserialize)]
+struct EventTime {
+    #[serde(rename = "E")]
+    event_time: Option<u64>,
+    data: Option<Box<EventTime>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BinanceTradeUpdate {
+    #[serde(rename = "p")]
+    pub price: String,
+    #[serde(rename = "q")]
+    pub quantity: String,
+    #[serde(rename = "T")]
+    pub timestamp: u64,
+    #[serde(rename = "m")]
+    pub is_buyer_maker: bool,
+    #[serde(rename = "s")]
+    pub symbol: Option<String>,
+    /// Id of a trade event. On a trade event `a` is the seller's order id, so an aggTrade's
+    /// own id only counts without one.
+    #[serde(rename = "t")]
+    pub trade_id: Option<u64>,
+    #[serde(rename = "a")]
+    pub agg_trade_id: Option<u64>,
+    /// First and last trade an aggTrade aggregates.
+    #[serde(rename = "f")]
+    pub first_trade_id: Option<u64>,
+    #[serde(rename = "l")]
+    pub last_trade_id: Option<u64>,
+}
+
+/// One trade of a `/api/v3/trades` response, which spells out the fields the stream abbreviates.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RecentTrade {
+    id: u64,
+    price: String,
+    qty: String,
+    time: u64,
+    is_buyer_maker: bool,
+}
+
+impl From<RecentTrade> for BinanceTradeUpdate {
+    fn from(trade: RecentTrade) -> Self {
+        Self {
+            price: trade.price,
+            quantity: trade.qty,
+            timestamp: trade.time,
+            is_buyer_maker: trade.is_buyer_maker,
+            symbol: None,
+            trade_id: Some(trade.id),
+            agg_trade_id: None,
+            first_trade_id: None,
+            last_trade_id: None,
+        }
+    }
+}
+
+impl TryFrom<BinanceTradeUpdate> for Trade {
+    type Error = AdapterError;
+
+    fn try_from(update: BinanceTradeUpdate) -> Result<Self, Self::Error> {
+        let trade = Trade::new(
+            Decimal::from_str(&update.price).map_err(|_| AdapterError::DecimalConversion)?,
+            Decimal::from_str(&update.quantity).map_err(|_| AdapterError::DecimalConversion)?,
+            update.timestamp,
+            update.is_buyer_maker,
+        )?;
+        let trade = Trade {
+            trade_id: update.trade_id.or(update.agg_trade_id),
+            aggregated_trade_ids: update.first_trade_id.zip(update.last_trade_id),
+            ..trade
+        };
+        Ok(match &update.symbol {
+            Some(symbol) => trade.with_symbol(symbol),
+            None => trade,
+        })
+    }
+}
+
+impl From<DepthUpdateEvent> for DepthDiff {
+    fn from(update: DepthUpdateEvent) -> Self {
+        DepthDiff {
+            first_update_id: update.first_update_id,
+            final_update_id: update.final_update_id,
+            bids: parse_levels(update.bids),
+            asks: parse_levels(update.asks),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BinanceAdapter {
+    ws_base: String,
+    rest_base: String,
+    depth_speed: DepthSpeed,
+    trade_stream: TradeStreamKind,
+}
+
+impl BinanceAdapter {
+    pub fn new() -> Self {
+        Self::from_config(&StreamConfig::default())
+    }
+
+    /// Takes stream flavours and host from the config; the symbol is supplied per call.
+    pub fn from_config(config: &StreamConfig) -> Self {
+        Self {
+            ws_base: config.base_url.clone(),
+            rest_base: config.rest_base_url(),
+            depth_speed: config.depth_speed,
+            trade_stream: config.trade_stream,
+        }
+    }
+
+    /// Overrides the REST host used to bootstrap and resync books.
+    pub fn with_rest_base(mut self, rest_base: String) -> Self {
+        self.rest_base = rest_base;
+        self
+    }
+
+    pub fn ws_base(&self) -> &str {
+        &self.ws_base
+    }
+
+    fn stream_config(&self, symbol: &str) -> StreamConfig {
+        StreamConfig {
+            exchange: Exchange::Binance,
+            symbol: symbol.to_string(),
+            depth_speed: self.depth_speed,
+            trade_stream: self.trade_stream,
+            base_url: self.ws_base.clone(),
+            environment: Environment::Prod,
+            fallback_base_urls: Vec::new(),
+            proxy: None,
+            tls: None,
+            headers: Default::default(),
+        }
+    }
+
+    /// Unwraps a combined-stream envelope and parses `data` by its stream suffix, so depth and
+    /// trade streams can share one socket: `<symbol>@depth...` as depth, `@trade`/`@aggTrade` as trades.
+    pub fn parse_combined(&self, text: &str) -> Result<CombinedPayload, AdapterError> {
+        let envelope: CombinedStreamEnvelope = serde_json::from_str(text)?;
+        let (symbol, kind) = envelope.stream.split_once('@').unwrap_or((envelope.stream.as_str(), ""));
+        let symbol = symbol.to_lowercase();
+
+        if kind.starts_with("depth") {
+            let message: DepthMessage = serde_json::from_value(envelope.data)?;
+            Ok(CombinedPayload::Depth(Self::depth_event(message, Some(symbol))))
+        } else if kind == "trade" || kind == "aggTrade" {
+            let update: BinanceTradeUpdate = serde_json::from_value(envelope.data)?;
+            Ok(CombinedPayload::Trade(Trade::try_from(update)?.with_symbol(&symbol)))
+        } else if kind == "bookTicker" {
+            let event: BookTickerEvent = serde_json::from_value(envelope.data)?;
+            Ok(CombinedPayload::BookTicker(BestQuotes::try_from(event)?))
+        } else {
+            Err(AdapterError::Unsupported(format!("stream {}", envelope.stream)))
+        }
+    }
+
+    fn is_combined(text: &str) -> bool {
+        text.starts_with(r#"{"stream""#)
+    }
+
+    fn depth_event(message: DepthMessage, stream_symbol: Option<String>) -> DepthEvent {
+        match message {
+            DepthMessage::Update(update) => DepthEvent::Diff {
+                symbol: stream_symbol.or_else(|| Some(update.symbol.to_lowercase())),
+                diff: update.into(),
+                checksum: None,
+            },
+            DepthMessage::Partial(snapshot) => DepthEvent::Snapshot {
+                symbol: stream_symbol,
+                last_update_id: snapshot.last_update_id,
+                bids: parse_levels(snapshot.bids),
+                asks: parse_levels(snapshot.asks),
+                checksum: None,
+            },
+        }
+    }
+}
+
+impl Default for BinanceAdapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExchangeAdapter for BinanceAdapter {
+    fn name(&self) -> &'static str {
+        "binance"
+    }
+
+    fn depth_url(&self, symbol: &str) -> String {
+        self.stream_config(symbol).build_depth_url()
+    }
+
+    fn trade_url(&self, symbol: &str) -> String {
+        self.stream_config(symbol).build_trade_url()
+    }
+
+    /// Only diff streams are synced against REST; partial streams carry whole books.
+    fn snapshot_url(&self, symbol: &str) -> Option<String> {
+        if !self.depth_speed.is_diff() {
+            return None;
+        }
+        Some(format!(
+            "{}/api/v3/depth?symbol={}&limit={}",
+            self.rest_base,
+            symbol.to_uppercase(),
+            SNAPSHOT_DEPTH_LIMIT
+        ))
+    }
+
+    /// Accepts bare payloads and combined-stream envelopes.
+    fn parse_depth(&self, text: &str) -> Result<DepthEvent, AdapterError> {
+        if Self::is_combined(text) {
+            return match self.parse_combined(text)? {
+                CombinedPayload::Depth(event) => Ok(event),
+                _ => Err(AdapterError::Unsupported("non-depth payload on a depth feed".to_string())),
+            };
+        }
+        let message: DepthMessage = serde_json::from_str(text)?;
+        Ok(Self::depth_event(message, None))
+    }
+
+    /// Accepts bare payloads and combined-stream envelopes.
+    fn parse_trades(&self, text: &str) -> Result<Vec<Trade>, AdapterError> {
+        if Self::is_combined(text) {
+            return match self.parse_combined(text)? {
+                CombinedPayload::Trade(trade) => Ok(vec![trade]),
+                _ => Err(AdapterError::Unsupported("non-trade payload on a trade feed".to_string())),
+            };
+        }
+        let update: BinanceTradeUpdate = serde_json::from_str(text)?;
+        Ok(vec![Trade::try_from(update)?])
+    }
+
+    /// `E` of diff depth, trade and aggTrade events; partial depth payloads carry none.
+    fn event_time_ms(&self, text: &str) -> Option<u64> {
+        let stamped: EventTime = serde_json::from_str(text).ok()?;
+        stamped.event_time.or_else(|| stamped.data?.event_time)
+    }
+
+    /// Binance drops every connection after 24 hours.
+    fn max_connection_age(&self) -> Option<Duration> {
+        Some(Duration::from_secs(24 * 60 * 60))
+    }
+
+    /// Partial depth streams (`@depth5`/`10`/`20`) push the top levels in full every time.
+    fn streams_whole_books(&self) -> bool {
+        !self.depth_speed.is_diff()
+    }
+
+    fn book_ticker_url(&self, symbol: &str) -> Option<String> {
+        Some(self.stream_config(symbol).build_book_ticker_url())
+    }
+
+    /// Accepts bare payloads and combined-stream envelopes.
+    fn parse_book_ticker(&self, text: &str) -> Result<BestQuotes, AdapterError> {
+        if Self::is_combined(text) {
+            return match self.parse_combined(text)? {
+                CombinedPayload::BookTicker(quotes) => Ok(quotes),
+                _ => Err(AdapterError::Unsupported("non-ticker payload on a book ticker feed".to_string())),
+            };
+        }
+        let event: BookTickerEvent = serde_json::from_str(text)?;
+        BestQuotes::try_from(event)
+    }
+
+    fn parse_snapshot(&self, body: &str) -> Result<DepthEvent, AdapterError> {
+        let (last_update_id, bids, asks) = from_binance_rest_snapshot(body)?;
+        Ok(DepthEvent::Snapshot {
+            symbol: None,
+            last_update_id,
+            bids,
+            asks,
+            checksum: None,
+        })
+    }
+
+    /// `/api/v3/trades` for `@trade` feeds and `/api/v3/aggTrades` for `@aggTrade` ones, so
+    /// the ids match the stream's `t` or `a`. Binance caps `limit` at 1000.
+    fn recent_trades_url(&self, symbol: &str, limit: usize) -> Option<String> {
+        let endpoint = match self.trade_stream {
+            TradeStreamKind::Trade => "trades",
+            TradeStreamKind::AggTrade => "aggTrades",
+        };
+        Some(format!(
+            "{}/api/v3/{}?symbol={}&limit={}",
+            self.rest_base,
+            endpoint,
+            symbol.to_uppercase(),
+            limit.min(RECENT_TRADES_LIMIT)
+        ))
+    }
+
+    /// `/api/v3/aggTrades` spells its trades in the aggTrade stream's own fields.
+    fn parse_recent_trades(&self, body: &str) -> Result<Vec<Trade>, AdapterError> {
+        let updates: Vec<BinanceTradeUpdate> = match self.trade_stream {
+            TradeStreamKind::Trade => serde_json::from_str::<Vec<RecentTrade>>(body)?
+                .into_iter()
+                .map(BinanceTradeUpdate::from)
+                .collect(),
+            TradeStreamKind::AggTrade => serde_json::from_str(body)?,
+        };
+        updates.into_iter().map(Trade::try_from).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream_config::BINANCE_WS_BASE;
+
+    use crate::tradeslog::TradeError;
+    use rust_decimal_macros::dec;
+
+    const DIFF_SAMPLE: &str = r#"{"e":"depthUpdate","E":1712745600123,"s":"BTCUSDT","U":46018271123,"u":46018271130,"b":[["69420.01000000","0.51200000"],["69419.50000000","0.00000000"]],"a":[["69420.02000000","1.20300000"]]}"#;
+
+    const PARTIAL_SAMPLE: &str = r#"{"lastUpdateId":46018271130,"bids":[["69420.01000000","0.51200000"],["69419.99000000","0.03000000"]],"asks":[["69420.02000000","1.20300000"],["69420.10000000","0.00800000"]]}"#;
+
+    const TRADE_SAMPLE: &str = r#"{"e":"trade","E":1712745600123,"s":"BTCUSDT","t":3550000001,"p":"69420.01000000","q":"0.01500000","b":0,"a":0,"T":1712745600120,"m":true,"M":true}"#;
+
+    #[test]
+    fn test_parse_diff_depth_event() {
+        match serde_json::from_str::<DepthMessage>(DIFF_SAMPLE).unwrap() {
+            DepthMessage::Update(update) => {
+                assert_eq!(update.event_type, "depthUpdate");
+                assert_eq!(update.symbol, "BTCUSDT");
+                assert_eq!(update.first_update_id, 46018271123);
+                assert_eq!(update.final_update_id, 46018271130);
+            }
+            other => panic!("Expected diff update, got {:?}", other),
+        }
+
+        match BinanceAdapter::new().parse_depth(DIFF_SAMPLE).unwrap() {
+            DepthEvent::Diff { symbol, diff, .. } => {
+                assert_eq!(symbol.as_deref(), Some("btcusdt"));
+                assert_eq!(diff.first_update_id, 46018271123);
+                assert_eq!(diff.bids, vec![
+                    (dec!(69420.01000000), dec!(0.51200000)),
+                    (dec!(69419.50000000), dec!(0)),
+                ]);
+                assert_eq!(diff.asks, vec![(dec!(69420.02000000), dec!(1.20300000))]);
+            }
+            other => panic!("Expected diff, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_partial_depth_snapshot() {
+        match BinanceAdapter::new().parse_depth(PARTIAL_SAMPLE).unwrap() {
+            DepthEvent::Snapshot { symbol, last_update_id, bids, asks, .. } => {
+                assert_eq!(symbol, None);
+                assert_eq!(last_update_id, 46018271130);
+                assert_eq!(bids.len(), 2);
+                assert_eq!(asks.len(), 2);
+            }
+            other => panic!("Expected snapshot, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_combined_envelope_takes_symbol_from_stream() {
+        let text = format!(r#"{{"stream":"ethusdt@depth20@100ms","data":{}}}"#, PARTIAL_SAMPLE);
+        match BinanceAdapter::new().parse_depth(&text).unwrap() {
+            DepthEvent::Snapshot { symbol, .. } => assert_eq!(symbol.as_deref(), Some("ethusdt")),
+            other => panic!("Expected snapshot, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_combined_stream_dispatches_by_suffix() {
+        let adapter = BinanceAdapter::new();
+
+        let depth = format!(r#"{{"stream":"btcusdt@depth@100ms","data":{}}}"#, DIFF_SAMPLE);
+        match adapter.parse_combined(&depth).unwrap() {
+            CombinedPayload::Depth(DepthEvent::Diff { symbol, diff, .. }) => {
+                assert_eq!(symbol.as_deref(), Some("btcusdt"));
+                assert_eq!(diff.final_update_id, 46018271130);
+            }
+            other => panic!("Expected depth diff, got {:?}", other),
+        }
+
+        let trade = format!(r#"{{"stream":"btcusdt@trade","data":{}}}"#, TRADE_SAMPLE);
+        match adapter.parse_combined(&trade).unwrap() {
+            CombinedPayload::Trade(trade) => {
+                assert_eq!(trade.price, dec!(69420.01));
+                assert_eq!(trade.symbol.as_deref(), Some("btcusdt"));
+            }
+            other => panic!("Expected trade, got {:?}", other),
+        }
+
+        // Both feeds read the same socket and keep only their own payloads
+        assert_eq!(adapter.parse_trades(&trade).unwrap().len(), 1);
+        assert!(matches!(adapter.parse_trades(&depth), Err(AdapterError::Unsupported(_))));
+        assert!(matches!(adapter.parse_depth(&trade), Err(AdapterError::Unsupported(_))));
+
+        let kline = r#"{"stream":"btcusdt@kline_1m","data":{"e":"kline","s":"BTCUSDT"}}"#;
+        assert!(matches!(adapter.parse_combined(kline), Err(AdapterError::Unsupported(_))));
+    }
+
+    #[test]
+    fn test_parse_book_ticker() {
+        let adapter = BinanceAdapter::new();
+        let bare = r#"{"u":400900217,"s":"BTCUSDT","b":"69420.01000000","B":"0.51200000","a":"69420.02000000","A":"1.20300000"}"#;
+        let expected = BestQuotes {
+            symbol: Some("btcusdt".to_string()),
+            bid: (dec!(69420.01), dec!(0.512)),
+            ask: (dec!(69420.02), dec!(1.203)),
+        };
+        assert_eq!(adapter.parse_book_ticker(bare).unwrap(), expected);
+
+        let combined = format!(r#"{{"stream":"btcusdt@bookTicker","data":{}}}"#, bare);
+        assert_eq!(adapter.parse_book_ticker(&combined).unwrap(), expected);
+        assert!(matches!(adapter.parse_depth(&combined), Err(AdapterError::Unsupported(_))));
+        assert!(adapter.parse_book_ticker(DIFF_SAMPLE).is_err());
+
+        assert_eq!(
+            adapter.book_ticker_url("BTCUSDT").as_deref(),
+            Some("wss://stream.binance.com:9443/ws/btcusdt@bookTicker")
+        );
+    }
+
+    #[test]
+    fn test_event_time() {
+        let adapter = BinanceAdapter::new();
+        assert_eq!(adapter.event_time_ms(DIFF_SAMPLE), Some(1712745600123));
+        assert_eq!(adapter.event_time_ms(TRADE_SAMPLE), Some(1712745600123));
+        let combined = format!(r#"{{"stream":"btcusdt@trade","data":{}}}"#, TRADE_SAMPLE);
+        assert_eq!(adapter.event_time_ms(&combined), Some(1712745600123));
+        assert_eq!(adapter.event_time_ms(PARTIAL_SAMPLE), None);
+        assert_eq!(adapter.event_time_ms("not json"), None);
+    }
+
+    #[test]
+    fn test_trade_ids() {
+        let adapter = BinanceAdapter::new();
+        let ids = |text: &str| {
+            let trade = adapter.parse_trades(text).unwrap().remove(0);
+            (trade.symbol, trade.trade_id, trade.aggregated_trade_ids)
+        };
+        // `a` is the seller's order id here, not an aggregate trade id
+        assert_eq!(ids(TRADE_SAMPLE), (Some("btcusdt".to_string()), Some(3550000001), None));
+        let combined = format!(r#"{{"stream":"btcusdt@trade","data":{}}}"#, TRADE_SAMPLE);
+        assert_eq!(ids(&combined), (Some("btcusdt".to_string()), Some(3550000001), None));
+        let agg = r#"{"e":"aggTrade","E":1712745600123,"s":"BTCUSDT","a":26129,"p":"69420.01","q":"0.015","f":100,"l":105,"T":1712745600120,"m":true}"#;
+        assert_eq!(ids(agg), (Some("btcusdt".to_string()), Some(26129), Some((100, 105))));
+    }
+
+    #[test]
+    fn test_parse_rest_snapshot_fixture() {
+        let body = include_str!("../../tests/fixtures/binance_depth_snapshot.json");
+        let (last_update_id, bids, asks) = from_binance_rest_snapshot(body).unwrap();
+        assert_eq!(last_update_id, 46018271130);
+        assert_eq!(bids.len(), 20);
+        assert_eq!(asks.len(), 20);
+        assert_eq!(bids[0], (dec!(69420.01), dec!(0.0125)));
+        assert_eq!(asks[0], (dec!(69420.02), dec!(0.031)));
+
+        match BinanceAdapter::new().parse_snapshot(body).unwrap() {
+            DepthEvent::Snapshot { last_update_id, bids, .. } => {
+                assert_eq!(last_update_id, 46018271130);
+                assert_eq!(bids.len(), 20);
+            }
+            other => panic!("Expected snapshot, got {:?}", other),
+        }
+        assert!(from_binance_rest_snapshot(r#"{"code":-1121,"msg":"Invalid symbol."}"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_rejects_unknown_payload() {
+        assert!(BinanceAdapter::new().parse_depth(r#"{"result":null,"id":1}"#).is_err());
+        assert!(BinanceAdapter::new().parse_trades(DIFF_SAMPLE).is_err());
+    }
+
+    #[test]
+    fn test_parse_trade() {
+        let trades = BinanceAdapter::new().parse_trades(TRADE_SAMPLE).unwrap();
+        assert_eq!(trades.len(), 1);
+        let trade = &trades[0];
+        assert_eq!(trade.price, dec!(69420.01));
+        assert_eq!(trade.quantity, dec!(0.015));
+        assert_eq!(trade.timestamp, 1712745600120);
+        assert!(trade.is_buyer_maker);
+        assert_eq!(trade.symbol.as_deref(), Some("btcusdt"));
+    }
+
+    #[test]
+    fn test_parse_trade_rejects_negative_quantity() {
+        let negative = TRADE_SAMPLE.replace(r#""q":"0.01500000""#, r#""q":"-0.01500000""#);
+        match BinanceAdapter::new().parse_trades(&negative) {
+            Err(AdapterError::InvalidTrade(TradeError::NegativeQuantity(quantity))) => {
+                assert_eq!(quantity, dec!(-0.015))
+            }
+            other => panic!("Expected a negative quantity error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_malformed_levels_are_counted_and_the_rest_kept() {
+        use crate::orderbook::OrderBook;
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+
+        let _ = DebuggingRecorder::per_thread().install();
+        let text = r#"{"e":"depthUpdate","E":1,"s":"BTCUSDT","U":1,"u":2,"b":[["69420.01","0.5"],["69419.x","1.0"]],"a":[["69420.02","1.2"]]}"#;
+        let diff = match BinanceAdapter::new().parse_depth(text).unwrap() {
+            DepthEvent::Diff { diff, .. } => diff,
+            other => panic!("Expected a diff, got {:?}", other),
+        };
+
+        let mut book = OrderBook::new();
+        book.apply_deltas(diff.bids, diff.asks);
+        assert_eq!(book.top_bids(10), vec![(dec!(69420.01), dec!(0.5))]);
+        assert_eq!(book.top_asks(10), vec![(dec!(69420.02), dec!(1.2))]);
+
+        let dropped = Snapshotter::current_thread_snapshot()
+            .unwrap()
+            .into_vec()
+            .into_iter()
+            .find(|(key, _, _, _)| key.key().name() == "depth_levels_dropped")
+            .map(|(_, _, _, value)| value);
+        assert_eq!(dropped, Some(DebugValue::Counter(1)));
+    }
+
+    #[test]
+    fn test_parse_recent_trades() {
+        let body = r#"[{"id":3550000001,"price":"69420.01000000","qty":"0.01500000","quoteQty":"1041.30015000","time":1712745600120,"isBuyerMaker":true,"isBestMatch":true},{"id":3550000002,"price":"69420.02000000","qty":"0.20000000","quoteQty":"13884.00400000","time":1712745600125,"isBuyerMaker":false,"isBestMatch":true}]"#;
+        let trades = BinanceAdapter::new().parse_recent_trades(body).unwrap();
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].trade_id, Some(3550000001));
+        assert_eq!(trades[0].price, dec!(69420.01));
+        assert!(trades[0].is_buyer_maker);
+        assert_eq!(trades[1].trade_id, Some(3550000002));
+        assert_eq!(trades[1].quantity, dec!(0.2));
+        assert_eq!(trades[1].timestamp, 1712745600125);
+
+        let config = StreamConfig { trade_stream: TradeStreamKind::AggTrade, ..StreamConfig::default() };
+        let adapter = BinanceAdapter::from_config(&config);
+        let body = r#"[{"a":26129,"p":"0.01633102","q":"4.70443515","f":27781,"l":27781,"T":1498793709153,"m":true,"M":true}]"#;
+        let trades = adapter.parse_recent_trades(body).unwrap();
+        assert_eq!((trades[0].trade_id, trades[0].aggregated_trade_ids), (Some(26129), Some((27781, 27781))));
+        assert_eq!(trades[0].price, dec!(0.01633102));
+        assert!(adapter.parse_recent_trades(body.replace("0.01633102", "oops").as_str()).is_err());
+    }
+
+    #[test]
+    fn test_urls() {
+        let adapter = BinanceAdapter::new().with_rest_base("http://127.0.0.1:8080".to_string());
+        assert_eq!(adapter.depth_url("btcusdt"), "wss://stream.binance.com:9443/ws/btcusdt@depth@100ms");
+        assert_eq!(adapter.trade_url("ETHUSDT"), "wss://stream.binance.com:9443/ws/ethusdt@trade");
+        assert_eq!(
+            adapter.snapshot_url("btcusdt").as_deref(),
+            Some("http://127.0.0.1:8080/api/v3/depth?symbol=BTCUSDT&limit=1000")
+        );
+        assert_eq!(
+            adapter.recent_trades_url("btcusdt", 5000).as_deref(),
+            Some("http://127.0.0.1:8080/api/v3/trades?symbol=BTCUSDT&limit=1000")
+        );
+        assert_eq!(adapter.ws_base(), BINANCE_WS_BASE);
+
+        let mut config = StreamConfig { depth_speed: DepthSpeed::Partial20, ..StreamConfig::default() };
+        assert_eq!(BinanceAdapter::from_config(&config).snapshot_url("btcusdt"), None);
+        config.trade_stream = TradeStreamKind::AggTrade;
+        assert_eq!(
+            BinanceAdapter::from_config(&config).recent_trades_url("btcusdt", 100).as_deref(),
+            Some("https://api.binance.com/api/v3/aggTrades?symbol=BTCUSDT&limit=100")
+        );
+
+        let testnet = BinanceAdapter::from_config(&StreamConfig::new("btcusdt").with_environment(Environment::Testnet));
+        assert_eq!(testnet.depth_url("btcusdt"), "wss://stream.testnet.binance.vision/ws/btcusdt@depth@100ms");
+        assert_eq!(
+            testnet.snapshot_url("btcusdt").as_deref(),
+            Some("https://testnet.binance.vision/api/v3/depth?symbol=BTCUSDT&limit=1000")
+        );
+    }
+}
@@ -0,0 +1,314 @@
+use super::{parse_levels, AdapterError, DepthEvent, ExchangeAdapter};
+use crate::depth_sync::DepthDiff;
+use crate::stream_config::{StreamConfig, BITSTAMP_WS_BASE};
+use crate::tradeslog::Trade;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::json;
+use std::str::FromStr;
+
+pub const BITSTAMP_REST_BASE: &str = "https://www.bitstamp.net";
+const DEPTH_CHANNEL: &str = "diff_order_book_";
+const TRADE_CHANNEL: &str = "live_trades_";
+
+/// Book data of a `diff_order_book` push or a REST order book.
+#[derive(Debug, Deserialize)]
+pub struct BitstampBook {
+    pub microtimestamp: String,
+    pub bids: Vec<(String, String)>,
+    pub asks: Vec<(String, String)>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BitstampTrade {
+    pub id: u64,
+    pub amount_str: String,
+    pub price_str: String,
+    /// Side of the taker: `0` buy, `1` sell.
+    #[serde(rename = "type")]
+    pub side: u8,
+    pub microtimestamp: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BitstampErrorData {
+    pub code: Option<i64>,
+    pub message: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "event")]
+pub enum BitstampMessage {
+    #[serde(rename = "data")]
+    Book { channel: String, data: BitstampBook },
+    #[serde(rename = "trade")]
+    Trade { channel: String, data: BitstampTrade },
+    #[serde(rename = "bts:error")]
+    Error { data: BitstampErrorData },
+    /// Subscription confirmations, reconnect requests and anything else we do not consume.
+    #[serde(other)]
+    Other,
+}
+
+/// Parses Bitstamp's microsecond timestamps into epoch ms.
+fn timestamp_ms(microtimestamp: &str) -> Result<u64, AdapterError> {
+    microtimestamp
+        .parse::<u64>()
+        .map(|micros| micros / 1000)
+        .map_err(|_| AdapterError::InvalidTimestamp(microtimestamp.to_string()))
+}
+
+impl TryFrom<BitstampTrade> for Trade {
+    type Error = AdapterError;
+
+    fn try_from(update: BitstampTrade) -> Result<Self, Self::Error> {
+        let is_buyer_maker = match update.side {
+            0 => false,
+            1 => true,
+            other => return Err(AdapterError::Unsupported(format!("trade type {}", other))),
+        };
+
+        Ok(Trade::new(
+            Decimal::from_str(&update.price_str).map_err(|_| AdapterError::DecimalConversion)?,
+            Decimal::from_str(&update.amount_str).map_err(|_| AdapterError::DecimalConversion)?,
+            timestamp_ms(&update.microtimestamp)?,
+            is_buyer_maker,
+        )?)
+    }
+}
+
+/// Bitstamp v2 public feed: `diff_order_book` for depth, `live_trades` for the tape.
+///
+/// Diffs are applied over a REST order book like Binance's. They carry no sequence ids,
+/// only a microsecond timestamp, so that stands in for the update id: diffs stamped at or
+/// before the snapshot's are dropped, and the rest apply in the order the socket delivers
+/// them. Gaps can't be detected; a dropped connection resyncs from a new snapshot.
+#[derive(Debug, Clone)]
+pub struct BitstampAdapter {
+    ws_base: String,
+    rest_base: String,
+}
+
+impl BitstampAdapter {
+    pub fn new() -> Self {
+        Self {
+            ws_base: BITSTAMP_WS_BASE.to_string(),
+            rest_base: BITSTAMP_REST_BASE.to_string(),
+        }
+    }
+
+    pub fn from_config(config: &StreamConfig) -> Self {
+        Self {
+            ws_base: config.base_url.clone(),
+            rest_base: BITSTAMP_REST_BASE.to_string(),
+        }
+    }
+
+    /// Overrides the REST host used to bootstrap and resync books.
+    pub fn with_rest_base(mut self, rest_base: String) -> Self {
+        self.rest_base = rest_base;
+        self
+    }
+
+    /// Bitstamp takes one channel per request.
+    fn subscribe_frame(channel: &str, symbol: &str) -> String {
+        json!({
+            "event": "bts:subscribe",
+            "data": {"channel": format!("{}{}", channel, symbol.to_lowercase())},
+        })
+        .to_string()
+    }
+
+    /// Pair a channel is for, e.g. `btcusd` for `diff_order_book_btcusd`.
+    fn symbol(channel: &str, prefix: &str) -> Option<String> {
+        channel.strip_prefix(prefix).map(str::to_string)
+    }
+
+    fn unexpected(message: BitstampMessage) -> AdapterError {
+        match message {
+            BitstampMessage::Error { data } => {
+                AdapterError::Unsupported(format!("error {}: {}", data.code.unwrap_or_default(), data.message))
+            }
+            other => AdapterError::Unsupported(format!("{:?}", other)),
+        }
+    }
+}
+
+impl Default for BitstampAdapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExchangeAdapter for BitstampAdapter {
+    fn name(&self) -> &'static str {
+        "bitstamp"
+    }
+
+    fn depth_url(&self, _symbol: &str) -> String {
+        self.ws_base.clone()
+    }
+
+    fn trade_url(&self, _symbol: &str) -> String {
+        self.ws_base.clone()
+    }
+
+    fn snapshot_url(&self, symbol: &str) -> Option<String> {
+        Some(format!("{}/api/v2/order_book/{}/", self.rest_base, symbol.to_lowercase()))
+    }
+
+    /// Only the first symbol is subscribed; the managers give adapters one each.
+    fn depth_subscribe_frame(&self, symbols: &[String]) -> Option<String> {
+        symbols.first().map(|symbol| Self::subscribe_frame(DEPTH_CHANNEL, symbol))
+    }
+
+    fn trade_subscribe_frame(&self, symbol: &str) -> Option<String> {
+        Some(Self::subscribe_frame(TRADE_CHANNEL, symbol))
+    }
+
+    fn parse_depth(&self, text: &str) -> Result<DepthEvent, AdapterError> {
+        match serde_json::from_str(text)? {
+            BitstampMessage::Book { channel, data } => {
+                let update_id =
+                    data.microtimestamp.parse().map_err(|_| AdapterError::InvalidTimestamp(data.microtimestamp))?;
+                Ok(DepthEvent::Diff {
+                    symbol: Self::symbol(&channel, DEPTH_CHANNEL),
+                    // Nothing to chain on, so every diff continues from whatever came before
+                    diff: DepthDiff {
+                        first_update_id: 0,
+                        final_update_id: update_id,
+                        bids: parse_levels(data.bids),
+                        asks: parse_levels(data.asks),
+                    },
+                    checksum: None,
+                })
+            }
+            other => Err(Self::unexpected(other)),
+        }
+    }
+
+    fn parse_trades(&self, text: &str) -> Result<Vec<Trade>, AdapterError> {
+        match serde_json::from_str(text)? {
+            BitstampMessage::Trade { data, .. } => Ok(vec![Trade::try_from(data)?]),
+            other => Err(Self::unexpected(other)),
+        }
+    }
+
+    /// `microtimestamp` of book and trade pushes.
+    fn event_time_ms(&self, text: &str) -> Option<u64> {
+        match serde_json::from_str(text).ok()? {
+            BitstampMessage::Book { data, .. } => timestamp_ms(&data.microtimestamp).ok(),
+            BitstampMessage::Trade { data, .. } => timestamp_ms(&data.microtimestamp).ok(),
+            _ => None,
+        }
+    }
+
+    fn parse_snapshot(&self, body: &str) -> Result<DepthEvent, AdapterError> {
+        let book: BitstampBook = serde_json::from_str(body)?;
+        let last_update_id =
+            book.microtimestamp.parse().map_err(|_| AdapterError::InvalidTimestamp(book.microtimestamp))?;
+        Ok(DepthEvent::Snapshot {
+            symbol: None,
+            last_update_id,
+            bids: parse_levels(book.bids),
+            asks: parse_levels(book.asks),
+            checksum: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::depth_sync::{DepthSynchronizer, SyncOutcome};
+    use rust_decimal_macros::dec;
+
+    const DIFF_SAMPLE: &str = r#"{"data":{"timestamp":"1712745600","microtimestamp":"1712745600123456","bids":[["69420.00","0.51200000"],["69419.00","0.00000000"]],"asks":[["69421.00","1.20300000"]]},"channel":"diff_order_book_btcusd","event":"data"}"#;
+
+    const SNAPSHOT_SAMPLE: &str = r#"{"timestamp":"1712745600","microtimestamp":"1712745600100000","bids":[["69420.00","0.40000000"],["69419.00","2.00000000"]],"asks":[["69421.00","1.00000000"],["69422.00","0.75000000"]]}"#;
+
+    const TRADE_SAMPLE: &str = r#"{"data":{"id":336285941,"timestamp":"1712745600","amount":0.0123,"amount_str":"0.01230000","price":69420,"price_str":"69420","type":1,"microtimestamp":"1712745600250000","buy_order_id":1740000000000001,"sell_order_id":1740000000000002},"channel":"live_trades_btcusd","event":"trade"}"#;
+
+    #[test]
+    fn test_parse_diff() {
+        let adapter = BitstampAdapter::new();
+        match adapter.parse_depth(DIFF_SAMPLE).unwrap() {
+            DepthEvent::Diff { symbol, diff, checksum } => {
+                assert_eq!(symbol.as_deref(), Some("btcusd"));
+                assert_eq!(diff.final_update_id, 1712745600123456);
+                assert_eq!(diff.bids, vec![(dec!(69420.00), dec!(0.512)), (dec!(69419.00), dec!(0))]);
+                assert_eq!(diff.asks, vec![(dec!(69421.00), dec!(1.203))]);
+                assert_eq!(checksum, None);
+            }
+            other => panic!("Expected diff, got {:?}", other),
+        }
+        assert_eq!(adapter.event_time_ms(DIFF_SAMPLE), Some(1712745600123));
+    }
+
+    #[test]
+    fn test_diffs_sequence_against_the_rest_snapshot() {
+        let adapter = BitstampAdapter::new();
+        let diff = |micros: u64| {
+            let text = DIFF_SAMPLE.replace("1712745600123456", &micros.to_string());
+            match adapter.parse_depth(&text) {
+                Ok(DepthEvent::Diff { diff, .. }) => diff,
+                other => panic!("Expected diff, got {:?}", other),
+            }
+        };
+        let last_update_id = match adapter.parse_snapshot(SNAPSHOT_SAMPLE).unwrap() {
+            DepthEvent::Snapshot { last_update_id, bids, asks, .. } => {
+                assert_eq!(bids.len(), 2);
+                assert_eq!(asks[1], (dec!(69422.00), dec!(0.75)));
+                last_update_id
+            }
+            other => panic!("Expected snapshot, got {:?}", other),
+        };
+
+        let mut sync = DepthSynchronizer::new();
+        assert_eq!(sync.on_diff(diff(1712745600050000)), SyncOutcome::Buffered);
+        assert_eq!(sync.on_diff(diff(1712745600123456)), SyncOutcome::Buffered);
+        // The snapshot covers the first diff; the second follows it
+        match sync.on_snapshot(last_update_id) {
+            SyncOutcome::Apply(diffs) => assert_eq!(diffs.len(), 1),
+            other => panic!("Expected apply, got {:?}", other),
+        }
+        // However far apart the timestamps, later diffs never count as a gap
+        assert!(matches!(sync.on_diff(diff(1712745609000000)), SyncOutcome::Apply(_)));
+        assert_eq!(
+            adapter.snapshot_url("BTCUSD").as_deref(),
+            Some("https://www.bitstamp.net/api/v2/order_book/btcusd/")
+        );
+    }
+
+    #[test]
+    fn test_parse_trade() {
+        let adapter = BitstampAdapter::new();
+        let trades = adapter.parse_trades(TRADE_SAMPLE).unwrap();
+        let trade = &trades[0];
+        assert_eq!(trade.price, dec!(69420));
+        assert_eq!(trade.quantity, dec!(0.0123));
+        assert_eq!(trade.timestamp, 1712745600250);
+        // The taker sold into a resting bid
+        assert!(trade.is_buyer_maker);
+        assert!(adapter.parse_depth(TRADE_SAMPLE).is_err());
+    }
+
+    #[test]
+    fn test_subscribe_frames_and_control_messages() {
+        let adapter = BitstampAdapter::new();
+        let depth: serde_json::Value =
+            serde_json::from_str(&adapter.depth_subscribe_frame(&["BTCUSD".to_string()]).unwrap()).unwrap();
+        assert_eq!(depth, json!({"event": "bts:subscribe", "data": {"channel": "diff_order_book_btcusd"}}));
+        let trades: serde_json::Value = serde_json::from_str(&adapter.trade_subscribe_frame("ethusd").unwrap()).unwrap();
+        assert_eq!(trades["data"]["channel"], "live_trades_ethusd");
+
+        let succeeded = r#"{"event":"bts:subscription_succeeded","channel":"diff_order_book_btcusd","data":{}}"#;
+        assert!(matches!(adapter.parse_depth(succeeded), Err(AdapterError::Unsupported(_))));
+        let error = r#"{"event":"bts:error","channel":"","data":{"code":null,"message":"Incorrect JSON format."}}"#;
+        match adapter.parse_depth(error) {
+            Err(AdapterError::Unsupported(msg)) => assert!(msg.contains("Incorrect JSON format.")),
+            other => panic!("Expected the error to surface, got {:?}", other),
+        }
+        assert_eq!(adapter.depth_url("btcusd"), BITSTAMP_WS_BASE);
+    }
+}
@@ -0,0 +1,296 @@
+use super::{parse_levels, AdapterError, DepthEvent, ExchangeAdapter};
+use crate::depth_sync::DepthDiff;
+use crate::stream_config::{StreamConfig, BYBIT_WS_BASE};
+use crate::tradeslog::Trade;
+use rust_decimal::Decimal;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use std::str::FromStr;
+
+const ORDERBOOK_DEPTH: u32 = 50;
+
+#[derive(Debug, Deserialize)]
+pub struct BybitBook {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "b")]
+    pub bids: Vec<(String, String)>,
+    #[serde(rename = "a")]
+    pub asks: Vec<(String, String)>,
+    /// Update id, consecutive per topic; `1` on a snapshot after a service restart.
+    #[serde(rename = "u")]
+    pub update_id: u64,
+    /// Cross sequence, comparable across depth levels of the same symbol.
+    pub seq: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BybitTrade {
+    #[serde(rename = "T")]
+    pub timestamp: u64,
+    #[serde(rename = "s")]
+    pub symbol: String,
+    /// Side of the taker.
+    #[serde(rename = "S")]
+    pub side: String,
+    #[serde(rename = "v")]
+    pub quantity: String,
+    #[serde(rename = "p")]
+    pub price: String,
+    #[serde(rename = "i")]
+    pub trade_id: String,
+}
+
+/// Topic data; `kind` is `snapshot` or `delta`.
+#[derive(Debug, Deserialize)]
+pub struct BybitPush<T> {
+    pub topic: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub ts: u64,
+    pub data: T,
+}
+
+/// Reply to a subscribe/unsubscribe request.
+#[derive(Debug, Deserialize)]
+pub struct BybitOpResponse {
+    pub success: bool,
+    pub ret_msg: String,
+    pub op: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum BybitMessage<T> {
+    Push(BybitPush<T>),
+    Response(BybitOpResponse),
+}
+
+impl TryFrom<BybitTrade> for Trade {
+    type Error = AdapterError;
+
+    fn try_from(update: BybitTrade) -> Result<Self, Self::Error> {
+        let is_buyer_maker = match update.side.as_str() {
+            "Buy" => false,
+            "Sell" => true,
+            other => return Err(AdapterError::Unsupported(format!("trade side {}", other))),
+        };
+
+        Ok(Trade::new(
+            Decimal::from_str(&update.price).map_err(|_| AdapterError::DecimalConversion)?,
+            Decimal::from_str(&update.quantity).map_err(|_| AdapterError::DecimalConversion)?,
+            update.timestamp,
+            is_buyer_maker,
+        )?)
+    }
+}
+
+/// Bybit v5 public spot feed: `orderbook.50.<SYMBOL>` and `publicTrade.<SYMBOL>`.
+///
+/// Deltas follow a snapshot with consecutive `u`. A snapshot can arrive at any
+/// time and replaces the book; `u == 1` marks one sent after a service restart.
+#[derive(Debug, Clone)]
+pub struct BybitAdapter {
+    ws_base: String,
+}
+
+impl BybitAdapter {
+    pub fn new() -> Self {
+        Self {
+            ws_base: BYBIT_WS_BASE.to_string(),
+        }
+    }
+
+    pub fn from_config(config: &StreamConfig) -> Self {
+        Self {
+            ws_base: config.base_url.clone(),
+        }
+    }
+
+    fn op_frame(op: &str, topics: Vec<String>) -> String {
+        json!({"op": op, "args": topics}).to_string()
+    }
+
+    fn book_topics(symbols: &[String]) -> Vec<String> {
+        symbols
+            .iter()
+            .map(|symbol| format!("orderbook.{}.{}", ORDERBOOK_DEPTH, symbol.to_uppercase()))
+            .collect()
+    }
+
+    fn parse<T: DeserializeOwned>(text: &str) -> Result<BybitPush<T>, AdapterError> {
+        match serde_json::from_str(text)? {
+            BybitMessage::Push(push) => Ok(push),
+            BybitMessage::Response(response) => Err(AdapterError::Unsupported(format!(
+                "{} response (success: {}) {}",
+                response.op, response.success, response.ret_msg
+            ))),
+        }
+    }
+}
+
+impl Default for BybitAdapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExchangeAdapter for BybitAdapter {
+    fn name(&self) -> &'static str {
+        "bybit"
+    }
+
+    fn depth_url(&self, _symbol: &str) -> String {
+        self.ws_base.clone()
+    }
+
+    fn trade_url(&self, _symbol: &str) -> String {
+        self.ws_base.clone()
+    }
+
+    fn depth_subscribe_frame(&self, symbols: &[String]) -> Option<String> {
+        Some(Self::op_frame("subscribe", Self::book_topics(symbols)))
+    }
+
+    fn depth_unsubscribe_frame(&self, symbols: &[String]) -> Option<String> {
+        Some(Self::op_frame("unsubscribe", Self::book_topics(symbols)))
+    }
+
+    fn trade_subscribe_frame(&self, symbol: &str) -> Option<String> {
+        Some(Self::op_frame("subscribe", vec![format!("publicTrade.{}", symbol.to_uppercase())]))
+    }
+
+    fn parse_depth(&self, text: &str) -> Result<DepthEvent, AdapterError> {
+        let push: BybitPush<BybitBook> = Self::parse(text)?;
+        let book = push.data;
+        let symbol = Some(book.symbol.to_lowercase());
+
+        match push.kind.as_str() {
+            "snapshot" => Ok(DepthEvent::Snapshot {
+                symbol,
+                last_update_id: book.update_id,
+                bids: parse_levels(book.bids),
+                asks: parse_levels(book.asks),
+                checksum: None,
+            }),
+            "delta" => Ok(DepthEvent::Diff {
+                symbol,
+                diff: DepthDiff {
+                    first_update_id: book.update_id,
+                    final_update_id: book.update_id,
+                    bids: parse_levels(book.bids),
+                    asks: parse_levels(book.asks),
+                },
+                checksum: None,
+            }),
+            other => Err(AdapterError::Unsupported(format!("orderbook type {}", other))),
+        }
+    }
+
+    fn parse_trades(&self, text: &str) -> Result<Vec<Trade>, AdapterError> {
+        let push: BybitPush<Vec<BybitTrade>> = Self::parse(text)?;
+        push.data.into_iter().map(Trade::try_from).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::depth_sync::{DepthSynchronizer, SyncOutcome, SyncState};
+    use rust_decimal_macros::dec;
+
+    const SNAPSHOT_SAMPLE: &str = r#"{"topic":"orderbook.50.BTCUSDT","type":"snapshot","ts":1712745600123,"data":{"s":"BTCUSDT","b":[["69420.01","0.512"],["69419.99","0.03"]],"a":[["69420.02","1.203"]],"u":4818227,"seq":61412537713},"cts":1712745600120}"#;
+
+    const DELTA_SAMPLE: &str = r#"{"topic":"orderbook.50.BTCUSDT","type":"delta","ts":1712745600143,"data":{"s":"BTCUSDT","b":[["69419.99","0"]],"a":[["69420.05","0.4"]],"u":4818228,"seq":61412537790},"cts":1712745600141}"#;
+
+    const RESTART_SNAPSHOT_SAMPLE: &str = r#"{"topic":"orderbook.50.BTCUSDT","type":"snapshot","ts":1712745900000,"data":{"s":"BTCUSDT","b":[["69500.00","1"]],"a":[["69500.10","2"]],"u":1,"seq":61412601002},"cts":1712745899998}"#;
+
+    const TRADE_SAMPLE: &str = r#"{"topic":"publicTrade.BTCUSDT","type":"snapshot","ts":1712745600200,"data":[{"T":1712745600198,"s":"BTCUSDT","S":"Buy","v":"0.001","p":"69420.02","L":"PlusTick","i":"20f43950-d8dd-5b31-9112-a178eb6023af","BT":false},{"T":1712745600199,"s":"BTCUSDT","S":"Sell","v":"0.25","p":"69420.01","L":"MinusTick","i":"2a3c1fd1-8d2b-5a8f-8c1e-5c4d3e2b1a09","BT":false}]}"#;
+
+    #[test]
+    fn test_parse_snapshot_and_delta() {
+        let adapter = BybitAdapter::new();
+        match adapter.parse_depth(SNAPSHOT_SAMPLE).unwrap() {
+            DepthEvent::Snapshot { symbol, last_update_id, bids, asks, .. } => {
+                assert_eq!(symbol.as_deref(), Some("btcusdt"));
+                assert_eq!(last_update_id, 4818227);
+                assert_eq!(bids, vec![(dec!(69420.01), dec!(0.512)), (dec!(69419.99), dec!(0.03))]);
+                assert_eq!(asks, vec![(dec!(69420.02), dec!(1.203))]);
+            }
+            other => panic!("Expected snapshot, got {:?}", other),
+        }
+
+        match adapter.parse_depth(DELTA_SAMPLE).unwrap() {
+            DepthEvent::Diff { diff, .. } => {
+                assert_eq!((diff.first_update_id, diff.final_update_id), (4818228, 4818228));
+                assert_eq!(diff.bids, vec![(dec!(69419.99), dec!(0))]);
+                assert_eq!(diff.asks, vec![(dec!(69420.05), dec!(0.4))]);
+            }
+            other => panic!("Expected diff, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_restart_snapshot_resets_sequence() {
+        let adapter = BybitAdapter::new();
+        let mut sync = DepthSynchronizer::new();
+
+        let snapshot_id = |event: DepthEvent| match event {
+            DepthEvent::Snapshot { last_update_id, .. } => last_update_id,
+            other => panic!("Expected snapshot, got {:?}", other),
+        };
+        let delta = |u: u64| match adapter
+            .parse_depth(&DELTA_SAMPLE.replace(r#""u":4818228"#, &format!(r#""u":{}"#, u)))
+            .unwrap()
+        {
+            DepthEvent::Diff { diff, .. } => diff,
+            other => panic!("Expected diff, got {:?}", other),
+        };
+
+        sync.on_snapshot(snapshot_id(adapter.parse_depth(SNAPSHOT_SAMPLE).unwrap()));
+        assert!(matches!(sync.on_diff(delta(4818228)), SyncOutcome::Apply(_)));
+
+        // After a restart the ids start over at 1; the snapshot replaces everything
+        let restart_id = snapshot_id(adapter.parse_depth(RESTART_SNAPSHOT_SAMPLE).unwrap());
+        assert_eq!(restart_id, 1);
+        assert_eq!(sync.on_snapshot(restart_id), SyncOutcome::Apply(vec![]));
+        assert_eq!(sync.state(), SyncState::Live { last_update_id: 1 });
+        assert!(matches!(sync.on_diff(delta(2)), SyncOutcome::Apply(_)));
+        assert!(matches!(sync.on_diff(delta(4)), SyncOutcome::Gap { expected: 3, received: 4 }));
+    }
+
+    #[test]
+    fn test_parse_trades() {
+        let trades = BybitAdapter::new().parse_trades(TRADE_SAMPLE).unwrap();
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].price, dec!(69420.02));
+        assert_eq!(trades[0].quantity, dec!(0.001));
+        assert_eq!(trades[0].timestamp, 1712745600198);
+        // Taker bought
+        assert!(!trades[0].is_buyer_maker);
+        assert!(trades[1].is_buyer_maker);
+    }
+
+    #[test]
+    fn test_op_responses_are_not_market_data() {
+        let adapter = BybitAdapter::new();
+        let ack = r#"{"success":true,"ret_msg":"subscribe","conn_id":"2324d924-aa4d-45b0-a858-7b8be29ab52b","op":"subscribe"}"#;
+        assert!(matches!(adapter.parse_depth(ack), Err(AdapterError::Unsupported(_))));
+        assert!(adapter.parse_trades(ack).is_err());
+    }
+
+    #[test]
+    fn test_op_frames() {
+        let adapter = BybitAdapter::new();
+        let symbols = vec!["btcusdt".to_string()];
+        let subscribe: serde_json::Value =
+            serde_json::from_str(&adapter.depth_subscribe_frame(&symbols).unwrap()).unwrap();
+        assert_eq!(subscribe, json!({"op": "subscribe", "args": ["orderbook.50.BTCUSDT"]}));
+
+        let trades: serde_json::Value =
+            serde_json::from_str(&adapter.trade_subscribe_frame("ethusdt").unwrap()).unwrap();
+        assert_eq!(trades["args"], json!(["publicTrade.ETHUSDT"]));
+        assert_eq!(adapter.depth_url("btcusdt"), BYBIT_WS_BASE);
+    }
+}
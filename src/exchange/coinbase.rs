@@ -0,0 +1,244 @@
+use super::{parse_levels, AdapterError, DepthEvent, ExchangeAdapter};
+use crate::stream_config::{StreamConfig, COINBASE_WS_BASE};
+use crate::tradeslog::Trade;
+use chrono::DateTime;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use serde_json::json;
+use std::str::FromStr;
+
+const DEPTH_CHANNEL: &str = "level2_batch";
+const TRADE_CHANNEL: &str = "matches";
+
+#[derive(Debug, Deserialize)]
+pub struct CoinbaseMatch {
+    pub trade_id: u64,
+    pub product_id: String,
+    pub price: String,
+    pub size: String,
+    /// Side of the maker order.
+    pub side: String,
+    pub time: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum CoinbaseMessage {
+    #[serde(rename = "snapshot")]
+    Snapshot {
+        product_id: String,
+        bids: Vec<(String, String)>,
+        asks: Vec<(String, String)>,
+    },
+    /// `changes` are `[side, price, size]`, size `0` removing the level.
+    #[serde(rename = "l2update")]
+    L2Update {
+        product_id: String,
+        changes: Vec<(String, String, String)>,
+    },
+    #[serde(rename = "match")]
+    Match(CoinbaseMatch),
+    /// Most recent trade, sent once after subscribing to `matches`.
+    #[serde(rename = "last_match")]
+    LastMatch(CoinbaseMatch),
+    #[serde(rename = "error")]
+    Error { message: String },
+    /// Subscription confirmations, heartbeats and anything else we do not consume.
+    #[serde(other)]
+    Other,
+}
+
+impl TryFrom<CoinbaseMatch> for Trade {
+    type Error = AdapterError;
+
+    fn try_from(update: CoinbaseMatch) -> Result<Self, Self::Error> {
+        let is_buyer_maker = match update.side.as_str() {
+            "buy" => true,
+            "sell" => false,
+            other => return Err(AdapterError::Unsupported(format!("match side {}", other))),
+        };
+        let timestamp = DateTime::parse_from_rfc3339(&update.time)
+            .map_err(|_| AdapterError::InvalidTimestamp(update.time.clone()))?
+            .timestamp_millis() as u64;
+
+        Ok(Trade::new(
+            Decimal::from_str(&update.price).map_err(|_| AdapterError::DecimalConversion)?,
+            Decimal::from_str(&update.size).map_err(|_| AdapterError::DecimalConversion)?,
+            timestamp,
+            is_buyer_maker,
+        )?)
+    }
+}
+
+/// Coinbase Exchange public feed: one endpoint, channels chosen by a subscribe message.
+#[derive(Debug, Clone)]
+pub struct CoinbaseAdapter {
+    ws_base: String,
+}
+
+impl CoinbaseAdapter {
+    pub fn new() -> Self {
+        Self {
+            ws_base: COINBASE_WS_BASE.to_string(),
+        }
+    }
+
+    pub fn from_config(config: &StreamConfig) -> Self {
+        Self {
+            ws_base: config.base_url.clone(),
+        }
+    }
+
+    fn subscribe_frame(product_ids: Vec<String>, channel: &str) -> String {
+        json!({
+            "type": "subscribe",
+            "product_ids": product_ids,
+            "channels": [channel],
+        })
+        .to_string()
+    }
+
+    fn unexpected(message: CoinbaseMessage) -> AdapterError {
+        match message {
+            CoinbaseMessage::Error { message } => AdapterError::Unsupported(format!("error: {}", message)),
+            other => AdapterError::Unsupported(format!("{:?}", other)),
+        }
+    }
+}
+
+impl Default for CoinbaseAdapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExchangeAdapter for CoinbaseAdapter {
+    fn name(&self) -> &'static str {
+        "coinbase"
+    }
+
+    fn depth_url(&self, _symbol: &str) -> String {
+        self.ws_base.clone()
+    }
+
+    fn trade_url(&self, _symbol: &str) -> String {
+        self.ws_base.clone()
+    }
+
+    fn depth_subscribe_frame(&self, symbols: &[String]) -> Option<String> {
+        let product_ids = symbols.iter().map(|symbol| symbol.to_uppercase()).collect();
+        Some(Self::subscribe_frame(product_ids, DEPTH_CHANNEL))
+    }
+
+    fn trade_subscribe_frame(&self, symbol: &str) -> Option<String> {
+        Some(Self::subscribe_frame(vec![symbol.to_uppercase()], TRADE_CHANNEL))
+    }
+
+    fn parse_depth(&self, text: &str) -> Result<DepthEvent, AdapterError> {
+        match serde_json::from_str(text)? {
+            CoinbaseMessage::Snapshot { product_id, bids, asks } => Ok(DepthEvent::Snapshot {
+                symbol: Some(product_id.to_lowercase()),
+                // level2 carries no sequence numbers
+                last_update_id: 0,
+                bids: parse_levels(bids),
+                asks: parse_levels(asks),
+                checksum: None,
+            }),
+            CoinbaseMessage::L2Update { product_id, changes } => {
+                let (bid_changes, ask_changes): (Vec<_>, Vec<_>) =
+                    changes.into_iter().partition(|(side, _, _)| side == "buy");
+                let levels = |changes: Vec<(String, String, String)>| {
+                    parse_levels(changes.into_iter().map(|(_, price, size)| (price, size)).collect())
+                };
+                Ok(DepthEvent::Delta {
+                    symbol: Some(product_id.to_lowercase()),
+                    bids: levels(bid_changes),
+                    asks: levels(ask_changes),
+                    checksum: None,
+                })
+            }
+            other => Err(Self::unexpected(other)),
+        }
+    }
+
+    fn parse_trades(&self, text: &str) -> Result<Vec<Trade>, AdapterError> {
+        match serde_json::from_str(text)? {
+            CoinbaseMessage::Match(update) | CoinbaseMessage::LastMatch(update) => Ok(vec![Trade::try_from(update)?]),
+            other => Err(Self::unexpected(other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    const SNAPSHOT_SAMPLE: &str = r#"{"type":"snapshot","product_id":"BTC-USD","bids":[["67012.45","0.51234000"],["67012.01","1.20000000"]],"asks":[["67013.10","0.00500000"]]}"#;
+
+    const L2UPDATE_SAMPLE: &str = r#"{"type":"l2update","product_id":"BTC-USD","changes":[["buy","67012.50","0.25000000"],["sell","67013.10","0.00000000"],["sell","67013.40","2.10000000"]],"time":"2024-04-10T10:40:00.123456Z"}"#;
+
+    const MATCH_SAMPLE: &str = r#"{"type":"match","trade_id":631823711,"maker_order_id":"ac928c66-ca53-498f-9c13-a110027a60e8","taker_order_id":"132fb6ae-456b-4654-b4e0-d681ac05cea1","side":"sell","size":"0.00412000","price":"67013.10","product_id":"BTC-USD","sequence":78123902211,"time":"2024-04-10T10:40:00.250Z"}"#;
+
+    const SUBSCRIPTIONS_SAMPLE: &str = r#"{"type":"subscriptions","channels":[{"name":"level2_batch","product_ids":["BTC-USD"]}]}"#;
+
+    #[test]
+    fn test_parse_snapshot() {
+        match CoinbaseAdapter::new().parse_depth(SNAPSHOT_SAMPLE).unwrap() {
+            DepthEvent::Snapshot { symbol, bids, asks, .. } => {
+                assert_eq!(symbol.as_deref(), Some("btc-usd"));
+                assert_eq!(bids, vec![(dec!(67012.45), dec!(0.51234)), (dec!(67012.01), dec!(1.2))]);
+                assert_eq!(asks, vec![(dec!(67013.10), dec!(0.005))]);
+            }
+            other => panic!("Expected snapshot, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_l2update_splits_sides() {
+        match CoinbaseAdapter::new().parse_depth(L2UPDATE_SAMPLE).unwrap() {
+            DepthEvent::Delta { symbol, bids, asks, .. } => {
+                assert_eq!(symbol.as_deref(), Some("btc-usd"));
+                assert_eq!(bids, vec![(dec!(67012.50), dec!(0.25))]);
+                // Size zero removes the level
+                assert_eq!(asks, vec![(dec!(67013.10), dec!(0)), (dec!(67013.40), dec!(2.1))]);
+            }
+            other => panic!("Expected delta, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_match() {
+        let trades = CoinbaseAdapter::new().parse_trades(MATCH_SAMPLE).unwrap();
+        let trade = &trades[0];
+        assert_eq!(trade.price, dec!(67013.10));
+        assert_eq!(trade.quantity, dec!(0.00412));
+        assert_eq!(trade.timestamp, 1712745600250);
+        // Maker sold, so the taker bought
+        assert!(!trade.is_buyer_maker);
+    }
+
+    #[test]
+    fn test_control_messages_are_not_market_data() {
+        let adapter = CoinbaseAdapter::new();
+        assert!(matches!(adapter.parse_depth(SUBSCRIPTIONS_SAMPLE), Err(AdapterError::Unsupported(_))));
+        assert!(adapter.parse_trades(SNAPSHOT_SAMPLE).is_err());
+        assert!(adapter.parse_depth(r#"{"type":"error","message":"Failed to subscribe"}"#).is_err());
+    }
+
+    #[test]
+    fn test_subscribe_frames() {
+        let adapter = CoinbaseAdapter::new();
+        let depth: serde_json::Value =
+            serde_json::from_str(&adapter.depth_subscribe_frame(&["btc-usd".to_string()]).unwrap()).unwrap();
+        assert_eq!(
+            depth,
+            json!({"type": "subscribe", "product_ids": ["BTC-USD"], "channels": ["level2_batch"]})
+        );
+
+        let trades: serde_json::Value =
+            serde_json::from_str(&adapter.trade_subscribe_frame("eth-usd").unwrap()).unwrap();
+        assert_eq!(trades["channels"], json!(["matches"]));
+        assert_eq!(adapter.depth_url("btc-usd"), COINBASE_WS_BASE);
+    }
+}
@@ -0,0 +1,362 @@
+use super::{parse_levels, AdapterError, DepthEvent, ExchangeAdapter};
+use crate::orderbook::ChecksumFormat;
+use crate::stream_config::{StreamConfig, KRAKEN_WS_BASE};
+use crate::tradeslog::Trade;
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde_json::{json, Value};
+use std::str::FromStr;
+
+/// Subscribed book depth; Kraken's checksum covers the same ten levels.
+const BOOK_DEPTH: usize = 10;
+
+/// Kraken v1 public feed: `book` and `trade` channels.
+///
+/// Data arrives as positional arrays `[channelID, payload..., channelName, pair]`.
+/// Book updates carry no sequence ids, only a CRC32 of the resulting top ten levels.
+#[derive(Debug, Clone)]
+pub struct KrakenAdapter {
+    ws_base: String,
+}
+
+/// One data frame split into its channel name, pair and payloads.
+struct KrakenFrame {
+    channel: String,
+    pair: String,
+    payloads: Vec<Value>,
+}
+
+impl KrakenAdapter {
+    pub fn new() -> Self {
+        Self {
+            ws_base: KRAKEN_WS_BASE.to_string(),
+        }
+    }
+
+    pub fn from_config(config: &StreamConfig) -> Self {
+        Self {
+            ws_base: config.base_url.clone(),
+        }
+    }
+
+    fn subscription_frame(event: &str, pairs: Vec<String>, subscription: Value) -> String {
+        json!({"event": event, "pair": pairs, "subscription": subscription}).to_string()
+    }
+
+    fn book_frame(event: &str, symbols: &[String]) -> String {
+        let pairs = symbols.iter().map(|symbol| symbol.to_uppercase()).collect();
+        Self::subscription_frame(event, pairs, json!({"name": "book", "depth": BOOK_DEPTH}))
+    }
+
+    /// Splits a data frame; event objects (heartbeats, status, acks) are rejected.
+    fn frame(text: &str) -> Result<KrakenFrame, AdapterError> {
+        let mut items = match serde_json::from_str::<Value>(text)? {
+            Value::Array(items) if items.len() >= 4 => items,
+            Value::Object(fields) => {
+                let event = fields.get("event").and_then(Value::as_str).unwrap_or("unknown");
+                let detail = fields.get("errorMessage").and_then(Value::as_str).unwrap_or_default();
+                return Err(AdapterError::Unsupported(format!("{} event {}", event, detail)));
+            }
+            other => return Err(AdapterError::Unsupported(other.to_string())),
+        };
+
+        let text_at = |value: Option<Value>| match value {
+            Some(Value::String(text)) => Ok(text),
+            other => Err(AdapterError::Unsupported(format!("frame field {:?}", other))),
+        };
+        let pair = text_at(items.pop())?;
+        let channel = text_at(items.pop())?;
+        // Leading channel id
+        items.remove(0);
+
+        Ok(KrakenFrame {
+            channel,
+            pair,
+            payloads: items,
+        })
+    }
+
+    /// `[price, volume, timestamp(, "r")]` levels from one side of a payload.
+    fn levels(side: Option<&Value>) -> Vec<(Decimal, Decimal)> {
+        let levels = side
+            .and_then(Value::as_array)
+            .map(|levels| {
+                levels
+                    .iter()
+                    .filter_map(|level| {
+                        let price = level.get(0)?.as_str()?;
+                        let volume = level.get(1)?.as_str()?;
+                        Some((price.to_string(), volume.to_string()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        parse_levels(levels)
+    }
+
+    fn trade(fields: &Value) -> Result<Trade, AdapterError> {
+        let field = |i: usize| {
+            fields
+                .get(i)
+                .and_then(Value::as_str)
+                .ok_or_else(|| AdapterError::Unsupported(format!("trade field {} in {}", i, fields)))
+        };
+        // Side of the taker; the order type (`l`imit/`m`arket) is not carried on `Trade`
+        let is_buyer_maker = match field(3)? {
+            "b" => false,
+            "s" => true,
+            other => return Err(AdapterError::Unsupported(format!("trade side {}", other))),
+        };
+        let time = field(2)?;
+        let timestamp = Decimal::from_str(time)
+            .ok()
+            .and_then(|seconds| (seconds * dec!(1000)).trunc().to_u64())
+            .ok_or_else(|| AdapterError::InvalidTimestamp(time.to_string()))?;
+
+        Ok(Trade::new(
+            Decimal::from_str(field(0)?).map_err(|_| AdapterError::DecimalConversion)?,
+            Decimal::from_str(field(1)?).map_err(|_| AdapterError::DecimalConversion)?,
+            timestamp,
+            is_buyer_maker,
+        )?)
+    }
+}
+
+impl Default for KrakenAdapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExchangeAdapter for KrakenAdapter {
+    fn name(&self) -> &'static str {
+        "kraken"
+    }
+
+    fn depth_url(&self, _symbol: &str) -> String {
+        self.ws_base.clone()
+    }
+
+    fn trade_url(&self, _symbol: &str) -> String {
+        self.ws_base.clone()
+    }
+
+    fn checksum_format(&self) -> Option<ChecksumFormat> {
+        Some(ChecksumFormat::Concatenated { depth: BOOK_DEPTH })
+    }
+
+    fn book_depth(&self) -> Option<usize> {
+        Some(BOOK_DEPTH)
+    }
+
+    fn depth_subscribe_frame(&self, symbols: &[String]) -> Option<String> {
+        Some(Self::book_frame("subscribe", symbols))
+    }
+
+    fn depth_unsubscribe_frame(&self, symbols: &[String]) -> Option<String> {
+        Some(Self::book_frame("unsubscribe", symbols))
+    }
+
+    fn trade_subscribe_frame(&self, symbol: &str) -> Option<String> {
+        Some(Self::subscription_frame(
+            "subscribe",
+            vec![symbol.to_uppercase()],
+            json!({"name": "trade"}),
+        ))
+    }
+
+    fn parse_depth(&self, text: &str) -> Result<DepthEvent, AdapterError> {
+        let frame = Self::frame(text)?;
+        if !frame.channel.starts_with("book") {
+            return Err(AdapterError::Unsupported(format!("channel {}", frame.channel)));
+        }
+        let symbol = Some(frame.pair.to_lowercase());
+
+        let first = &frame.payloads[0];
+        if first.get("as").is_some() || first.get("bs").is_some() {
+            return Ok(DepthEvent::Snapshot {
+                symbol,
+                // No sequence ids on this feed
+                last_update_id: 0,
+                bids: Self::levels(first.get("bs")),
+                asks: Self::levels(first.get("as")),
+                checksum: None,
+            });
+        }
+
+        // Ask and bid changes may come in one payload or two, the checksum on the last
+        let mut bids = Vec::new();
+        let mut asks = Vec::new();
+        let mut checksum = None;
+        for payload in &frame.payloads {
+            bids.extend(Self::levels(payload.get("b")));
+            asks.extend(Self::levels(payload.get("a")));
+            if let Some(c) = payload.get("c").and_then(Value::as_str) {
+                let c: u32 = c
+                    .parse()
+                    .map_err(|_| AdapterError::Unsupported(format!("checksum {}", c)))?;
+                checksum = Some(c as i32);
+            }
+        }
+        Ok(DepthEvent::Delta {
+            symbol,
+            bids,
+            asks,
+            checksum,
+        })
+    }
+
+    fn parse_trades(&self, text: &str) -> Result<Vec<Trade>, AdapterError> {
+        let frame = Self::frame(text)?;
+        if frame.channel != "trade" {
+            return Err(AdapterError::Unsupported(format!("channel {}", frame.channel)));
+        }
+        frame.payloads[0]
+            .as_array()
+            .ok_or_else(|| AdapterError::Unsupported("trade payload".to_string()))?
+            .iter()
+            .map(Self::trade)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::OrderBook;
+
+    const SNAPSHOT_SAMPLE: &str = r#"[336,{"as":[["0.05010","0.50000000","1712745600.123456"],["0.05011","12.00000000","1712745600.223456"]],"bs":[["0.05005","0.00000500","1712745600.323456"],["0.05004","1.00000000","1712745600.423456"]]},"book-10","ETH/XBT"]"#;
+
+    const TRADE_SAMPLE: &str = r#"[337,[["69420.10000","0.00150000","1712745600.250123","b","m",""],["69420.00000","0.20000000","1712745600.251000","s","l",""]],"trade","XBT/USD"]"#;
+
+    fn update_sample(bids: &str, checksum: u32) -> String {
+        format!(
+            r#"[336,{{"a":[["0.05010","0.00000000","1712745601.000001"]]}},{{"b":{},"c":"{}"}},"book-10","ETH/XBT"]"#,
+            bids, checksum
+        )
+    }
+
+    fn applied(book: &mut OrderBook, event: DepthEvent) -> Option<i32> {
+        match event {
+            DepthEvent::Snapshot { bids, asks, checksum, .. } => {
+                book.apply_snapshot(bids, asks);
+                checksum
+            }
+            DepthEvent::Delta { bids, asks, checksum, .. } => {
+                book.apply_deltas(bids, asks);
+                book.truncate(BOOK_DEPTH);
+                checksum
+            }
+            other => panic!("Unexpected event {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_snapshot() {
+        match KrakenAdapter::new().parse_depth(SNAPSHOT_SAMPLE).unwrap() {
+            DepthEvent::Snapshot { symbol, bids, asks, .. } => {
+                assert_eq!(symbol.as_deref(), Some("eth/xbt"));
+                assert_eq!(bids, vec![(dec!(0.05005), dec!(0.000005)), (dec!(0.05004), dec!(1))]);
+                assert_eq!(asks, vec![(dec!(0.05010), dec!(0.5)), (dec!(0.05011), dec!(12))]);
+            }
+            other => panic!("Expected snapshot, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_update_checksum_matches_book() {
+        let adapter = KrakenAdapter::new();
+        let format = adapter.checksum_format().unwrap();
+        let mut book = OrderBook::new();
+        applied(&mut book, adapter.parse_depth(SNAPSHOT_SAMPLE).unwrap());
+
+        // Expected book: the 0.05010 ask removed, a new 0.05006 bid on top
+        let mut expected = OrderBook::new();
+        expected.apply_snapshot(
+            vec![(dec!(0.05006), dec!(2.00000000)), (dec!(0.05005), dec!(0.00000500)), (dec!(0.05004), dec!(1.00000000))],
+            vec![(dec!(0.05011), dec!(12.00000000))],
+        );
+        let checksum = expected.checksum(format) as u32;
+
+        let update = update_sample(r#"[["0.05006","2.00000000","1712745601.000002"]]"#, checksum);
+        let event = adapter.parse_depth(&update).unwrap();
+        match &event {
+            DepthEvent::Delta { bids, asks, .. } => {
+                assert_eq!(bids, &vec![(dec!(0.05006), dec!(2))]);
+                assert_eq!(asks, &vec![(dec!(0.05010), dec!(0))]);
+            }
+            other => panic!("Expected delta, got {:?}", other),
+        }
+        let reported = applied(&mut book, event);
+        assert_eq!(reported, Some(book.checksum(format)));
+    }
+
+    #[test]
+    fn test_checksum_matches_krakens_documented_example() {
+        // The book and CRC32 from Kraken's websocket checksum guide
+        let level = |price: &str| format!(r#"["{}","0.00000500","1582905487.684110"]"#, price);
+        let side = |prices: &[&str]| prices.iter().map(|price| level(price)).collect::<Vec<_>>().join(",");
+        let asks = side(&[
+            "0.05005", "0.05010", "0.05015", "0.05020", "0.05025", "0.05030", "0.05035", "0.05040", "0.05045", "0.05050",
+        ]);
+        let bids = side(&[
+            "0.05000", "0.04995", "0.04990", "0.04980", "0.04975", "0.04970", "0.04965", "0.04960", "0.04955", "0.04950",
+        ]);
+        let snapshot = format!(r#"[0,{{"as":[{}],"bs":[{}]}},"book-10","XBT/USD"]"#, asks, bids);
+
+        let adapter = KrakenAdapter::new();
+        let mut book = OrderBook::new();
+        applied(&mut book, adapter.parse_depth(&snapshot).unwrap());
+        assert_eq!(book.checksum(adapter.checksum_format().unwrap()) as u32, 974947235);
+    }
+
+    #[test]
+    fn test_update_checksum_failure_is_detected() {
+        let adapter = KrakenAdapter::new();
+        let format = adapter.checksum_format().unwrap();
+        let mut book = OrderBook::new();
+        applied(&mut book, adapter.parse_depth(SNAPSHOT_SAMPLE).unwrap());
+
+        // The checksum of the untouched snapshot no longer describes the updated book
+        let stale = book.checksum(format) as u32;
+        let update = update_sample(r#"[["0.05006","2.00000000","1712745601.000002"]]"#, stale);
+        let reported = applied(&mut book, adapter.parse_depth(&update).unwrap());
+        assert_ne!(reported, Some(book.checksum(format)));
+    }
+
+    #[test]
+    fn test_parse_trades() {
+        let trades = KrakenAdapter::new().parse_trades(TRADE_SAMPLE).unwrap();
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].price, dec!(69420.1));
+        assert_eq!(trades[0].quantity, dec!(0.0015));
+        assert_eq!(trades[0].timestamp, 1712745600250);
+        // Taker bought
+        assert!(!trades[0].is_buyer_maker);
+        assert!(trades[1].is_buyer_maker);
+    }
+
+    #[test]
+    fn test_events_are_not_market_data() {
+        let adapter = KrakenAdapter::new();
+        assert!(matches!(adapter.parse_depth(r#"{"event":"heartbeat"}"#), Err(AdapterError::Unsupported(_))));
+        let status = r#"{"channelID":336,"channelName":"book-10","event":"subscriptionStatus","pair":"ETH/XBT","status":"subscribed","subscription":{"depth":10,"name":"book"}}"#;
+        assert!(adapter.parse_depth(status).is_err());
+        assert!(adapter.parse_trades(SNAPSHOT_SAMPLE).is_err());
+        assert!(adapter.parse_depth(TRADE_SAMPLE).is_err());
+    }
+
+    #[test]
+    fn test_subscription_frames() {
+        let adapter = KrakenAdapter::new();
+        let book: Value = serde_json::from_str(&adapter.depth_subscribe_frame(&["eth/xbt".to_string()]).unwrap()).unwrap();
+        assert_eq!(
+            book,
+            json!({"event": "subscribe", "pair": ["ETH/XBT"], "subscription": {"name": "book", "depth": 10}})
+        );
+        let trades: Value = serde_json::from_str(&adapter.trade_subscribe_frame("xbt/usd").unwrap()).unwrap();
+        assert_eq!(trades["subscription"], json!({"name": "trade"}));
+        assert_eq!(adapter.depth_url("eth/xbt"), KRAKEN_WS_BASE);
+    }
+}
@@ -0,0 +1,238 @@
+pub mod binance;
+pub mod bitstamp;
+pub mod bybit;
+pub mod coinbase;
+pub mod kraken;
+pub mod okx;
+pub mod relay;
+
+use crate::depth_sync::DepthDiff;
+use crate::orderbook::ChecksumFormat;
+use crate::stream_config::{Exchange, StreamConfig};
+use crate::tradeslog::{Trade, TradeError};
+use log::warn;
+use rust_decimal::Decimal;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+
+pub use binance::BinanceAdapter;
+pub use bitstamp::BitstampAdapter;
+pub use bybit::BybitAdapter;
+pub use coinbase::CoinbaseAdapter;
+pub use kraken::KrakenAdapter;
+pub use okx::OkxAdapter;
+pub use relay::RelayAdapter;
+
+#[derive(Debug, Error)]
+pub enum AdapterError {
+    #[error("JSON parse error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Decimal conversion error")]
+    DecimalConversion,
+    #[error("Invalid timestamp: {0}")]
+    InvalidTimestamp(String),
+    #[error("Unsupported message: {0}")]
+    Unsupported(String),
+    #[error("Invalid trade: {0}")]
+    InvalidTrade(#[from] TradeError),
+    #[error("Binary decode error: {0}")]
+    Binary(String),
+}
+
+/// Exchange-neutral depth message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DepthEvent {
+    /// Incremental change that must be sequenced against a snapshot.
+    Diff {
+        symbol: Option<String>,
+        diff: DepthDiff,
+        /// Book checksum after applying this diff, if the exchange sends one.
+        checksum: Option<i32>,
+    },
+    /// Incremental change without sequence ids; the exchange guarantees ordering
+    /// on the connection, so it applies directly once a snapshot is in place.
+    Delta {
+        symbol: Option<String>,
+        bids: Vec<(Decimal, Decimal)>,
+        asks: Vec<(Decimal, Decimal)>,
+        checksum: Option<i32>,
+    },
+    /// Full book (or top-N) that replaces the current state.
+    Snapshot {
+        symbol: Option<String>,
+        last_update_id: u64,
+        bids: Vec<(Decimal, Decimal)>,
+        asks: Vec<(Decimal, Decimal)>,
+        checksum: Option<i32>,
+    },
+}
+
+impl DepthEvent {
+    /// Lowercase symbol, when the payload identifies one.
+    pub fn symbol(&self) -> Option<&str> {
+        match self {
+            DepthEvent::Diff { symbol, .. }
+            | DepthEvent::Delta { symbol, .. }
+            | DepthEvent::Snapshot { symbol, .. } => symbol.as_deref(),
+        }
+    }
+}
+
+/// What a binary frame decoded to; see `ExchangeAdapter::parse_binary`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FeedEvent {
+    /// Text sent as a binary frame, read by `parse_depth` or `parse_trades` like any other.
+    Text(String),
+    Depth(DepthEvent),
+    Trades(Vec<Trade>),
+}
+
+/// Best bid and offer pushed by a book ticker stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BestQuotes {
+    pub symbol: Option<String>,
+    /// `(price, quantity)` at the touch.
+    pub bid: (Decimal, Decimal),
+    pub ask: (Decimal, Decimal),
+}
+
+/// Everything exchange specific about a market data feed: where to connect and how to read it.
+///
+/// The feed managers own the transport (connect, reconnect, keepalive) and the books;
+/// adapters only build URLs and turn payloads into `DepthEvent`s and `Trade`s.
+pub trait ExchangeAdapter: Send + Sync + Debug {
+    fn name(&self) -> &'static str;
+
+    fn depth_url(&self, symbol: &str) -> String;
+
+    fn trade_url(&self, symbol: &str) -> String;
+
+    /// REST endpoint serving a depth snapshot, if the exchange needs one to start syncing.
+    fn snapshot_url(&self, _symbol: &str) -> Option<String> {
+        None
+    }
+
+    /// How to recompute the checksums carried by depth events, if the exchange sends any.
+    fn checksum_format(&self) -> Option<ChecksumFormat> {
+        None
+    }
+
+    /// Levels per side the exchange keeps current. Levels pushed out of that range are
+    /// never explicitly removed, so the local book is truncated to it after every update.
+    fn book_depth(&self) -> Option<usize> {
+        None
+    }
+
+    /// Whether every depth message is the whole book with an update id that only moves
+    /// forward, so one arriving behind the book can be dropped.
+    fn streams_whole_books(&self) -> bool {
+        false
+    }
+
+    /// Frame to send after connecting, for exchanges that select depth channels in-band.
+    fn depth_subscribe_frame(&self, _symbols: &[String]) -> Option<String> {
+        None
+    }
+
+    /// Frame dropping depth channels again; together with `depth_subscribe_frame` this
+    /// resyncs books on exchanges that only deliver snapshots over the socket.
+    fn depth_unsubscribe_frame(&self, _symbols: &[String]) -> Option<String> {
+        None
+    }
+
+    /// Best bid/offer stream, for exchanges that push the touch ahead of depth updates.
+    fn book_ticker_url(&self, _symbol: &str) -> Option<String> {
+        None
+    }
+
+    /// Frame to send after connecting, for exchanges that select trade channels in-band.
+    fn trade_subscribe_frame(&self, _symbol: &str) -> Option<String> {
+        None
+    }
+
+    fn parse_depth(&self, text: &str) -> Result<DepthEvent, AdapterError>;
+
+    /// Trades in one payload; some exchanges batch several per message. Exchanges whose
+    /// trade ids run consecutively per symbol set `Trade::trade_id`: the trade feed drops
+    /// trades whose id it has just seen, e.g. while two connections overlap during a
+    /// rollover, and counts the ids it skips over as dropped trades.
+    fn parse_trades(&self, text: &str) -> Result<Vec<Trade>, AdapterError>;
+
+    /// Decodes a binary frame. Most exchanges only send JSON, some of it in binary
+    /// frames, so by default the payload is read as UTF-8 text.
+    fn parse_binary(&self, bytes: &[u8]) -> Result<FeedEvent, AdapterError> {
+        match std::str::from_utf8(bytes) {
+            Ok(text) => Ok(FeedEvent::Text(text.to_string())),
+            Err(e) => Err(AdapterError::Binary(format!("not UTF-8: {}", e))),
+        }
+    }
+
+    /// Exchange-side event time of a depth or trade payload in epoch ms, for measuring
+    /// how far behind the feed runs.
+    fn event_time_ms(&self, _text: &str) -> Option<u64> {
+        None
+    }
+
+    /// How long the exchange keeps a connection open before closing it. The trade feed
+    /// rolls over to a fresh connection shortly before then instead of losing the trades
+    /// that arrive while it reconnects.
+    fn max_connection_age(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Parses a payload from `book_ticker_url`.
+    fn parse_book_ticker(&self, _text: &str) -> Result<BestQuotes, AdapterError> {
+        Err(AdapterError::Unsupported(format!("{} book ticker", self.name())))
+    }
+
+    /// Parses the body returned by `snapshot_url`.
+    fn parse_snapshot(&self, body: &str) -> Result<DepthEvent, AdapterError> {
+        self.parse_depth(body)
+    }
+
+    /// REST endpoint serving `symbol`'s last `limit` trades, if the trade feed can be
+    /// backfilled from one on startup.
+    fn recent_trades_url(&self, _symbol: &str, _limit: usize) -> Option<String> {
+        None
+    }
+
+    /// Parses the body returned by `recent_trades_url` into trades, oldest first, each
+    /// with the `trade_id` it has on the trade feed.
+    fn parse_recent_trades(&self, _body: &str) -> Result<Vec<Trade>, AdapterError> {
+        Err(AdapterError::Unsupported(format!("{} recent trades", self.name())))
+    }
+}
+
+/// Adapter for the exchange selected in `config`.
+pub fn adapter_for(config: &StreamConfig) -> Arc<dyn ExchangeAdapter> {
+    match config.exchange {
+        Exchange::Binance => Arc::new(BinanceAdapter::from_config(config)),
+        Exchange::Coinbase => Arc::new(CoinbaseAdapter::from_config(config)),
+        Exchange::Okx => Arc::new(OkxAdapter::from_config(config)),
+        Exchange::Bybit => Arc::new(BybitAdapter::from_config(config)),
+        Exchange::Kraken => Arc::new(KrakenAdapter::from_config(config)),
+        Exchange::Bitstamp => Arc::new(BitstampAdapter::from_config(config)),
+    }
+}
+
+/// Parses `[price, quantity]` string pairs. Malformed levels are skipped so the rest of
+/// the message still applies, but each one is counted under `depth_levels_dropped` and
+/// every message that loses any is logged with how many.
+pub(crate) fn parse_levels(levels: Vec<(String, String)>) -> Vec<(Decimal, Decimal)> {
+    let total = levels.len();
+    let parsed: Vec<_> = levels
+        .into_iter()
+        .filter_map(|(p, q)| match (p.parse::<Decimal>(), q.parse::<Decimal>()) {
+            (Ok(price), Ok(qty)) => Some((price, qty)),
+            _ => None,
+        })
+        .collect();
+    let dropped = total - parsed.len();
+    if dropped > 0 {
+        warn!("Dropped {} of {} price levels that failed to parse", dropped, total);
+        metrics::counter!("depth_levels_dropped", dropped as u64);
+    }
+    parsed
+}
@@ -0,0 +1,303 @@
+use super::{parse_levels, AdapterError, DepthEvent, ExchangeAdapter};
+use crate::depth_sync::DepthDiff;
+use crate::orderbook::ChecksumFormat;
+use crate::stream_config::{StreamConfig, OKX_WS_BASE};
+use crate::tradeslog::Trade;
+use rust_decimal::Decimal;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::json;
+use std::str::FromStr;
+
+const BOOKS_CHANNEL: &str = "books";
+const TRADES_CHANNEL: &str = "trades";
+const CHECKSUM_DEPTH: usize = 25;
+
+/// `[price, size, deprecated, order count]`.
+type OkxLevel = (String, String, String, String);
+
+#[derive(Debug, Deserialize)]
+pub struct OkxArg {
+    pub channel: String,
+    #[serde(rename = "instId")]
+    pub inst_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OkxBookData {
+    pub asks: Vec<OkxLevel>,
+    pub bids: Vec<OkxLevel>,
+    pub ts: String,
+    /// CRC32 of the top 25 levels after this message is applied.
+    pub checksum: i32,
+    /// `-1` on snapshots; on updates, the `seqId` of the previous message.
+    #[serde(rename = "prevSeqId")]
+    pub prev_seq_id: i64,
+    #[serde(rename = "seqId")]
+    pub seq_id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OkxTrade {
+    #[serde(rename = "instId")]
+    pub inst_id: String,
+    #[serde(rename = "tradeId")]
+    pub trade_id: String,
+    pub px: String,
+    pub sz: String,
+    /// Side of the taker.
+    pub side: String,
+    pub ts: String,
+}
+
+/// Channel data; `action` is only set on `books` pushes.
+#[derive(Debug, Deserialize)]
+pub struct OkxPush<T> {
+    pub arg: OkxArg,
+    pub action: Option<String>,
+    pub data: Vec<T>,
+}
+
+/// Reply to a subscribe/unsubscribe request, or an error.
+#[derive(Debug, Deserialize)]
+pub struct OkxEvent {
+    pub event: String,
+    pub code: Option<String>,
+    pub msg: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum OkxMessage<T> {
+    Event(OkxEvent),
+    Push(OkxPush<T>),
+}
+
+impl TryFrom<OkxTrade> for Trade {
+    type Error = AdapterError;
+
+    fn try_from(update: OkxTrade) -> Result<Self, Self::Error> {
+        let is_buyer_maker = match update.side.as_str() {
+            "buy" => false,
+            "sell" => true,
+            other => return Err(AdapterError::Unsupported(format!("trade side {}", other))),
+        };
+
+        Ok(Trade::new(
+            Decimal::from_str(&update.px).map_err(|_| AdapterError::DecimalConversion)?,
+            Decimal::from_str(&update.sz).map_err(|_| AdapterError::DecimalConversion)?,
+            update
+                .ts
+                .parse()
+                .map_err(|_| AdapterError::InvalidTimestamp(update.ts.clone()))?,
+            is_buyer_maker,
+        )?)
+    }
+}
+
+/// OKX v5 public feed: `books` for depth, `trades` for the tape.
+///
+/// Books arrive as one snapshot followed by updates chained through
+/// `prevSeqId`/`seqId`, each carrying a checksum of the resulting top 25 levels.
+#[derive(Debug, Clone)]
+pub struct OkxAdapter {
+    ws_base: String,
+}
+
+impl OkxAdapter {
+    pub fn new() -> Self {
+        Self {
+            ws_base: OKX_WS_BASE.to_string(),
+        }
+    }
+
+    pub fn from_config(config: &StreamConfig) -> Self {
+        Self {
+            ws_base: config.base_url.clone(),
+        }
+    }
+
+    fn op_frame(op: &str, channel: &str, symbols: &[String]) -> String {
+        let args: Vec<_> = symbols
+            .iter()
+            .map(|symbol| json!({"channel": channel, "instId": symbol.to_uppercase()}))
+            .collect();
+        json!({"op": op, "args": args}).to_string()
+    }
+
+    fn parse<T: DeserializeOwned>(text: &str) -> Result<OkxPush<T>, AdapterError> {
+        match serde_json::from_str(text)? {
+            OkxMessage::Push(push) => Ok(push),
+            OkxMessage::Event(OkxEvent { event, code, msg }) => Err(AdapterError::Unsupported(format!(
+                "{} event {} {}",
+                event,
+                code.unwrap_or_default(),
+                msg.unwrap_or_default()
+            ))),
+        }
+    }
+
+    fn levels(levels: Vec<OkxLevel>) -> Vec<(Decimal, Decimal)> {
+        parse_levels(levels.into_iter().map(|(price, size, _, _)| (price, size)).collect())
+    }
+}
+
+impl Default for OkxAdapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ExchangeAdapter for OkxAdapter {
+    fn name(&self) -> &'static str {
+        "okx"
+    }
+
+    fn depth_url(&self, _symbol: &str) -> String {
+        self.ws_base.clone()
+    }
+
+    fn trade_url(&self, _symbol: &str) -> String {
+        self.ws_base.clone()
+    }
+
+    fn checksum_format(&self) -> Option<ChecksumFormat> {
+        Some(ChecksumFormat::Interleaved { depth: CHECKSUM_DEPTH })
+    }
+
+    fn depth_subscribe_frame(&self, symbols: &[String]) -> Option<String> {
+        Some(Self::op_frame("subscribe", BOOKS_CHANNEL, symbols))
+    }
+
+    fn depth_unsubscribe_frame(&self, symbols: &[String]) -> Option<String> {
+        Some(Self::op_frame("unsubscribe", BOOKS_CHANNEL, symbols))
+    }
+
+    fn trade_subscribe_frame(&self, symbol: &str) -> Option<String> {
+        Some(Self::op_frame("subscribe", TRADES_CHANNEL, &[symbol.to_string()]))
+    }
+
+    fn parse_depth(&self, text: &str) -> Result<DepthEvent, AdapterError> {
+        let push: OkxPush<OkxBookData> = Self::parse(text)?;
+        let symbol = Some(push.arg.inst_id.to_lowercase());
+        let data = push
+            .data
+            .into_iter()
+            .next()
+            .ok_or_else(|| AdapterError::Unsupported("empty books push".to_string()))?;
+
+        match push.action.as_deref() {
+            Some("snapshot") => Ok(DepthEvent::Snapshot {
+                symbol,
+                last_update_id: data.seq_id,
+                bids: Self::levels(data.bids),
+                asks: Self::levels(data.asks),
+                checksum: Some(data.checksum),
+            }),
+            // Chained ids map onto the contiguous-range rule of `DepthSynchronizer`
+            Some("update") => Ok(DepthEvent::Diff {
+                symbol,
+                diff: DepthDiff {
+                    first_update_id: (data.prev_seq_id + 1).max(0) as u64,
+                    final_update_id: data.seq_id,
+                    bids: Self::levels(data.bids),
+                    asks: Self::levels(data.asks),
+                },
+                checksum: Some(data.checksum),
+            }),
+            other => Err(AdapterError::Unsupported(format!("books action {:?}", other))),
+        }
+    }
+
+    fn parse_trades(&self, text: &str) -> Result<Vec<Trade>, AdapterError> {
+        let push: OkxPush<OkxTrade> = Self::parse(text)?;
+        push.data.into_iter().map(Trade::try_from).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::OrderBook;
+    use rust_decimal_macros::dec;
+
+    // Levels from OKX's checksum documentation
+    const SNAPSHOT_SAMPLE: &str = r#"{"arg":{"channel":"books","instId":"BTC-USDT"},"action":"snapshot","data":[{"asks":[["3366.8","9","0","3"],["3368","8","0","4"]],"bids":[["3366.1","7","0","3"],["3366","6","0","4"]],"ts":"1712745600123","checksum":-1881014294,"prevSeqId":-1,"seqId":123456}]}"#;
+
+    const UPDATE_SAMPLE: &str = r#"{"arg":{"channel":"books","instId":"BTC-USDT"},"action":"update","data":[{"asks":[["3366.8","0","0","0"]],"bids":[["3366.2","1.5","0","1"]],"ts":"1712745600223","checksum":123,"prevSeqId":123456,"seqId":123460}]}"#;
+
+    const TRADES_SAMPLE: &str = r#"{"arg":{"channel":"trades","instId":"BTC-USDT"},"data":[{"instId":"BTC-USDT","tradeId":"130639474","px":"42219.9","sz":"0.12060306","side":"buy","ts":"1630048897897","count":"3"},{"instId":"BTC-USDT","tradeId":"130639475","px":"42219.8","sz":"0.01","side":"sell","ts":"1630048897901","count":"1"}]}"#;
+
+    #[test]
+    fn test_snapshot_checksum_matches_book() {
+        match OkxAdapter::new().parse_depth(SNAPSHOT_SAMPLE).unwrap() {
+            DepthEvent::Snapshot { symbol, last_update_id, bids, asks, checksum } => {
+                assert_eq!(symbol.as_deref(), Some("btc-usdt"));
+                assert_eq!(last_update_id, 123456);
+                assert_eq!(bids, vec![(dec!(3366.1), dec!(7)), (dec!(3366), dec!(6))]);
+
+                let mut book = OrderBook::new();
+                book.apply_snapshot(bids, asks);
+                let format = OkxAdapter::new().checksum_format().unwrap();
+                assert_eq!(checksum, Some(book.checksum(format)));
+            }
+            other => panic!("Expected snapshot, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_update_chains_sequence_ids() {
+        match OkxAdapter::new().parse_depth(UPDATE_SAMPLE).unwrap() {
+            DepthEvent::Diff { diff, checksum, .. } => {
+                assert_eq!(diff.first_update_id, 123457);
+                assert_eq!(diff.final_update_id, 123460);
+                assert_eq!(diff.bids, vec![(dec!(3366.2), dec!(1.5))]);
+                assert_eq!(diff.asks, vec![(dec!(3366.8), dec!(0))]);
+                assert_eq!(checksum, Some(123));
+            }
+            other => panic!("Expected diff, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_trades() {
+        let trades = OkxAdapter::new().parse_trades(TRADES_SAMPLE).unwrap();
+        assert_eq!(trades.len(), 2);
+        assert_eq!(trades[0].price, dec!(42219.9));
+        assert_eq!(trades[0].quantity, dec!(0.12060306));
+        assert_eq!(trades[0].timestamp, 1630048897897);
+        // Taker bought
+        assert!(!trades[0].is_buyer_maker);
+        assert!(trades[1].is_buyer_maker);
+    }
+
+    #[test]
+    fn test_events_are_not_market_data() {
+        let adapter = OkxAdapter::new();
+        let ack = r#"{"event":"subscribe","arg":{"channel":"books","instId":"BTC-USDT"},"connId":"a4d3ae55"}"#;
+        assert!(matches!(adapter.parse_depth(ack), Err(AdapterError::Unsupported(_))));
+        let error = r#"{"event":"error","code":"60012","msg":"Invalid request","connId":"a4d3ae55"}"#;
+        assert!(adapter.parse_trades(error).is_err());
+    }
+
+    #[test]
+    fn test_op_frames() {
+        let adapter = OkxAdapter::new();
+        let symbols = vec!["btc-usdt".to_string()];
+        let subscribe: serde_json::Value =
+            serde_json::from_str(&adapter.depth_subscribe_frame(&symbols).unwrap()).unwrap();
+        assert_eq!(
+            subscribe,
+            json!({"op": "subscribe", "args": [{"channel": "books", "instId": "BTC-USDT"}]})
+        );
+
+        let unsubscribe: serde_json::Value =
+            serde_json::from_str(&adapter.depth_unsubscribe_frame(&symbols).unwrap()).unwrap();
+        assert_eq!(unsubscribe["op"], "unsubscribe");
+
+        let trades: serde_json::Value =
+            serde_json::from_str(&adapter.trade_subscribe_frame("eth-usdt").unwrap()).unwrap();
+        assert_eq!(trades["args"][0]["channel"], "trades");
+        assert_eq!(adapter.depth_url("btc-usdt"), OKX_WS_BASE);
+    }
+}
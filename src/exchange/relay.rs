@@ -0,0 +1,199 @@
+use super::binance::{BinanceRestSnapshot, DepthUpdateEvent};
+use super::{AdapterError, DepthEvent, ExchangeAdapter, FeedEvent};
+use crate::tradeslog::Trade;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Bytes of the little-endian record length in front of every frame.
+const LENGTH_PREFIX: usize = 4;
+
+/// A trade as the relay sends it; decimals travel as strings, which bincode can read back.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelayTrade {
+    pub price: String,
+    pub quantity: String,
+    pub timestamp: u64,
+    pub is_buyer_maker: bool,
+}
+
+impl From<&Trade> for RelayTrade {
+    fn from(trade: &Trade) -> Self {
+        Self {
+            price: trade.price.to_string(),
+            quantity: trade.quantity.to_string(),
+            timestamp: trade.timestamp,
+            is_buyer_maker: trade.is_buyer_maker,
+        }
+    }
+}
+
+impl TryFrom<RelayTrade> for Trade {
+    type Error = AdapterError;
+
+    fn try_from(trade: RelayTrade) -> Result<Self, Self::Error> {
+        Ok(Trade::new(
+            Decimal::from_str(&trade.price).map_err(|_| AdapterError::DecimalConversion)?,
+            Decimal::from_str(&trade.quantity).map_err(|_| AdapterError::DecimalConversion)?,
+            trade.timestamp,
+            trade.is_buyer_maker,
+        )?)
+    }
+}
+
+/// One record of the relay encoding: Binance's depth events and our trades, as the tape
+/// tooling replays them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RelayRecord {
+    /// REST depth snapshot the relay bootstrapped from, sent ahead of the diffs after it.
+    Snapshot(BinanceRestSnapshot),
+    Depth(DepthUpdateEvent),
+    Trade(RelayTrade),
+}
+
+/// Encodes `record` as one frame: its bincode length as a little-endian `u32`, then the
+/// bincode itself.
+pub fn encode_frame(record: &RelayRecord) -> Vec<u8> {
+    let body = bincode::serialize(record).expect("relay records always serialize");
+    let mut frame = Vec::with_capacity(LENGTH_PREFIX + body.len());
+    frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&body);
+    frame
+}
+
+/// Decodes a frame written by `encode_frame`, rejecting one whose length prefix doesn't
+/// match what follows it.
+pub fn decode_frame(bytes: &[u8]) -> Result<RelayRecord, AdapterError> {
+    if bytes.len() < LENGTH_PREFIX {
+        return Err(AdapterError::Binary(format!("{} byte frame is shorter than its length prefix", bytes.len())));
+    }
+    let (prefix, body) = bytes.split_at(LENGTH_PREFIX);
+    let length = u32::from_le_bytes(prefix.try_into().unwrap()) as usize;
+    if body.len() != length {
+        return Err(AdapterError::Binary(format!("{} byte record announced, {} received", length, body.len())));
+    }
+    bincode::deserialize(body).map_err(|e| AdapterError::Binary(e.to_string()))
+}
+
+/// Reference adapter for binary-framed feeds: an internal relay sending `RelayRecord`s
+/// as `encode_frame` writes them, depth and trades alike, over one URL.
+///
+/// Books are synced like Binance's diff stream, from the snapshot the relay sends in-band.
+#[derive(Debug, Clone)]
+pub struct RelayAdapter {
+    url: String,
+}
+
+impl RelayAdapter {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into() }
+    }
+
+    fn text_frame() -> AdapterError {
+        AdapterError::Unsupported("text frame from a binary relay".to_string())
+    }
+}
+
+impl ExchangeAdapter for RelayAdapter {
+    fn name(&self) -> &'static str {
+        "relay"
+    }
+
+    fn depth_url(&self, _symbol: &str) -> String {
+        self.url.clone()
+    }
+
+    fn trade_url(&self, _symbol: &str) -> String {
+        self.url.clone()
+    }
+
+    fn parse_depth(&self, _text: &str) -> Result<DepthEvent, AdapterError> {
+        Err(Self::text_frame())
+    }
+
+    fn parse_trades(&self, _text: &str) -> Result<Vec<Trade>, AdapterError> {
+        Err(Self::text_frame())
+    }
+
+    fn parse_binary(&self, bytes: &[u8]) -> Result<FeedEvent, AdapterError> {
+        Ok(match decode_frame(bytes)? {
+            RelayRecord::Snapshot(snapshot) => {
+                let (last_update_id, bids, asks) = snapshot.into_levels();
+                FeedEvent::Depth(DepthEvent::Snapshot {
+                    symbol: None,
+                    last_update_id,
+                    bids,
+                    asks,
+                    checksum: None,
+                })
+            }
+            RelayRecord::Depth(update) => FeedEvent::Depth(DepthEvent::Diff {
+                symbol: Some(update.symbol.to_lowercase()),
+                diff: update.into(),
+                checksum: None,
+            }),
+            RelayRecord::Trade(trade) => FeedEvent::Trades(vec![trade.try_into()?]),
+        })
+    }
+}
+
+/// Levels as the relay sends them, for building records.
+pub fn relay_levels(levels: &[(Decimal, Decimal)]) -> Vec<(String, String)> {
+    levels.iter().map(|(price, quantity)| (price.to_string(), quantity.to_string())).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    fn diff(first: u64, last: u64) -> DepthUpdateEvent {
+        DepthUpdateEvent {
+            event_type: "depthUpdate".to_string(),
+            event_time: 1712745600123,
+            symbol: "BTCUSDT".to_string(),
+            first_update_id: first,
+            final_update_id: last,
+            bids: relay_levels(&[(dec!(69420.01), dec!(0.512))]),
+            asks: relay_levels(&[(dec!(69420.02), dec!(0))]),
+        }
+    }
+
+    #[test]
+    fn test_records_round_trip_through_frames() {
+        let adapter = RelayAdapter::new("ws://relay");
+        match adapter.parse_binary(&encode_frame(&RelayRecord::Depth(diff(101, 105)))).unwrap() {
+            FeedEvent::Depth(DepthEvent::Diff { symbol, diff, .. }) => {
+                assert_eq!(symbol.as_deref(), Some("btcusdt"));
+                assert_eq!((diff.first_update_id, diff.final_update_id), (101, 105));
+                assert_eq!(diff.bids, vec![(dec!(69420.01), dec!(0.512))]);
+                assert_eq!(diff.asks, vec![(dec!(69420.02), dec!(0))]);
+            }
+            other => panic!("Expected a diff, got {:?}", other),
+        }
+
+        let trade = Trade::new(dec!(69000.50), dec!(0.25), 1712745600200, true).unwrap();
+        let frame = encode_frame(&RelayRecord::Trade(RelayTrade::from(&trade)));
+        assert_eq!(adapter.parse_binary(&frame).unwrap(), FeedEvent::Trades(vec![trade]));
+        // The prefix counts the bytes after it
+        assert_eq!(u32::from_le_bytes(frame[..4].try_into().unwrap()) as usize, frame.len() - 4);
+    }
+
+    #[test]
+    fn test_malformed_frames_are_rejected() {
+        let adapter = RelayAdapter::new("ws://relay");
+        let frame = encode_frame(&RelayRecord::Depth(diff(1, 2)));
+        for bytes in [&frame[..2], &frame[..frame.len() - 1], &[0, 0, 0, 0][..]] {
+            assert!(matches!(adapter.parse_binary(bytes), Err(AdapterError::Binary(_))), "{:?}", bytes);
+        }
+        let negative = RelayTrade {
+            price: "69000".to_string(),
+            quantity: "-1".to_string(),
+            timestamp: 0,
+            is_buyer_maker: false,
+        };
+        let frame = encode_frame(&RelayRecord::Trade(negative));
+        assert!(matches!(adapter.parse_binary(&frame), Err(AdapterError::InvalidTrade(_))));
+        assert!(matches!(adapter.parse_depth("{}"), Err(AdapterError::Unsupported(_))));
+    }
+}
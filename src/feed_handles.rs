@@ -0,0 +1,69 @@
+use crate::reconnect::ReconnectError;
+use log::error;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// The running tasks of one feed manager, as returned by its `spawn`.
+///
+/// `stop` asks every task to close its connection and return, and `join` waits until
+/// they have, so a manager can be stopped and spawned again without leaking a
+/// connection. Dropping the handles stops the feeds too, but without waiting for them.
+#[derive(Debug)]
+pub struct FeedHandles {
+    tasks: Vec<JoinHandle<Result<(), ReconnectError>>>,
+    stop: watch::Sender<bool>,
+    /// First error a joined task returned, kept across cancelled `join`s.
+    failure: Option<ReconnectError>,
+}
+
+impl FeedHandles {
+    pub(crate) fn new(tasks: Vec<JoinHandle<Result<(), ReconnectError>>>, stop: watch::Sender<bool>) -> Self {
+        Self { tasks, stop, failure: None }
+    }
+
+    /// Tasks not yet joined, one per connection in the order the manager opened them;
+    /// each can be checked or aborted on its own.
+    pub fn tasks(&self) -> &[JoinHandle<Result<(), ReconnectError>>] {
+        &self.tasks
+    }
+
+    /// Whether every task has returned, stopped or not.
+    pub fn is_finished(&self) -> bool {
+        self.tasks.iter().all(|task| task.is_finished())
+    }
+
+    /// Signals every task to shut down, without waiting for it.
+    pub fn stop(&self) {
+        self.stop.send_replace(true);
+    }
+
+    /// Waits for every task to return. A task the reconnect policy gave up on stops
+    /// alone; its error is returned once the others have stopped too.
+    ///
+    /// Cancel safe: tasks joined before a cancelled call aren't waited for again, and
+    /// their error is still returned by the next one.
+    pub async fn join(&mut self) -> Result<(), ReconnectError> {
+        while let Some(task) = self.tasks.first_mut() {
+            let stopped = task.await;
+            self.tasks.remove(0);
+            match stopped {
+                Ok(Err(e)) => {
+                    error!("{}", e);
+                    self.failure.get_or_insert(e);
+                }
+                Err(e) => error!("Feed task failed: {}", e),
+                Ok(Ok(())) => {}
+            }
+        }
+        match self.failure.take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// `stop`, then `join`.
+    pub async fn shutdown(mut self) -> Result<(), ReconnectError> {
+        self.stop();
+        self.join().await
+    }
+}
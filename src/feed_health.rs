@@ -0,0 +1,207 @@
+use crate::clock::{Clock, SystemClock};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/// Which of the two feeds a health update is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feed {
+    Depth,
+    Trade,
+}
+
+/// Times each feed dropped and had to reconnect.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ReconnectCounts {
+    pub depth: u64,
+    pub trade: u64,
+}
+
+/// Liveness of the depth and trade feeds, as last published by their managers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FeedHealth {
+    /// When the depth feed last received a message (epoch ms).
+    pub last_depth_msg_ms: Option<u64>,
+    pub last_trade_msg_ms: Option<u64>,
+    pub depth_connected: bool,
+    pub trade_connected: bool,
+    pub reconnect_counts: ReconnectCounts,
+    /// Whether the current trade connection skipped over trade ids, i.e. dropped trades.
+    /// A reconnect clears it, as the new connection's first trade sets a new baseline.
+    pub trade_feed_gap_detected: bool,
+    /// Smoothed time from the exchange stamping a trade to its arrival here, in whole ms;
+    /// see `IngestLag`. `None` before the first trade.
+    pub trade_ingest_lag_ms: Option<i64>,
+}
+
+impl FeedHealth {
+    /// Milliseconds from `feed`'s last message to `now_ms`, or `None` before its first.
+    pub fn message_age_ms(&self, feed: Feed, now_ms: u64) -> Option<u64> {
+        let last = match feed {
+            Feed::Depth => self.last_depth_msg_ms,
+            Feed::Trade => self.last_trade_msg_ms,
+        };
+        last.map(|last| now_ms.saturating_sub(last))
+    }
+
+    /// Whether `feed` is connected and has received a message within `stale_after`.
+    pub fn is_healthy(&self, feed: Feed, now_ms: u64, stale_after: Duration) -> bool {
+        let connected = match feed {
+            Feed::Depth => self.depth_connected,
+            Feed::Trade => self.trade_connected,
+        };
+        connected && matches!(self.message_age_ms(feed, now_ms), Some(age) if age <= stale_after.as_millis() as u64)
+    }
+}
+
+/// Publishes `FeedHealth` to any number of watchers, with each feed manager keeping its
+/// own half up to date. Clones share the health.
+#[derive(Debug, Clone)]
+pub struct HealthPublisher {
+    tx: Arc<watch::Sender<FeedHealth>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl HealthPublisher {
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        let (tx, _) = watch::channel(FeedHealth::default());
+        Self { tx: Arc::new(tx), clock }
+    }
+
+    /// Receiver that sees the health as it changes, e.g. for `AnalyticsConfig::feed_health`.
+    pub fn subscribe(&self) -> watch::Receiver<FeedHealth> {
+        self.tx.subscribe()
+    }
+
+    pub fn health(&self) -> FeedHealth {
+        self.tx.borrow().clone()
+    }
+
+    /// Notes a message on `feed` just now. Only publishes when the millisecond changed,
+    /// so a burst of messages costs one update.
+    pub fn message(&self, feed: Feed) {
+        let now_ms = Some(self.clock.now_ms());
+        self.tx.send_if_modified(|health| {
+            let last = match feed {
+                Feed::Depth => &mut health.last_depth_msg_ms,
+                Feed::Trade => &mut health.last_trade_msg_ms,
+            };
+            let changed = *last != now_ms;
+            *last = now_ms;
+            changed
+        });
+    }
+
+    /// Notes that `feed` connected; `reconnect` says the connection replaces a dropped one.
+    pub fn connected(&self, feed: Feed, reconnect: bool) {
+        self.tx.send_modify(|health| match feed {
+            Feed::Depth => {
+                health.depth_connected = true;
+                health.reconnect_counts.depth += u64::from(reconnect);
+            }
+            Feed::Trade => {
+                health.trade_connected = true;
+                health.trade_feed_gap_detected = false;
+                health.reconnect_counts.trade += u64::from(reconnect);
+            }
+        });
+    }
+
+    /// Notes the trade feed's smoothed ingest lag; only publishes when the whole
+    /// millisecond changed.
+    pub fn trade_ingest_lag(&self, lag_ms: Option<f64>) {
+        let lag_ms = lag_ms.map(|lag| lag.round() as i64);
+        self.tx.send_if_modified(|health| std::mem::replace(&mut health.trade_ingest_lag_ms, lag_ms) != lag_ms);
+    }
+
+    /// Notes that the trade feed skipped over trade ids on its current connection.
+    pub fn trade_gap(&self) {
+        self.tx.send_if_modified(|health| !std::mem::replace(&mut health.trade_feed_gap_detected, true));
+    }
+
+    pub fn disconnected(&self, feed: Feed) {
+        self.tx.send_modify(|health| match feed {
+            Feed::Depth => health.depth_connected = false,
+            Feed::Trade => health.trade_connected = false,
+        });
+    }
+}
+
+impl Default for HealthPublisher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_each_feed_updates_its_own_half() {
+        let clock = ManualClock::new(1_000);
+        let publisher = HealthPublisher::with_clock(Arc::new(clock.clone()));
+        let mut rx = publisher.subscribe();
+
+        publisher.connected(Feed::Depth, false);
+        publisher.message(Feed::Depth);
+        assert!(rx.has_changed().unwrap());
+        let health = rx.borrow_and_update().clone();
+        assert_eq!(health.last_depth_msg_ms, Some(1_000));
+        assert!(health.depth_connected && !health.trade_connected);
+        assert_eq!(health.last_trade_msg_ms, None);
+
+        // Another message in the same millisecond changes nothing
+        publisher.message(Feed::Depth);
+        assert!(!rx.has_changed().unwrap());
+
+        publisher.disconnected(Feed::Depth);
+        publisher.connected(Feed::Depth, true);
+        publisher.connected(Feed::Trade, false);
+        assert_eq!(publisher.health().reconnect_counts, ReconnectCounts { depth: 1, trade: 0 });
+        // Clones share the health
+        assert!(publisher.clone().health().trade_connected);
+
+        // A gap stays flagged until the trade feed reconnects
+        rx.borrow_and_update();
+        publisher.trade_gap();
+        assert!(rx.has_changed().unwrap() && rx.borrow_and_update().trade_feed_gap_detected);
+        publisher.trade_gap();
+        assert!(!rx.has_changed().unwrap());
+        publisher.connected(Feed::Trade, true);
+        assert!(!publisher.health().trade_feed_gap_detected);
+
+        rx.borrow_and_update();
+        publisher.trade_ingest_lag(Some(41.6));
+        assert_eq!(rx.borrow_and_update().trade_ingest_lag_ms, Some(42));
+        publisher.trade_ingest_lag(Some(42.2));
+        assert!(!rx.has_changed().unwrap());
+    }
+
+    #[test]
+    fn test_a_feed_is_healthy_while_connected_and_not_quiet() {
+        let stale_after = Duration::from_secs(5);
+        let health = FeedHealth {
+            last_depth_msg_ms: Some(10_000),
+            last_trade_msg_ms: Some(10_000),
+            depth_connected: true,
+            trade_connected: false,
+            ..FeedHealth::default()
+        };
+        assert!(health.is_healthy(Feed::Depth, 15_000, stale_after));
+        assert!(!health.is_healthy(Feed::Depth, 15_001, stale_after));
+        assert!(!health.is_healthy(Feed::Trade, 10_000, stale_after));
+        assert_eq!(health.message_age_ms(Feed::Depth, 12_500), Some(2_500));
+
+        // Connected but nothing received yet
+        let silent = FeedHealth { depth_connected: true, ..FeedHealth::default() };
+        assert!(!silent.is_healthy(Feed::Depth, 10_000, stale_after));
+        assert_eq!(silent.message_age_ms(Feed::Depth, 10_000), None);
+    }
+}
@@ -0,0 +1,103 @@
+use tokio::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy)]
+pub struct KeepAliveConfig {
+    /// Silence after which we ping the server ourselves.
+    pub idle_interval: Duration,
+    /// How long to wait for anything (including the pong) after our ping.
+    pub pong_timeout: Duration,
+}
+
+impl Default for KeepAliveConfig {
+    fn default() -> Self {
+        Self {
+            idle_interval: Duration::from_secs(30),
+            pong_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeartbeatAction {
+    SendPing,
+    Reconnect,
+}
+
+/// Tracks connection liveness and decides when to ping or give up.
+#[derive(Debug, Clone)]
+pub struct Heartbeat {
+    config: KeepAliveConfig,
+    last_received: Instant,
+    ping_sent_at: Option<Instant>,
+}
+
+impl Heartbeat {
+    pub fn new(config: KeepAliveConfig, now: Instant) -> Self {
+        Self {
+            config,
+            last_received: now,
+            ping_sent_at: None,
+        }
+    }
+
+    /// Any inbound frame proves the connection is alive.
+    pub fn on_message(&mut self, now: Instant) {
+        self.last_received = now;
+        self.ping_sent_at = None;
+    }
+
+    /// When the read loop should wake up if nothing arrives.
+    pub fn deadline(&self) -> Instant {
+        match self.ping_sent_at {
+            Some(sent) => sent + self.config.pong_timeout,
+            None => self.last_received + self.config.idle_interval,
+        }
+    }
+
+    /// Called once the deadline passes without traffic.
+    pub fn on_deadline(&mut self, now: Instant) -> HeartbeatAction {
+        if self.ping_sent_at.is_some() {
+            HeartbeatAction::Reconnect
+        } else {
+            self.ping_sent_at = Some(now);
+            HeartbeatAction::SendPing
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> KeepAliveConfig {
+        KeepAliveConfig {
+            idle_interval: Duration::from_secs(5),
+            pong_timeout: Duration::from_secs(2),
+        }
+    }
+
+    #[test]
+    fn test_idle_then_ping_then_reconnect() {
+        let start = Instant::now();
+        let mut heartbeat = Heartbeat::new(config(), start);
+        assert_eq!(heartbeat.deadline(), start + Duration::from_secs(5));
+
+        let idle = start + Duration::from_secs(5);
+        assert_eq!(heartbeat.on_deadline(idle), HeartbeatAction::SendPing);
+        assert_eq!(heartbeat.deadline(), idle + Duration::from_secs(2));
+
+        assert_eq!(heartbeat.on_deadline(idle + Duration::from_secs(2)), HeartbeatAction::Reconnect);
+    }
+
+    #[test]
+    fn test_message_after_ping_resets() {
+        let start = Instant::now();
+        let mut heartbeat = Heartbeat::new(config(), start);
+        heartbeat.on_deadline(start + Duration::from_secs(5));
+
+        let pong = start + Duration::from_secs(6);
+        heartbeat.on_message(pong);
+        assert_eq!(heartbeat.deadline(), pong + Duration::from_secs(5));
+        assert_eq!(heartbeat.on_deadline(pong + Duration::from_secs(5)), HeartbeatAction::SendPing);
+    }
+}
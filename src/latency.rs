@@ -0,0 +1,326 @@
+use crate::clock::{Clock, SystemClock};
+use log::{debug, warn};
+use metrics::{Gauge, Histogram};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Weight of the newest reading in the smoothed lag.
+const LAG_ALPHA: f64 = 0.1;
+/// Latest samples a `ProcessLatency` keeps for its percentiles.
+const LATENCY_WINDOW: usize = 1024;
+/// How often a `ProcessLatency` logs its percentiles, at most.
+const LATENCY_LOG_INTERVAL: Duration = Duration::from_secs(60);
+/// How often an `IngestLag` warns about clamped readings, at most.
+const SKEW_WARN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How far a feed runs behind the exchange: local receive time minus the event time the
+/// exchange stamped on each message, smoothed as an EWMA and exported as a gauge.
+///
+/// Readings are reported as-is, not clamped. Clock skew between this host and the exchange
+/// shifts every reading by the same offset and makes the lag negative when the local clock
+/// runs behind, so changes in the lag are more telling than its level. Clones share the reading.
+#[derive(Clone)]
+pub struct FeedLag {
+    clock: Arc<dyn Clock>,
+    gauge: Gauge,
+    ewma_ms: Arc<Mutex<Option<f64>>>,
+}
+
+impl FeedLag {
+    /// Lag exported under the gauge `name`, measured against the system clock.
+    pub fn new(name: &'static str) -> Self {
+        Self::with_clock(name, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(name: &'static str, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            gauge: metrics::register_gauge!(name),
+            ewma_ms: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Folds in a message stamped `event_time_ms` (epoch ms) that arrived just now, and
+    /// returns its own lag.
+    pub fn record(&self, event_time_ms: u64) -> i64 {
+        let lag_ms = self.clock.now_ms() as i64 - event_time_ms as i64;
+        let mut ewma_ms = self.ewma_ms.lock().unwrap();
+        let smoothed = match *ewma_ms {
+            Some(ewma) => LAG_ALPHA * lag_ms as f64 + (1.0 - LAG_ALPHA) * ewma,
+            None => lag_ms as f64,
+        };
+        *ewma_ms = Some(smoothed);
+        self.gauge.set(smoothed);
+        lag_ms
+    }
+
+    /// Smoothed lag in ms, or `None` before the first stamped message.
+    pub fn lag_ms(&self) -> Option<f64> {
+        *self.ewma_ms.lock().unwrap()
+    }
+}
+
+impl fmt::Debug for FeedLag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FeedLag").field("lag_ms", &self.lag_ms()).finish()
+    }
+}
+
+/// How long trades take to reach the log: local arrival time minus the trade time the
+/// exchange stamped on each trade, smoothed as an EWMA and recorded into a histogram.
+///
+/// Unlike `FeedLag`, readings are clamped at a floor, 0ms unless set with `with_floor`:
+/// a reading below it can only come from clock skew, and is logged as such at most once
+/// every `SKEW_WARN_INTERVAL`. Clones share the reading.
+#[derive(Clone)]
+pub struct IngestLag {
+    clock: Arc<dyn Clock>,
+    histogram: Histogram,
+    floor_ms: i64,
+    state: Arc<Mutex<IngestLagState>>,
+}
+
+struct IngestLagState {
+    ewma_ms: Option<f64>,
+    /// When the last skew warning was logged (epoch ms).
+    last_skew_warning: Option<u64>,
+}
+
+impl IngestLag {
+    /// Lag recorded under the histogram `name`, measured against the system clock.
+    pub fn new(name: &'static str) -> Self {
+        Self::with_clock(name, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(name: &'static str, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            histogram: metrics::register_histogram!(name),
+            floor_ms: 0,
+            state: Arc::new(Mutex::new(IngestLagState { ewma_ms: None, last_skew_warning: None })),
+        }
+    }
+
+    /// Clamps readings at `floor_ms` instead of 0, e.g. a negative floor to keep some of
+    /// the skew visible.
+    pub fn with_floor(mut self, floor_ms: i64) -> Self {
+        self.floor_ms = floor_ms;
+        self
+    }
+
+    pub fn floor_ms(&self) -> i64 {
+        self.floor_ms
+    }
+
+    /// Folds in a trade stamped `trade_time_ms` (epoch ms) that arrived just now, and
+    /// returns its own lag after clamping.
+    pub fn record(&self, trade_time_ms: u64) -> i64 {
+        let now_ms = self.clock.now_ms();
+        let raw_ms = now_ms as i64 - trade_time_ms as i64;
+        let lag_ms = raw_ms.max(self.floor_ms);
+        self.histogram.record(lag_ms as f64);
+
+        let mut state = self.state.lock().unwrap();
+        state.ewma_ms = Some(match state.ewma_ms {
+            Some(ewma) => LAG_ALPHA * lag_ms as f64 + (1.0 - LAG_ALPHA) * ewma,
+            None => lag_ms as f64,
+        });
+        if raw_ms < self.floor_ms {
+            let due = match state.last_skew_warning {
+                Some(warned) => now_ms.saturating_sub(warned) >= SKEW_WARN_INTERVAL.as_millis() as u64,
+                None => true,
+            };
+            if due {
+                state.last_skew_warning = Some(now_ms);
+                warn!(
+                    "Trade lag of {}ms clamped to the {}ms floor; the local clock runs behind the exchange's",
+                    raw_ms, self.floor_ms
+                );
+            }
+        }
+        lag_ms
+    }
+
+    /// Smoothed lag in ms, or `None` before the first trade.
+    pub fn lag_ms(&self) -> Option<f64> {
+        self.state.lock().unwrap().ewma_ms
+    }
+
+    /// When the last skew warning was logged (epoch ms), if ever.
+    pub fn last_skew_warning(&self) -> Option<u64> {
+        self.state.lock().unwrap().last_skew_warning
+    }
+}
+
+impl fmt::Debug for IngestLag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IngestLag").field("lag_ms", &self.lag_ms()).field("floor_ms", &self.floor_ms).finish()
+    }
+}
+
+/// How long each run of one piece of work takes, such as handling a feed message, in µs,
+/// exported as a histogram.
+///
+/// The latest `LATENCY_WINDOW` samples are kept as well, and their p50 and p99 logged at
+/// debug level about once every `LATENCY_LOG_INTERVAL` while samples keep coming.
+/// Clones share the samples.
+#[derive(Clone)]
+pub struct ProcessLatency {
+    name: &'static str,
+    histogram: Histogram,
+    window: Arc<Mutex<LatencyWindow>>,
+}
+
+struct LatencyWindow {
+    samples: VecDeque<u64>,
+    last_logged: Instant,
+}
+
+impl ProcessLatency {
+    /// Latency exported under the histogram `name`.
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            histogram: metrics::register_histogram!(name),
+            window: Arc::new(Mutex::new(LatencyWindow {
+                samples: VecDeque::with_capacity(LATENCY_WINDOW),
+                last_logged: Instant::now(),
+            })),
+        }
+    }
+
+    /// Records one run that took `elapsed`.
+    pub fn record(&self, elapsed: Duration) {
+        let micros = elapsed.as_micros() as u64;
+        self.histogram.record(micros as f64);
+
+        let mut window = self.window.lock().unwrap();
+        if window.samples.len() == LATENCY_WINDOW {
+            window.samples.pop_front();
+        }
+        window.samples.push_back(micros);
+        if window.last_logged.elapsed() >= LATENCY_LOG_INTERVAL {
+            window.last_logged = Instant::now();
+            if let (Some(p50), Some(p99)) = (percentile(&window.samples, 0.5), percentile(&window.samples, 0.99)) {
+                debug!("{}: p50 {}us, p99 {}us over the last {} samples", self.name, p50, p99, window.samples.len());
+            }
+        }
+    }
+
+    /// Latency in µs that a `q` share of the kept samples don't exceed, `q` in [0, 1];
+    /// `None` before the first sample.
+    pub fn percentile(&self, q: f64) -> Option<u64> {
+        percentile(&self.window.lock().unwrap().samples, q)
+    }
+}
+
+impl fmt::Debug for ProcessLatency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProcessLatency")
+            .field("name", &self.name)
+            .field("p50_us", &self.percentile(0.5))
+            .field("p99_us", &self.percentile(0.99))
+            .finish()
+    }
+}
+
+/// Nearest-rank percentile of `samples`.
+fn percentile(samples: &VecDeque<u64>, q: f64) -> Option<u64> {
+    let mut sorted: Vec<u64> = samples.iter().copied().collect();
+    sorted.sort_unstable();
+    let rank = (q * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.clamp(1, sorted.len().max(1)) - 1).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use std::time::Duration;
+
+    #[test]
+    fn test_lag_is_smoothed_against_the_clock() {
+        let clock = ManualClock::new(1_000_000);
+        let lag = FeedLag::with_clock("test_feed_lag_ms", Arc::new(clock.clone()));
+        assert_eq!(lag.lag_ms(), None);
+
+        assert_eq!(lag.record(999_950), 50);
+        assert_eq!(lag.lag_ms(), Some(50.0));
+
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(lag.record(999_950), 150);
+        assert!((lag.lag_ms().unwrap() - 60.0).abs() < 1e-9);
+
+        // Clones share the reading
+        let shared = lag.clone();
+        assert_eq!(shared.lag_ms(), lag.lag_ms());
+    }
+
+    #[test]
+    fn test_process_latency_percentiles_cover_the_latest_samples() {
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+
+        let _ = DebuggingRecorder::per_thread().install();
+        let latency = ProcessLatency::new("test_process_latency_us");
+        assert_eq!(latency.percentile(0.5), None);
+
+        for micros in 1..=100 {
+            latency.record(Duration::from_micros(micros));
+        }
+        assert_eq!(latency.percentile(0.5), Some(50));
+        assert_eq!(latency.percentile(0.99), Some(99));
+        assert_eq!(latency.clone().percentile(1.0), Some(100));
+        assert_eq!(latency.percentile(0.0), Some(1));
+
+        // Older samples fall out of the window, but every one reached the histogram
+        for _ in 0..LATENCY_WINDOW {
+            latency.record(Duration::from_micros(7));
+        }
+        assert_eq!(latency.percentile(0.99), Some(7));
+        let recorded = Snapshotter::current_thread_snapshot()
+            .unwrap()
+            .into_vec()
+            .into_iter()
+            .find_map(|(key, _, _, value)| match value {
+                DebugValue::Histogram(samples) if key.key().name() == "test_process_latency_us" => Some(samples),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(recorded.len(), 100 + LATENCY_WINDOW);
+    }
+
+    #[test]
+    fn test_ingest_lag_clamps_skewed_readings_at_its_floor() {
+        let clock = ManualClock::new(1_000_000);
+        let lag = IngestLag::with_clock("test_trade_ingest_lag_ms", Arc::new(clock.clone()));
+        assert_eq!(lag.record(999_900), 100);
+        assert_eq!(lag.last_skew_warning(), None);
+
+        // Stamped after arriving: clamped, and warned about once a minute at most
+        assert_eq!(lag.record(1_000_050), 0);
+        assert!((lag.lag_ms().unwrap() - 90.0).abs() < 1e-9);
+        assert_eq!(lag.last_skew_warning(), Some(1_000_000));
+        clock.advance(Duration::from_secs(30));
+        lag.record(1_030_050);
+        assert_eq!(lag.last_skew_warning(), Some(1_000_000));
+        clock.advance(Duration::from_secs(30));
+        lag.record(1_060_050);
+        assert_eq!(lag.last_skew_warning(), Some(1_060_000));
+
+        let floored = IngestLag::with_clock("test_trade_ingest_lag_ms", Arc::new(clock)).with_floor(-20);
+        assert_eq!(floored.record(1_060_010), -10);
+        assert_eq!(floored.record(1_060_050), -20);
+        assert_eq!(floored.last_skew_warning(), Some(1_060_000));
+    }
+
+    #[test]
+    fn test_skewed_clock_reports_negative_lag() {
+        let clock = ManualClock::new(1_000_000);
+        let lag = FeedLag::with_clock("test_feed_lag_ms", Arc::new(clock));
+        // Stamped 20ms in our future: the exchange clock is ahead of ours
+        assert_eq!(lag.record(1_000_020), -20);
+        assert_eq!(lag.lag_ms(), Some(-20.0));
+    }
+}
@@ -1,4 +1,29 @@
 pub mod orderbook;
 pub mod tradeslog;
+pub mod lob_feed_manager;
+pub mod log_feed_manager;
 pub mod analytics;
-pub mod persistence;
\ No newline at end of file
+pub mod persistence;
+pub mod depth_sync;
+pub mod stream_config;
+pub mod clock;
+pub mod connector;
+pub mod heartbeat;
+pub mod subscription;
+pub mod exchange;
+pub mod transport;
+pub mod reconnect;
+pub mod tape;
+pub mod latency;
+pub mod proxy;
+pub mod quarantine;
+pub mod replay;
+pub mod snapshot_fetcher;
+pub mod feed_handles;
+pub mod tls;
+pub mod consolidated;
+pub mod book_audit;
+pub mod feed_health;
+pub mod trade_sink;
+pub mod session_stats;
+pub mod metrics_server;
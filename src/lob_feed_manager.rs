@@ -1,126 +1,2045 @@
-use crate::orderbook::ConcurrentOrderBook;
-use futures_util::StreamExt;
+use crate::book_audit::{self, AuditConfig};
+use crate::depth_sync::{DepthDiff, DepthSynchronizer, SyncOutcome, SyncState};
+use crate::exchange::{self, AdapterError, BestQuotes, BinanceAdapter, DepthEvent, ExchangeAdapter, FeedEvent};
+use crate::clock::{Clock, SystemClock};
+use crate::connector::{ConnectorFsm, ConnectorState};
+use crate::feed_handles::FeedHandles;
+use crate::feed_health::{Feed, HealthPublisher};
+use crate::heartbeat::KeepAliveConfig;
+use crate::latency::{FeedLag, ProcessLatency};
+use crate::reconnect::{Backoff, FailureEscalation, PersistentFailure, ReconnectError, ReconnectPolicy};
+use crate::orderbook::{ChecksumFormat, ConcurrentOrderBook, MidMoved};
+use crate::stream_config::{DepthMode, StreamConfig, BINANCE_WS_BASE};
+use crate::subscription::{SubscriptionError, SubscriptionHandle, SubscriptionRequest};
+use crate::proxy::ProxyConfig;
+use crate::quarantine::Quarantine;
+use crate::snapshot_fetcher::SnapshotFetcher;
+use crate::tape::{TapeRecorder, DEPTH_SNAPSHOT_STREAM, DEPTH_STREAM};
+use crate::tls::TlsConfig;
+use crate::transport::{FeedConnection, Frame, Transport, DEFAULT_MAX_MESSAGE_SIZE};
+use tokio_tungstenite::tungstenite::{http::HeaderMap, Error as WsError};
+use anyhow::{Context, Result};
 use log::{debug, error, info, warn};
+use thiserror::Error;
+use metrics::{Counter, Gauge};
 use rust_decimal::Decimal;
-use serde::Deserialize;
-use std::str::FromStr;
-use std::time::{Duration, Instant};
-use tokio::time::sleep;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, watch, Mutex};
+use tokio::time::{sleep_until, Instant};
 use tokio::task;
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
-
-#[derive(Debug, Deserialize)]
-pub struct BinanceDepthUpdate {
-    #[serde(rename = "b")]
-    pub bids: Vec<(String, String)>,
-    #[serde(rename = "a")]
-    pub asks: Vec<(String, String)>,
+
+pub use crate::exchange::binance::{
+    BinanceRestSnapshot, CombinedStreamMessage, DepthMessage, DepthUpdateEvent, PartialDepthSnapshot,
+};
+
+const DEFAULT_COALESCE_WINDOW: Duration = Duration::from_millis(5);
+const FEED_LAG_GAUGE: &str = "lob_feed_lag_ms";
+const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(30);
+/// Pacing of repeated resyncs of one book, e.g. while every REST snapshot comes back older
+/// than the diffs buffered behind it. The connection is dropped once it runs out.
+const DEFAULT_RESYNC_POLICY: ReconnectPolicy = ReconnectPolicy {
+    initial: Duration::from_millis(250),
+    max: Duration::from_secs(30),
+    multiplier: 2.0,
+    jitter_fraction: 0.2,
+    max_attempts: Some(8),
+    max_consecutive_failures: None,
+    stable_after: Duration::ZERO,
+};
+
+/// Owns one order book per symbol and routes combined-stream payloads to them.
+#[derive(Debug, Clone)]
+pub struct SymbolRouter {
+    books: HashMap<String, ConcurrentOrderBook>,
+}
+
+impl SymbolRouter {
+    pub fn new(symbols: Vec<String>) -> Self {
+        let books = symbols
+            .into_iter()
+            .map(|symbol| (symbol.to_lowercase(), ConcurrentOrderBook::new()))
+            .collect();
+        Self { books }
+    }
+
+    /// Routed symbols, lowercase and sorted.
+    pub fn symbols(&self) -> Vec<String> {
+        let mut symbols: Vec<String> = self.books.keys().cloned().collect();
+        symbols.sort();
+        symbols
+    }
+
+    pub fn get_order_book(&self, symbol: &str) -> Option<ConcurrentOrderBook> {
+        self.books.get(&symbol.to_lowercase()).cloned()
+    }
+
+    /// Builds `<base>/stream?streams=<sym>@<stream>/<sym>@<stream>...`.
+    pub fn combined_stream_url(&self, base_url: &str, stream: &str) -> String {
+        let streams: Vec<String> = self
+            .symbols()
+            .iter()
+            .map(|symbol| format!("{}@{}", symbol, stream))
+            .collect();
+        format!("{}/stream?streams={}", base_url, streams.join("/"))
+    }
+
+    /// Unwraps a combined-stream message into its symbol and payload.
+    pub fn route(&self, text: &str) -> Result<(String, DepthMessage), serde_json::Error> {
+        let message: CombinedStreamMessage = serde_json::from_str(text)?;
+        let symbol = message.stream.split('@').next().unwrap_or_default().to_lowercase();
+        Ok((symbol, message.data))
+    }
+}
+
+/// Failures the depth feed recovers from, by resyncing or reconnecting.
+#[derive(Debug, Error)]
+pub enum LobFeedError {
+    #[error("WebSocket error: {0}")]
+    Websocket(#[from] WsError),
+    #[error("Failed to parse depth message {text}: {source}")]
+    Parse { text: String, source: AdapterError },
+    #[error("Received depth message for unrouted symbol {0}")]
+    UnroutedSymbol(String),
+    #[error("Depth sequence gap: expected update {expected}, got {received}")]
+    SequenceGap { expected: u64, received: u64 },
+    #[error("Book checksum mismatch: exchange sent {expected}, local book has {actual}")]
+    ChecksumMismatch { expected: i32, actual: i32 },
+    #[error("Depth snapshot unusable: {reason:#}")]
+    Snapshot { reason: anyhow::Error },
+    #[error("No depth update applied for {0:?}")]
+    Stale(Duration),
+    #[error("Book diverged from the REST depth snapshot at {0} levels")]
+    Diverged(usize),
+    #[error("Gave up after {0} resyncs in a row without getting back in sync")]
+    ResyncsExhausted(u32),
+}
+
+#[derive(Clone)]
+pub struct LobFeedMetrics {
+    pub messages_received: Counter,
+    pub parse_failures: Counter,
+    pub unrouted_messages: Counter,
+    pub snapshots_applied: Counter,
+    /// Whole-book messages dropped for carrying an update id no newer than the book's.
+    pub stale_snapshots: Counter,
+    pub snapshot_failures: Counter,
+    /// Diffs and deltas written to a book, counted individually even when coalesced.
+    pub deltas_applied: Counter,
+    pub sequence_gaps: Counter,
+    /// Diffs dropped for arriving after later ones were already applied past them.
+    pub late_diffs: Counter,
+    pub checksum_mismatches: Counter,
+    pub resyncs: Counter,
+    /// Books resynced from a fresh snapshot on a connection that replaced a dropped one.
+    pub resyncs_after_reconnect: Counter,
+    /// Connections dropped for a book that kept needing resyncs without getting back in sync.
+    pub resyncs_exhausted: Counter,
+    /// Books marked untrusted by the stale feed watchdog.
+    pub stale_books: Counter,
+    /// Audits that found a book further from its REST snapshot than allowed.
+    pub book_divergence: Counter,
+    pub connection_errors: Counter,
+    pub current_connections: Gauge,
+    /// From receiving a depth message to having sequenced it; coalesced diffs reach the
+    /// book at the next flush, which isn't included.
+    pub process_latency: ProcessLatency,
+}
+
+impl LobFeedMetrics {
+    pub(crate) fn register() -> Self {
+        Self {
+            messages_received: metrics::register_counter!("lob_feed_messages_received"),
+            parse_failures: metrics::register_counter!("lob_feed_parse_failures"),
+            unrouted_messages: metrics::register_counter!("lob_feed_unrouted_messages"),
+            snapshots_applied: metrics::register_counter!("lob_feed_snapshots_applied"),
+            stale_snapshots: metrics::register_counter!("lob_feed_stale_snapshots"),
+            snapshot_failures: metrics::register_counter!("lob_feed_snapshot_failures"),
+            deltas_applied: metrics::register_counter!("lob_feed_deltas_applied"),
+            sequence_gaps: metrics::register_counter!("lob_feed_sequence_gaps"),
+            late_diffs: metrics::register_counter!("lob_feed_late_diffs"),
+            checksum_mismatches: metrics::register_counter!("lob_feed_checksum_mismatches"),
+            resyncs: metrics::register_counter!("lob_feed_resyncs"),
+            resyncs_after_reconnect: metrics::register_counter!("lob_feed_resyncs_after_reconnect"),
+            resyncs_exhausted: metrics::register_counter!("lob_feed_resyncs_exhausted"),
+            stale_books: metrics::register_counter!("lob_feed_stale_books"),
+            book_divergence: metrics::register_counter!("lob_feed_book_divergence"),
+            connection_errors: metrics::register_counter!("lob_feed_connection_errors"),
+            current_connections: metrics::register_gauge!("lob_feed_current_connections"),
+            process_latency: ProcessLatency::new("lob_process_latency_us"),
+        }
+    }
+
+    /// Counts a failure under the counter for its kind.
+    pub fn record(&self, error: &LobFeedError) {
+        let counter = match error {
+            LobFeedError::Websocket(_) => &self.connection_errors,
+            LobFeedError::Parse { .. } => &self.parse_failures,
+            LobFeedError::UnroutedSymbol(_) => &self.unrouted_messages,
+            LobFeedError::SequenceGap { .. } => &self.sequence_gaps,
+            LobFeedError::ChecksumMismatch { .. } => &self.checksum_mismatches,
+            LobFeedError::Snapshot { .. } => &self.snapshot_failures,
+            LobFeedError::Stale(_) => &self.stale_books,
+            LobFeedError::Diverged(_) => &self.book_divergence,
+            LobFeedError::ResyncsExhausted(_) => &self.resyncs_exhausted,
+        };
+        counter.increment(1);
+    }
+}
+
+/// Per-symbol sequencing state for one connection.
+pub(crate) struct SymbolSync {
+    book: ConcurrentOrderBook,
+    sync: DepthSynchronizer,
+    snapshot_uri: Option<String>,
+    pending: Vec<DepthDiff>,
+    /// Checksum the exchange reported for the book once `pending` is applied.
+    checksum: Option<i32>,
+    checksum_format: Option<ChecksumFormat>,
+    book_depth: Option<usize>,
+    whole_books: bool,
+    /// Paces resyncs that follow one another without the book getting back in sync.
+    resyncs: Backoff,
+    metrics: LobFeedMetrics,
+}
+
+impl SymbolSync {
+    pub(crate) fn new(
+        book: ConcurrentOrderBook,
+        adapter: &dyn ExchangeAdapter,
+        snapshot_uri: Option<String>,
+        metrics: LobFeedMetrics,
+    ) -> Self {
+        Self {
+            book,
+            sync: DepthSynchronizer::new(),
+            snapshot_uri,
+            pending: Vec::new(),
+            checksum: None,
+            checksum_format: adapter.checksum_format(),
+            book_depth: adapter.book_depth(),
+            whole_books: adapter.streams_whole_books(),
+            resyncs: Backoff::new(DEFAULT_RESYNC_POLICY),
+            metrics,
+        }
+    }
+
+    pub(crate) fn with_resync_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.resyncs = Backoff::new(policy);
+        self
+    }
+
+    /// Holds out-of-order diffs for up to `window`; see `DepthSynchronizer::with_reorder_window`.
+    pub(crate) fn with_reorder_window(mut self, window: Duration) -> Self {
+        self.sync = self.sync.with_reorder_window(window, Arc::new(SystemClock));
+        self
+    }
+
+    /// Sequences one parsed depth event. Snapshots are applied straight away; diffs
+    /// come back in the outcome, with the checksum to verify once they are applied.
+    pub(crate) async fn on_event(&mut self, event: DepthEvent) -> (SyncOutcome, Option<i32>) {
+        match event {
+            DepthEvent::Diff { diff, checksum, .. } => {
+                debug!("Parsed depth update {}..{}", diff.first_update_id, diff.final_update_id);
+                let outcome = self.sync.on_diff(diff);
+                if outcome == SyncOutcome::Late {
+                    self.metrics.late_diffs.increment(1);
+                }
+                (outcome, checksum)
+            }
+            DepthEvent::Delta { bids, asks, checksum, .. } => {
+                let outcome = match self.sync.state() {
+                    // Unsequenced: valid only on top of the snapshot from this connection
+                    SyncState::Live { last_update_id } => SyncOutcome::Apply(vec![DepthDiff {
+                        first_update_id: last_update_id,
+                        final_update_id: last_update_id,
+                        bids,
+                        asks,
+                    }]),
+                    SyncState::AwaitingSnapshot => SyncOutcome::Discarded,
+                };
+                (outcome, checksum)
+            }
+            DepthEvent::Snapshot { last_update_id, bids, asks, checksum, .. } => {
+                debug!("Parsed partial depth snapshot at update {}", last_update_id);
+                if let (true, SyncState::Live { last_update_id: current }) = (self.whole_books, self.sync.state()) {
+                    if last_update_id <= current {
+                        self.metrics.stale_snapshots.increment(1);
+                        debug!("Dropping stale depth snapshot {} behind {}", last_update_id, current);
+                        return (SyncOutcome::Discarded, None);
+                    }
+                }
+                (self.apply_snapshot(last_update_id, bids, asks, checksum).await, None)
+            }
+        }
+    }
+
+    /// Applies an outcome of `on_event` without coalescing. Returns `false` once the book
+    /// has been dropped and is waiting for the next snapshot.
+    pub(crate) async fn apply_now(&mut self, outcome: SyncOutcome, checksum: Option<i32>) -> bool {
+        match outcome {
+            SyncOutcome::Apply(diffs) => {
+                if !diffs.is_empty() {
+                    self.pending.extend(diffs);
+                    self.checksum = checksum;
+                    self.flush().await;
+                }
+                match self.verify_checksum().await {
+                    Ok(()) => return true,
+                    Err(e) => self.report(&e),
+                }
+                // Diffs sequenced after a bad book can't repair it
+                self.sync.reset();
+            }
+            SyncOutcome::Gap { expected, received } => {
+                self.report(&LobFeedError::SequenceGap { expected, received });
+            }
+            SyncOutcome::Buffered | SyncOutcome::Discarded | SyncOutcome::Late => return true,
+        }
+        self.metrics.resyncs.increment(1);
+        self.clear().await;
+        false
+    }
+
+    fn report(&self, error: &LobFeedError) {
+        self.metrics.record(error);
+        warn!("{}", error);
+    }
+
+    /// Drops the book along with anything queued for it.
+    async fn clear(&mut self) {
+        self.pending.clear();
+        self.checksum = None;
+        self.book.clear().await;
+    }
+
+    /// Applies coalesced diffs under one write lock.
+    async fn flush(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let batch: Vec<_> = self.pending.drain(..).map(|diff| (diff.bids, diff.asks)).collect();
+        self.metrics.deltas_applied.increment(batch.len() as u64);
+        self.book.apply_delta_batch(batch).await;
+        if let Some(depth) = self.book_depth {
+            self.book.truncate(depth).await;
+        }
+    }
+
+    /// Replaces the book with a snapshot and releases buffered diffs that follow it.
+    async fn apply_snapshot(
+        &mut self,
+        last_update_id: u64,
+        bids: Vec<(Decimal, Decimal)>,
+        asks: Vec<(Decimal, Decimal)>,
+        checksum: Option<i32>,
+    ) -> SyncOutcome {
+        self.flush().await;
+        self.book.apply_snapshot(bids, asks).await;
+        self.metrics.snapshots_applied.increment(1);
+        let outcome = self.sync.on_snapshot(last_update_id);
+        // The checksum covers the snapshot alone, not diffs released behind it
+        self.checksum = match &outcome {
+            SyncOutcome::Apply(diffs) if diffs.is_empty() => checksum,
+            _ => None,
+        };
+        outcome
+    }
+
+    /// Compares the book against the last reported checksum, if there is one to check.
+    async fn verify_checksum(&mut self) -> Result<(), LobFeedError> {
+        let (expected, format) = match (self.checksum.take(), self.checksum_format) {
+            (Some(expected), Some(format)) => (expected, format),
+            _ => return Ok(()),
+        };
+        let actual = self.book.checksum(format).await;
+        if actual != expected {
+            return Err(LobFeedError::ChecksumMismatch { expected, actual });
+        }
+        Ok(())
+    }
+}
+
+type SnapshotResult = (String, Result<DepthEvent>);
+
+/// Everything a connection task takes from its manager.
+#[derive(Clone)]
+struct FeedContext {
+    router: SymbolRouter,
+    adapter: Arc<dyn ExchangeAdapter>,
+    keepalive: KeepAliveConfig,
+    reconnect: ReconnectPolicy,
+    escalation: Option<FailureEscalation>,
+    coalesce_window: Duration,
+    stale_after: Duration,
+    reorder_window: Option<Duration>,
+    resync_policy: ReconnectPolicy,
+    metrics: LobFeedMetrics,
+    lag: FeedLag,
+    health: HealthPublisher,
+    tape: Option<TapeRecorder>,
+    proxy: Option<ProxyConfig>,
+    tls: Option<TlsConfig>,
+    headers: HeaderMap,
+    max_message_size: usize,
+    quarantine: Option<Quarantine>,
+    snapshots: SnapshotFetcher,
+    audit: Option<AuditConfig>,
 }
 
 pub struct LobFeedManager {
-    order_book: ConcurrentOrderBook,
-    hf_uri: String,
-    lf_uri: String,
+    router: SymbolRouter,
+    /// One entry per connection: the equivalent endpoints it rotates through, preferred first.
+    endpoints: Vec<Vec<String>>,
+    adapter: Arc<dyn ExchangeAdapter>,
+    keepalive: KeepAliveConfig,
+    reconnect: ReconnectPolicy,
+    escalation: Option<FailureEscalation>,
+    coalesce_window: Duration,
+    stale_after: Duration,
+    reorder_window: Option<Duration>,
+    resync_policy: ReconnectPolicy,
+    metrics: LobFeedMetrics,
+    lag: FeedLag,
+    health: HealthPublisher,
+    tape: Option<TapeRecorder>,
+    proxy: Option<ProxyConfig>,
+    tls: Option<TlsConfig>,
+    headers: HeaderMap,
+    max_message_size: usize,
+    quarantine: Option<Quarantine>,
+    snapshots: SnapshotFetcher,
+    audit: Option<AuditConfig>,
+    /// State of the first depth connection.
+    connector: ConnectorFsm,
+    book_ticker: bool,
+    subscriptions: SubscriptionHandle,
+    requests: Arc<Mutex<mpsc::UnboundedReceiver<SubscriptionRequest>>>,
 }
 
 impl LobFeedManager {
 
-    pub fn new(hf_uri: String, lf_uri: String) -> Self {
+    /// Binance's plain `@depth` stream is a diff stream too, so a second socket never
+    /// delivered snapshots; only `hf_uri` is connected, `lf_uri` is ignored.
+    #[deprecated(note = "use `with_depth_mode` or `from_config`; only one depth socket is opened")]
+    pub fn new(hf_uri: String, _lf_uri: String) -> Self {
+        let symbol = Self::stream_symbol(&hf_uri);
+        Self::build(SymbolRouter::new(vec![symbol]), vec![hf_uri], Arc::new(BinanceAdapter::new()))
+    }
+
+    /// Keeps `symbol`'s Binance book current over one socket in the given mode.
+    pub fn with_depth_mode(symbol: &str, mode: DepthMode) -> Self {
+        Self::from_config(&StreamConfig::new(symbol).with_depth_mode(mode))
+    }
+
+    pub fn from_config(config: &StreamConfig) -> Self {
+        let adapter = exchange::adapter_for(config);
+        let endpoints = config.endpoints_for(&adapter.depth_url(&config.symbol));
+        let mut manager = Self::with_adapter(adapter, &config.symbol).with_endpoints(endpoints);
+        manager.proxy = config.proxy.clone();
+        manager.tls = config.tls.clone();
+        manager.headers = config.headers.clone();
+        manager
+    }
+
+    /// Keeps `symbol`'s book in sync with whichever exchange the adapter speaks for.
+    pub fn with_adapter(adapter: Arc<dyn ExchangeAdapter>, symbol: &str) -> Self {
+        let uri = adapter.depth_url(symbol);
+        Self::build(SymbolRouter::new(vec![symbol.to_string()]), vec![uri], adapter)
+    }
+
+    /// Subscribes to the diff depth stream of several symbols over one combined-stream connection.
+    pub fn with_symbols(symbols: Vec<String>) -> Self {
+        let router = SymbolRouter::new(symbols);
+        let uri = router.combined_stream_url(BINANCE_WS_BASE, "depth@100ms");
+        Self::build(router, vec![uri], Arc::new(BinanceAdapter::new()))
+    }
+
+    fn build(router: SymbolRouter, uris: Vec<String>, adapter: Arc<dyn ExchangeAdapter>) -> Self {
+        let (subscriptions, requests) = SubscriptionHandle::channel();
         Self {
-            order_book: ConcurrentOrderBook::new(),
-            hf_uri,
-            lf_uri,
+            router,
+            endpoints: uris.into_iter().map(|uri| vec![uri]).collect(),
+            adapter,
+            keepalive: KeepAliveConfig::default(),
+            reconnect: ReconnectPolicy::default(),
+            escalation: None,
+            coalesce_window: DEFAULT_COALESCE_WINDOW,
+            stale_after: DEFAULT_STALE_AFTER,
+            reorder_window: None,
+            resync_policy: DEFAULT_RESYNC_POLICY,
+            metrics: LobFeedMetrics::register(),
+            lag: FeedLag::new(FEED_LAG_GAUGE),
+            health: HealthPublisher::new(),
+            tape: None,
+            proxy: None,
+            tls: None,
+            headers: HeaderMap::new(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            quarantine: None,
+            snapshots: SnapshotFetcher::new(),
+            audit: None,
+            connector: ConnectorFsm::new(),
+            book_ticker: false,
+            subscriptions,
+            requests: Arc::new(Mutex::new(requests)),
         }
     }
 
+    pub fn with_keepalive(mut self, keepalive: KeepAliveConfig) -> Self {
+        self.keepalive = keepalive;
+        self
+    }
+
+    /// Applies to each connection separately.
+    pub fn with_reconnect_policy(mut self, reconnect: ReconnectPolicy) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Stops each connection after `max` failed connects in a row instead of reconnecting
+    /// forever; see `ReconnectPolicy::max_consecutive_failures`. Set it after
+    /// `with_reconnect_policy`, which replaces the whole policy.
+    pub fn with_max_consecutive_failures(mut self, max: usize) -> Self {
+        self.reconnect.max_consecutive_failures = Some(max);
+        self
+    }
+
+    /// Sends a `PersistentFailure` to `tx` whenever a connection has failed `after`
+    /// connects in a row, and keeps reconnecting. Each connection counts on its own, and
+    /// only one that stays up past the policy's `stable_after` starts its count over.
+    pub fn on_persistent_failure(mut self, after: usize, tx: mpsc::UnboundedSender<PersistentFailure>) -> Self {
+        self.escalation = Some(FailureEscalation::new(after, tx));
+        self
+    }
+
+    /// Records every frame received on any connection under the `depth` stream, and
+    /// REST snapshot bodies under `depth_snapshot`.
+    pub fn with_tape(mut self, tape: TapeRecorder) -> Self {
+        self.tape = Some(tape.for_stream(DEPTH_STREAM));
+        self
+    }
+
+    /// Rotates the first connection through these endpoints, e.g. the same streams on
+    /// several hosts, instead of the one it was built with. Must not be empty.
+    pub fn with_endpoints(mut self, endpoints: Vec<String>) -> Self {
+        self.endpoints[0] = endpoints;
+        self
+    }
+
+    /// Tunnels every connection, book ticker streams included, through `proxy`. REST
+    /// snapshots are fetched by reqwest, which follows `HTTPS_PROXY` instead.
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Runs the TLS handshake of every `wss://` connection with `tls`. REST snapshots
+    /// keep reqwest's defaults.
+    pub fn with_tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Sends `headers` with the handshake of every connection, book ticker streams included.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Drops frames larger than `bytes` on every connection instead of parsing them.
+    pub fn with_max_message_size(mut self, bytes: usize) -> Self {
+        self.max_message_size = bytes;
+        self
+    }
+
+    /// Keeps oversized frames and depth payloads that fail to parse under the `depth` stream.
+    pub fn with_quarantine(mut self, quarantine: Quarantine) -> Self {
+        self.quarantine = Some(quarantine.for_stream(DEPTH_STREAM));
+        self
+    }
+
+    /// Fetches REST depth snapshots through `snapshots`, e.g. one with other rate limits
+    /// or shared with another manager on the same IP.
+    pub fn with_snapshot_fetcher(mut self, snapshots: SnapshotFetcher) -> Self {
+        self.snapshots = snapshots;
+        self
+    }
+
+    /// Every `audit.interval`, fetches a REST depth snapshot of each synced book and
+    /// compares its top levels with the live ones, logging what differs and counting
+    /// audits that diverge under `book_divergence`. Only books synced from REST
+    /// snapshots are audited; whole-book streams replace themselves anyway.
+    pub fn with_audit(mut self, audit: AuditConfig) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Diffs arriving within this window are applied to the book as one batch.
+    pub fn with_coalesce_window(mut self, coalesce_window: Duration) -> Self {
+        self.coalesce_window = coalesce_window;
+        self
+    }
+
+    /// When a connection goes this long without applying a depth update, its books are
+    /// marked untrusted and resynced; they are trusted again once a snapshot lands.
+    pub fn with_stale_after(mut self, stale_after: Duration) -> Self {
+        self.stale_after = stale_after;
+        self
+    }
+
+    /// Holds diffs that arrive ahead of their turn for up to `window`, waiting for the
+    /// ones before them, instead of resyncing on the first out-of-order one. Off by
+    /// default, since each exchange stream arrives in order over one connection.
+    pub fn with_reorder_window(mut self, window: Duration) -> Self {
+        self.reorder_window = Some(window);
+        self
+    }
+
+    /// Paces resyncs of a book that keeps failing to get back in sync, dropping the
+    /// connection once `max_attempts` of them have gone by. Only REST snapshot fetches are
+    /// delayed; resubscribes go out straight away, but count towards the limit.
+    pub fn with_resync_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.resync_policy = policy;
+        self
+    }
+
+    /// Measures feed lag against `clock` instead of the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.lag = FeedLag::with_clock(FEED_LAG_GAUGE, clock);
+        self
+    }
+
+    /// Publishes this feed's health through `health`; hand both managers the same one
+    /// so a single receiver follows both feeds.
+    pub fn with_health(mut self, health: HealthPublisher) -> Self {
+        self.health = health;
+        self
+    }
+
+    /// Also follows each symbol's best bid/offer stream, where the exchange has one, and
+    /// writes its quotes over the touch between depth updates.
+    pub fn with_book_ticker(mut self, enabled: bool) -> Self {
+        self.book_ticker = enabled;
+        self
+    }
+
+    /// Extracts the symbol from a stream URI such as `.../ws/btcusdt@depth`.
+    fn stream_symbol(stream_uri: &str) -> String {
+        let stream = stream_uri.rsplit('/').next().unwrap_or_default();
+        stream.split('@').next().unwrap_or_default().to_lowercase()
+    }
+
+    /// Order book of the first (or only) symbol.
     pub fn get_order_book(&self) -> ConcurrentOrderBook {
-        self.order_book.clone()
+        let symbol = &self.router.symbols()[0];
+        self.router.get_order_book(symbol).unwrap()
     }
 
-    pub async fn start(&self) {
-        let hf_book = self.order_book.clone();
-        let lf_book = self.order_book.clone();
+    pub fn router(&self) -> &SymbolRouter {
+        &self.router
+    }
 
-        let hf_uri = self.hf_uri.clone();
-        let lf_uri = self.lf_uri.clone();
+    /// Mid moves of `symbol`'s book at that symbol's `tick_size`; see `OrderBook::mid_moves`.
+    /// `None` if the symbol isn't routed here.
+    pub async fn mid_moves(&self, symbol: &str, tick_size: Decimal) -> Option<broadcast::Receiver<MidMoved>> {
+        Some(self.router.get_order_book(symbol)?.mid_moves(tick_size).await)
+    }
 
-        let hf_task = task::spawn(Self::run_feed(hf_uri, hf_book, true));
-        let lf_task = task::spawn(Self::run_feed(lf_uri, lf_book, false));
+    /// Whether the (first) depth connection is up right now.
+    pub fn connection_state(&self) -> ConnectorState {
+        self.connector.state()
+    }
+
+    /// Follows the (first) depth connection's state, e.g. to flag data gathered while it was down.
+    pub fn watch_connection_state(&self) -> watch::Receiver<ConnectorState> {
+        self.connector.subscribe()
+    }
+
+    /// How far depth messages arrive behind their exchange event time, across all connections.
+    pub fn feed_lag(&self) -> FeedLag {
+        self.lag.clone()
+    }
+
+    /// Where the depth half of the feed health is published; book ticker streams don't count.
+    pub fn health(&self) -> HealthPublisher {
+        self.health.clone()
+    }
+
+    /// Adds streams to the first connection, waiting for the exchange to acknowledge them.
+    ///
+    /// Depth updates are only applied for symbols the router already owns a book for.
+    pub async fn subscribe(&self, streams: Vec<String>) -> Result<(), SubscriptionError> {
+        self.subscriptions.subscribe(streams).await
+    }
+
+    pub async fn unsubscribe(&self, streams: Vec<String>) -> Result<(), SubscriptionError> {
+        self.subscriptions.unsubscribe(streams).await
+    }
+
+    /// Handle for changing streams once the manager has been moved into its task.
+    pub fn subscription_handle(&self) -> SubscriptionHandle {
+        self.subscriptions.clone()
+    }
+
+    /// Runs one feed per connection until `shutdown_rx` fires.
+    ///
+    /// A connection the reconnect policy gives up on stops alone; its error is returned
+    /// once the others have stopped too.
+    pub async fn start(&self, mut shutdown_rx: watch::Receiver<bool>) -> Result<(), ReconnectError> {
+        let mut feeds = self.spawn();
+        tokio::select! {
+            stopped = feeds.join() => return stopped,
+            _ = shutdown_rx.changed() => {}
+        }
+        feeds.shutdown().await
+    }
 
-        let _ = tokio::join!(hf_task, lf_task);
+    /// Spawns one feed task per connection, plus the book ticker ones, and hands them
+    /// back to be stopped and joined. The manager can be spawned again once they have.
+    pub fn spawn(&self) -> FeedHandles {
+        let (stop, shutdown_rx) = watch::channel(false);
+        let context = FeedContext {
+            router: self.router.clone(),
+            adapter: self.adapter.clone(),
+            keepalive: self.keepalive,
+            reconnect: self.reconnect,
+            escalation: self.escalation.clone(),
+            coalesce_window: self.coalesce_window,
+            stale_after: self.stale_after,
+            reorder_window: self.reorder_window,
+            resync_policy: self.resync_policy,
+            metrics: self.metrics.clone(),
+            lag: self.lag.clone(),
+            health: self.health.clone(),
+            tape: self.tape.clone(),
+            proxy: self.proxy.clone(),
+            tls: self.tls.clone(),
+            headers: self.headers.clone(),
+            max_message_size: self.max_message_size,
+            quarantine: self.quarantine.clone(),
+            snapshots: self.snapshots.clone(),
+            audit: self.audit.clone(),
+        };
+        let mut tasks: Vec<_> = self
+            .endpoints
+            .iter()
+            .enumerate()
+            .map(|(i, endpoints)| {
+                // Only the first connection takes subscription requests and reports its state
+                let requests = (i == 0).then(|| self.requests.clone());
+                let mut transport = Self::transport(endpoints.clone(), &context);
+                if i == 0 {
+                    transport = transport.with_connector(self.connector.clone());
+                }
+                task::spawn(Self::run_feed(transport, context.clone(), requests, shutdown_rx.clone()))
+            })
+            .collect();
+        if self.book_ticker {
+            for symbol in self.router.symbols() {
+                let uri = match self.adapter.book_ticker_url(&symbol) {
+                    Some(uri) => uri,
+                    None => {
+                        warn!("{} has no book ticker stream for {}", self.adapter.name(), symbol);
+                        continue;
+                    }
+                };
+                let book = self.router.get_order_book(&symbol).unwrap();
+                tasks.push(task::spawn(Self::run_book_ticker(uri, book, context.clone(), shutdown_rx.clone())));
+            }
+        }
+        FeedHandles::new(tasks, stop)
     }
 
-    async fn run_feed(uri: String, order_book: ConcurrentOrderBook, _is_delta: bool) {
-        let mut retry_delay = Duration::from_secs(1);
-    
+    async fn run_feed(
+        mut transport: Transport,
+        context: FeedContext,
+        requests: Option<Arc<Mutex<mpsc::UnboundedReceiver<SubscriptionRequest>>>>,
+        mut shutdown_rx: watch::Receiver<bool>,
+    ) -> Result<(), ReconnectError> {
+        let metrics = &context.metrics;
+        if let Some(tape) = &context.tape {
+            transport = transport.with_tape(tape.clone());
+        }
+        let mut requests = match &requests {
+            Some(requests) => Some(requests.lock().await),
+            None => None,
+        };
+        let mut reconnected = false;
+
         loop {
-            match connect_async(&uri).await {
-                Ok((ws_stream, _)) => {
+            let connected = tokio::select! {
+                connected = transport.connect(requests.as_deref_mut()) => connected,
+                _ = shutdown_rx.changed() => break,
+            };
+            match connected {
+                Ok(mut conn) => {
+                    let uri = transport.uri();
+                    metrics.current_connections.increment(1.0);
+                    context.health.connected(Feed::Depth, reconnected);
                     info!("Connected to WebSocket at {}", uri);
-                    let (_, mut read) = ws_stream.split();
-    
-                    while let Some(msg) = read.next().await {
-                        match msg {
-                            Ok(Message::Text(text)) => {
-                                if let Ok(parsed) = serde_json::from_str::<BinanceDepthUpdate>(&text) {
-                                    debug!("Parsed Binance depth update (text)");
-                                    let parsed_bids = Self::parse_levels(parsed.bids);
-                                    let parsed_asks = Self::parse_levels(parsed.asks);
-                                    order_book.apply_deltas(parsed_bids, parsed_asks).await;
-                                } else {
-                                    warn!("Failed to parse depth update: {}", text);
-                                }
-                            }
-                            Ok(Message::Binary(bin)) => {
-                                if let Ok(text) = String::from_utf8(bin) {
-                                    if let Ok(parsed) = serde_json::from_str::<BinanceDepthUpdate>(&text) {
-                                        debug!("Parsed Binance depth update (binary)");
-                                        let parsed_bids = Self::parse_levels(parsed.bids);
-                                        let parsed_asks = Self::parse_levels(parsed.asks);
-                                        order_book.apply_deltas(parsed_bids, parsed_asks).await;
-                                    } else {
-                                        warn!("Failed to parse binary depth update: {}", text);
-                                    }
-                                }
-                            }
-                            Ok(_) => {
-                                // Ignore other message types
+                    Self::sync_books(uri, &mut conn, &context, reconnected, &mut shutdown_rx).await;
+                    metrics.current_connections.decrement(1.0);
+                    context.health.disconnected(Feed::Depth);
+                    if *shutdown_rx.borrow() {
+                        break;
+                    }
+                    warn!("⚠️ WebSocket stream closed for {}", uri);
+                    // Diffs missed while disconnected can't be recovered; nothing on the
+                    // books is trusted again until the next connection snapshots them
+                    for symbol in context.router.symbols() {
+                        if let Some(book) = context.router.get_order_book(&symbol) {
+                            book.clear().await;
+                        }
+                    }
+                    reconnected = true;
+                }
+                Err(e) => {
+                    metrics.connection_errors.increment(1);
+                    error!("Failed to connect to {}: {}", transport.uri(), e);
+                }
+            }
+
+            tokio::select! {
+                waited = transport.backoff() => waited?,
+                _ = shutdown_rx.changed() => break,
+            }
+        }
+        info!("Depth feed for {} shutting down", transport.uri());
+        Ok(())
+    }
+
+    fn transport(endpoints: Vec<String>, context: &FeedContext) -> Transport {
+        let mut transport = Transport::with_endpoints(endpoints, context.keepalive, context.reconnect)
+            .with_max_message_size(context.max_message_size)
+            .with_headers(context.headers.clone());
+        if let Some(proxy) = &context.proxy {
+            transport = transport.with_proxy(proxy.clone());
+        }
+        if let Some(tls) = &context.tls {
+            transport = transport.with_tls(tls.clone());
+        }
+        if let Some(quarantine) = &context.quarantine {
+            transport = transport.with_quarantine(quarantine.clone());
+        }
+        if let Some(escalation) = &context.escalation {
+            transport = transport.with_escalation(escalation.clone());
+        }
+        transport
+    }
+
+    /// Applies best bid/offer updates from one ticker stream to `book` until shutdown.
+    async fn run_book_ticker(
+        uri: String,
+        book: ConcurrentOrderBook,
+        context: FeedContext,
+        mut shutdown_rx: watch::Receiver<bool>,
+    ) -> Result<(), ReconnectError> {
+        let FeedContext { adapter, metrics, .. } = &context;
+        let mut transport = Self::transport(vec![uri.clone()], &context);
+
+        loop {
+            let connected = tokio::select! {
+                connected = transport.connect(None) => connected,
+                _ = shutdown_rx.changed() => break,
+            };
+            match connected {
+                Ok(mut conn) => {
+                    info!("Connected to book ticker at {}", uri);
+                    loop {
+                        let text = tokio::select! {
+                            text = conn.next_text() => text,
+                            _ = shutdown_rx.changed() => {
+                                conn.close().await;
+                                break;
                             }
+                        };
+                        let text = match text {
+                            Ok(Some(text)) => text,
+                            Ok(None) => break,
                             Err(e) => {
-                                error!("WebSocket error on {}: {}", uri, e);
+                                let e = LobFeedError::from(e);
+                                metrics.record(&e);
+                                error!("{} on {}", e, uri);
                                 break;
                             }
+                        };
+                        match adapter.parse_book_ticker(&text) {
+                            Ok(BestQuotes { bid, ask, .. }) => book.apply_best_quotes(bid.0, bid.1, ask.0, ask.1).await,
+                            Err(source) => {
+                                conn.quarantine(&text, &source);
+                                let e = LobFeedError::Parse { text, source };
+                                metrics.record(&e);
+                                warn!("{} on {}", e, uri);
+                            }
                         }
                     }
-    
-                    warn!("⚠️ WebSocket stream closed for {}", uri);
+                    if *shutdown_rx.borrow() {
+                        break;
+                    }
+                    warn!("⚠️ Book ticker stream closed for {}", uri);
                 }
                 Err(e) => {
+                    metrics.connection_errors.increment(1);
                     error!("Failed to connect to {}: {}", uri, e);
                 }
             }
-    
-            warn!("Reconnecting to {} in {:?}...", uri, retry_delay);
-            sleep(retry_delay).await;
-            retry_delay = std::cmp::min(retry_delay * 2, Duration::from_secs(60));
+
+            tokio::select! {
+                waited = transport.backoff() => waited?,
+                _ = shutdown_rx.changed() => break,
+            }
         }
+        info!("Book ticker for {} shutting down", uri);
+        Ok(())
     }
-    
-    async fn process_binance_update(update: BinanceDepthUpdate, order_book: &ConcurrentOrderBook) {
-        let parsed_bids = LobFeedManager::parse_levels(update.bids);
-        let parsed_asks = LobFeedManager::parse_levels(update.asks);
-        order_book.apply_deltas(parsed_bids, parsed_asks).await;
+
+    /// Reads depth events until the connection ends or shutdown is signalled, keeping every
+    /// routed book in sync with the exchange.
+    ///
+    /// Snapshots are fetched on separate tasks so the socket keeps being drained;
+    /// each symbol's synchronizer buffers diffs until its snapshot lands. `reconnected`
+    /// says the connection replaces a dropped one, whose books are resynced from scratch.
+    async fn sync_books(
+        uri: &str,
+        conn: &mut FeedConnection<'_>,
+        context: &FeedContext,
+        reconnected: bool,
+        shutdown_rx: &mut watch::Receiver<bool>,
+    ) {
+        let FeedContext {
+            router, adapter, coalesce_window, stale_after, reorder_window, resync_policy, metrics, lag, health, audit, ..
+        } = context;
+        let (snapshot_tx, mut snapshot_rx) = mpsc::channel::<SnapshotResult>(16);
+        let (audit_tx, mut audit_rx) = mpsc::channel::<SnapshotResult>(16);
+        let mut feeds: HashMap<String, SymbolSync> = HashMap::new();
+        for symbol in router.symbols() {
+            let snapshot_uri = adapter.snapshot_url(&symbol);
+            if let Some(snapshot_uri) = &snapshot_uri {
+                Self::spawn_snapshot_fetch(&symbol, snapshot_uri, Duration::ZERO, context, snapshot_tx.clone());
+            }
+            let book = router.get_order_book(&symbol).unwrap();
+            if reconnected {
+                metrics.resyncs_after_reconnect.increment(1);
+            }
+            let mut feed = SymbolSync::new(book, adapter.as_ref(), snapshot_uri, metrics.clone())
+                .with_resync_policy(*resync_policy);
+            if let Some(window) = reorder_window {
+                feed = feed.with_reorder_window(*window);
+            }
+            feeds.insert(symbol.clone(), feed);
+        }
+        if let Some(frame) = adapter.depth_subscribe_frame(&router.symbols()) {
+            if let Err(e) = conn.send_text(frame).await {
+                error!("Failed to subscribe on {}: {}", uri, e);
+                return;
+            }
+        }
+        let single_symbol = router.symbols()[0].clone();
+        let mut flush_deadline: Option<Instant> = None;
+        let mut stale_deadline = Instant::now() + *stale_after;
+        let mut audit_deadline = audit.as_ref().map(|audit| Instant::now() + audit.interval);
+
+        loop {
+            let reorder_deadline = feeds
+                .values()
+                .filter_map(|feed| feed.sync.held_expires_in())
+                .min()
+                .map(|expires_in| Instant::now() + expires_in);
+            let (symbol, outcome, checksum) = tokio::select! {
+                frame = conn.next_frame() => {
+                    let (text, event) = match frame {
+                        Ok(Some(Frame::Text(text))) => {
+                            let event = adapter.parse_depth(&text);
+                            (text, event)
+                        }
+                        Ok(Some(Frame::Binary(bytes))) => Self::parse_binary(adapter.as_ref(), &bytes),
+                        Ok(None) => break,
+                        Err(e) => {
+                            let e = LobFeedError::from(e);
+                            metrics.record(&e);
+                            error!("{} on {}", e, uri);
+                            break;
+                        }
+                    };
+                    let received = Instant::now();
+                    metrics.messages_received.increment(1);
+                    health.message(Feed::Depth);
+                    if let Some(event_time) = adapter.event_time_ms(&text) {
+                        lag.record(event_time);
+                    }
+
+                    match Self::process_event(&text, event, &mut feeds, &single_symbol).await {
+                        Ok(processed) => {
+                            metrics.process_latency.record(received.elapsed());
+                            processed
+                        }
+                        Err(e) => {
+                            metrics.record(&e);
+                            warn!("{} on {}", e, uri);
+                            if let LobFeedError::Parse { source, .. } = &e {
+                                conn.quarantine(&text, source);
+                            }
+                            continue;
+                        }
+                    }
+                }
+                _ = sleep_until(flush_deadline.unwrap_or_else(Instant::now)), if flush_deadline.is_some() => {
+                    flush_deadline = None;
+                    for (symbol, feed) in feeds.iter_mut() {
+                        let flushed = !feed.pending.is_empty();
+                        feed.flush().await;
+                        if let Err(e) = feed.verify_checksum().await {
+                            metrics.record(&e);
+                            warn!("{} for {} on {}; resyncing", e, symbol, uri);
+                            if let Err(e) = Self::resync(symbol, feed, conn, context, &snapshot_tx).await {
+                                metrics.record(&e);
+                                error!("Failed to resync {} on {}: {}", symbol, uri, e);
+                                return;
+                            }
+                        } else if flushed {
+                            // Diffs landed on top of the book: it is back in sync
+                            feed.resyncs.reset();
+                        }
+                    }
+                    continue;
+                }
+                _ = sleep_until(reorder_deadline.unwrap_or_else(Instant::now)), if reorder_deadline.is_some() => {
+                    for (symbol, feed) in feeds.iter_mut() {
+                        if let Some(SyncOutcome::Gap { expected, received }) = feed.sync.expire_held() {
+                            let e = LobFeedError::SequenceGap { expected, received };
+                            metrics.record(&e);
+                            warn!("{} for {} on {}; resyncing", e, symbol, uri);
+                            if let Err(e) = Self::resync(symbol, feed, conn, context, &snapshot_tx).await {
+                                metrics.record(&e);
+                                error!("Failed to resync {} on {}: {}", symbol, uri, e);
+                                return;
+                            }
+                        }
+                    }
+                    continue;
+                }
+                _ = sleep_until(stale_deadline) => {
+                    stale_deadline = Instant::now() + *stale_after;
+                    for (symbol, feed) in feeds.iter_mut() {
+                        // Cleared books are already waiting on a snapshot
+                        if !feed.book.is_trusted().await {
+                            continue;
+                        }
+                        feed.book.mark_untrusted().await;
+                        let e = LobFeedError::Stale(*stale_after);
+                        metrics.record(&e);
+                        warn!("{} for {} on {}; resyncing", e, symbol, uri);
+                        if let Err(e) = Self::resync(symbol, feed, conn, context, &snapshot_tx).await {
+                            metrics.record(&e);
+                            error!("Failed to resync {} on {}: {}", symbol, uri, e);
+                            return;
+                        }
+                    }
+                    continue;
+                }
+                _ = sleep_until(audit_deadline.unwrap_or_else(Instant::now)), if audit_deadline.is_some() => {
+                    let interval = audit.as_ref().map(|audit| audit.interval).unwrap_or_default();
+                    audit_deadline = Some(Instant::now() + interval);
+                    for (symbol, feed) in feeds.iter() {
+                        // Books waiting on a snapshot have nothing to compare yet. Audit snapshots
+                        // stay off the tape, whose replay would seed the book with them
+                        if let (Some(snapshot_uri), true) = (&feed.snapshot_uri, feed.book.is_trusted().await) {
+                            Self::spawn_fetch(symbol, snapshot_uri, Duration::ZERO, context, None, audit_tx.clone());
+                        }
+                    }
+                    continue;
+                }
+                Some((symbol, fetched)) = audit_rx.recv() => {
+                    let (feed, audit) = (feeds.get_mut(&symbol).unwrap(), audit.as_ref().unwrap());
+                    let diverged = match fetched {
+                        Ok(DepthEvent::Snapshot { last_update_id, bids, asks, .. }) => {
+                            Self::audit(&symbol, feed, audit, last_update_id, &bids, &asks).await
+                        }
+                        fetched => {
+                            let reason = fetched.err().unwrap_or_else(|| anyhow::anyhow!("not a snapshot"));
+                            warn!("Skipping audit of {} on {}: {:#}", symbol, uri, reason);
+                            false
+                        }
+                    };
+                    if diverged && audit.resync {
+                        if let Err(e) = Self::resync(&symbol, feed, conn, context, &snapshot_tx).await {
+                            metrics.record(&e);
+                            error!("Failed to resync {} on {}: {}", symbol, uri, e);
+                            return;
+                        }
+                    }
+                    continue;
+                }
+                _ = shutdown_rx.changed() => {
+                    conn.close().await;
+                    return;
+                }
+                Some((symbol, fetched)) = snapshot_rx.recv() => {
+                    let feed = feeds.get_mut(&symbol).unwrap();
+                    match fetched {
+                        Ok(DepthEvent::Snapshot { last_update_id, bids, asks, checksum, .. }) => {
+                            info!("Fetched {} depth snapshot at update {}", symbol, last_update_id);
+                            let outcome = feed.apply_snapshot(last_update_id, bids, asks, checksum).await;
+                            (symbol, outcome, None)
+                        }
+                        fetched => {
+                            let reason = fetched
+                                .err()
+                                .unwrap_or_else(|| anyhow::anyhow!("endpoint returned an incremental update"));
+                            let e = LobFeedError::Snapshot { reason };
+                            metrics.record(&e);
+                            error!("{} for {} on {}", e, symbol, uri);
+                            break;
+                        }
+                    }
+                }
+            };
+
+            if matches!(outcome, SyncOutcome::Apply(_)) {
+                stale_deadline = Instant::now() + *stale_after;
+            }
+            let feed = feeds.get_mut(&symbol).unwrap();
+            let failure = match outcome {
+                // A snapshot with nothing buffered behind it: the book is already current
+                SyncOutcome::Apply(diffs) if diffs.is_empty() => match feed.verify_checksum().await {
+                    Ok(()) => {
+                        feed.resyncs.reset();
+                        continue;
+                    }
+                    Err(e) => e,
+                },
+                SyncOutcome::Apply(diffs) => {
+                    feed.pending.extend(diffs);
+                    feed.checksum = checksum;
+                    flush_deadline.get_or_insert_with(|| Instant::now() + *coalesce_window);
+                    continue;
+                }
+                SyncOutcome::Gap { expected, received } => LobFeedError::SequenceGap { expected, received },
+                SyncOutcome::Buffered | SyncOutcome::Discarded | SyncOutcome::Late => continue,
+            };
+            metrics.record(&failure);
+            warn!("{} for {} on {}; resyncing", failure, symbol, uri);
+
+            if let Err(e) = Self::resync(&symbol, feed, conn, context, &snapshot_tx).await {
+                metrics.record(&e);
+                error!("Failed to resync {} on {}: {}", symbol, uri, e);
+                break;
+            }
+        }
     }
 
-    fn parse_levels(levels: Vec<(String, String)>) -> Vec<(Decimal, Decimal)> {
-        levels
-            .into_iter()
-            .filter_map(|(p, q)| {
-                match (Decimal::from_str(&p), Decimal::from_str(&q)) {
-                    (Ok(price), Ok(qty)) => Some((price, qty)),
-                    _ => None,
+    /// Decodes a binary depth frame through the adapter's `parse_binary`, alongside a
+    /// lossy text rendering of it for errors and quarantine.
+    fn parse_binary(adapter: &dyn ExchangeAdapter, bytes: &[u8]) -> (String, Result<DepthEvent, AdapterError>) {
+        let text = String::from_utf8_lossy(bytes).into_owned();
+        let event = match adapter.parse_binary(bytes) {
+            Ok(FeedEvent::Text(text)) => adapter.parse_depth(&text),
+            Ok(FeedEvent::Depth(event)) => Ok(event),
+            Ok(FeedEvent::Trades(_)) => Err(AdapterError::Unsupported("trades on the depth feed".to_string())),
+            Err(e) => Err(e),
+        };
+        (text, event)
+    }
+
+    /// Sequences one parsed depth message against its symbol's book.
+    async fn process_event(
+        text: &str,
+        event: Result<DepthEvent, AdapterError>,
+        feeds: &mut HashMap<String, SymbolSync>,
+        single_symbol: &str,
+    ) -> Result<(String, SyncOutcome, Option<i32>), LobFeedError> {
+        let event = event.map_err(|source| LobFeedError::Parse {
+            text: text.to_string(),
+            source,
+        })?;
+        let symbol = event.symbol().unwrap_or(single_symbol).to_string();
+        let feed = feeds
+            .get_mut(&symbol)
+            .ok_or_else(|| LobFeedError::UnroutedSymbol(symbol.clone()))?;
+        let (outcome, checksum) = feed.on_event(event).await;
+        Ok((symbol, outcome, checksum))
+    }
+
+    /// Drops a symbol's book and requests a fresh snapshot: over REST when the exchange
+    /// serves one, otherwise by resubscribing its depth channel on this connection.
+    async fn resync(
+        symbol: &str,
+        feed: &mut SymbolSync,
+        conn: &mut FeedConnection<'_>,
+        context: &FeedContext,
+        snapshot_tx: &mpsc::Sender<SnapshotResult>,
+    ) -> Result<(), LobFeedError> {
+        let delay = match feed.resyncs.next_delay() {
+            Some(delay) => delay,
+            None => return Err(LobFeedError::ResyncsExhausted(feed.resyncs.attempts())),
+        };
+        feed.metrics.resyncs.increment(1);
+        feed.clear().await;
+
+        if let Some(snapshot_uri) = &feed.snapshot_uri {
+            Self::spawn_snapshot_fetch(symbol, snapshot_uri, delay, context, snapshot_tx.clone());
+            return Ok(());
+        }
+        let adapter = &context.adapter;
+
+        let symbols = [symbol.to_string()];
+        if let Some(unsubscribe) = adapter.depth_unsubscribe_frame(&symbols) {
+            // Updates still in flight are older than the snapshot the new subscription starts with
+            feed.sync.reset();
+            conn.send_text(unsubscribe).await?;
+            if let Some(subscribe) = adapter.depth_subscribe_frame(&symbols) {
+                conn.send_text(subscribe).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Compares a live book with a REST snapshot fetched for its audit and reports the
+    /// outcome. Returns whether it diverged.
+    async fn audit(
+        symbol: &str,
+        feed: &mut SymbolSync,
+        audit: &AuditConfig,
+        snapshot_update_id: u64,
+        bids: &[(Decimal, Decimal)],
+        asks: &[(Decimal, Decimal)],
+    ) -> bool {
+        // Resynced while the snapshot was in flight
+        let live_update_id = match (feed.sync.state(), feed.book.is_trusted().await) {
+            (SyncState::Live { last_update_id }, true) => last_update_id,
+            _ => return false,
+        };
+        feed.flush().await;
+        let live_bids = feed.book.top_bids(audit.depth).await;
+        let live_asks = feed.book.top_asks(audit.depth).await;
+        let report = book_audit::diff_books(&live_bids, &live_asks, bids, asks, audit);
+        let report_json = serde_json::to_string(&report).unwrap_or_default();
+        if !report.diverged(audit) {
+            debug!(
+                "Audit of {} at update {} against snapshot {}: {}",
+                symbol, live_update_id, snapshot_update_id, report_json
+            );
+            return false;
+        }
+        let e = LobFeedError::Diverged(report.divergent_levels());
+        feed.metrics.record(&e);
+        warn!(
+            "{} for {} at update {} against snapshot {}: {}",
+            e, symbol, live_update_id, snapshot_update_id, report_json
+        );
+        true
+    }
+
+    fn spawn_snapshot_fetch(
+        symbol: &str,
+        snapshot_uri: &str,
+        delay: Duration,
+        context: &FeedContext,
+        tx: mpsc::Sender<SnapshotResult>,
+    ) {
+        let tape = context.tape.as_ref().map(|tape| tape.for_stream(DEPTH_SNAPSHOT_STREAM));
+        Self::spawn_fetch(symbol, snapshot_uri, delay, context, tape, tx);
+    }
+
+    /// Fetches a snapshot on its own task after `delay`, recording its body on `tape` if
+    /// there is one.
+    fn spawn_fetch(
+        symbol: &str,
+        snapshot_uri: &str,
+        delay: Duration,
+        context: &FeedContext,
+        tape: Option<TapeRecorder>,
+        tx: mpsc::Sender<SnapshotResult>,
+    ) {
+        let symbol = symbol.to_string();
+        let snapshot_uri = snapshot_uri.to_string();
+        let adapter = context.adapter.clone();
+        let snapshots = context.snapshots.clone();
+        task::spawn(async move {
+            tokio::time::sleep(delay).await;
+            let fetched = Self::fetch_snapshot(adapter.as_ref(), &snapshots, &snapshot_uri, tape.as_ref()).await;
+            let _ = tx.send((symbol, fetched)).await;
+        });
+    }
+
+    async fn fetch_snapshot(
+        adapter: &dyn ExchangeAdapter,
+        snapshots: &SnapshotFetcher,
+        snapshot_uri: &str,
+        tape: Option<&TapeRecorder>,
+    ) -> Result<DepthEvent> {
+        let body = snapshots.fetch(snapshot_uri).await?;
+        if let Some(tape) = tape {
+            tape.record(&body);
+        }
+        adapter
+            .parse_snapshot(&body)
+            .context("Failed to parse depth snapshot")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream_config::Exchange;
+    use crate::subscription::PendingAcks;
+    use rust_decimal_macros::dec;
+
+    const DIFF_SAMPLE: &str = r#"{"e":"depthUpdate","E":1712745600123,"s":"BTCUSDT","U":46018271123,"u":46018271130,"b":[["69420.01000000","0.51200000"],["69419.50000000","0.00000000"]],"a":[["69420.02000000","1.20300000"]]}"#;
+
+    const PARTIAL_SAMPLE: &str = r#"{"lastUpdateId":46018271130,"bids":[["69420.01000000","0.51200000"],["69419.99000000","0.03000000"]],"asks":[["69420.02000000","1.20300000"],["69420.10000000","0.00800000"]]}"#;
+
+    async fn route_and_apply(router: &SymbolRouter, text: &str) {
+        let (symbol, message) = router.route(text).unwrap();
+        let book = router.get_order_book(&symbol).unwrap();
+        let diff = match message {
+            DepthMessage::Update(update) => DepthDiff::from(update),
+            other => panic!("Expected diff update, got {:?}", other),
+        };
+        book.apply_deltas(diff.bids, diff.asks).await;
+    }
+
+    #[test]
+    fn test_combined_stream_url() {
+        let router = SymbolRouter::new(vec!["ETHUSDT".to_string(), "btcusdt".to_string()]);
+        assert_eq!(
+            router.combined_stream_url("wss://stream.binance.com:9443", "depth"),
+            "wss://stream.binance.com:9443/stream?streams=btcusdt@depth/ethusdt@depth"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_router_routes_each_symbol_to_its_own_book() {
+        let router = SymbolRouter::new(vec!["btcusdt".to_string(), "ethusdt".to_string()]);
+
+        route_and_apply(&router, r#"{"stream":"btcusdt@depth","data":{"e":"depthUpdate","E":1,"s":"BTCUSDT","U":1,"u":2,"b":[["69000.00","1.5"]],"a":[["69001.00","2.0"]]}}"#).await;
+        route_and_apply(&router, r#"{"stream":"ethusdt@depth","data":{"e":"depthUpdate","E":1,"s":"ETHUSDT","U":7,"u":9,"b":[["3500.10","10"]],"a":[]}}"#).await;
+
+        let btc = router.get_order_book("BTCUSDT").unwrap();
+        let eth = router.get_order_book("ethusdt").unwrap();
+
+        assert_eq!(btc.best_bid().await, Some((dec!(69000.00), dec!(1.5))));
+        assert_eq!(btc.best_ask().await, Some((dec!(69001.00), dec!(2.0))));
+        assert_eq!(eth.best_bid().await, Some((dec!(3500.10), dec!(10))));
+        assert_eq!(eth.best_ask().await, None);
+        assert_eq!(btc.top_bids(10).await.len(), 1);
+    }
+
+    #[test]
+    fn test_router_rejects_bare_payload() {
+        let router = SymbolRouter::new(vec!["btcusdt".to_string()]);
+        assert!(router.route(DIFF_SAMPLE).is_err());
+    }
+
+    #[test]
+    fn test_depth_payloads_are_not_taken_for_acks() {
+        let mut pending = PendingAcks::new();
+        assert!(pending.on_text(DIFF_SAMPLE).is_none());
+        assert!(pending.on_text(PARTIAL_SAMPLE).is_none());
+        assert!(pending.on_text(r#"{"result":null,"id":1}"#).is_some());
+    }
+
+    #[test]
+    fn test_urls_come_from_the_adapter() {
+        let manager = LobFeedManager::from_config(&StreamConfig::new("ETHUSDT"));
+        assert_eq!(
+            manager.endpoints,
+            vec![vec![
+                "wss://stream.binance.com:9443/ws/ethusdt@depth@100ms".to_string(),
+                "wss://stream.binance.com:443/ws/ethusdt@depth@100ms".to_string(),
+                "wss://data-stream.binance.vision/ws/ethusdt@depth@100ms".to_string(),
+            ]]
+        );
+        assert_eq!(manager.router().symbols(), vec!["ethusdt".to_string()]);
+
+        let config = StreamConfig::new("BTC-USD").with_exchange(Exchange::Coinbase);
+        let manager = LobFeedManager::from_config(&config);
+        assert_eq!(manager.adapter.name(), "coinbase");
+        assert_eq!(manager.endpoints, vec![vec!["wss://ws-feed.exchange.coinbase.com".to_string()]]);
+        assert_eq!(manager.router().symbols(), vec!["btc-usd".to_string()]);
+    }
+
+    fn okx_books(action: &str, bids: &[(&str, &str)], asks: &[(&str, &str)], prev_seq_id: i64, seq_id: u64, checksum: i32) -> String {
+        let levels = |levels: &[(&str, &str)]| -> Vec<[String; 4]> {
+            levels.iter().map(|(p, q)| [p.to_string(), q.to_string(), "0".to_string(), "1".to_string()]).collect()
+        };
+        serde_json::json!({
+            "arg": {"channel": "books", "instId": "BTC-USDT"},
+            "action": action,
+            "data": [{
+                "asks": levels(asks),
+                "bids": levels(bids),
+                "ts": "1712745600123",
+                "checksum": checksum,
+                "prevSeqId": prev_seq_id,
+                "seqId": seq_id,
+            }],
+        })
+        .to_string()
+    }
+
+    fn book_checksum(bids: Vec<(Decimal, Decimal)>, asks: Vec<(Decimal, Decimal)>) -> i32 {
+        let mut book = crate::orderbook::OrderBook::new();
+        book.apply_snapshot(bids, asks);
+        book.checksum(ChecksumFormat::Interleaved { depth: 25 })
+    }
+
+    #[tokio::test]
+    async fn test_checksum_mismatch_resubscribes() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio::net::TcpListener;
+        use tokio::time::timeout;
+        use tokio_tungstenite::tungstenite::protocol::Message;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut config = StreamConfig::new("BTC-USDT").with_exchange(Exchange::Okx);
+        config.base_url = format!("ws://{}", listener.local_addr().unwrap());
+        let manager = LobFeedManager::from_config(&config);
+        let book = manager.get_order_book();
+
+        let (frames_tx, mut frames_rx) = mpsc::unbounded_channel::<serde_json::Value>();
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            let mut frames = 0;
+            while let Some(Ok(msg)) = ws.next().await {
+                let text = match msg {
+                    Message::Text(text) => text,
+                    _ => continue,
+                };
+                let frame: serde_json::Value = serde_json::from_str(&text).unwrap();
+                frames_tx.send(frame).unwrap();
+                frames += 1;
+                match frames {
+                    1 => {
+                        let snapshot_checksum = book_checksum(
+                            vec![(dec!(3366.1), dec!(7))],
+                            vec![(dec!(3366.8), dec!(9))],
+                        );
+                        ws.send(Message::Text(okx_books("snapshot", &[("3366.1", "7")], &[("3366.8", "9")], -1, 100, snapshot_checksum))).await.unwrap();
+
+                        let update_checksum = book_checksum(
+                            vec![(dec!(3366.1), dec!(7)), (dec!(3366), dec!(6))],
+                            vec![(dec!(3366.8), dec!(9))],
+                        );
+                        ws.send(Message::Text(okx_books("update", &[("3366", "6")], &[], 100, 101, update_checksum))).await.unwrap();
+                        // Claims a book the client cannot have
+                        ws.send(Message::Text(okx_books("update", &[("3365", "1")], &[], 101, 102, 42))).await.unwrap();
+                    }
+                    // Resubscribed: serve a fresh book
+                    3 => {
+                        let checksum = book_checksum(vec![(dec!(3370), dec!(2))], vec![(dec!(3371), dec!(3))]);
+                        ws.send(Message::Text(okx_books("snapshot", &[("3370", "2")], &[("3371", "3")], -1, 200, checksum))).await.unwrap();
+                    }
+                    _ => {}
+                }
+            }
+        });
+
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+        let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+
+        let mut ops = Vec::new();
+        for _ in 0..3 {
+            let frame = timeout(Duration::from_secs(2), frames_rx.recv()).await.unwrap().unwrap();
+            assert_eq!(frame["args"][0]["instId"], "BTC-USDT");
+            ops.push(frame["op"].as_str().unwrap().to_string());
+        }
+        assert_eq!(ops, vec!["subscribe", "unsubscribe", "subscribe"]);
+
+        timeout(Duration::from_secs(2), async {
+            while book.best_bid().await != Some((dec!(3370), dec!(2))) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        // Nothing from the corrupted book survives the resync
+        assert_eq!(book.top_bids(10).await, vec![(dec!(3370), dec!(2))]);
+        assert_eq!(book.top_asks(10).await, vec![(dec!(3371), dec!(3))]);
+
+        feed.abort();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_closes_connection_and_returns() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio::net::TcpListener;
+        use tokio::time::timeout;
+        use tokio_tungstenite::tungstenite::protocol::Message;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut config = StreamConfig::new("BTC-USDT").with_exchange(Exchange::Okx);
+        config.base_url = format!("ws://{}", listener.local_addr().unwrap());
+        let manager = LobFeedManager::from_config(&config);
+        let book = manager.get_order_book();
+
+        let (closed_tx, mut closed_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            while let Some(Ok(msg)) = ws.next().await {
+                match msg {
+                    Message::Text(_) => {
+                        let checksum = book_checksum(vec![(dec!(3366.1), dec!(7))], vec![(dec!(3366.8), dec!(9))]);
+                        ws.send(Message::Text(okx_books("snapshot", &[("3366.1", "7")], &[("3366.8", "9")], -1, 100, checksum))).await.unwrap();
+                    }
+                    Message::Close(_) => {
+                        let _ = closed_tx.send(());
+                        break;
+                    }
+                    _ => {}
                 }
+            }
+        });
+
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+
+        timeout(Duration::from_secs(2), async {
+            while book.best_bid().await.is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        shutdown_tx.send(true).unwrap();
+        let stopped = timeout(Duration::from_secs(1), feed).await.expect("feed did not stop").unwrap();
+        assert_eq!(stopped, Ok(()));
+        timeout(Duration::from_secs(1), closed_rx.recv()).await.expect("no close frame").unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_persistent_failure_fires_once_while_connects_keep_failing() {
+        use tokio::net::TcpListener;
+        use tokio::time::timeout;
+
+        // Accepts and hangs up, so every handshake fails
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut config = StreamConfig::new("BTC-USDT").with_exchange(Exchange::Okx);
+        config.base_url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { while listener.accept().await.is_ok() {} });
+
+        let (escalations_tx, mut escalations) = mpsc::unbounded_channel();
+        let manager = LobFeedManager::from_config(&config)
+            .with_reconnect_policy(ReconnectPolicy {
+                initial: Duration::from_millis(1),
+                jitter_fraction: 0.0,
+                ..ReconnectPolicy::default()
             })
-            .collect()
+            .with_max_consecutive_failures(7)
+            .on_persistent_failure(3, escalations_tx);
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        let result = timeout(Duration::from_secs(2), manager.start(shutdown_rx)).await.expect("feed kept retrying");
+        assert!(matches!(result, Err(ReconnectError::TooManyFailures { failures: 7, .. })), "{:?}", result);
+        let escalation = escalations.try_recv().unwrap();
+        assert_eq!(escalation.failures, 3);
+        assert!(escalation.uri.starts_with(&config.base_url), "{}", escalation.uri);
+        assert!(escalations.try_recv().is_err());
     }
-} 
+
+    #[tokio::test]
+    async fn test_metrics_count_scripted_session() {
+        use futures_util::{SinkExt, StreamExt};
+        use crate::orderbook::LOCK_WAIT_HISTOGRAM;
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+        use tokio::net::TcpListener;
+        use tokio::time::timeout;
+        use tokio_tungstenite::tungstenite::protocol::Message;
+
+        // Handles are bound at registration, so the recorder must be in place before the manager exists
+        let _ = DebuggingRecorder::per_thread().install();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut config = StreamConfig::new("BTC-USDT").with_exchange(Exchange::Okx);
+        config.base_url = format!("ws://{}", listener.local_addr().unwrap());
+        let manager = LobFeedManager::from_config(&config);
+        let book = manager.get_order_book();
+
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            let mut subscribes = 0;
+            while let Some(Ok(msg)) = ws.next().await {
+                let frame: serde_json::Value = match msg {
+                    Message::Text(text) => serde_json::from_str(&text).unwrap(),
+                    _ => continue,
+                };
+                if frame["op"] != "subscribe" {
+                    continue;
+                }
+                subscribes += 1;
+                let script = if subscribes == 1 {
+                    let snapshot_checksum = book_checksum(vec![(dec!(3366.1), dec!(7))], vec![(dec!(3366.8), dec!(9))]);
+                    let update_checksum = book_checksum(
+                        vec![(dec!(3366.1), dec!(7)), (dec!(3366), dec!(6))],
+                        vec![(dec!(3366.8), dec!(9))],
+                    );
+                    vec![
+                        okx_books("snapshot", &[("3366.1", "7")], &[("3366.8", "9")], -1, 100, snapshot_checksum),
+                        okx_books("update", &[("3366", "6")], &[], 100, 101, update_checksum),
+                        "not a books push".to_string(),
+                        okx_books("update", &[("3365", "1")], &[], 101, 102, 42),
+                    ]
+                } else {
+                    let checksum = book_checksum(vec![(dec!(3370), dec!(2))], vec![(dec!(3371), dec!(3))]);
+                    vec![okx_books("snapshot", &[("3370", "2")], &[("3371", "3")], -1, 200, checksum)]
+                };
+                for text in script {
+                    ws.send(Message::Text(text)).await.unwrap();
+                }
+            }
+        });
+
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+
+        timeout(Duration::from_secs(2), async {
+            while book.best_bid().await != Some((dec!(3370), dec!(2))) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        shutdown_tx.send(true).unwrap();
+        timeout(Duration::from_secs(1), feed).await.unwrap().unwrap().unwrap();
+
+        let recorded: HashMap<String, DebugValue> = Snapshotter::current_thread_snapshot()
+            .unwrap()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| (key.key().name().to_string(), value))
+            .collect();
+        let expected = [
+            ("lob_feed_messages_received", 5),
+            ("lob_feed_parse_failures", 1),
+            ("lob_feed_snapshots_applied", 2),
+            ("lob_feed_deltas_applied", 2),
+            ("lob_feed_checksum_mismatches", 1),
+            ("lob_feed_resyncs", 1),
+            ("lob_feed_connection_errors", 0),
+        ];
+        for (name, count) in expected {
+            assert_eq!(recorded.get(name), Some(&DebugValue::Counter(count)), "{}", name);
+        }
+        assert_eq!(recorded.get("lob_feed_current_connections"), Some(&DebugValue::Gauge(0.0.into())));
+        // Every message but the unparseable one was timed, and every book update its lock wait
+        match recorded.get("lob_process_latency_us") {
+            Some(DebugValue::Histogram(samples)) => assert_eq!(samples.len(), 4),
+            other => panic!("Expected process latencies, got {:?}", other),
+        }
+        match recorded.get(LOCK_WAIT_HISTOGRAM) {
+            Some(DebugValue::Histogram(samples)) => assert!(!samples.is_empty()),
+            other => panic!("Expected lock waits, got {:?}", other),
+        }
+    }
+
+    fn sync_for(adapter: &dyn ExchangeAdapter) -> SymbolSync {
+        SymbolSync::new(ConcurrentOrderBook::new(), adapter, None, LobFeedMetrics::register())
+    }
+
+    async fn process_message(
+        text: &str,
+        adapter: &BinanceAdapter,
+        feeds: &mut HashMap<String, SymbolSync>,
+    ) -> Result<(String, SyncOutcome, Option<i32>), LobFeedError> {
+        LobFeedManager::process_event(text, adapter.parse_depth(text), feeds, "btcusdt").await
+    }
+
+    #[tokio::test]
+    async fn test_process_message_reports_parse_and_routing_failures() {
+        let adapter = BinanceAdapter::new();
+        let mut feeds = HashMap::from([("btcusdt".to_string(), sync_for(&adapter))]);
+
+        let truncated = r#"{"e":"depthUpdate","E":1,"s":"BTCUSDT","U":1"#;
+        match process_message(truncated, &adapter, &mut feeds).await {
+            Err(LobFeedError::Parse { text, .. }) => assert_eq!(text, truncated),
+            other => panic!("Expected a parse failure, got {:?}", other),
+        }
+
+        let eth = r#"{"stream":"ethusdt@depth","data":{"e":"depthUpdate","E":1,"s":"ETHUSDT","U":1,"u":2,"b":[],"a":[]}}"#;
+        match process_message(eth, &adapter, &mut feeds).await {
+            Err(LobFeedError::UnroutedSymbol(symbol)) => assert_eq!(symbol, "ethusdt"),
+            other => panic!("Expected an unrouted symbol, got {:?}", other),
+        }
+
+        // Routed, and buffered until a snapshot arrives
+        let (symbol, outcome, _) = process_message(DIFF_SAMPLE, &adapter, &mut feeds).await.unwrap();
+        assert_eq!(symbol, "btcusdt");
+        assert_eq!(outcome, SyncOutcome::Buffered);
+    }
+
+    #[tokio::test]
+    async fn test_apply_failures_are_typed_and_drop_the_book() {
+        let config = StreamConfig::new("BTC-USDT").with_exchange(Exchange::Okx);
+        let okx = exchange::adapter_for(&config);
+        let mut feed = sync_for(okx.as_ref());
+
+        // The exchange claims a checksum the snapshot can't have
+        let event = okx.parse_depth(&okx_books("snapshot", &[("3366.1", "7")], &[("3366.8", "9")], -1, 100, 42)).unwrap();
+        let (outcome, _) = feed.on_event(event).await;
+        assert_eq!(outcome, SyncOutcome::Apply(vec![]));
+        let expected_actual = book_checksum(vec![(dec!(3366.1), dec!(7))], vec![(dec!(3366.8), dec!(9))]);
+        match feed.verify_checksum().await {
+            Err(LobFeedError::ChecksumMismatch { expected, actual }) => {
+                assert_eq!((expected, actual), (42, expected_actual));
+            }
+            other => panic!("Expected a checksum mismatch, got {:?}", other),
+        }
+
+        // A sequence gap on an applied book drops it until the next snapshot
+        let binance = BinanceAdapter::new();
+        let mut feed = sync_for(&binance);
+        let (outcome, checksum) = feed.on_event(binance.parse_depth(PARTIAL_SAMPLE).unwrap()).await;
+        assert!(feed.apply_now(outcome, checksum).await);
+        assert!(feed.book.best_bid().await.is_some());
+
+        let gapped = r#"{"e":"depthUpdate","E":1,"s":"BTCUSDT","U":46018271140,"u":46018271141,"b":[],"a":[]}"#;
+        let (outcome, checksum) = feed.on_event(binance.parse_depth(gapped).unwrap()).await;
+        assert!(matches!(outcome, SyncOutcome::Gap { .. }));
+        assert!(!feed.apply_now(outcome, checksum).await);
+        assert_eq!(feed.book.best_bid().await, None);
+    }
+
+    #[tokio::test]
+    async fn test_partial_mode_drops_out_of_order_snapshots() {
+        let partial = BinanceAdapter::from_config(&StreamConfig::new("btcusdt").with_depth_mode(DepthMode::PartialBook(20)));
+        assert!(partial.streams_whole_books());
+        assert!(!BinanceAdapter::new().streams_whole_books());
+        let mut feed = sync_for(&partial);
+
+        let payload = |id: u64, bid: &str, ask: &str| {
+            format!(r#"{{"lastUpdateId":{id},"bids":[["{bid}","1.0"]],"asks":[["{ask}","2.0"]]}}"#)
+        };
+        // (lastUpdateId, bid, ask, applied)
+        let arrivals = [
+            (500, "100.00", "100.10", true),
+            (502, "100.20", "100.30", true),
+            (501, "99.00", "99.10", false),
+            (502, "98.00", "98.10", false),
+            (503, "100.40", "100.50", true),
+        ];
+        let mut latest = None;
+        for (id, bid, ask, applied) in arrivals {
+            let event = partial.parse_depth(&payload(id, bid, ask)).unwrap();
+            let (outcome, checksum) = feed.on_event(event).await;
+            assert_eq!(outcome == SyncOutcome::Discarded, !applied, "update {}", id);
+            assert!(feed.apply_now(outcome, checksum).await);
+            if applied {
+                latest = Some((bid, ask));
+            }
+
+            // The book is always exactly the latest accepted payload
+            let (bid, ask) = latest.unwrap();
+            assert_eq!(feed.book.top_bids(10).await, vec![(bid.parse::<Decimal>().unwrap(), dec!(1.0))]);
+            assert_eq!(feed.book.top_asks(10).await, vec![(ask.parse::<Decimal>().unwrap(), dec!(2.0))]);
+        }
+    }
+
+    /// Serves one canned REST response and counts the requests it got.
+    async fn serve_http(listener: tokio::net::TcpListener, body: &'static str, requests: Arc<std::sync::atomic::AtomicUsize>) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        while let Ok((mut tcp, _)) = listener.accept().await {
+            requests.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            let mut request = [0u8; 1024];
+            let _ = tcp.read(&mut request).await;
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = tcp.write_all(response.as_bytes()).await;
+        }
+    }
+
+    /// Runs a Binance depth feed in `mode` against mock WebSocket and REST servers until the
+    /// book settles. Returns the book, the depth URI and how many REST snapshots were fetched.
+    async fn binance_mode_session(mode: DepthMode, frames: Vec<&'static str>) -> (ConcurrentOrderBook, String, usize) {
+        use futures_util::SinkExt;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::net::TcpListener;
+        use tokio_tungstenite::tungstenite::protocol::Message;
+
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let http_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut config = StreamConfig::new("btcusdt").with_depth_mode(mode);
+        config.base_url = format!("ws://{}", ws_listener.local_addr().unwrap());
+        let adapter = BinanceAdapter::from_config(&config)
+            .with_rest_base(format!("http://{}", http_listener.local_addr().unwrap()));
+        let manager = LobFeedManager::with_adapter(Arc::new(adapter), "btcusdt");
+
+        let requests = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(serve_http(
+            http_listener,
+            r#"{"lastUpdateId":100,"bids":[["100.00","1.0"],["99.50","2.0"]],"asks":[["100.50","1.5"],["101.00","3.0"]]}"#,
+            requests.clone(),
+        ));
+        let (accepted_tx, mut accepted_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = ws_listener.accept().await {
+                let _ = accepted_tx.send(());
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                for frame in &frames {
+                    ws.send(Message::Text(frame.to_string())).await.unwrap();
+                }
+                // Hold the socket open until the client goes away
+                let _ = futures_util::StreamExt::next(&mut ws).await;
+            }
+        });
+
+        let book = manager.get_order_book();
+        let uri = manager.endpoints[0][0].clone();
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while book.best_ask().await.is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        // Let anything still in flight land before the book is inspected
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        shutdown_tx.send(true).unwrap();
+        feed.await.unwrap().unwrap();
+
+        let mut connections = 0;
+        while accepted_rx.try_recv().is_ok() {
+            connections += 1;
+        }
+        assert_eq!(connections, 1, "one depth socket per symbol");
+        (book, uri, requests.load(Ordering::SeqCst))
+    }
+
+    #[tokio::test]
+    async fn test_diff_mode_syncs_one_socket_against_rest_snapshot() {
+        let (book, uri, rest_requests) = binance_mode_session(
+            DepthMode::DiffWithRestSnapshot,
+            vec![
+                // Straddles the snapshot: buffered, then released on top of it
+                r#"{"e":"depthUpdate","E":1,"s":"BTCUSDT","U":95,"u":101,"b":[["100.00","1.2"]],"a":[]}"#,
+                r#"{"e":"depthUpdate","E":2,"s":"BTCUSDT","U":102,"u":103,"b":[["99.50","0"]],"a":[["100.25","0.4"]]}"#,
+            ],
+        )
+        .await;
+
+        assert!(uri.ends_with("/ws/btcusdt@depth@100ms"));
+        assert_eq!(rest_requests, 1);
+        assert_eq!(book.top_bids(10).await, vec![(dec!(100.00), dec!(1.2))]);
+        assert_eq!(
+            book.top_asks(10).await,
+            vec![(dec!(100.25), dec!(0.4)), (dec!(100.50), dec!(1.5)), (dec!(101.00), dec!(3.0))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resyncs_back_off_then_drop_the_connection() {
+        use futures_util::SinkExt;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use tokio::net::TcpListener;
+        use tokio_tungstenite::tungstenite::protocol::Message;
+
+        let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let http_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut config = StreamConfig::new("btcusdt").with_depth_mode(DepthMode::DiffWithRestSnapshot);
+        config.base_url = format!("ws://{}", ws_listener.local_addr().unwrap());
+        let adapter = BinanceAdapter::from_config(&config)
+            .with_rest_base(format!("http://{}", http_listener.local_addr().unwrap()));
+        let policy = ReconnectPolicy {
+            initial: Duration::from_millis(50),
+            multiplier: 2.0,
+            jitter_fraction: 0.0,
+            max_attempts: Some(2),
+            ..ReconnectPolicy::default()
+        };
+        let manager = LobFeedManager::with_adapter(Arc::new(adapter), "btcusdt")
+            .with_reconnect_policy(ReconnectPolicy { initial: Duration::from_millis(10), ..ReconnectPolicy::default() })
+            .with_resync_policy(policy);
+
+        // Every snapshot is older than the diff buffered behind it
+        let requests = Arc::new(AtomicUsize::new(0));
+        tokio::spawn(serve_http(
+            http_listener,
+            r#"{"lastUpdateId":100,"bids":[["100.00","1.0"]],"asks":[["100.50","1.5"]]}"#,
+            requests.clone(),
+        ));
+        let (accepted_tx, mut accepted_rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = ws_listener.accept().await {
+                let _ = accepted_tx.send(Instant::now());
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                let frame = r#"{"e":"depthUpdate","E":1,"s":"BTCUSDT","U":200,"u":201,"b":[],"a":[]}"#;
+                ws.send(Message::Text(frame.to_string())).await.unwrap();
+                let _ = futures_util::StreamExt::next(&mut ws).await;
+            }
+        });
+
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+        let first = accepted_rx.recv().await.unwrap();
+        let second = tokio::time::timeout(Duration::from_secs(5), accepted_rx.recv()).await.unwrap().unwrap();
+        let fetched = requests.load(Ordering::SeqCst);
+        shutdown_tx.send(true).unwrap();
+        feed.await.unwrap().unwrap();
+
+        // The first snapshot, then one per resync allowed, 50ms and 100ms apart
+        assert!(fetched >= 3, "fetched {} snapshots", fetched);
+        assert!(second - first >= Duration::from_millis(150));
+    }
+
+    #[tokio::test]
+    async fn test_partial_mode_replaces_book_without_rest() {
+        let (book, uri, rest_requests) = binance_mode_session(
+            DepthMode::PartialBook(5),
+            vec![
+                r#"{"lastUpdateId":500,"bids":[["100.00","1.0"],["99.90","2.0"]],"asks":[["100.10","1.0"]]}"#,
+                r#"{"lastUpdateId":501,"bids":[["100.05","0.5"]],"asks":[["100.10","2.0"],["100.20","1.0"]]}"#,
+            ],
+        )
+        .await;
+
+        assert!(uri.ends_with("/ws/btcusdt@depth5@100ms"));
+        assert_eq!(rest_requests, 0);
+        // Each partial message is the whole book; nothing from the first survives
+        assert_eq!(book.top_bids(10).await, vec![(dec!(100.05), dec!(0.5))]);
+        assert_eq!(book.top_asks(10).await, vec![(dec!(100.10), dec!(2.0)), (dec!(100.20), dec!(1.0))]);
+    }
+
+    #[tokio::test]
+    #[allow(clippy::result_large_err)] // The handshake callback's signature is tungstenite's
+    async fn test_book_ticker_moves_the_touch_without_crossing_depth() {
+        use futures_util::SinkExt;
+        use tokio::net::TcpListener;
+        use tokio::sync::Notify;
+        use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+        use tokio_tungstenite::tungstenite::protocol::Message;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut config = StreamConfig::new("btcusdt").with_depth_mode(DepthMode::PartialBook(5));
+        config.base_url = format!("ws://{}", listener.local_addr().unwrap());
+        let manager = LobFeedManager::with_adapter(Arc::new(BinanceAdapter::from_config(&config)), "btcusdt")
+            .with_book_ticker(true);
+
+        // Ticker frames are held back until the depth snapshot is in the book
+        let release = Arc::new(Notify::new());
+        let server_release = release.clone();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                let release = server_release.clone();
+                tokio::spawn(async move {
+                    let mut path = String::new();
+                    let mut ws = tokio_tungstenite::accept_hdr_async(tcp, |request: &Request, response: Response| {
+                        path = request.uri().path().to_string();
+                        Ok(response)
+                    })
+                    .await
+                    .unwrap();
+                    let frames = if path.ends_with("@bookTicker") {
+                        release.notified().await;
+                        vec![
+                            // Crossed in itself: ignored
+                            r#"{"u":1,"s":"BTCUSDT","b":"100.30","B":"1.0","a":"100.20","A":"1.0"}"#,
+                            r#"{"u":2,"s":"BTCUSDT","b":"100.15","B":"0.7","a":"100.25","A":"0.3"}"#,
+                        ]
+                    } else {
+                        vec![r#"{"lastUpdateId":500,"bids":[["100.00","1.0"],["99.90","2.0"]],"asks":[["100.10","1.0"],["100.20","1.0"],["100.30","4.0"]]}"#]
+                    };
+                    for frame in frames {
+                        ws.send(Message::Text(frame.to_string())).await.unwrap();
+                    }
+                    let _ = futures_util::StreamExt::next(&mut ws).await;
+                });
+            }
+        });
+
+        let book = manager.get_order_book();
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+
+        tokio::time::timeout(Duration::from_secs(2), async {
+            while book.best_ask().await.is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            release.notify_one();
+            while book.best_bid().await.map(|(price, _)| price) != Some(dec!(100.15)) {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        shutdown_tx.send(true).unwrap();
+        feed.await.unwrap().unwrap();
+
+        // Depth asks below the quoted ask are gone; deeper levels on both sides survive
+        assert_eq!(
+            book.top_bids(10).await,
+            vec![(dec!(100.15), dec!(0.7)), (dec!(100.00), dec!(1.0)), (dec!(99.90), dec!(2.0))]
+        );
+        assert_eq!(book.top_asks(10).await, vec![(dec!(100.25), dec!(0.3)), (dec!(100.30), dec!(4.0))]);
+    }
+
+    #[tokio::test]
+    async fn test_silent_feed_marks_book_untrusted_until_next_snapshot() {
+        use futures_util::SinkExt;
+        use tokio::net::TcpListener;
+        use tokio::sync::Notify;
+        use tokio_tungstenite::tungstenite::protocol::Message;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut config = StreamConfig::new("btcusdt").with_depth_mode(DepthMode::PartialBook(5));
+        config.base_url = format!("ws://{}", listener.local_addr().unwrap());
+        let manager = LobFeedManager::from_config(&config).with_stale_after(Duration::from_millis(200));
+
+        let resume = Arc::new(Notify::new());
+        let server_resume = resume.clone();
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            ws.send(Message::Text(r#"{"lastUpdateId":1,"bids":[["100.00","1.0"]],"asks":[["100.10","1.0"]]}"#.to_string()))
+                .await
+                .unwrap();
+            // Go quiet until the test has seen the watchdog fire
+            server_resume.notified().await;
+            ws.send(Message::Text(r#"{"lastUpdateId":2,"bids":[["100.05","2.0"]],"asks":[["100.10","1.0"]]}"#.to_string()))
+                .await
+                .unwrap();
+            let _ = futures_util::StreamExt::next(&mut ws).await;
+        });
+
+        let book = manager.get_order_book();
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+
+        let wait_for = |trusted: bool| {
+            let book = book.clone();
+            async move {
+                tokio::time::timeout(Duration::from_secs(2), async {
+                    while book.is_trusted().await != trusted {
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                })
+                .await
+                .unwrap();
+            }
+        };
+
+        wait_for(true).await;
+        wait_for(false).await;
+        let snapshot = book.get_snapshot().await;
+        assert!(!snapshot.trusted);
+        assert_eq!(snapshot.best_bid, None, "the stale book is dropped for a resync");
+
+        resume.notify_one();
+        wait_for(true).await;
+        assert_eq!(book.best_bid().await, Some((dec!(100.05), dec!(2.0))));
+
+        shutdown_tx.send(true).unwrap();
+        feed.await.unwrap().unwrap();
+    }
+}
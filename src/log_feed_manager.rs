@@ -1,91 +1,477 @@
+use crate::clock::Clock;
+use crate::connector::{ConnectorFsm, ConnectorState};
+use crate::exchange::{self, AdapterError, BinanceAdapter, ExchangeAdapter, FeedEvent};
+use crate::feed_handles::FeedHandles;
+use crate::feed_health::{Feed, HealthPublisher};
+use crate::heartbeat::KeepAliveConfig;
+use crate::latency::{FeedLag, IngestLag, ProcessLatency};
+use crate::proxy::ProxyConfig;
+use crate::quarantine::Quarantine;
+use crate::reconnect::{FailureEscalation, PersistentFailure, ReconnectError, ReconnectPolicy};
+use crate::session_stats::SessionStats;
+use crate::snapshot_fetcher::{SnapshotFetchError, SnapshotFetcher};
+use crate::stream_config::{StreamConfig, BINANCE_WS_BASE};
+use crate::tape::{TapeRecorder, TRADE_STREAM};
+use crate::trade_sink::{RawTrade, TradeSink};
+use crate::subscription::{SubscriptionError, SubscriptionHandle, SubscriptionRequest};
 use crate::tradeslog::{ConcurrentTradesLog, Trade};
-use futures_util::StreamExt;
-use log::{debug, error, info, warn};
-use rust_decimal::Decimal;
-use serde::Deserialize;
-use std::str::FromStr;
-use std::time::Duration;
-use tokio::sync::mpsc;
-use tokio::time::sleep;
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+use crate::tls::TlsConfig;
+use crate::transport::{FeedConnection, Frame, Transport, DEFAULT_MAX_MESSAGE_SIZE};
+use log::{error, info, warn};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch, Mutex};
+use tokio::time::{sleep_until, Duration, Instant};
+use tokio_tungstenite::tungstenite::http::HeaderMap;
 use thiserror::Error;
 use metrics::{Counter, Gauge};
 
-#[derive(Debug, Deserialize)]
-pub struct BinanceTradeUpdate {
-    #[serde(rename = "p")]
-    pub price: String,
-    #[serde(rename = "q")]
-    pub quantity: String,
-    #[serde(rename = "T")]
-    pub timestamp: u64,
-    #[serde(rename = "m")]
-    pub is_buyer_maker: bool,
+pub use crate::exchange::binance::BinanceTradeUpdate;
+
+const FEED_LAG_GAUGE: &str = "trade_feed_lag_ms";
+const INGEST_LAG_HISTOGRAM: &str = "trade_ingest_lag_ms";
+/// How long before the exchange's `max_connection_age` the feed rolls over.
+const ROLLOVER_LEAD: Duration = Duration::from_secs(10 * 60);
+/// Longest the old connection keeps being read once the new one is flowing, for an old
+/// one that never catches up with it.
+const ROLLOVER_OVERLAP: Duration = Duration::from_secs(5);
+/// Wait before trying again when the connection to roll over to couldn't be opened.
+const ROLLOVER_RETRY: Duration = Duration::from_secs(60);
+/// Trade ids remembered for dropping trades received twice.
+const RECENT_TRADE_IDS: usize = 4096;
+
+/// Routes trades to one `ConcurrentTradesLog` per symbol, for a feed carrying several.
+#[derive(Debug, Clone)]
+pub struct TradesRouter {
+    /// Lowercase symbol to its log; empty for a router of a single log.
+    logs: HashMap<String, ConcurrentTradesLog>,
+    /// The log that takes every trade, named or not, when the feed fills only one.
+    single: Option<ConcurrentTradesLog>,
+}
+
+impl TradesRouter {
+    /// Routes each symbol's trades to its log in `logs`; trades in any other symbol, or
+    /// naming none, are unrouted.
+    pub fn new(logs: HashMap<String, ConcurrentTradesLog>) -> Self {
+        let logs = logs.into_iter().map(|(symbol, log)| (symbol.to_lowercase(), log)).collect();
+        Self { logs, single: None }
+    }
+
+    /// Routes every trade to `log`, whichever symbol it names.
+    pub fn single(log: ConcurrentTradesLog) -> Self {
+        Self { logs: HashMap::new(), single: Some(log) }
+    }
+
+    /// Routed symbols, lowercase and sorted; empty for a router of a single log.
+    pub fn symbols(&self) -> Vec<String> {
+        let mut symbols: Vec<String> = self.logs.keys().cloned().collect();
+        symbols.sort();
+        symbols
+    }
+
+    pub fn get_trades_log(&self, symbol: &str) -> Option<ConcurrentTradesLog> {
+        self.logs.get(&symbol.to_lowercase()).or(self.single.as_ref()).cloned()
+    }
+
+    /// Builds `<base>/stream?streams=<sym>@<stream>/<sym>@<stream>...`.
+    pub fn combined_stream_url(&self, base_url: &str, stream: &str) -> String {
+        let streams: Vec<String> = self.symbols().iter().map(|symbol| format!("{}@{}", symbol, stream)).collect();
+        format!("{}/stream?streams={}", base_url, streams.join("/"))
+    }
+
+    /// The log `trade` belongs in, if any.
+    pub fn route(&self, trade: &Trade) -> Option<&ConcurrentTradesLog> {
+        self.single.as_ref().or_else(|| self.logs.get(trade.symbol.as_ref()?))
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum FeedError {
     #[error("WebSocket error: {0}")]
     Websocket(#[from] tokio_tungstenite::tungstenite::Error),
-    #[error("JSON parse error: {0}")]
-    Json(#[from] serde_json::Error),
-    #[error("Decimal conversion error")]
-    DecimalConversion,
+    #[error("Failed to parse trade: {0}")]
+    Adapter(#[from] AdapterError),
+    #[error("Received trade for unrouted symbol {0:?}")]
+    UnroutedSymbol(Option<String>),
+    #[error("Failed to fetch recent trades: {0}")]
+    Backfill(#[from] SnapshotFetchError),
 }
 
 pub struct FeedMetrics {
     pub messages_received: Counter,
     pub trades_processed: Counter,
     pub connection_errors: Counter,
+    /// Trades dropped for a symbol the feed has no log for.
+    pub unrouted_trades: Counter,
+    /// Trades the exchange's trade ids show were never received, summed over every gap.
+    pub trade_gaps: Counter,
+    /// Trades fetched over REST on startup; see `LogFeedManager::with_backfill`.
+    pub backfilled_trades: Counter,
     pub current_connections: Gauge,
+    /// From receiving a trade message to having its trades in the log.
+    pub process_latency: ProcessLatency,
 }
 
 pub struct LogFeedManager {
-    trades_log: ConcurrentTradesLog,
-    uri: String,
+    router: TradesRouter,
+    /// Symbols the feed streams, where known; what a backfill fetches.
+    symbols: Vec<String>,
+    /// Trades per symbol fetched over REST before the first connect.
+    backfill: Option<usize>,
+    snapshots: SnapshotFetcher,
+    /// Equivalent endpoints for the one connection, preferred first.
+    endpoints: Vec<String>,
+    adapter: Arc<dyn ExchangeAdapter>,
+    /// Sent after every connect for exchanges that pick channels in-band.
+    subscribe_frame: Option<String>,
+    /// Connection age at which the feed moves onto a fresh connection; see `with_rollover_after`.
+    rollover_after: Option<Duration>,
     metrics: FeedMetrics,
+    lag: FeedLag,
+    /// Trade time to arrival of every trade; published through `health`.
+    ingest_lag: IngestLag,
+    health: HealthPublisher,
+    keepalive: KeepAliveConfig,
+    reconnect: ReconnectPolicy,
+    escalation: Option<FailureEscalation>,
+    tape: Option<TapeRecorder>,
+    trade_sink: Option<TradeSink>,
+    session_stats: Option<SessionStats>,
+    proxy: Option<ProxyConfig>,
+    tls: Option<TlsConfig>,
+    headers: HeaderMap,
+    connector: ConnectorFsm,
+    max_message_size: usize,
+    quarantine: Option<Quarantine>,
+    subscriptions: SubscriptionHandle,
+    requests: Mutex<mpsc::UnboundedReceiver<SubscriptionRequest>>,
 }
 
 impl LogFeedManager {
     pub fn new(uri: String, trades_log: ConcurrentTradesLog) -> Self {
+        Self::build(uri, Arc::new(BinanceAdapter::new()), TradesRouter::single(trades_log))
+    }
+
+    /// Streams the Binance trades of every symbol in `logs` over one combined-stream
+    /// connection, each into its own log.
+    pub fn with_symbols(logs: HashMap<String, ConcurrentTradesLog>) -> Self {
+        let router = TradesRouter::new(logs);
+        let uri = router.combined_stream_url(BINANCE_WS_BASE, "trade");
+        let mut manager = Self::build(uri, Arc::new(BinanceAdapter::new()), router);
+        manager.symbols = manager.router.symbols();
+        manager
+    }
+
+    pub fn from_config(config: &StreamConfig, trades_log: ConcurrentTradesLog) -> Self {
+        let mut manager = Self::with_adapter(exchange::adapter_for(config), &config.symbol, trades_log);
+        manager.endpoints = config.endpoints_for(&manager.endpoints[0]);
+        manager.proxy = config.proxy.clone();
+        manager.tls = config.tls.clone();
+        manager.headers = config.headers.clone();
+        manager
+    }
+
+    /// Streams `symbol`'s trades from whichever exchange the adapter speaks for.
+    pub fn with_adapter(adapter: Arc<dyn ExchangeAdapter>, symbol: &str, trades_log: ConcurrentTradesLog) -> Self {
+        let uri = adapter.trade_url(symbol);
+        let mut manager = Self::build(uri, adapter, TradesRouter::single(trades_log));
+        manager.subscribe_frame = manager.adapter.trade_subscribe_frame(symbol);
+        manager.symbols = vec![symbol.to_lowercase()];
+        manager
+    }
+
+    fn build(uri: String, adapter: Arc<dyn ExchangeAdapter>, router: TradesRouter) -> Self {
+        let (subscriptions, requests) = SubscriptionHandle::channel();
         Self {
-            trades_log,
-            uri,
+            router,
+            symbols: Vec::new(),
+            backfill: None,
+            snapshots: SnapshotFetcher::new(),
+            endpoints: vec![uri],
+            rollover_after: adapter.max_connection_age().map(|age| age.saturating_sub(ROLLOVER_LEAD)),
+            adapter,
+            subscribe_frame: None,
             metrics: FeedMetrics {
                 messages_received: metrics::register_counter!("log_feed_messages_received"),
                 trades_processed: metrics::register_counter!("log_feed_trades_processed"),
                 connection_errors: metrics::register_counter!("log_feed_connection_errors"),
+                unrouted_trades: metrics::register_counter!("log_feed_unrouted_trades"),
+                trade_gaps: metrics::register_counter!("log_feed_trade_gaps"),
+                backfilled_trades: metrics::register_counter!("log_feed_backfilled_trades"),
                 current_connections: metrics::register_gauge!("log_feed_current_connections"),
+                process_latency: ProcessLatency::new("trade_process_latency_us"),
             },
+            lag: FeedLag::new(FEED_LAG_GAUGE),
+            ingest_lag: IngestLag::new(INGEST_LAG_HISTOGRAM),
+            health: HealthPublisher::new(),
+            keepalive: KeepAliveConfig::default(),
+            reconnect: ReconnectPolicy::default(),
+            escalation: None,
+            tape: None,
+            trade_sink: None,
+            session_stats: None,
+            proxy: None,
+            tls: None,
+            headers: HeaderMap::new(),
+            connector: ConnectorFsm::new(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            quarantine: None,
+            subscriptions,
+            requests: Mutex::new(requests),
         }
     }
 
-    pub async fn start(&self) {
-        let mut retry_delay = Duration::from_secs(1);
+    pub fn with_keepalive(mut self, keepalive: KeepAliveConfig) -> Self {
+        self.keepalive = keepalive;
+        self
+    }
 
-        loop {
-            match connect_async(&self.uri).await {
-                Ok((ws_stream, _)) => {
-                    self.metrics.current_connections.set(1.0);
-                    info!("Connected to Trade WebSocket at {}", self.uri);
+    pub fn with_reconnect_policy(mut self, reconnect: ReconnectPolicy) -> Self {
+        self.reconnect = reconnect;
+        self
+    }
+
+    /// Stops after `max` failed connects in a row instead of reconnecting forever, e.g. to
+    /// a mistyped endpoint; see `ReconnectPolicy::max_consecutive_failures`. Set it after
+    /// `with_reconnect_policy`, which replaces the whole policy.
+    pub fn with_max_consecutive_failures(mut self, max: usize) -> Self {
+        self.reconnect.max_consecutive_failures = Some(max);
+        self
+    }
+
+    /// Sends a `PersistentFailure` to `tx` once `after` connects in a row have failed, and
+    /// keeps reconnecting; it fires again only after a connection stayed up past the
+    /// policy's `stable_after`.
+    pub fn on_persistent_failure(mut self, after: usize, tx: mpsc::UnboundedSender<PersistentFailure>) -> Self {
+        self.escalation = Some(FailureEscalation::new(after, tx));
+        self
+    }
+
+    /// Rolls over to a fresh connection once the current one is `after` old, instead of
+    /// shortly before the adapter's `max_connection_age`. Trades arriving on both while
+    /// they overlap are only inserted once, so nothing is lost or doubled.
+    pub fn with_rollover_after(mut self, after: Duration) -> Self {
+        self.rollover_after = Some(after);
+        self
+    }
+
+    /// Measures feed and ingest lag against `clock` instead of the system clock.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.lag = FeedLag::with_clock(FEED_LAG_GAUGE, clock.clone());
+        self.ingest_lag = IngestLag::with_clock(INGEST_LAG_HISTOGRAM, clock).with_floor(self.ingest_lag.floor_ms());
+        self
+    }
+
+    /// Clamps ingest lag readings at `floor_ms` instead of 0; see `IngestLag::with_floor`.
+    pub fn with_ingest_lag_floor(mut self, floor_ms: i64) -> Self {
+        self.ingest_lag = self.ingest_lag.with_floor(floor_ms);
+        self
+    }
+
+    /// Publishes this feed's health through `health`; hand both managers the same one
+    /// so a single receiver follows both feeds.
+    pub fn with_health(mut self, health: HealthPublisher) -> Self {
+        self.health = health;
+        self
+    }
+
+    /// Records every received frame under the `trades` stream.
+    pub fn with_tape(mut self, tape: TapeRecorder) -> Self {
+        self.tape = Some(tape.for_stream(TRADE_STREAM));
+        self
+    }
+
+    /// Persists every trade parsed off the feed through `sink`, e.g. for research beyond
+    /// the features files. Backfilled trades aren't, as they were received by an earlier run.
+    pub fn with_trade_sink(mut self, sink: TradeSink) -> Self {
+        self.trade_sink = Some(sink);
+        self
+    }
+
+    /// Adds every trade parsed off the feed to the session's totals; backfilled ones
+    /// belong to before the session and aren't.
+    pub fn with_session_stats(mut self, stats: SessionStats) -> Self {
+        self.session_stats = Some(stats);
+        self
+    }
+
+    /// Rotates the connection through these endpoints, e.g. the same stream on several
+    /// hosts, instead of the one it was built with. Must not be empty.
+    pub fn with_endpoints(mut self, endpoints: Vec<String>) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+
+    /// Tunnels the connection through `proxy`.
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Runs the TLS handshake of a `wss://` connection with `tls`.
+    pub fn with_tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Sends `headers` with every handshake, e.g. an API key for authenticated streams.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Drops frames larger than `bytes` instead of parsing them.
+    pub fn with_max_message_size(mut self, bytes: usize) -> Self {
+        self.max_message_size = bytes;
+        self
+    }
+
+    /// Seeds each symbol's log with its last `limit` trades over REST before the first
+    /// connect, so windowed metrics don't start out empty. Trades the live stream delivers
+    /// again at the handoff are dropped by id. Only for adapters with a `recent_trades_url`.
+    pub fn with_backfill(mut self, limit: usize) -> Self {
+        self.backfill = Some(limit);
+        self
+    }
+
+    /// Fetches backfilled trades through `snapshots`, e.g. one shared with a depth feed so
+    /// both count against the same request weight.
+    pub fn with_snapshot_fetcher(mut self, snapshots: SnapshotFetcher) -> Self {
+        self.snapshots = snapshots;
+        self
+    }
+
+    /// Keeps oversized frames and trade payloads that fail to parse under the `trades` stream.
+    pub fn with_quarantine(mut self, quarantine: Quarantine) -> Self {
+        self.quarantine = Some(quarantine.for_stream(TRADE_STREAM));
+        self
+    }
+
+    /// Adds trade streams (e.g. `ethusdt@trade`) to the live connection.
+    pub async fn subscribe(&self, streams: Vec<String>) -> Result<(), SubscriptionError> {
+        self.subscriptions.subscribe(streams).await
+    }
 
-                    let (_, mut read) = ws_stream.split();
+    pub async fn unsubscribe(&self, streams: Vec<String>) -> Result<(), SubscriptionError> {
+        self.subscriptions.unsubscribe(streams).await
+    }
+
+    /// Handle for changing streams once the manager has been moved into its task.
+    pub fn subscription_handle(&self) -> SubscriptionHandle {
+        self.subscriptions.clone()
+    }
+
+    /// Whether the trade connection is up right now.
+    pub fn connection_state(&self) -> ConnectorState {
+        self.connector.state()
+    }
+
+    /// Follows the trade connection's state, e.g. to flag data gathered while it was down.
+    pub fn watch_connection_state(&self) -> watch::Receiver<ConnectorState> {
+        self.connector.subscribe()
+    }
+
+    /// How far trade messages arrive behind their exchange event time.
+    pub fn feed_lag(&self) -> FeedLag {
+        self.lag.clone()
+    }
+
+    /// How far trades arrive behind their exchange trade time.
+    pub fn ingest_lag(&self) -> IngestLag {
+        self.ingest_lag.clone()
+    }
 
-                    while let Some(message_result) = read.next().await {
-                        self.metrics.messages_received.increment(1);
+    /// Where the trade half of the feed health is published.
+    pub fn health(&self) -> HealthPublisher {
+        self.health.clone()
+    }
+
+    /// Runs `start` on its own task and hands it back to be stopped and joined. The
+    /// manager can be spawned again once it has.
+    pub fn spawn(self: &Arc<Self>) -> FeedHandles {
+        let (stop, shutdown_rx) = watch::channel(false);
+        let manager = self.clone();
+        let task = tokio::spawn(async move { manager.start(shutdown_rx).await });
+        FeedHandles::new(vec![task], stop)
+    }
+
+    /// Streams trades into the log until `shutdown_rx` fires, or fails once the
+    /// reconnect policy gives up on the exchange.
+    pub async fn start(&self, mut shutdown_rx: watch::Receiver<bool>) -> Result<(), ReconnectError> {
+        let mut transport = Transport::with_endpoints(self.endpoints.clone(), self.keepalive, self.reconnect)
+            .with_connector(self.connector.clone())
+            .with_max_message_size(self.max_message_size)
+            .with_headers(self.headers.clone());
+        if let Some(tape) = &self.tape {
+            transport = transport.with_tape(tape.clone());
+        }
+        if let Some(proxy) = &self.proxy {
+            transport = transport.with_proxy(proxy.clone());
+        }
+        if let Some(tls) = &self.tls {
+            transport = transport.with_tls(tls.clone());
+        }
+        if let Some(quarantine) = &self.quarantine {
+            transport = transport.with_quarantine(quarantine.clone());
+        }
+        if let Some(escalation) = &self.escalation {
+            transport = transport.with_escalation(escalation.clone());
+        }
+        let mut requests = self.requests.lock().await;
+        let mut reconnected = false;
+        let mut backfilled = match self.backfill {
+            Some(limit) => tokio::select! {
+                seen = self.backfill(limit) => Some(seen),
+                _ = shutdown_rx.changed() => return Ok(()),
+            },
+            None => None,
+        };
 
-                        match message_result {
-                            Ok(Message::Text(text)) => {
-                                if let Err(err) = self.process_text_message(&text).await {
-                                    error!("Failed to process trade message: {}", err);
+        loop {
+            let connected = tokio::select! {
+                connected = transport.connect(Some(&mut *requests)) => connected,
+                _ = shutdown_rx.changed() => break,
+            };
+            match connected {
+                Ok(mut conn) => {
+                    self.metrics.current_connections.set(1.0);
+                    self.health.connected(Feed::Trade, reconnected);
+                    info!("Connected to Trade WebSocket at {}", transport.uri());
+                    self.send_subscribe(&mut conn, transport.uri()).await;
+                    let mut rollover_at = self.rollover_after.map(|after| Instant::now() + after);
+                    // Only rollovers and the backfill overlap; a reconnect starts from whatever
+                    // the exchange sends
+                    let mut seen = backfilled.take().unwrap_or_else(|| RecentTradeIds::new(RECENT_TRADE_IDS));
+                    // and its first trade is the new baseline for spotting skipped ids
+                    let mut continuity = TradeIdContinuity::default();
+
+                    loop {
+                        let frame = tokio::select! {
+                            frame = conn.next_frame() => frame,
+                            _ = sleep_until(rollover_at.unwrap_or_else(Instant::now)), if rollover_at.is_some() => {
+                                let rolled =
+                                    self.roll_over(&mut transport, &mut conn, &mut seen, &mut continuity, &mut shutdown_rx);
+                                match rolled.await {
+                                    Rollover::Done => rollover_at = self.rollover_after.map(|after| Instant::now() + after),
+                                    Rollover::Failed => rollover_at = Some(Instant::now() + ROLLOVER_RETRY),
+                                    Rollover::ShuttingDown => {
+                                        self.shut_down(&mut conn, transport.uri()).await;
+                                        return Ok(());
+                                    }
                                 }
+                                continue;
                             }
-                            Ok(Message::Binary(bin)) => {
-                                if let Ok(text) = String::from_utf8(bin) {
-                                    debug!("Trade Message (binary): {}", text);
-                                }
+                            _ = shutdown_rx.changed() => {
+                                self.shut_down(&mut conn, transport.uri()).await;
+                                return Ok(());
+                            }
+                        };
+                        match frame {
+                            Ok(Some(frame)) => {
+                                self.on_frame(&conn, frame, &mut seen, &mut continuity).await;
                             }
-                            Ok(_) => {}
+                            Ok(None) => break,
                             Err(err) => {
                                 self.metrics.connection_errors.increment(1);
                                 error!("WebSocket error: {}", err);
@@ -94,41 +480,1199 @@ impl LogFeedManager {
                         }
                     }
 
-                    warn!("⚠️ Trade WebSocket stream closed for {}", self.uri);
+                    warn!("⚠️ Trade WebSocket stream closed for {}", transport.uri());
                     self.metrics.current_connections.set(0.0);
+                    self.health.disconnected(Feed::Trade);
+                    reconnected = true;
                 }
                 Err(err) => {
                     self.metrics.connection_errors.increment(1);
-                    error!("Failed to connect to {}: {}", self.uri, err);
+                    error!("Failed to connect to {}: {}", transport.uri(), err);
                 }
             }
 
-            warn!("Reconnecting to {} in {:?}...", self.uri, retry_delay);
-            sleep(retry_delay).await;
-            retry_delay = std::cmp::min(retry_delay * 2, Duration::from_secs(60));
+            tokio::select! {
+                waited = transport.backoff() => waited?,
+                _ = shutdown_rx.changed() => break,
+            }
         }
+        info!("Trade feed for {} shutting down", transport.uri());
+        Ok(())
     }
 
-    async fn process_text_message(&self, text: &str) -> Result<(), FeedError> {
-        let update: BinanceTradeUpdate = serde_json::from_str(text)?;
-        let trade = Trade::try_from(update)?;
-        self.trades_log.insert_trade(trade).await;
-        self.metrics.trades_processed.increment(1);
-        Ok(())
+    async fn send_subscribe(&self, conn: &mut FeedConnection<'_>, uri: &str) {
+        if let Some(frame) = &self.subscribe_frame {
+            if let Err(err) = conn.send_text(frame.clone()).await {
+                error!("Failed to subscribe on {}: {}", uri, err);
+            }
+        }
+    }
+
+    async fn shut_down(&self, conn: &mut FeedConnection<'_>, uri: &str) {
+        conn.close().await;
+        self.metrics.current_connections.set(0.0);
+        self.health.disconnected(Feed::Trade);
+        info!("Trade feed for {} shutting down", uri);
+    }
+
+    /// Moves the feed onto a fresh connection before the exchange drops `conn` for its age.
+    ///
+    /// Both are read side by side, trades arriving on both inserted once, until the new
+    /// one is flowing and the old one has caught up with it: a trade already received on
+    /// the new one arrives on the old one too. Until then, trades still in flight on the
+    /// old connection aren't lost. An old connection that never catches up is given up
+    /// `ROLLOVER_OVERLAP` after the new one started flowing, or as soon as it closes.
+    ///
+    /// Each connection's trade ids are checked for gaps on their own, the new one's from
+    /// its first trade on.
+    async fn roll_over<'a>(
+        &self,
+        transport: &mut Transport,
+        conn: &mut FeedConnection<'a>,
+        seen: &mut RecentTradeIds<TradeKey>,
+        continuity: &mut TradeIdContinuity,
+        shutdown_rx: &mut watch::Receiver<bool>,
+    ) -> Rollover {
+        info!("Rolling the trade connection to {} over", transport.uri());
+        let mut next = match transport.connect_alongside().await {
+            Ok(next) => next,
+            Err(err) => {
+                self.metrics.connection_errors.increment(1);
+                warn!("Failed to open a connection to roll over to on {}: {}", transport.uri(), err);
+                return Rollover::Failed;
+            }
+        };
+        self.send_subscribe(&mut next, transport.uri()).await;
+        self.metrics.current_connections.set(2.0);
+
+        let mut next_continuity = TradeIdContinuity::default();
+        let mut switch_by: Option<Instant> = None;
+        loop {
+            tokio::select! {
+                frame = conn.next_frame() => match frame {
+                    Ok(Some(frame)) => {
+                        let delivery = self.on_frame(conn, frame, seen, continuity).await;
+                        if delivery == Delivery::Duplicate && switch_by.is_some() {
+                            break;
+                        }
+                    }
+                    // Closed first, so whatever is still to come arrives on the new one
+                    Ok(None) | Err(_) => break,
+                },
+                frame = next.next_frame() => match frame {
+                    Ok(Some(frame)) => {
+                        self.on_frame(&next, frame, seen, &mut next_continuity).await;
+                        switch_by.get_or_insert_with(|| Instant::now() + ROLLOVER_OVERLAP);
+                    }
+                    Ok(None) | Err(_) => {
+                        self.metrics.connection_errors.increment(1);
+                        self.metrics.current_connections.set(1.0);
+                        warn!("Connection to roll over to on {} closed; staying on the old one", transport.uri());
+                        return Rollover::Failed;
+                    }
+                },
+                _ = sleep_until(switch_by.unwrap_or_else(Instant::now)), if switch_by.is_some() => break,
+                _ = shutdown_rx.changed() => {
+                    next.close().await;
+                    return Rollover::ShuttingDown;
+                }
+            }
+        }
+
+        let previous = std::mem::replace(conn, next);
+        conn.take_over(previous).await;
+        *continuity = next_continuity;
+        self.metrics.current_connections.set(1.0);
+        info!("Trade feed rolled over to a new connection to {}", transport.uri());
+        Rollover::Done
     }
+
+    /// Handles one data frame, dropping the trades in it that were received just before.
+    async fn on_frame(
+        &self,
+        conn: &FeedConnection<'_>,
+        frame: Frame,
+        seen: &mut RecentTradeIds<TradeKey>,
+        continuity: &mut TradeIdContinuity,
+    ) -> Delivery {
+        self.health.message(Feed::Trade);
+        match frame {
+            Frame::Text(text) => {
+                let received = Instant::now();
+                let trades = match self.parse_trade_message(&text) {
+                    Ok(trades) => trades,
+                    Err(err) => {
+                        self.report(conn, &text, err.into());
+                        return Delivery::Processed;
+                    }
+                };
+                let count = trades.len();
+                let mut fresh = Vec::with_capacity(count);
+                for trade in trades {
+                    if let Some(span) = TradeIdSpan::of(&trade) {
+                        self.check_continuity(&span, continuity);
+                    }
+                    // Ids only identify a trade within its symbol
+                    match trade.trade_id {
+                        Some(id) if !seen.insert((trade.symbol.clone(), id)) => {}
+                        _ => fresh.push(trade),
+                    }
+                }
+                if fresh.is_empty() && count > 0 {
+                    return Delivery::Duplicate;
+                }
+                if let Err(err) = self.insert_trades(fresh, received).await {
+                    self.report(conn, &text, err);
+                }
+            }
+            Frame::Binary(bytes) => {
+                if let Err(err) = self.handle_binary_message(&bytes).await {
+                    self.report(conn, &String::from_utf8_lossy(&bytes), err);
+                }
+            }
+        }
+        Delivery::Processed
+    }
+
+    /// Inserts each symbol's recent trades into its log, returning their ids so the first
+    /// connection drops the ones it delivers again. A symbol whose trades can't be fetched
+    /// just starts out with the live stream's.
+    async fn backfill(&self, limit: usize) -> RecentTradeIds<TradeKey> {
+        let mut seen = RecentTradeIds::new(RECENT_TRADE_IDS);
+        if self.symbols.is_empty() {
+            warn!("Not backfilling trades: the feed's symbols aren't known");
+        }
+        for symbol in &self.symbols {
+            match self.recent_trades(symbol, limit).await {
+                Ok(trades) => {
+                    let count = trades.len();
+                    for trade in &trades {
+                        if let Some(id) = trade.trade_id {
+                            seen.insert((trade.symbol.clone(), id));
+                        }
+                    }
+                    match trades.first().and_then(|trade| self.router.route(trade)) {
+                        Some(log) => log.insert_trades(trades).await,
+                        None => self.metrics.unrouted_trades.increment(count as u64),
+                    }
+                    self.metrics.backfilled_trades.increment(count as u64);
+                    info!("Backfilled {} recent trade(s) for {}", count, symbol);
+                }
+                Err(err) => warn!("Not backfilling trades for {}: {}", symbol, err),
+            }
+        }
+        seen
+    }
+
+    /// `symbol`'s last `limit` trades with their ids, oldest first, tagged with the symbol.
+    async fn recent_trades(&self, symbol: &str, limit: usize) -> Result<Vec<Trade>, FeedError> {
+        let url = self
+            .adapter
+            .recent_trades_url(symbol, limit)
+            .ok_or_else(|| AdapterError::Unsupported(format!("{} recent trades", self.adapter.name())))?;
+        let body = self.snapshots.fetch(&url).await?;
+        let trades = self.adapter.parse_recent_trades(&body)?;
+        Ok(trades.into_iter().map(|trade| trade.with_symbol(symbol)).collect())
+    }
+
+    /// Counts a trade for a symbol without a log; quarantines anything else as malformed.
+    fn report(&self, conn: &FeedConnection<'_>, payload: &str, err: FeedError) {
+        if let FeedError::UnroutedSymbol(_) = err {
+            self.metrics.unrouted_trades.increment(1);
+            warn!("{}", err);
+        } else {
+            error!("Failed to process trade message: {}", err);
+            conn.quarantine(payload, &err);
+        }
+    }
+
+    /// Counts, logs and flags in the feed health the trades `span` shows this connection
+    /// skipped since its previous trade.
+    fn check_continuity(&self, span: &TradeIdSpan, continuity: &mut TradeIdContinuity) {
+        let missing = continuity.observe(span);
+        if missing > 0 {
+            self.metrics.trade_gaps.increment(missing);
+            self.health.trade_gap();
+            warn!(
+                "⚠️ Trade feed skipped {} trade(s) before trade id {} for {}",
+                missing,
+                span.first,
+                span.symbol.as_deref().unwrap_or("its symbol")
+            );
+        }
+    }
+
+    /// Handles one received frame: records its feed lag, then parses the trades it carries
+    /// into the log and returns how many there were. Nothing is inserted if any trade in
+    /// the frame fails to parse.
+    async fn handle_trade_message(&self, text: &str) -> Result<usize, FeedError> {
+        let received = Instant::now();
+        let trades = self.parse_trade_message(text)?;
+        self.insert_trades(trades, received).await
+    }
+
+    /// Records a received frame's feed lag and parses the trades it carries.
+    fn parse_trade_message(&self, text: &str) -> Result<Vec<Trade>, AdapterError> {
+        self.metrics.messages_received.increment(1);
+        if let Some(event_time) = self.adapter.event_time_ms(text) {
+            self.lag.record(event_time);
+        }
+        self.adapter.parse_trades(text)
+    }
+
+    /// Handles one binary frame through the adapter's `parse_binary`. Frames it hands back
+    /// as text go through `handle_trade_message`; decoded trades have their feed lag taken
+    /// from the last trade's time, as binary frames carry no separate event time.
+    async fn handle_binary_message(&self, bytes: &[u8]) -> Result<usize, FeedError> {
+        let received = Instant::now();
+        let trades = match self.adapter.parse_binary(bytes)? {
+            FeedEvent::Text(text) => return self.handle_trade_message(&text).await,
+            FeedEvent::Trades(trades) => trades,
+            FeedEvent::Depth(_) => {
+                return Err(AdapterError::Unsupported("depth event on the trade feed".to_string()).into())
+            }
+        };
+        self.metrics.messages_received.increment(1);
+        if let Some(trade) = trades.last() {
+            self.lag.record(trade.timestamp);
+        }
+        self.insert_trades(trades, received).await
+    }
+
+    /// Inserts `trades` into their symbols' logs, or none of them if any is unrouted. Each
+    /// log takes its share as one batch, so a frame carrying many trades locks it once.
+    /// Inserted trades also go to the trade sink, if any.
+    async fn insert_trades(&self, trades: Vec<Trade>, received: Instant) -> Result<usize, FeedError> {
+        let mut batches: Vec<(&ConcurrentTradesLog, Vec<Trade>)> = Vec::new();
+        for trade in trades {
+            let log = self.router.route(&trade).ok_or_else(|| FeedError::UnroutedSymbol(trade.symbol.clone()))?;
+            match batches.iter_mut().find(|(batch_log, _)| std::ptr::eq(*batch_log, log)) {
+                Some((_, batch)) => batch.push(trade),
+                None => batches.push((log, vec![trade])),
+            }
+        }
+        let count = batches.iter().map(|(_, batch)| batch.len()).sum();
+        for (log, batch) in batches {
+            for trade in &batch {
+                self.ingest_lag.record(trade.timestamp);
+                if let Some(sink) = &self.trade_sink {
+                    sink.record(RawTrade::new(trade));
+                }
+                if let Some(stats) = &self.session_stats {
+                    stats.record_trade(trade);
+                }
+            }
+            log.insert_trades(batch).await;
+        }
+        self.metrics.trades_processed.increment(count as u64);
+        self.metrics.process_latency.record(received.elapsed());
+        if count > 0 {
+            self.health.trade_ingest_lag(self.ingest_lag.lag_ms());
+        }
+        Ok(count)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rollover {
+    Done,
+    /// The new connection couldn't be opened or closed early; the old one carries on.
+    Failed,
+    ShuttingDown,
+}
+
+/// What became of a received frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Delivery {
+    Processed,
+    /// Its trade had already been received, e.g. on the other connection of a rollover.
+    Duplicate,
+}
+
+/// A trade's id along with the symbol it is unique within, where the payload names it.
+type TradeKey = (Option<String>, u64);
+
+/// Exchange trade ids one trade covers, for spotting trades the feed never received.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct TradeIdSpan {
+    /// Lowercase symbol, for payloads that name it.
+    symbol: Option<String>,
+    first: u64,
+    last: u64,
 }
 
-impl TryFrom<BinanceTradeUpdate> for Trade {
-    type Error = FeedError;
-
-    fn try_from(update: BinanceTradeUpdate) -> Result<Self, Self::Error> {
-        Ok(Self {
-            price: Decimal::from_str(&update.price)
-                .map_err(|_| FeedError::DecimalConversion)?,
-            quantity: Decimal::from_str(&update.quantity)
-                .map_err(|_| FeedError::DecimalConversion)?,
-            timestamp: update.timestamp,
-            is_buyer_maker: update.is_buyer_maker,
+impl TradeIdSpan {
+    /// The trades an aggregate trade stands for, or a plain trade's own id.
+    fn of(trade: &Trade) -> Option<Self> {
+        let (first, last) = trade.aggregated_trade_ids.or(trade.trade_id.map(|id| (id, id)))?;
+        Some(Self { symbol: trade.symbol.clone(), first, last })
+    }
+}
+
+/// Ids of the last `capacity` trades received.
+#[derive(Debug)]
+struct RecentTradeIds<K> {
+    capacity: usize,
+    order: VecDeque<K>,
+    ids: HashSet<K>,
+}
+
+impl<K: Clone + Eq + Hash> RecentTradeIds<K> {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            order: VecDeque::with_capacity(capacity),
+            ids: HashSet::with_capacity(capacity),
+        }
+    }
+
+    /// Remembers `id`, returning false if it already was.
+    fn insert(&mut self, id: K) -> bool {
+        if !self.ids.insert(id.clone()) {
+            return false;
+        }
+        self.order.push_back(id);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// Last trade id received per symbol on one connection.
+#[derive(Debug, Default)]
+struct TradeIdContinuity {
+    last: HashMap<String, u64>,
+}
+
+impl TradeIdContinuity {
+    /// Takes in the next span received and returns how many ids it skipped after the last
+    /// one. A symbol's first span only sets its baseline; spans at or behind it skip none.
+    fn observe(&mut self, span: &TradeIdSpan) -> u64 {
+        let symbol = span.symbol.as_deref().unwrap_or_default();
+        match self.last.get_mut(symbol) {
+            Some(last) => {
+                let missing = span.first.saturating_sub(*last + 1);
+                *last = span.last.max(*last);
+                missing
+            }
+            None => {
+                self.last.insert(symbol.to_string(), span.last);
+                0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot_fetcher::{SnapshotHttp, SnapshotResponse};
+    use futures_util::future::BoxFuture;
+    use futures_util::{SinkExt, StreamExt};
+    use rust_decimal_macros::dec;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+    use tokio::time::{sleep, timeout};
+    use tokio_tungstenite::tungstenite::protocol::Message;
+
+    #[test]
+    fn test_recent_trade_ids_forget_the_oldest() {
+        let mut seen = RecentTradeIds::new(3);
+        assert!([1, 2, 3].into_iter().all(|id| seen.insert(id)));
+        assert!(!seen.insert(2));
+        assert!(seen.insert(4));
+        // 1 fell out of the window, so it would be taken again
+        assert!(seen.insert(1));
+        assert!(!seen.insert(4));
+    }
+
+    #[test]
+    fn test_trade_id_continuity_counts_skipped_ids() {
+        let span = |symbol: &str, first, last| TradeIdSpan { symbol: Some(symbol.to_string()), first, last };
+        let mut continuity = TradeIdContinuity::default();
+        // @trade ids: the first sets the baseline, then 13 and 14 never arrive
+        let missing: Vec<_> =
+            [10, 11, 12, 15, 16].iter().map(|&id| continuity.observe(&span("btcusdt", id, id))).collect();
+        assert_eq!(missing, vec![0, 0, 0, 2, 0]);
+        // Repeats and stragglers from behind skip nothing and don't move the baseline back
+        assert_eq!(continuity.observe(&span("btcusdt", 14, 14)), 0);
+        assert_eq!(continuity.observe(&span("btcusdt", 17, 17)), 0);
+
+        // aggTrades continue from the last trade id of the one before
+        assert_eq!(continuity.observe(&span("ethusdt", 100, 105)), 0);
+        assert_eq!(continuity.observe(&span("ethusdt", 106, 106)), 0);
+        assert_eq!(continuity.observe(&span("ethusdt", 110, 112)), 3);
+        // Each symbol has its own baseline
+        assert_eq!(continuity.observe(&span("btcusdt", 18, 18)), 0);
+
+        // A new connection starts over wherever the exchange now is
+        let mut reconnected = TradeIdContinuity::default();
+        assert_eq!(reconnected.observe(&span("btcusdt", 500, 500)), 0);
+        assert_eq!(reconnected.observe(&span("btcusdt", 501, 501)), 0);
+    }
+
+    #[test]
+    fn test_binance_connections_roll_over_before_they_age_out() {
+        let manager = LogFeedManager::new("ws://127.0.0.1:1".to_string(), ConcurrentTradesLog::new(10));
+        assert_eq!(manager.rollover_after, Some(Duration::from_secs(24 * 60 * 60) - ROLLOVER_LEAD));
+
+        let coinbase = LogFeedManager::with_adapter(Arc::new(exchange::CoinbaseAdapter::new()), "BTC-USD", ConcurrentTradesLog::new(10));
+        assert_eq!(coinbase.rollover_after, None);
+        let forced = coinbase.with_rollover_after(Duration::from_secs(60));
+        assert_eq!(forced.rollover_after, Some(Duration::from_secs(60)));
+    }
+
+    #[tokio::test]
+    async fn test_handle_trade_message_fills_the_log() {
+        let trades_log = ConcurrentTradesLog::new(10);
+        let manager = LogFeedManager::new("ws://127.0.0.1:1".to_string(), trades_log.clone());
+
+        let trade = r#"{"e":"trade","E":1712745600100,"s":"BTCUSDT","t":1,"p":"69000.00","q":"0.5","T":1712745600100,"m":true}"#;
+        assert_eq!(manager.handle_trade_message(trade).await.unwrap(), 1);
+
+        let trades = trades_log.last_n_trades(10).await;
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].price, dec!(69000.00));
+        assert_eq!(trades[0].quantity, dec!(0.5));
+        assert!(trades[0].is_buyer_maker);
+        assert!(manager.feed_lag().lag_ms().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_parsed_trades_reach_the_trade_sink() {
+        use crate::clock::ManualClock;
+        use crate::persistence::load_raw_trades_from_parquet;
+
+        let dir = tempfile::tempdir().unwrap();
+        let (sink, writer) = TradeSink::spawn_with_clock(
+            dir.path(),
+            16,
+            Duration::from_secs(10),
+            Arc::new(ManualClock::new(1712745600000)),
+        );
+        let trades_log = ConcurrentTradesLog::new(10);
+        let manager = LogFeedManager::new("ws://127.0.0.1:1".to_string(), trades_log).with_trade_sink(sink);
+
+        let trade = r#"{"e":"trade","E":1712745600100,"s":"BTCUSDT","t":7,"p":"69000.00","q":"0.5","T":1712745600100,"m":true}"#;
+        manager.handle_trade_message(trade).await.unwrap();
+        // Unparseable frames never reach the sink
+        assert!(manager.handle_trade_message(r#"{"e":"trade"}"#).await.is_err());
+        drop(manager);
+        writer.await.unwrap();
+
+        let file = dir.path().join("trades_20240410_10.parquet");
+        let trades = load_raw_trades_from_parquet(&file.to_string_lossy()).unwrap();
+        assert_eq!(
+            trades,
+            vec![RawTrade {
+                timestamp: 1712745600100,
+                price: dec!(69000.00),
+                quantity: dec!(0.5),
+                is_buyer_maker: true,
+                trade_id: Some(7),
+                symbol: Some("btcusdt".to_string()),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_router_keeps_each_symbols_trades_apart() {
+        let (btc, eth) = (ConcurrentTradesLog::new(10), ConcurrentTradesLog::new(10));
+        let logs = HashMap::from([("BTCUSDT".to_string(), btc.clone()), ("ethusdt".to_string(), eth.clone())]);
+        let manager = LogFeedManager::with_symbols(logs);
+        assert_eq!(
+            manager.endpoints,
+            vec![format!("{}/stream?streams=btcusdt@trade/ethusdt@trade", BINANCE_WS_BASE)]
+        );
+
+        let trade = |symbol: &str, id: u64, price: &str| {
+            format!(
+                r#"{{"stream":"{}@trade","data":{{"e":"trade","E":1,"s":"{}","t":{},"p":"{}","q":"0.5","T":1,"m":true}}}}"#,
+                symbol,
+                symbol.to_uppercase(),
+                id,
+                price
+            )
+        };
+        for text in [
+            trade("btcusdt", 1, "69000.00"),
+            trade("ethusdt", 1, "3500.00"),
+            trade("ethusdt", 2, "3500.50"),
+            trade("btcusdt", 2, "69001.00"),
+            // A bare payload is routed by its `s`
+            r#"{"e":"trade","E":1,"s":"ETHUSDT","t":3,"p":"3501.00","q":"0.5","T":1,"m":true}"#.to_string(),
+        ] {
+            assert_eq!(manager.handle_trade_message(&text).await.unwrap(), 1);
+        }
+        let prices = |trades: Vec<Trade>| trades.iter().rev().map(|trade| trade.price).collect::<Vec<_>>();
+        assert_eq!(prices(btc.last_n_trades(10).await), vec![dec!(69000.00), dec!(69001.00)]);
+        assert_eq!(prices(eth.last_n_trades(10).await), vec![dec!(3500.00), dec!(3500.50), dec!(3501.00)]);
+        assert!(eth.last_n_trades(10).await.iter().all(|trade| trade.symbol.as_deref() == Some("ethusdt")));
+
+        match manager.handle_trade_message(&trade("solusdt", 1, "150.00")).await {
+            Err(FeedError::UnroutedSymbol(symbol)) => assert_eq!(symbol.as_deref(), Some("solusdt")),
+            other => panic!("Expected an unrouted trade, got {:?}", other),
+        }
+        assert_eq!(btc.last_n_trades(10).await.len() + eth.last_n_trades(10).await.len(), 5);
+
+        // A single log takes every trade, as before
+        let single = ConcurrentTradesLog::new(10);
+        let manager = LogFeedManager::new("ws://127.0.0.1:1".to_string(), single.clone());
+        manager.handle_trade_message(&trade("solusdt", 1, "150.00")).await.unwrap();
+        assert_eq!(single.last_n_trades(10).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_handled_messages_are_timed() {
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+
+        let _ = DebuggingRecorder::per_thread().install();
+        let manager = LogFeedManager::new("ws://127.0.0.1:1".to_string(), ConcurrentTradesLog::new(10));
+
+        let trade = r#"{"e":"trade","E":1712745600100,"s":"BTCUSDT","t":1,"p":"69000.00","q":"0.5","T":1712745600100,"m":true}"#;
+        manager.handle_trade_message(trade).await.unwrap();
+        manager.handle_trade_message(trade).await.unwrap();
+        assert!(manager.handle_trade_message("not json").await.is_err());
+
+        let samples = Snapshotter::current_thread_snapshot()
+            .unwrap()
+            .into_vec()
+            .into_iter()
+            .find_map(|(key, _, _, value)| match value {
+                DebugValue::Histogram(samples) if key.key().name() == "trade_process_latency_us" => Some(samples),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(samples.len(), 2);
+        assert!(manager.metrics.process_latency.percentile(0.99).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_handle_trade_message_rejects_bad_payloads() {
+        let trades_log = ConcurrentTradesLog::new(10);
+        let manager = LogFeedManager::new("ws://127.0.0.1:1".to_string(), trades_log.clone());
+
+        for text in [
+            r#"{"e":"trade","p":"69000.00""#,
+            "not json",
+            r#"{"e":"trade","E":1,"s":"BTCUSDT","t":1,"p":"abc","q":"0.5","T":1,"m":true}"#,
+            r#"{"e":"trade","E":1,"s":"BTCUSDT","t":1,"p":"69000.00","q":"-0.5","T":1,"m":true}"#,
+        ] {
+            match manager.handle_trade_message(text).await {
+                Err(FeedError::Adapter(_)) => {}
+                other => panic!("Expected a parse error for {}, got {:?}", text, other),
+            }
+        }
+        assert!(trades_log.last_n_trades(10).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unparseable_trade_is_quarantined_once() {
+        use crate::clock::ManualClock;
+        use crate::quarantine::{quarantine_file_name, read_quarantine};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}", listener.local_addr().unwrap());
+        let garbage = r#"{"e":"trade","p":"#;
+        let trade = r#"{"e":"trade","E":1,"s":"BTCUSDT","t":1,"p":"69000.00","q":"0.5","T":1,"m":true}"#;
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            ws.send(Message::Text(garbage.to_string())).await.unwrap();
+            ws.send(Message::Text(trade.to_string())).await.unwrap();
+            while let Some(Ok(_)) = ws.next().await {}
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let clock = Arc::new(ManualClock::new(1_712_745_600_000));
+        let trades_log = ConcurrentTradesLog::new(10);
+        let manager = LogFeedManager::new(uri, trades_log.clone())
+            .with_quarantine(Quarantine::with_clock(dir.path(), 10, clock.clone()));
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+
+        // The good trade behind the garbage still gets through
+        timeout(Duration::from_secs(2), async {
+            while trades_log.last_n_trades(1).await.is_empty() {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        shutdown_tx.send(true).unwrap();
+        feed.await.unwrap().unwrap();
+
+        let expected_error = match BinanceAdapter::new().parse_trades(garbage) {
+            Err(e) => FeedError::from(e).to_string(),
+            Ok(_) => panic!("garbage parsed"),
+        };
+        let entries = read_quarantine(dir.path().join(quarantine_file_name(clock.now_ms()))).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].stream, "trades");
+        assert_eq!(entries[0].payload, garbage);
+        assert_eq!(entries[0].error, expected_error);
+    }
+
+    #[tokio::test]
+    async fn test_answers_pings_and_reconnects_when_server_goes_silent() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let (pong_tx, mut pong_rx) = mpsc::unbounded_channel();
+
+        let server_connections = connections.clone();
+        tokio::spawn(async move {
+            loop {
+                let (tcp, _) = listener.accept().await.unwrap();
+                let attempt = server_connections.fetch_add(1, Ordering::SeqCst);
+                let pong_tx = pong_tx.clone();
+                tokio::spawn(async move {
+                    let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                    if attempt == 0 {
+                        ws.send(Message::Ping(b"hello".to_vec())).await.unwrap();
+                        while let Some(Ok(msg)) = ws.next().await {
+                            if let Message::Pong(payload) = msg {
+                                let _ = pong_tx.send(payload);
+                                break;
+                            }
+                        }
+                    }
+                    // Go silent without closing the socket
+                    sleep(Duration::from_secs(30)).await;
+                    drop(ws);
+                });
+            }
+        });
+
+        let manager = LogFeedManager::new(uri, ConcurrentTradesLog::new(10)).with_keepalive(KeepAliveConfig {
+            idle_interval: Duration::from_millis(100),
+            pong_timeout: Duration::from_millis(100),
+        });
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+        let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+
+        let pong = timeout(Duration::from_secs(2), pong_rx.recv()).await.unwrap();
+        assert_eq!(pong, Some(b"hello".to_vec()));
+
+        // Idle ping goes unanswered, so the manager reconnects after its 1s backoff
+        timeout(Duration::from_secs(5), async {
+            while connections.load(Ordering::SeqCst) < 2 {
+                sleep(Duration::from_millis(20)).await;
+            }
+        })
+        .await
+        .expect("manager did not reconnect");
+
+        feed.abort();
+    }
+
+    #[tokio::test]
+    async fn test_runtime_subscribe_and_unsubscribe() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}", listener.local_addr().unwrap());
+        let (frame_tx, mut frame_rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            while let Some(Ok(msg)) = ws.next().await {
+                if let Message::Text(text) = msg {
+                    let frame: serde_json::Value = serde_json::from_str(&text).unwrap();
+                    let id = frame["id"].as_u64().unwrap();
+                    let reply = if frame["method"] == "SUBSCRIBE" {
+                        // Ack followed by a trade from the newly added stream
+                        ws.send(Message::Text(format!(r#"{{"result":null,"id":{}}}"#, id))).await.unwrap();
+                        r#"{"e":"trade","E":1,"s":"ETHUSDT","t":7,"p":"3500.10","q":"2.5","T":1712745600000,"m":false}"#.to_string()
+                    } else {
+                        format!(r#"{{"error":{{"code":2,"msg":"Invalid request: unknown stream"}},"id":{}}}"#, id)
+                    };
+                    let _ = frame_tx.send(frame);
+                    ws.send(Message::Text(reply)).await.unwrap();
+                }
+            }
+        });
+
+        let trades_log = ConcurrentTradesLog::new(10);
+        let manager = Arc::new(LogFeedManager::new(uri, trades_log.clone()));
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+        let feed = tokio::spawn({
+            let manager = manager.clone();
+            async move { manager.start(shutdown_rx).await }
+        });
+
+        let subscribed = timeout(Duration::from_secs(2), manager.subscribe(vec!["ethusdt@trade".to_string()]))
+            .await
+            .unwrap();
+        assert_eq!(subscribed, Ok(()));
+        assert_eq!(
+            frame_rx.recv().await.unwrap(),
+            serde_json::json!({"method": "SUBSCRIBE", "params": ["ethusdt@trade"], "id": 1})
+        );
+
+        let unsubscribed = timeout(Duration::from_secs(2), manager.unsubscribe(vec!["bogus@trade".to_string()]))
+            .await
+            .unwrap();
+        assert_eq!(
+            unsubscribed,
+            Err(SubscriptionError::Rejected {
+                id: 2,
+                code: 2,
+                msg: "Invalid request: unknown stream".to_string(),
+            })
+        );
+        assert_eq!(
+            frame_rx.recv().await.unwrap(),
+            serde_json::json!({"method": "UNSUBSCRIBE", "params": ["bogus@trade"], "id": 2})
+        );
+
+        // The trade that followed the first ack was ingested, the ack itself was not
+        assert_eq!(trades_log.last_n_trades(10).await.len(), 1);
+
+        feed.abort();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_closes_connection_and_returns() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}", listener.local_addr().unwrap());
+        let (closed_tx, mut closed_rx) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            ws.send(Message::Text(
+                r#"{"e":"trade","E":1,"s":"BTCUSDT","t":1,"p":"69000.00","q":"0.5","T":1712745600000,"m":true}"#.to_string(),
+            ))
+            .await
+            .unwrap();
+            while let Some(Ok(msg)) = ws.next().await {
+                if let Message::Close(_) = msg {
+                    let _ = closed_tx.send(());
+                    break;
+                }
+            }
+        });
+
+        let trades_log = ConcurrentTradesLog::new(10);
+        let manager = LogFeedManager::new(uri, trades_log.clone());
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+
+        timeout(Duration::from_secs(2), async {
+            while trades_log.last_n_trades(1).await.is_empty() {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        shutdown_tx.send(true).unwrap();
+        let stopped = timeout(Duration::from_secs(1), feed).await.expect("feed did not stop").unwrap();
+        assert_eq!(stopped, Ok(()));
+        timeout(Duration::from_secs(1), closed_rx.recv()).await.expect("no close frame").unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_skips_reconnect_backoff() {
+        // Nothing listens here, so the manager sits in its reconnect backoff
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let manager = LogFeedManager::new(uri, ConcurrentTradesLog::new(10));
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+
+        sleep(Duration::from_millis(100)).await;
+        shutdown_tx.send(true).unwrap();
+        let stopped = timeout(Duration::from_millis(500), feed).await.expect("feed did not stop").unwrap();
+        assert_eq!(stopped, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let manager = LogFeedManager::new(uri.clone(), ConcurrentTradesLog::new(10)).with_reconnect_policy(ReconnectPolicy {
+            initial: Duration::from_millis(10),
+            max_attempts: Some(3),
+            ..ReconnectPolicy::default()
+        });
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        let result = timeout(Duration::from_secs(1), manager.start(shutdown_rx)).await.expect("manager kept retrying");
+        assert_eq!(result, Err(ReconnectError::GaveUp { uri, attempts: 3 }));
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_stops_after_consecutive_failed_connects() {
+        // Accepts and hangs up, so every handshake fails
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}", listener.local_addr().unwrap());
+        let accepts = Arc::new(AtomicUsize::new(0));
+        let counted = accepts.clone();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                counted.fetch_add(1, Ordering::SeqCst);
+                drop(tcp);
+            }
+        });
+
+        let manager = LogFeedManager::new(uri.clone(), ConcurrentTradesLog::new(10))
+            .with_reconnect_policy(ReconnectPolicy {
+                initial: Duration::from_millis(10),
+                ..ReconnectPolicy::default()
+            })
+            .with_max_consecutive_failures(3);
+        let states = manager.watch_connection_state();
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        let result = timeout(Duration::from_secs(1), manager.start(shutdown_rx)).await.expect("manager kept retrying");
+        assert_eq!(result, Err(ReconnectError::TooManyFailures { uri, failures: 3 }));
+        assert_eq!(accepts.load(Ordering::SeqCst), 3);
+        assert_eq!(*states.borrow(), ConnectorState::Error);
+    }
+
+    #[tokio::test]
+    async fn test_persistent_failure_fires_once_per_threshold_crossing() {
+        // Accepts and hangs up, so every handshake fails
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { while listener.accept().await.is_ok() {} });
+
+        let (escalations_tx, mut escalations) = mpsc::unbounded_channel();
+        let manager = LogFeedManager::new(uri.clone(), ConcurrentTradesLog::new(10))
+            .with_reconnect_policy(ReconnectPolicy {
+                initial: Duration::from_millis(1),
+                jitter_fraction: 0.0,
+                ..ReconnectPolicy::default()
+            })
+            .with_max_consecutive_failures(7)
+            .on_persistent_failure(3, escalations_tx);
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        let result = timeout(Duration::from_secs(2), manager.start(shutdown_rx)).await.expect("manager kept retrying");
+        assert_eq!(result, Err(ReconnectError::TooManyFailures { uri: uri.clone(), failures: 7 }));
+        let escalation = escalations.try_recv().unwrap();
+        assert_eq!((escalation.uri, escalation.failures), (uri, 3));
+        assert!(!escalation.error.is_empty());
+        // Four more failures past the threshold don't fire again
+        assert!(escalations.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_to_the_next_endpoint() {
+        let first = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let second = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoints = vec![
+            format!("ws://{}", first.local_addr().unwrap()),
+            format!("ws://{}", second.local_addr().unwrap()),
+        ];
+        let trade = |price: &str| {
+            format!(r#"{{"e":"trade","E":1,"s":"BTCUSDT","t":1,"p":"{price}","q":"0.5","T":1712745600000,"m":true}}"#)
+        };
+
+        // The first host serves one trade and then goes away for good
+        let first_trade = trade("100.00");
+        tokio::spawn(async move {
+            let (tcp, _) = first.accept().await.unwrap();
+            drop(first);
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            ws.send(Message::Text(first_trade)).await.unwrap();
+        });
+        let (accepted_tx, mut accepted_rx) = mpsc::unbounded_channel();
+        let second_trade = trade("101.00");
+        tokio::spawn(async move {
+            let (tcp, _) = second.accept().await.unwrap();
+            let _ = accepted_tx.send(());
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            ws.send(Message::Text(second_trade)).await.unwrap();
+            let _ = ws.next().await;
+        });
+
+        let trades_log = ConcurrentTradesLog::new(10);
+        let manager = LogFeedManager::new(endpoints[0].clone(), trades_log.clone())
+            .with_endpoints(endpoints)
+            .with_reconnect_policy(ReconnectPolicy {
+                initial: Duration::from_millis(10),
+                ..ReconnectPolicy::default()
+            });
+        let health = manager.health();
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+
+        timeout(Duration::from_secs(1), accepted_rx.recv()).await.expect("never reached the second host").unwrap();
+        timeout(Duration::from_secs(1), async {
+            while trades_log.last_n_trades(2).await.len() < 2 {
+                sleep(Duration::from_millis(10)).await;
+            }
         })
+        .await
+        .unwrap();
+        assert_eq!(trades_log.last_n_trades(1).await[0].price, dec!(101.00));
+        let published = health.health();
+        assert!(published.trade_connected && published.last_trade_msg_ms.is_some());
+        assert_eq!(published.reconnect_counts.trade, 1);
+
+        shutdown_tx.send(true).unwrap();
+        feed.await.unwrap().unwrap();
+        assert!(!health.health().trade_connected);
+    }
+
+    #[tokio::test]
+    async fn test_backoff_counts_whole_rotations() {
+        // Both hosts accept and hang up, so every handshake fails
+        let accepts = Arc::new([AtomicUsize::new(0), AtomicUsize::new(0)]);
+        let mut endpoints = Vec::new();
+        for i in 0..2 {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            endpoints.push(format!("ws://{}", listener.local_addr().unwrap()));
+            let accepts = accepts.clone();
+            tokio::spawn(async move {
+                while let Ok((tcp, _)) = listener.accept().await {
+                    accepts[i].fetch_add(1, Ordering::SeqCst);
+                    drop(tcp);
+                }
+            });
+        }
+
+        let manager = LogFeedManager::new(endpoints[0].clone(), ConcurrentTradesLog::new(10))
+            .with_endpoints(endpoints.clone())
+            .with_reconnect_policy(ReconnectPolicy {
+                initial: Duration::from_millis(10),
+                max_attempts: Some(2),
+                ..ReconnectPolicy::default()
+            });
+        let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+        let result = timeout(Duration::from_secs(1), manager.start(shutdown_rx)).await.expect("manager kept retrying");
+
+        // One rotation up front plus one per backoff attempt, each visiting both hosts
+        assert_eq!(
+            result,
+            Err(ReconnectError::GaveUp {
+                uri: endpoints[0].clone(),
+                attempts: 2
+            })
+        );
+        assert_eq!(accepts[0].load(Ordering::SeqCst), 3);
+        assert_eq!(accepts[1].load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_connection_state_is_exposed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}", listener.local_addr().unwrap());
+        let (hang_up_tx, hang_up_rx) = tokio::sync::oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            drop(listener);
+            let _ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            let _ = hang_up_rx.await;
+        });
+
+        let manager = LogFeedManager::new(uri, ConcurrentTradesLog::new(10)).with_reconnect_policy(ReconnectPolicy {
+            initial: Duration::from_secs(10),
+            ..ReconnectPolicy::default()
+        });
+        assert_eq!(manager.connection_state(), ConnectorState::Idle);
+        let mut states = manager.watch_connection_state();
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+
+        timeout(Duration::from_secs(1), states.wait_for(|state| *state == ConnectorState::Connected))
+            .await
+            .expect("never connected")
+            .unwrap();
+        // The drop passes through Idle on the way into the backoff
+        hang_up_tx.send(()).unwrap();
+        timeout(Duration::from_secs(1), states.wait_for(|state| *state == ConnectorState::Backoff))
+            .await
+            .expect("never backed off")
+            .unwrap();
+
+        shutdown_tx.send(true).unwrap();
+        feed.await.unwrap().unwrap();
+        assert_eq!(*states.borrow(), ConnectorState::Idle);
+    }
+
+    #[tokio::test]
+    async fn test_tape_records_received_frames() {
+        use crate::tape::read_tape;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}", listener.local_addr().unwrap());
+        let trade = r#"{"e":"trade","E":1,"s":"BTCUSDT","t":1,"p":"69000.00","q":"0.5","T":1712745600000,"m":true}"#;
+
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            ws.send(Message::Text(trade.to_string())).await.unwrap();
+            while let Some(Ok(_)) = ws.next().await {}
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let (tape, writer) = TapeRecorder::spawn(dir.path());
+        let trades_log = ConcurrentTradesLog::new(10);
+        let manager = LogFeedManager::new(uri, trades_log.clone()).with_tape(tape);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+
+        timeout(Duration::from_secs(2), async {
+            while trades_log.last_n_trades(1).await.is_empty() {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        shutdown_tx.send(true).unwrap();
+        feed.await.unwrap().unwrap();
+        timeout(Duration::from_secs(1), writer).await.unwrap().unwrap();
+
+        let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|f| f.unwrap().path()).collect();
+        assert_eq!(files.len(), 1);
+        let entries = read_tape(&files[0]).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].stream, "trades");
+        assert_eq!(entries[0].payload, trade);
+    }
+
+    /// Answers every request with `body`, recording the URIs asked for.
+    struct StaticHttp {
+        body: &'static str,
+        uris: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl SnapshotHttp for StaticHttp {
+        fn get<'a>(&'a self, uri: &'a str) -> BoxFuture<'a, anyhow::Result<SnapshotResponse>> {
+            self.uris.lock().unwrap().push(uri.to_string());
+            let response = SnapshotResponse {
+                status: 200,
+                used_weight: None,
+                retry_after: None,
+                body: self.body.to_string(),
+            };
+            Box::pin(async move { Ok(response) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_backfill_seeds_the_log_and_hands_off_to_the_live_stream() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}", listener.local_addr().unwrap());
+        // The first live trade was already backfilled; only the second is new
+        let live = [
+            r#"{"e":"trade","E":3,"s":"BTCUSDT","t":2,"p":"69001.00","q":"0.2","T":1712745600002,"m":false}"#,
+            r#"{"e":"trade","E":4,"s":"BTCUSDT","t":3,"p":"69002.00","q":"0.3","T":1712745600003,"m":true}"#,
+        ];
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            for trade in live {
+                ws.send(Message::Text(trade.to_string())).await.unwrap();
+            }
+            while let Some(Ok(_)) = ws.next().await {}
+        });
+
+        let http = Arc::new(StaticHttp {
+            body: r#"[{"id":1,"price":"69000.00","qty":"0.1","quoteQty":"6900.0","time":1712745600001,"isBuyerMaker":true,"isBestMatch":true},{"id":2,"price":"69001.00","qty":"0.2","quoteQty":"13800.2","time":1712745600002,"isBuyerMaker":false,"isBestMatch":true}]"#,
+            uris: std::sync::Mutex::new(Vec::new()),
+        });
+        let adapter = BinanceAdapter::new().with_rest_base("http://rest.test".to_string());
+        let trades_log = ConcurrentTradesLog::new(10);
+        let manager = LogFeedManager::with_adapter(Arc::new(adapter), "BTCUSDT", trades_log.clone())
+            .with_endpoints(vec![uri])
+            .with_backfill(500)
+            .with_snapshot_fetcher(SnapshotFetcher::with_http(http.clone()));
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+
+        timeout(Duration::from_secs(2), async {
+            while trades_log.last_n_trades(3).await.len() < 3 {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        // Give a wrongly kept duplicate time to land
+        sleep(Duration::from_millis(50)).await;
+        shutdown_tx.send(true).unwrap();
+        feed.await.unwrap().unwrap();
+
+        assert_eq!(*http.uris.lock().unwrap(), vec!["http://rest.test/api/v3/trades?symbol=BTCUSDT&limit=500"]);
+        let trades = trades_log.trades_since(0).await;
+        let times: Vec<u64> = trades.iter().map(|trade| trade.timestamp).collect();
+        assert_eq!(times, vec![1712745600001, 1712745600002, 1712745600003]);
+        assert_eq!(trades[0].price, dec!(69000.00));
+        assert!(trades.iter().all(|trade| trade.symbol.as_deref() == Some("btcusdt")));
+    }
+
+    #[tokio::test]
+    async fn test_ingest_lag_follows_trade_times_into_the_health() {
+        use crate::clock::ManualClock;
+
+        let clock = ManualClock::new(1712745600000);
+        let manager = LogFeedManager::new("ws://127.0.0.1:1".to_string(), ConcurrentTradesLog::new(10))
+            .with_ingest_lag_floor(-5)
+            .with_clock(Arc::new(clock.clone()));
+        let trade = |time: u64| {
+            format!(r#"{{"e":"trade","E":{time},"s":"BTCUSDT","t":1,"p":"69000.00","q":"0.5","T":{time},"m":true}}"#)
+        };
+        assert_eq!(manager.health().health().trade_ingest_lag_ms, None);
+
+        manager.handle_trade_message(&trade(1712745599900)).await.unwrap();
+        assert_eq!(manager.health().health().trade_ingest_lag_ms, Some(100));
+
+        // Stamped 50ms ahead of the local clock, so clamped at the floor
+        clock.advance(Duration::from_millis(10));
+        manager.handle_trade_message(&trade(1712745600060)).await.unwrap();
+        assert!((manager.ingest_lag().lag_ms().unwrap() - 89.5).abs() < 1e-9);
+        assert_eq!(manager.ingest_lag().last_skew_warning(), Some(1712745600010));
+        assert_eq!(manager.health().health().trade_ingest_lag_ms, Some(90));
+    }
+
+    #[tokio::test]
+    async fn test_feed_lag_follows_event_times() {
+        use crate::clock::ManualClock;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            for (id, event_time) in [(1, 1712745600100u64), (2, 1712745600180)] {
+                let trade = format!(
+                    r#"{{"e":"trade","E":{event_time},"s":"BTCUSDT","t":{id},"p":"69000.00","q":"0.5","T":{event_time},"m":true}}"#
+                );
+                ws.send(Message::Text(trade)).await.unwrap();
+            }
+            while let Some(Ok(_)) = ws.next().await {}
+        });
+
+        let trades_log = ConcurrentTradesLog::new(10);
+        let manager = LogFeedManager::new(uri, trades_log.clone())
+            .with_clock(Arc::new(ManualClock::new(1712745600200)));
+        let lag = manager.feed_lag();
+        assert_eq!(lag.lag_ms(), None);
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+        let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+
+        timeout(Duration::from_secs(2), async {
+            while trades_log.last_n_trades(2).await.len() < 2 {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        shutdown_tx.send(true).unwrap();
+        feed.await.unwrap().unwrap();
+
+        // 100ms then 20ms behind, smoothed
+        assert!((lag.lag_ms().unwrap() - 92.0).abs() < 1e-9, "{:?}", lag.lag_ms());
     }
 }
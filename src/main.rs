@@ -6,14 +6,48 @@ mod lob_feed_manager;
 mod log_feed_manager;
 mod analytics;
 mod persistence;
+mod depth_sync;
+mod stream_config;
+mod clock;
+mod connector;
+mod heartbeat;
+mod subscription;
+mod exchange;
+mod transport;
+mod reconnect;
+mod tape;
+mod latency;
+mod proxy;
+mod quarantine;
+mod replay;
+mod snapshot_fetcher;
+mod feed_handles;
+mod tls;
+mod consolidated;
+mod book_audit;
+mod feed_health;
+mod trade_sink;
+mod session_stats;
+mod metrics_server;
 
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::{spawn, sync::watch, time::Duration};
 use crate::{
+    book_audit::AuditConfig,
+    feed_health::HealthPublisher,
     orderbook::ConcurrentOrderBook,
     tradeslog::ConcurrentTradesLog,
     lob_feed_manager::LobFeedManager,
-    log_feed_manager::LogFeedManager
+    log_feed_manager::LogFeedManager,
+    metrics_server::MetricsServer,
+    proxy::ProxyConfig,
+    quarantine::Quarantine,
+    session_stats::SessionStats,
+    stream_config::{Environment, Exchange, StreamConfig},
+    tape::TapeRecorder,
+    trade_sink::TradeSink,
+    tls::TlsConfig,
 };
 
 #[tokio::main]
@@ -23,52 +57,280 @@ async fn main() {
     // Set up shutdown channel - NOTE: Now mutable
     let (shutdown_tx, mut shutdown_rx) = watch::channel(false);
 
+    // INGESTOR_METRICS_PORT=<port> serves every feed metric on http://0.0.0.0:<port>/metrics;
+    // it has to be up before the feed managers are built, as they register theirs then
+    if let Some(port) = std::env::var("INGESTOR_METRICS_PORT").ok().and_then(|p| p.parse::<u16>().ok()) {
+        match MetricsServer::bind(SocketAddr::from(([0, 0, 0, 0], port))).await {
+            Ok(server) => {
+                spawn(server.serve(shutdown_rx.clone()));
+            }
+            Err(e) => {
+                eprintln!("Failed to start metrics server: {}", e);
+                return;
+            }
+        }
+    }
+
+    // INGESTOR_EXCHANGE=coinbase|okx|bybit|kraken|bitstamp switches venue; Binance otherwise
+    let stream_config = match std::env::var("INGESTOR_EXCHANGE").as_deref() {
+        Ok("coinbase") => StreamConfig::new("BTC-USD").with_exchange(Exchange::Coinbase),
+        Ok("okx") => StreamConfig::new("BTC-USDT").with_exchange(Exchange::Okx),
+        Ok("bybit") => StreamConfig::new("BTCUSDT").with_exchange(Exchange::Bybit),
+        Ok("kraken") => StreamConfig::new("XBT/USD").with_exchange(Exchange::Kraken),
+        Ok("bitstamp") => StreamConfig::new("btcusd").with_exchange(Exchange::Bitstamp),
+        _ => StreamConfig::default(),
+    };
+
+    // --env testnet|vision|<gateway url> points the Binance feeds and REST snapshots elsewhere
+    let args: Vec<String> = std::env::args().collect();
+    let env_arg = args
+        .iter()
+        .position(|arg| arg == "--env")
+        .and_then(|i| args.get(i + 1).cloned())
+        .or_else(|| args.iter().find_map(|arg| arg.strip_prefix("--env=").map(str::to_string)));
+    let stream_config = match env_arg {
+        Some(arg) => match Environment::from_arg(&arg) {
+            Some(_) if stream_config.exchange != Exchange::Binance => {
+                eprintln!("--env only applies to Binance");
+                return;
+            }
+            Some(environment) => stream_config.with_environment(environment),
+            None => {
+                eprintln!("Unknown environment {}; expected prod, testnet, vision or a URL", arg);
+                return;
+            }
+        },
+        None => stream_config,
+    };
+
+    // INGESTOR_PROXY=http://host:port or socks5://host:port tunnels both feeds through a
+    // proxy; INGESTOR_PROXY_AUTH=<user>:<password> adds credentials
+    let stream_config = match std::env::var("INGESTOR_PROXY") {
+        Ok(url) => {
+            let mut proxy = ProxyConfig::new(&url);
+            if let Ok(auth) = std::env::var("INGESTOR_PROXY_AUTH") {
+                let (username, password) = auth.split_once(':').unwrap_or((&auth, ""));
+                proxy = proxy.with_auth(username, password);
+            }
+            stream_config.with_proxy(proxy)
+        }
+        Err(_) => stream_config,
+    };
+
+    // INGESTOR_TLS_ROOTS=<ca bundle.pem> trusts extra CAs on top of the system ones;
+    // INGESTOR_TLS_SNI=<name> sends and verifies that name instead of the URI's host
+    let roots = std::env::var("INGESTOR_TLS_ROOTS").ok();
+    let sni = std::env::var("INGESTOR_TLS_SNI").ok();
+    let stream_config = if roots.is_some() || sni.is_some() {
+        let mut tls = TlsConfig::new();
+        if let Some(roots) = roots {
+            tls = tls.with_custom_roots(roots);
+        }
+        if let Some(sni) = sni {
+            tls = tls.with_sni_override(&sni);
+        }
+        if let Err(e) = tls.connector() {
+            eprintln!("Invalid TLS settings: {}", e);
+            return;
+        }
+        stream_config.with_tls(tls)
+    } else {
+        stream_config
+    };
+
+    // INGESTOR_TAPE_DIR=<dir> records every raw frame from both feeds
+    let tape = std::env::var("INGESTOR_TAPE_DIR").ok().map(TapeRecorder::spawn);
+
+    // Totals over the whole run, summarized on shutdown
+    let session_stats = SessionStats::new();
+
     // Set up the order book feed manager
-    let lob_manager = LobFeedManager::new(
-        "wss://stream.binance.com:9443/ws/btcusdt@depth@100ms".to_string(),
-        "wss://stream.binance.com:9443/ws/btcusdt@depth".to_string(),
-    );
+    let mut lob_manager = LobFeedManager::from_config(&stream_config);
     let order_book = lob_manager.get_order_book();
+    // INGESTOR_SNAPSHOT_TIMING=1 times every book snapshot and its costliest features
+    if std::env::var("INGESTOR_SNAPSHOT_TIMING").is_ok() {
+        order_book.set_snapshot_timing(true).await;
+    }
     let order_book_arc = Arc::new(order_book);
 
     // Set up the trade log and its feed manager
     let trades_log = ConcurrentTradesLog::new(10_000);
     let trades_log_arc = Arc::new(trades_log.clone());
-    let log_manager = LogFeedManager::new(
-        "wss://stream.binance.com:9443/ws/btcusdt@trade".to_string(),
-        trades_log,
-    );
+    let mut log_manager =
+        LogFeedManager::from_config(&stream_config, trades_log).with_session_stats(session_stats.clone());
 
-    // Spawn components
-    let lob_handle = spawn(async move {
-        lob_manager.start().await;
-    });
+    if let Some((recorder, _)) = &tape {
+        lob_manager = lob_manager.with_tape(recorder.clone());
+        log_manager = log_manager.with_tape(recorder.clone());
+    }
 
-    let trades_handle = spawn(async move {
-        log_manager.start().await;
-    });
+    // INGESTOR_TRADES_DIR=<dir> also persists every raw trade to hourly Parquet files
+    let trade_sink = std::env::var("INGESTOR_TRADES_DIR").ok().map(TradeSink::spawn);
+    if let Some((sink, _)) = &trade_sink {
+        log_manager = log_manager.with_trade_sink(sink.clone());
+    }
+
+    // INGESTOR_BACKFILL_TRADES=<n> seeds the trades log with the last n trades over REST,
+    // so windowed metrics aren't empty until the live stream has filled them
+    if let Some(limit) = std::env::var("INGESTOR_BACKFILL_TRADES").ok().and_then(|n| n.parse::<usize>().ok()) {
+        log_manager = log_manager.with_backfill(limit);
+    }
+
+    // INGESTOR_AUDIT_MINUTES=<n> compares the book with a REST depth snapshot every n
+    // minutes and resyncs it when they diverge
+    if let Some(minutes) = std::env::var("INGESTOR_AUDIT_MINUTES").ok().and_then(|m| m.parse::<u64>().ok()) {
+        let audit = AuditConfig::new(Duration::from_secs(minutes * 60)).with_resync(true);
+        lob_manager = lob_manager.with_audit(audit);
+    }
 
-    let analytics_handle = spawn({
+    // INGESTOR_ALERT_AFTER_FAILURES=<n> reports a feed that failed n connects in a row
+    // while it keeps retrying, e.g. DNS or credentials gone bad
+    let alert_after = std::env::var("INGESTOR_ALERT_AFTER_FAILURES").ok().and_then(|n| n.parse::<usize>().ok());
+    if let Some(after) = alert_after.filter(|&n| n > 0) {
+        let (escalations_tx, mut escalations) = tokio::sync::mpsc::unbounded_channel();
+        lob_manager = lob_manager.on_persistent_failure(after, escalations_tx.clone());
+        log_manager = log_manager.on_persistent_failure(after, escalations_tx);
+        spawn(async move {
+            while let Some(failure) = escalations.recv().await {
+                eprintln!(
+                    "Feed at {} failed {} connects in a row, last: {}",
+                    failure.uri, failure.failures, failure.error
+                );
+            }
+        });
+    }
+
+    // Frames that fail to parse or go over the size limit are kept under quarantine/
+    let quarantine = Quarantine::new("quarantine");
+    let lob_manager = lob_manager.with_quarantine(quarantine.clone());
+    let log_manager = log_manager.with_quarantine(quarantine);
+
+    // Both feeds publish their liveness to one place, and snapshots flag rows taken
+    // while either was down or quiet
+    let health = HealthPublisher::new();
+    let lob_manager = lob_manager.with_health(health.clone());
+    let log_manager = log_manager.with_health(health.clone());
+
+    // Snapshots report how far behind the slower of the two feeds is running
+    let mut analytics_config = analytics::AnalyticsConfig {
+        feed_lags: vec![lob_manager.feed_lag(), log_manager.feed_lag()],
+        feed_health: Some(health.subscribe()),
+        symbol: Some(stream_config.symbol.to_lowercase()),
+        session_stats: Some(session_stats.clone()),
+        ..analytics::AnalyticsConfig::default()
+    };
+
+    // INGESTOR_OUTPUT_DIR=<dir> and INGESTOR_FILENAME_TEMPLATE=<template> say where Parquet
+    // batches go, e.g. {symbol}/features_{date}_{time}_{batch}.parquet
+    if let Ok(dir) = std::env::var("INGESTOR_OUTPUT_DIR") {
+        analytics_config.output_dir = dir.into();
+    }
+    if let Ok(template) = std::env::var("INGESTOR_FILENAME_TEMPLATE") {
+        analytics_config.filename_template = template;
+    }
+    // INGESTOR_EXACT_DECIMALS=1 stores prices and quantities exactly rather than as f64
+    analytics_config.persistence.exact_decimals = std::env::var("INGESTOR_EXACT_DECIMALS").is_ok();
+    // INGESTOR_SKIP_EMPTY_BOOK=1 keeps snapshots taken before the book first synced out of Parquet
+    analytics_config.skip_empty_book = std::env::var("INGESTOR_SKIP_EMPTY_BOOK").is_ok();
+    // INGESTOR_WARMUP_SECS=<n> persists no snapshots for the first n seconds, while the
+    // trade windows fill
+    if let Some(secs) = std::env::var("INGESTOR_WARMUP_SECS").ok().and_then(|n| n.parse::<u64>().ok()) {
+        analytics_config.warmup = Duration::from_secs(secs);
+    }
+    // INGESTOR_LAGGED_FEATURES=mid_price_lag1,imbalance_lag5 adds those features' values
+    // from that many ticks back to every snapshot
+    if let Ok(names) = std::env::var("INGESTOR_LAGGED_FEATURES") {
+        let lagged: Result<Vec<analytics::LaggedFeature>, _> =
+            names.split(',').map(|name| name.trim().parse()).collect();
+        match lagged {
+            Ok(lagged) => analytics_config.lagged_features = lagged,
+            Err(e) => {
+                eprintln!("Invalid INGESTOR_LAGGED_FEATURES: {}", e);
+                return;
+            }
+        }
+    }
+    if let Err(e) = analytics_config.validate() {
+        eprintln!("Invalid analytics config: {}", e);
+        return;
+    }
+    let summary_path = analytics_config
+        .output_dir
+        .join(format!("session_{}.json", chrono::Local::now().format("%Y%m%d_%H%M%S")));
+
+    // Spawn components
+    let mut lob_feeds = lob_manager.spawn();
+    let mut trade_feeds = Arc::new(log_manager).spawn();
+
+    let mut analytics_handle = spawn({
         let mut shutdown_rx = shutdown_rx.clone(); // Now mutable
         async move {
             analytics::run_analytics_task(
                 order_book_arc,
                 trades_log_arc,
+                analytics_config,
                 shutdown_rx
             ).await;
         }
     });
 
-    // Ctrl+C handler
-    let ctrl_c = async {
-        tokio::signal::ctrl_c().await.unwrap();
-        shutdown_tx.send(true).unwrap();
+    // Tasks only return early if they crash or give up reconnecting. Either way the
+    // shutdown below still runs, so the trade sink and tape get their footers and the
+    // session summary says what failed
+    let mut analytics_stopped = false;
+    let failure = tokio::select! {
+        _ = tokio::signal::ctrl_c() => {
+            println!("Shutting down...");
+            None
+        }
+        stopped = lob_feeds.join() => Some(match stopped {
+            Err(e) => format!("Order book feed stopped: {}", e),
+            Ok(()) => "Order book feed crashed".to_string(),
+        }),
+        stopped = trade_feeds.join() => Some(match stopped {
+            Err(e) => format!("Trade feed stopped: {}", e),
+            Ok(()) => "Trade feed crashed".to_string(),
+        }),
+        _ = &mut analytics_handle => {
+            analytics_stopped = true;
+            Some("Analytics task crashed".to_string())
+        }
     };
+    if let Some(failure) = &failure {
+        eprintln!("{}", failure);
+        session_stats.record_failure(failure.as_str());
+    }
+
+    // Let every task close its connections and finish before exiting
+    shutdown_tx.send(true).unwrap();
+    let analytics_done = async {
+        // A finished handle can't be awaited again
+        if !analytics_stopped {
+            let _ = analytics_handle.await;
+        }
+    };
+    let _ = tokio::join!(lob_feeds.shutdown(), trade_feeds.shutdown(), analytics_done);
+
+    session_stats.record_reconnects(health.health().reconnect_counts);
+    match persistence::write_session_summary(&session_stats, &summary_path) {
+        Ok(()) => println!("Session summary written to {}", summary_path.display()),
+        Err(e) => eprintln!("Failed to write session summary: {:#}", e),
+    }
+
+    // The feeds dropped their recorders on the way out, so the writer drains and stops
+    // once the last ones go with the order book manager
+    drop(lob_manager);
+    if let Some((recorder, writer)) = tape {
+        drop(recorder);
+        let _ = writer.await;
+    }
+    // Likewise the trade feed's sink, so the last hour's file gets its footer
+    if let Some((sink, writer)) = trade_sink {
+        drop(sink);
+        let _ = writer.await;
+    }
 
-    tokio::select! {
-        _ = ctrl_c => println!("Shutting down..."),
-        _ = lob_handle => eprintln!("Order book feed crashed"),
-        _ = trades_handle => eprintln!("Trade feed crashed"),
-        _ = analytics_handle => eprintln!("Analytics task crashed"),
+    if failure.is_some() {
+        std::process::exit(1);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,98 @@
+use log::{debug, info};
+use metrics_exporter_prometheus::{BuildError, PrometheusBuilder, PrometheusHandle};
+use std::io;
+use std::net::SocketAddr;
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+
+/// Longest request head read from a scraper before hanging up on it.
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+/// The content type of Prometheus' text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+#[derive(Debug, Error)]
+pub enum MetricsServerError {
+    #[error("Failed to install the Prometheus recorder: {0}")]
+    Install(#[from] BuildError),
+    #[error("Failed to bind the metrics listener: {0}")]
+    Bind(#[from] io::Error),
+}
+
+/// Serves every counter, gauge and histogram registered through the `metrics` crate on
+/// `GET /metrics`, in Prometheus' text format.
+///
+/// The feed managers register their metrics when they are built, against whichever
+/// recorder is installed at the time; start the server first, or theirs won't show.
+pub struct MetricsServer {
+    listener: TcpListener,
+    handle: PrometheusHandle,
+}
+
+impl MetricsServer {
+    /// Installs the Prometheus recorder as the process-wide one and listens on `addr`.
+    /// Fails if another recorder is already installed.
+    pub async fn bind(addr: SocketAddr) -> Result<Self, MetricsServerError> {
+        let listener = TcpListener::bind(addr).await?;
+        let handle = PrometheusBuilder::new().install_recorder()?;
+        Ok(Self { listener, handle })
+    }
+
+    /// Where scrapes go, e.g. the port picked for `addr` port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Answers scrapes, each on its own task, until `shutdown_rx` fires.
+    pub async fn serve(self, mut shutdown_rx: watch::Receiver<bool>) {
+        if let Ok(addr) = self.listener.local_addr() {
+            info!("Serving metrics on http://{}/metrics", addr);
+        }
+        loop {
+            let accepted = tokio::select! {
+                accepted = self.listener.accept() => accepted,
+                _ = shutdown_rx.changed() => break,
+            };
+            match accepted {
+                Ok((stream, peer)) => {
+                    let handle = self.handle.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = respond(stream, &handle).await {
+                            debug!("Metrics scrape from {} failed: {}", peer, e);
+                        }
+                    });
+                }
+                Err(e) => debug!("Failed to accept a metrics scrape: {}", e),
+            }
+        }
+    }
+}
+
+async fn respond(mut stream: TcpStream, handle: &PrometheusHandle) -> io::Result<()> {
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_REQUEST_HEAD {
+            return stream.write_all(&response("431 Request Header Fields Too Large", "", "")).await;
+        }
+        head.push(stream.read_u8().await?);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+    let reply = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => response("200 OK", CONTENT_TYPE, &handle.render()),
+        (Some("GET"), _) => response("404 Not Found", "", ""),
+        _ => response("405 Method Not Allowed", "", ""),
+    };
+    stream.write_all(&reply).await?;
+    stream.shutdown().await
+}
+
+fn response(status: &str, content_type: &str, body: &str) -> Vec<u8> {
+    let mut head = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+    if !content_type.is_empty() {
+        head.push_str(&format!("Content-Type: {}\r\n", content_type));
+    }
+    head.push_str("\r\n");
+    [head.as_bytes(), body.as_bytes()].concat()
+}
@@ -1,13 +1,37 @@
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use rust_decimal::Decimal;
+use tokio::sync::{broadcast, RwLock, RwLockWriteGuard};
+use rust_decimal::{Decimal, RoundingStrategy};
 use rust_decimal_macros::dec;
 use serde::Serialize;
 use num::FromPrimitive;
-use std::collections::{BTreeMap, VecDeque};
-use std::time::{Instant, Duration};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use crate::clock::{Clock, SystemClock};
+use crate::latency::ProcessLatency;
+use std::time::{Duration, Instant};
+
+/// `(price, quantity)` levels of one side of a diff.
+type Levels = Vec<(Decimal, Decimal)>;
+
+/// Histogram of the µs each `ConcurrentOrderBook` update waited for the write lock.
+pub const LOCK_WAIT_HISTOGRAM: &str = "lob_book_lock_wait_us";
+/// Flow events the tracker's buffer holds before it has to grow.
+const DEFAULT_FLOW_CAPACITY: usize = 2000;
+/// Percent distances from mid of the volume bands in every snapshot.
+pub const DEPTH_PROFILE_PERCENTS: [Decimal; 4] = [dec!(0.01), dec!(0.05), dec!(0.1), dec!(0.5)];
+/// `MidMoved` events a lagging receiver can fall behind by before it misses some.
+const MID_MOVE_CAPACITY: usize = 1024;
+
+/// How an exchange serialises its top levels before taking the CRC32.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumFormat {
+    /// `bid:qty:ask:qty:...` best first; once one side runs out the other continues alone (OKX).
+    Interleaved { depth: usize },
+    /// Asks then bids best first, each price and quantity with the decimal point
+    /// and leading zeros removed, all concatenated (Kraken).
+    Concatenated { depth: usize },
+}
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize)]
 pub enum OrderFlowEvent {
     BidOrder(Decimal),  
     AskOrder(Decimal),
@@ -17,30 +41,48 @@ pub enum OrderFlowEvent {
 
 #[derive(Debug, Clone)]
 pub struct RollingFlowTracker {
-    events: VecDeque<(Instant, OrderFlowEvent)>,
-    window: Duration,
+    events: VecDeque<(u64, OrderFlowEvent)>, // (epoch ms, event)
+    window_ms: u64,
     cancel_penalty: Decimal,
     min_pressure: Decimal,
+    clock: Arc<dyn Clock>,
 }
 
 impl RollingFlowTracker {
     pub fn new(window_secs: u64) -> Self {
+        Self::with_clock(window_secs, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(window_secs: u64, clock: Arc<dyn Clock>) -> Self {
+        Self::with_capacity(window_secs, DEFAULT_FLOW_CAPACITY, clock)
+    }
+
+    /// Tracker whose buffer holds `capacity` events before reallocating; size it for the
+    /// busiest window expected.
+    pub fn with_capacity(window_secs: u64, capacity: usize, clock: Arc<dyn Clock>) -> Self {
         Self {
-            events: VecDeque::with_capacity(2000),
-            window: Duration::from_secs(window_secs),
+            events: VecDeque::with_capacity(capacity),
+            window_ms: window_secs * 1000,
             cancel_penalty: dec!(0.35),
             min_pressure: dec!(2.5),
+            clock,
         }
     }
 
     pub fn add_event(&mut self, event: OrderFlowEvent) {
-        let now = Instant::now();
-        self.prune_old(now);
-        self.events.push_back((now, event));
+        let now = self.clock.now_ms();
+        self.add_event_at(now, event);
     }
 
-    fn prune_old(&mut self, now: Instant) {
-        let cutoff = now - self.window;
+    /// Records an event observed at `timestamp_ms` (epoch millis).
+    pub fn add_event_at(&mut self, timestamp_ms: u64, event: OrderFlowEvent) {
+        self.prune_old(timestamp_ms);
+        self.events.push_back((timestamp_ms, event));
+    }
+
+    /// Drops events older than the window; an event exactly `window` old is kept.
+    fn prune_old(&mut self, now_ms: u64) {
+        let cutoff = now_ms.saturating_sub(self.window_ms);
         while let Some((time, _)) = self.events.front() {
             if *time < cutoff {
                 self.events.pop_front();
@@ -50,15 +92,37 @@ impl RollingFlowTracker {
         }
     }
 
+    /// Events in the window with their epoch-ms timestamps, oldest first.
+    pub fn events(&self) -> impl Iterator<Item = &(u64, OrderFlowEvent)> + '_ {
+        self.events.iter()
+    }
+
     pub fn imbalance(&self) -> (Option<Decimal>, Decimal) {
+        self.imbalance_at(self.clock.now_ms())
+    }
+
+    /// Age-weighted imbalance as seen at `now_ms`.
+    pub fn imbalance_at(&self, now_ms: u64) -> (Option<Decimal>, Decimal) {
+        let (bid_pressure, ask_pressure, imbalance) = self.side_pressures_at(now_ms);
+        (imbalance, bid_pressure + ask_pressure)
+    }
+
+    pub fn side_pressures(&self) -> (Decimal, Decimal, Option<Decimal>) {
+        self.side_pressures_at(self.clock.now_ms())
+    }
+
+    /// Age-weighted bid and ask pressure as seen at `now_ms`, with the imbalance between
+    /// them. The pressures add up to the total `imbalance_at` reports; cancels only
+    /// weigh on the imbalance.
+    pub fn side_pressures_at(&self, now_ms: u64) -> (Decimal, Decimal, Option<Decimal>) {
         let mut bids = dec!(0);
         let mut asks = dec!(0);
         let mut bid_cancel_penalty = dec!(0);
         let mut ask_cancel_penalty = dec!(0);
         
         for (time, event) in &self.events {
-            let age_secs = (Instant::now() - *time).as_secs_f64();
-            let weight = 1.0 - (age_secs / self.window.as_secs_f64()).min(1.0);
+            let age_ms = now_ms.saturating_sub(*time) as f64;
+            let weight = 1.0 - (age_ms / self.window_ms as f64).min(1.0);
             let weight = Decimal::from_f64(weight).unwrap_or(dec!(1));
     
             match event {
@@ -74,13 +138,85 @@ impl RollingFlowTracker {
             let net_bids = bids - bid_cancel_penalty;
             let net_asks = asks - ask_cancel_penalty;
             let imbalance = (net_bids - net_asks) / total_pressure;
-            (Some(imbalance), total_pressure)
+            (bids, asks, Some(imbalance))
         } else {
-            (None, total_pressure)
+            (bids, asks, None)
+        }
+    }
+}
+
+/// Relative weights of the components blended by `OrderBook::pressure_score`.
+#[derive(Debug, Clone, Copy)]
+pub struct PressureWeights {
+    pub top_of_book: Decimal,
+    pub depth: Decimal,
+    pub order_flow: Decimal,
+}
+
+impl Default for PressureWeights {
+    fn default() -> Self {
+        Self {
+            top_of_book: dec!(1),
+            depth: dec!(1),
+            order_flow: dec!(1),
         }
     }
 }
 
+/// Which way the mid moved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidDirection {
+    Up,
+    Down,
+}
+
+/// The mid price landed in a different tick than the mid before it; see `OrderBook::mid_moves`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MidMoved {
+    pub old: Decimal,
+    pub new: Decimal,
+    pub direction: MidDirection,
+}
+
+/// Tracks which tick the mid is in and publishes a `MidMoved` when that changes.
+#[derive(Debug, Clone)]
+struct MidTicks {
+    tick_size: Decimal,
+    /// The last mid seen and its tick; kept while the book is empty, so a resync that
+    /// moved the market is still reported.
+    last: Option<(Decimal, Decimal)>,
+    tx: broadcast::Sender<MidMoved>,
+}
+
+impl MidTicks {
+    fn on_mid(&mut self, mid: Option<Decimal>) {
+        if let Some(mid) = mid {
+            let tick = (mid / self.tick_size).floor();
+            if let Some((old, old_tick)) = self.last {
+                if tick != old_tick {
+                    let direction = if tick > old_tick { MidDirection::Up } else { MidDirection::Down };
+                    // Nobody listening is fine
+                    let _ = self.tx.send(MidMoved { old, new: mid, direction });
+                }
+            }
+            self.last = Some((mid, tick));
+        }
+    }
+}
+
+/// `price` rounded to the nearest multiple of `tick_size`, halfway prices rounding up.
+fn round_to_tick(price: Decimal, tick_size: Decimal) -> Decimal {
+    (price / tick_size).round_dp_with_strategy(0, RoundingStrategy::MidpointAwayFromZero) * tick_size
+}
+
+/// `raw` levels with the quantities of every price rounding to the same tick summed.
+fn merged_to_ticks(raw: &BTreeMap<Decimal, Decimal>, tick_size: Decimal) -> BTreeMap<Decimal, Decimal> {
+    let mut levels = BTreeMap::new();
+    for (price, qty) in raw {
+        *levels.entry(round_to_tick(*price, tick_size)).or_insert(Decimal::ZERO) += *qty;
+    }
+    levels
+}
 
 #[derive(Debug, Clone)]
 pub struct OrderBook {
@@ -89,6 +225,54 @@ pub struct OrderBook {
     best_bid: Option<Decimal>,        // cached best bid price
     best_ask: Option<Decimal>,        // cached best ask price
     pub flow_tracker: RollingFlowTracker,
+    bid_level_created: HashMap<Decimal, u64>, // price -> creation time (epoch ms)
+    ask_level_created: HashMap<Decimal, u64>,
+    /// Set by a full snapshot, dropped when the book is cleared or its feed goes stale.
+    trusted: bool,
+    /// When levels last changed through a snapshot, diff or quote update (epoch ms).
+    last_update: Option<u64>,
+    clock: Arc<dyn Clock>,
+    /// Set while `get_snapshot` is being timed.
+    snapshot_timing: Option<SnapshotTiming>,
+    /// Set once someone asked for `mid_moves`.
+    mid_ticks: Option<MidTicks>,
+    /// Tick the level prices are rounded to; see `set_tick_size`.
+    tick_size: Option<Decimal>,
+    /// The levels at the prices the exchange sent, kept while `tick_size` is set so a
+    /// change to one price can be merged into its tick again.
+    raw_bids: BTreeMap<Decimal, Decimal>,
+    raw_asks: BTreeMap<Decimal, Decimal>,
+}
+
+/// Times `OrderBook::get_snapshot` as a whole and the features that scan deepest into
+/// the book, each under its own histogram.
+#[derive(Debug, Clone)]
+struct SnapshotTiming {
+    total: ProcessLatency,
+    pwi: ProcessLatency,
+    slope: ProcessLatency,
+    depth_profile: ProcessLatency,
+}
+
+impl SnapshotTiming {
+    fn new() -> Self {
+        Self {
+            total: ProcessLatency::new("orderbook_snapshot_us"),
+            pwi: ProcessLatency::new("orderbook_snapshot_pwi_us"),
+            slope: ProcessLatency::new("orderbook_snapshot_slope_us"),
+            depth_profile: ProcessLatency::new("orderbook_snapshot_depth_profile_us"),
+        }
+    }
+}
+
+/// Runs `compute`, recording how long it took under `latency` if there is one.
+fn timed<T>(latency: Option<&ProcessLatency>, compute: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let value = compute();
+    if let Some(latency) = latency {
+        latency.record(started.elapsed());
+    }
+    value
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -111,50 +295,201 @@ pub struct OrderBookSnapshot {
     pub ask_depth_ratio: Option<Decimal>,
     pub bid_volume_001: Option<Decimal>,
     pub ask_volume_001: Option<Decimal>,
+    pub bid_volume_005: Option<Decimal>,
+    pub ask_volume_005: Option<Decimal>,
+    pub bid_volume_010: Option<Decimal>,
+    pub ask_volume_010: Option<Decimal>,
+    pub bid_volume_050: Option<Decimal>,
+    pub ask_volume_050: Option<Decimal>,
     pub bid_avg_distance: Option<Decimal>,
     pub ask_avg_distance: Option<Decimal>,
     pub order_flow_imbalance: Option<Decimal>,
     pub order_flow_pressure: Decimal,  
+    /// The bid and ask shares of `order_flow_pressure`.
+    pub bid_flow_pressure: Decimal,
+    pub ask_flow_pressure: Decimal,
     pub microprice: Option<Decimal>,
+    pub composite_pressure: Option<Decimal>,
+    /// Whether the book was rebuilt from a snapshot and kept current since.
+    pub trusted: bool,
+    /// When the book last took an update (epoch ms); `None` until its first one.
+    pub last_update: Option<u64>,
 }
 
 impl OrderBook {
     /// Creates a new, empty order book.
     pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    /// Creates an empty order book that reads time from `clock`.
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self::with_capacity(0, DEFAULT_FLOW_CAPACITY, clock)
+    }
+
+    /// Creates an empty order book pre-sized for `levels` price levels per side and
+    /// `flow_events` events in the flow window, so a deep, busy book doesn't reallocate
+    /// while it fills up. The level maps themselves are B-trees and can't be pre-sized.
+    pub fn with_capacity(levels: usize, flow_events: usize, clock: Arc<dyn Clock>) -> Self {
         Self {
             bids: BTreeMap::new(),
             asks: BTreeMap::new(),
             best_bid: None,
             best_ask: None,
-            flow_tracker: RollingFlowTracker::new(10),  // 10-second window
+            flow_tracker: RollingFlowTracker::with_capacity(10, flow_events, clock.clone()),  // 10-second window
+            bid_level_created: HashMap::with_capacity(levels),
+            ask_level_created: HashMap::with_capacity(levels),
+            trusted: false,
+            last_update: None,
+            clock,
+            snapshot_timing: None,
+            mid_ticks: None,
+            tick_size: None,
+            raw_bids: BTreeMap::new(),
+            raw_asks: BTreeMap::new(),
         }
     }
 
+    /// Rounds incoming level prices to the nearest `tick_size`, or stops with `None`.
+    ///
+    /// For feeds that send prices more precise than the symbol's tick, which would
+    /// otherwise show up as phantom levels right next to each other: prices rounding to
+    /// the same tick become one level holding their summed quantity, and every feature
+    /// sees the merged levels. Levels already in the book are merged right away. Checksums
+    /// are taken over the merged levels, so they stop matching the exchange's once any
+    /// levels merged.
+    pub fn set_tick_size(&mut self, tick_size: Option<Decimal>) {
+        if let Some(tick_size) = tick_size {
+            assert!(tick_size > Decimal::ZERO, "tick size must be positive");
+        }
+        if self.tick_size.is_none() {
+            self.raw_bids = self.bids.clone();
+            self.raw_asks = self.asks.clone();
+        }
+        self.tick_size = tick_size;
+        self.merge_raw_levels();
+    }
+
+    /// Tick the level prices are rounded to, if any.
+    pub fn tick_size(&self) -> Option<Decimal> {
+        self.tick_size
+    }
+
+    /// Rebuilds the levels from the raw ones at the current tick size, leaving the raw
+    /// levels empty once there is none. Levels surviving the rebuild keep their age.
+    fn merge_raw_levels(&mut self) {
+        match self.tick_size {
+            Some(tick_size) => {
+                self.bids = merged_to_ticks(&self.raw_bids, tick_size);
+                self.asks = merged_to_ticks(&self.raw_asks, tick_size);
+            }
+            None => {
+                self.bids = std::mem::take(&mut self.raw_bids);
+                self.asks = std::mem::take(&mut self.raw_asks);
+            }
+        }
+        let now = self.clock.now_ms();
+        let bids = &self.bids;
+        self.bid_level_created.retain(|price, _| bids.contains_key(price));
+        for price in self.bids.keys() {
+            self.bid_level_created.entry(*price).or_insert(now);
+        }
+        let asks = &self.asks;
+        self.ask_level_created.retain(|price, _| asks.contains_key(price));
+        for price in self.asks.keys() {
+            self.ask_level_created.entry(*price).or_insert(now);
+        }
+        self.update_best_bid_ask();
+    }
+
+    /// Turns timing of `get_snapshot` on or off. While on, the whole snapshot and its
+    /// price-weighted imbalances, slopes and depth profile are each recorded into a
+    /// histogram (`orderbook_snapshot_us`, `orderbook_snapshot_pwi_us`, ...) with their
+    /// p99 logged periodically; see `ProcessLatency`.
+    pub fn set_snapshot_timing(&mut self, enabled: bool) {
+        if enabled != self.snapshot_timing.is_some() {
+            self.snapshot_timing = enabled.then(SnapshotTiming::new);
+        }
+    }
+
+    /// Events fired whenever the mid lands in a different `tick_size` bucket than the mid
+    /// before it, e.g. for a strategy that only reacts to tick moves rather than polling.
+    /// Updates that leave the mid within its tick fire nothing. Asking again adds another
+    /// receiver, and moves every receiver to the new tick size if it differs.
+    pub fn mid_moves(&mut self, tick_size: Decimal) -> broadcast::Receiver<MidMoved> {
+        assert!(tick_size > Decimal::ZERO, "tick size must be positive");
+        let mid = self.mid_price();
+        let ticks = self.mid_ticks.get_or_insert_with(|| MidTicks {
+            tick_size,
+            last: None,
+            tx: broadcast::channel(MID_MOVE_CAPACITY).0,
+        });
+        ticks.tick_size = tick_size;
+        ticks.last = mid.map(|mid| (mid, (mid / tick_size).floor()));
+        ticks.tx.subscribe()
+    }
+
     /// Replaces current book state with full snapshot.
     pub fn apply_snapshot(&mut self, bids: Vec<(Decimal, Decimal)>, asks: Vec<(Decimal, Decimal)>) {
-        self.bids.clear();
-        self.asks.clear();
+        self.raw_bids.clear();
+        self.raw_asks.clear();
 
         for (price, quantity) in bids {
             if price >= dec!(0) && quantity >= dec!(0) {
-                self.bids.insert(price, quantity);
+                self.raw_bids.insert(price, quantity);
             }
         }
 
         for (price, quantity) in asks {
             if price >= dec!(0) && quantity >= dec!(0) {
-                self.asks.insert(price, quantity);
+                self.raw_asks.insert(price, quantity);
             }
         }
 
+        // Levels surviving the snapshot keep their age; new ones start now
+        self.merge_raw_levels();
+        self.trusted = true;
+        self.last_update = Some(self.clock.now_ms());
+    }
+
+    /// Removes all levels, e.g. while waiting for a resync.
+    pub fn clear(&mut self) {
+        self.bids.clear();
+        self.asks.clear();
+        self.raw_bids.clear();
+        self.raw_asks.clear();
+        self.bid_level_created.clear();
+        self.ask_level_created.clear();
+        self.trusted = false;
+        self.last_update = None;
         self.update_best_bid_ask();
     }
 
+    /// Whether the book can be relied on: it was seeded by a snapshot, and neither cleared
+    /// nor marked untrusted since.
+    pub fn is_trusted(&self) -> bool {
+        self.trusted
+    }
+
+    /// When the book last took a snapshot, diff or quote update (epoch ms); `None` if it
+    /// hasn't since it was created or cleared. Updates that change nothing still count,
+    /// as they show the feed is alive.
+    pub fn last_update(&self) -> Option<u64> {
+        self.last_update
+    }
+
+    /// Flags the book as unreliable until the next snapshot, e.g. because its feed went quiet.
+    pub fn mark_untrusted(&mut self) {
+        self.trusted = false;
+    }
+
     pub fn apply_deltas(&mut self, bids: Vec<(Decimal, Decimal)>, asks: Vec<(Decimal, Decimal)>) {
+        let now = self.clock.now_ms();
+
         // Process bids
         for (price, qty) in bids {
             let event = if qty == dec!(0) {
-                if self.bids.contains_key(&price) {
+                if self.exchange_levels(true).contains_key(&price) {
                     OrderFlowEvent::BidCancel
                 } else {
                     continue;  // Not a real cancel
@@ -162,20 +497,32 @@ impl OrderBook {
             } else {
                 OrderFlowEvent::BidOrder(qty)
             };
-            self.flow_tracker.add_event(event);
+            self.flow_tracker.add_event_at(now, event);
 
             // Update book
-            if qty == dec!(0) {
+            if let Some(tick_size) = self.tick_size {
+                Self::set_ticked_level(
+                    &mut self.raw_bids,
+                    &mut self.bids,
+                    &mut self.bid_level_created,
+                    tick_size,
+                    price,
+                    qty,
+                    now,
+                );
+            } else if qty == dec!(0) {
                 self.bids.remove(&price);
+                self.bid_level_created.remove(&price);
             } else {
                 self.bids.insert(price, qty);
+                self.bid_level_created.entry(price).or_insert(now);
             }
         }
 
         // Process asks (mirror of bids)
         for (price, qty) in asks {
             let event = if qty == dec!(0) {
-                if self.asks.contains_key(&price) {
+                if self.exchange_levels(false).contains_key(&price) {
                     OrderFlowEvent::AskCancel
                 } else {
                     continue;
@@ -183,21 +530,143 @@ impl OrderBook {
             } else {
                 OrderFlowEvent::AskOrder(qty)
             };
-            self.flow_tracker.add_event(event);
-
-            if qty == dec!(0) {
+            self.flow_tracker.add_event_at(now, event);
+
+            if let Some(tick_size) = self.tick_size {
+                Self::set_ticked_level(
+                    &mut self.raw_asks,
+                    &mut self.asks,
+                    &mut self.ask_level_created,
+                    tick_size,
+                    price,
+                    qty,
+                    now,
+                );
+            } else if qty == dec!(0) {
                 self.asks.remove(&price);
+                self.ask_level_created.remove(&price);
             } else {
                 self.asks.insert(price, qty);
+                self.ask_level_created.entry(price).or_insert(now);
             }
         }
 
+        self.last_update = Some(now);
+        self.update_best_bid_ask();
+    }
+
+    /// Overwrites the touch from a best bid/offer update, leaving deeper levels alone.
+    ///
+    /// The quote is the exchange's current touch, so levels a lagging depth stream left
+    /// better than it are dropped, which also keeps the book from crossing. Quotes that
+    /// cross themselves are ignored.
+    pub fn apply_best_quotes(&mut self, bid_price: Decimal, bid_qty: Decimal, ask_price: Decimal, ask_qty: Decimal) {
+        if bid_price >= ask_price {
+            return;
+        }
+        let now = self.clock.now_ms();
+        let (bid_level, ask_level) = match self.tick_size {
+            Some(tick_size) => (round_to_tick(bid_price, tick_size), round_to_tick(ask_price, tick_size)),
+            None => (bid_price, ask_price),
+        };
+
+        let bids_above = self.bids.split_off(&bid_level);
+        for price in bids_above.keys().filter(|price| **price != bid_level) {
+            self.bid_level_created.remove(price);
+        }
+        let asks_from_touch = self.asks.split_off(&ask_level);
+        for price in std::mem::replace(&mut self.asks, asks_from_touch).keys() {
+            self.ask_level_created.remove(price);
+        }
+
+        if let Some(tick_size) = self.tick_size {
+            self.raw_bids.split_off(&bid_price);
+            self.raw_asks = self.raw_asks.split_off(&ask_price);
+            Self::set_ticked_level(
+                &mut self.raw_bids,
+                &mut self.bids,
+                &mut self.bid_level_created,
+                tick_size,
+                bid_price,
+                bid_qty,
+                now,
+            );
+            Self::set_ticked_level(
+                &mut self.raw_asks,
+                &mut self.asks,
+                &mut self.ask_level_created,
+                tick_size,
+                ask_price,
+                ask_qty,
+                now,
+            );
+        } else {
+            Self::set_level(&mut self.bids, &mut self.bid_level_created, bid_price, bid_qty, now);
+            Self::set_level(&mut self.asks, &mut self.ask_level_created, ask_price, ask_qty, now);
+        }
+        self.last_update = Some(now);
         self.update_best_bid_ask();
     }
 
+    fn set_level(
+        levels: &mut BTreeMap<Decimal, Decimal>,
+        created: &mut HashMap<Decimal, u64>,
+        price: Decimal,
+        qty: Decimal,
+        now: u64,
+    ) {
+        if qty > dec!(0) {
+            levels.insert(price, qty);
+            created.entry(price).or_insert(now);
+        } else {
+            levels.remove(&price);
+            created.remove(&price);
+        }
+    }
+
+    /// Sets the exchange's level at `price` on a side kept at `tick_size`: the raw level
+    /// is stored as sent, and its tick's level becomes the sum of the raw levels rounding to it.
+    fn set_ticked_level(
+        raw: &mut BTreeMap<Decimal, Decimal>,
+        levels: &mut BTreeMap<Decimal, Decimal>,
+        created: &mut HashMap<Decimal, u64>,
+        tick_size: Decimal,
+        price: Decimal,
+        qty: Decimal,
+        now: u64,
+    ) {
+        if qty > dec!(0) {
+            raw.insert(price, qty);
+        } else {
+            raw.remove(&price);
+        }
+        let level = round_to_tick(price, tick_size);
+        let half = tick_size / dec!(2);
+        let merged = raw
+            .range(level - half..=level + half)
+            .filter(|(raw_price, _)| round_to_tick(**raw_price, tick_size) == level)
+            .map(|(_, raw_qty)| *raw_qty)
+            .sum();
+        Self::set_level(levels, created, level, merged, now);
+    }
+
+    /// The levels at the prices the exchange sent them at.
+    fn exchange_levels(&self, is_bid: bool) -> &BTreeMap<Decimal, Decimal> {
+        match (self.tick_size.is_some(), is_bid) {
+            (true, true) => &self.raw_bids,
+            (true, false) => &self.raw_asks,
+            (false, true) => &self.bids,
+            (false, false) => &self.asks,
+        }
+    }
+
     fn update_best_bid_ask(&mut self) {
         self.best_bid = self.bids.keys().next_back().cloned();
         self.best_ask = self.asks.keys().next().cloned();
+        let mid = self.mid_price();
+        if let Some(ticks) = &mut self.mid_ticks {
+            ticks.on_mid(mid);
+        }
     }
 
     /// Returns the best bid price and quantity.
@@ -291,6 +760,103 @@ impl OrderBook {
         self.asks.iter().take(n).map(|(&p, &q)| (p, q)).collect()
     }
 
+    /// Level `index` from the best price (0 is the best bid/ask).
+    pub fn level_at(&self, is_bid: bool, index: usize) -> Option<(Decimal, Decimal)> {
+        if is_bid {
+            self.bids.iter().rev().nth(index).map(|(&p, &q)| (p, q))
+        } else {
+            self.asks.iter().nth(index).map(|(&p, &q)| (p, q))
+        }
+    }
+
+    /// Quantity summed from the best price through level `index` inclusive.
+    pub fn cumulative_to_level(&self, is_bid: bool, index: usize) -> Decimal {
+        if is_bid {
+            self.bids.values().rev().take(index + 1).sum()
+        } else {
+            self.asks.values().take(index + 1).sum()
+        }
+    }
+
+    /// CRC32 of the top levels, as exchanges publish it to verify local books.
+    ///
+    /// Prices and quantities are rendered with the scale they arrived with, so
+    /// the book must be fed the exchange's strings unnormalised. The result is
+    /// the signed 32-bit value.
+    pub fn checksum(&self, format: ChecksumFormat) -> i32 {
+        let payload = match format {
+            ChecksumFormat::Interleaved { depth } => {
+                let bids = self.top_bids(depth);
+                let asks = self.top_asks(depth);
+                let mut fields = Vec::with_capacity(bids.len() + asks.len());
+                for i in 0..bids.len().max(asks.len()) {
+                    if let Some((price, qty)) = bids.get(i) {
+                        fields.push(format!("{}:{}", price, qty));
+                    }
+                    if let Some((price, qty)) = asks.get(i) {
+                        fields.push(format!("{}:{}", price, qty));
+                    }
+                }
+                fields.join(":")
+            }
+            ChecksumFormat::Concatenated { depth } => {
+                let digits = |value: &Decimal| value.to_string().replace('.', "").trim_start_matches('0').to_string();
+                self.top_asks(depth)
+                    .iter()
+                    .chain(self.top_bids(depth).iter())
+                    .map(|(price, qty)| format!("{}{}", digits(price), digits(qty)))
+                    .collect()
+            }
+        };
+        crc32fast::hash(payload.as_bytes()) as i32
+    }
+
+    /// Drops levels beyond the best `depth` on each side.
+    pub fn truncate(&mut self, depth: usize) {
+        while self.bids.len() > depth {
+            let (price, _) = self.bids.pop_first().unwrap();
+            self.bid_level_created.remove(&price);
+        }
+        while self.asks.len() > depth {
+            let (price, _) = self.asks.pop_last().unwrap();
+            self.ask_level_created.remove(&price);
+        }
+        if let Some(tick_size) = self.tick_size {
+            let (bids, asks) = (&self.bids, &self.asks);
+            self.raw_bids.retain(|price, _| bids.contains_key(&round_to_tick(*price, tick_size)));
+            self.raw_asks.retain(|price, _| asks.contains_key(&round_to_tick(*price, tick_size)));
+        }
+        self.update_best_bid_ask();
+    }
+
+    /// How long the level at `price` has existed, if it is in the book.
+    pub fn level_age(&self, price: Decimal, is_bid: bool) -> Option<Duration> {
+        let created = if is_bid {
+            self.bid_level_created.get(&price)?
+        } else {
+            self.ask_level_created.get(&price)?
+        };
+        Some(Duration::from_millis(self.clock.now_ms().saturating_sub(*created)))
+    }
+
+    /// Mean age of the top `levels` price levels on one side.
+    pub fn avg_level_age(&self, levels: usize, is_bid: bool) -> Option<Duration> {
+        let prices: Vec<Decimal> = if is_bid {
+            self.bids.keys().rev().take(levels).copied().collect()
+        } else {
+            self.asks.keys().take(levels).copied().collect()
+        };
+        if prices.is_empty() {
+            return None;
+        }
+
+        let total: Duration = prices
+            .iter()
+            .filter_map(|&price| self.level_age(price, is_bid))
+            .sum();
+        Some(total / prices.len() as u32)
+    }
+
     /// Computes the spread (difference between best ask and best bid).
     pub fn spread(&self) -> Option<Decimal> {
         match (self.best_bid, self.best_ask) {
@@ -335,7 +901,7 @@ impl OrderBook {
     }
 
     pub fn volume_imbalance(&self) -> Option<Decimal> {
-        let bid_qty: Decimal = self.bids.values().take(5).copied().sum();
+        let bid_qty: Decimal = self.bids.values().rev().take(5).copied().sum();
         let ask_qty: Decimal = self.asks.values().take(5).copied().sum();
         let total = bid_qty + ask_qty;
         if total > dec!(0) {
@@ -380,6 +946,35 @@ impl OrderBook {
         Some((bid_volume, ask_volume))
     }
 
+    /// Cumulative volume within each percent distance of mid, as `(percent, bid_volume,
+    /// ask_volume)` in the order given. Each side is walked once outward from the touch, so
+    /// a wider band never holds less than a narrower one. Empty unless both sides are quoted.
+    pub fn depth_profile(&self, percents: &[Decimal]) -> Vec<(Decimal, Decimal, Decimal)> {
+        let mid = match self.mid_price() {
+            Some(mid) => mid,
+            None => return Vec::new(),
+        };
+        let mut narrowest_first: Vec<usize> = (0..percents.len()).collect();
+        narrowest_first.sort_by_key(|&i| percents[i]);
+
+        let mut profile: Vec<_> = percents.iter().map(|&percent| (percent, dec!(0), dec!(0))).collect();
+        let mut bids = self.bids.iter().rev().peekable();
+        let mut asks = self.asks.iter().peekable();
+        let (mut bid_volume, mut ask_volume) = (dec!(0), dec!(0));
+        for i in narrowest_first {
+            let range = mid * percents[i] / dec!(100);
+            while let Some((_, qty)) = bids.next_if(|(price, _)| **price >= mid - range) {
+                bid_volume += qty;
+            }
+            while let Some((_, qty)) = asks.next_if(|(price, _)| **price <= mid + range) {
+                ask_volume += qty;
+            }
+            profile[i].1 = bid_volume;
+            profile[i].2 = ask_volume;
+        }
+        profile
+    }
+
     pub fn avg_price_distance(&self, levels: usize) -> Option<(Decimal, Decimal)> {
         let mid = self.mid_price()?;
     
@@ -406,14 +1001,50 @@ impl OrderBook {
         Some(numerator / denominator)
     }
 
+    /// Blends top-of-book, depth-5 and order-flow imbalance into a score in [-1, 1].
+    ///
+    /// Book imbalances are rescaled from [0, 1] to [-1, 1]; components that are
+    /// unavailable are left out and the remaining weights renormalised.
+    pub fn pressure_score(&self, weights: PressureWeights) -> Option<Decimal> {
+        let to_signed = |ratio: Decimal| ratio * dec!(2) - dec!(1);
+        let components = [
+            (self.order_book_imbalance().map(to_signed), weights.top_of_book),
+            (self.volume_imbalance().map(to_signed), weights.depth),
+            (self.flow_tracker.imbalance().0, weights.order_flow),
+        ];
+
+        let mut weighted_sum = dec!(0);
+        let mut total_weight = dec!(0);
+        for (value, weight) in components {
+            if let Some(value) = value {
+                weighted_sum += value.max(dec!(-1)).min(dec!(1)) * weight;
+                total_weight += weight;
+            }
+        }
+
+        if total_weight > dec!(0) {
+            Some(weighted_sum / total_weight)
+        } else {
+            None
+        }
+    }
+
     pub fn get_snapshot(&self) -> OrderBookSnapshot {
+        let timing = self.snapshot_timing.as_ref();
+        let started = Instant::now();
         let best_bid = self.best_bid();
         let best_ask = self.best_ask();
         
         // Get flow metrics from the tracker
-        let (flow_imbalance, flow_pressure) = self.flow_tracker.imbalance();
+        let (bid_flow_pressure, ask_flow_pressure, flow_imbalance) = self.flow_tracker.side_pressures();
+        let profile = timed(timing.map(|t| &t.depth_profile), || self.depth_profile(&DEPTH_PROFILE_PERCENTS));
+        let band = |i: usize| profile.get(i).map(|&(_, bid, ask)| (bid, ask));
+        let [pwi_1, pwi_5, pwi_25, pwi_50] = timed(timing.map(|t| &t.pwi), || {
+            [dec!(1), dec!(5), dec!(25), dec!(50)].map(|percent| self.price_weighted_imbalance_percent(percent))
+        });
+        let slope = timed(timing.map(|t| &t.slope), || self.slope(5));
     
-        OrderBookSnapshot {
+        let snapshot = OrderBookSnapshot {
             best_bid,
             best_ask,
             mid_price: self.mid_price(),
@@ -421,23 +1052,38 @@ impl OrderBook {
             imbalance: self.order_book_imbalance(),
             top_bids: self.top_bids(5),
             top_asks: self.top_asks(5),
-            pwi_1: self.price_weighted_imbalance_percent(dec!(1)),
-            pwi_5: self.price_weighted_imbalance_percent(dec!(5)),
-            pwi_25: self.price_weighted_imbalance_percent(dec!(25)),
-            pwi_50: self.price_weighted_imbalance_percent(dec!(50)),
-            bid_slope: self.slope(5).map(|(b, _)| b),
-            ask_slope: self.slope(5).map(|(_, a)| a),
+            pwi_1,
+            pwi_5,
+            pwi_25,
+            pwi_50,
+            bid_slope: slope.map(|(b, _)| b),
+            ask_slope: slope.map(|(_, a)| a),
             volume_imbalance_top5: self.volume_imbalance(),
             bid_depth_ratio: self.depth_ratio().map(|(b, _)| b),
             ask_depth_ratio: self.depth_ratio().map(|(_, a)| a),
-            bid_volume_001: self.volume_within_percent_range(dec!(0.01)).map(|(b, _)| b),
-            ask_volume_001: self.volume_within_percent_range(dec!(0.01)).map(|(_, a)| a),
+            bid_volume_001: band(0).map(|(b, _)| b),
+            ask_volume_001: band(0).map(|(_, a)| a),
+            bid_volume_005: band(1).map(|(b, _)| b),
+            ask_volume_005: band(1).map(|(_, a)| a),
+            bid_volume_010: band(2).map(|(b, _)| b),
+            ask_volume_010: band(2).map(|(_, a)| a),
+            bid_volume_050: band(3).map(|(b, _)| b),
+            ask_volume_050: band(3).map(|(_, a)| a),
             bid_avg_distance: self.avg_price_distance(5).map(|(b, _)| b),
             ask_avg_distance: self.avg_price_distance(5).map(|(_, a)| a),
             order_flow_imbalance: flow_imbalance,
-            order_flow_pressure: flow_pressure,
+            order_flow_pressure: bid_flow_pressure + ask_flow_pressure,
+            bid_flow_pressure,
+            ask_flow_pressure,
             microprice: self.microprice(),
+            composite_pressure: self.pressure_score(PressureWeights::default()),
+            trusted: self.trusted,
+            last_update: self.last_update,
+        };
+        if let Some(timing) = timing {
+            timing.total.record(started.elapsed());
         }
+        snapshot
     }
 }
 
@@ -454,16 +1100,89 @@ impl ConcurrentOrderBook {
         }
     }
 
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(OrderBook::with_clock(clock))),
+        }
+    }
+
+    /// See `OrderBook::with_capacity`.
+    pub fn with_capacity(levels: usize, flow_events: usize, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(OrderBook::with_capacity(levels, flow_events, clock))),
+        }
+    }
+
+    /// Takes the write lock, recording how long it took under `LOCK_WAIT_HISTOGRAM` so
+    /// readers holding up the feed show up.
+    async fn write(&self) -> RwLockWriteGuard<'_, OrderBook> {
+        let started = std::time::Instant::now();
+        let book = self.inner.write().await;
+        metrics::histogram!(LOCK_WAIT_HISTOGRAM, started.elapsed().as_micros() as f64);
+        book
+    }
+
     pub async fn apply_snapshot(&self, bids: Vec<(Decimal, Decimal)>, asks: Vec<(Decimal, Decimal)>) {
-        let mut book = self.inner.write().await;
+        let mut book = self.write().await;
         book.apply_snapshot(bids, asks);
     }
 
     pub async fn apply_deltas(&self, bids: Vec<(Decimal, Decimal)>, asks: Vec<(Decimal, Decimal)>) {
-        let mut book = self.inner.write().await;
+        let mut book = self.write().await;
         book.apply_deltas(bids, asks);
     }
 
+    pub async fn clear(&self) {
+        let mut book = self.write().await;
+        book.clear();
+    }
+
+    pub async fn is_trusted(&self) -> bool {
+        let book = self.inner.read().await;
+        book.is_trusted()
+    }
+
+    pub async fn last_update(&self) -> Option<u64> {
+        let book = self.inner.read().await;
+        book.last_update()
+    }
+
+    pub async fn mark_untrusted(&self) {
+        let mut book = self.write().await;
+        book.mark_untrusted();
+    }
+
+    /// See `OrderBook::mid_moves`.
+    pub async fn mid_moves(&self, tick_size: Decimal) -> broadcast::Receiver<MidMoved> {
+        let mut book = self.write().await;
+        book.mid_moves(tick_size)
+    }
+
+    /// See `OrderBook::set_tick_size`.
+    pub async fn set_tick_size(&self, tick_size: Option<Decimal>) {
+        let mut book = self.write().await;
+        book.set_tick_size(tick_size);
+    }
+
+    /// See `OrderBook::set_snapshot_timing`.
+    pub async fn set_snapshot_timing(&self, enabled: bool) {
+        let mut book = self.write().await;
+        book.set_snapshot_timing(enabled);
+    }
+
+    pub async fn apply_best_quotes(&self, bid_price: Decimal, bid_qty: Decimal, ask_price: Decimal, ask_qty: Decimal) {
+        let mut book = self.write().await;
+        book.apply_best_quotes(bid_price, bid_qty, ask_price, ask_qty);
+    }
+
+    /// Applies several diffs under a single write lock.
+    pub async fn apply_delta_batch(&self, batch: Vec<(Levels, Levels)>) {
+        let mut book = self.write().await;
+        for (bids, asks) in batch {
+            book.apply_deltas(bids, asks);
+        }
+    }
+
     pub async fn best_bid(&self) -> Option<(Decimal, Decimal)> {
         let book = self.inner.read().await;
         book.best_bid()
@@ -504,6 +1223,36 @@ impl ConcurrentOrderBook {
         book.top_asks(n)
     }
 
+    pub async fn level_at(&self, is_bid: bool, index: usize) -> Option<(Decimal, Decimal)> {
+        let book = self.inner.read().await;
+        book.level_at(is_bid, index)
+    }
+
+    pub async fn cumulative_to_level(&self, is_bid: bool, index: usize) -> Decimal {
+        let book = self.inner.read().await;
+        book.cumulative_to_level(is_bid, index)
+    }
+
+    pub async fn checksum(&self, format: ChecksumFormat) -> i32 {
+        let book = self.inner.read().await;
+        book.checksum(format)
+    }
+
+    pub async fn truncate(&self, depth: usize) {
+        let mut book = self.write().await;
+        book.truncate(depth);
+    }
+
+    pub async fn level_age(&self, price: Decimal, is_bid: bool) -> Option<Duration> {
+        let book = self.inner.read().await;
+        book.level_age(price, is_bid)
+    }
+
+    pub async fn avg_level_age(&self, levels: usize, is_bid: bool) -> Option<Duration> {
+        let book = self.inner.read().await;
+        book.avg_level_age(levels, is_bid)
+    }
+
     pub async fn spread(&self) -> Option<Decimal> {
         let book = self.inner.read().await;
         book.spread()
@@ -539,6 +1288,11 @@ impl ConcurrentOrderBook {
         book.avg_price_distance(levels)
     }
 
+    pub async fn pressure_score(&self, weights: PressureWeights) -> Option<Decimal> {
+        let book = self.inner.read().await;
+        book.pressure_score(weights)
+    }
+
     pub async fn get_flow_imbalance(&self) -> (Option<Decimal>, Decimal) {
         let book = self.inner.read().await;
         book.flow_tracker.imbalance()
@@ -554,20 +1308,169 @@ impl ConcurrentOrderBook {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::ManualClock;
     use std::thread;
-    use std::time::{Duration, Instant};
+    use std::time::Duration;
 
     #[test]
     fn test_flow_tracker_pruning() {
         let mut tracker = RollingFlowTracker::new(1); // 1-second window
-        tracker.add_event(OrderFlowEvent::BidOrder(dec!(1.0)));
-        thread::sleep(Duration::from_millis(500));
-        tracker.add_event(OrderFlowEvent::AskOrder(dec!(2.0)));
+        tracker.add_event_at(1_000_000, OrderFlowEvent::BidOrder(dec!(1.0)));
+        tracker.add_event_at(1_000_500, OrderFlowEvent::AskOrder(dec!(2.0)));
         assert_eq!(tracker.events.len(), 2);
 
-        thread::sleep(Duration::from_millis(600)); // Total time > window
-        tracker.prune_old(Instant::now());
+        // Exactly one window old: still inside
+        tracker.prune_old(1_001_000);
+        assert_eq!(tracker.events.len(), 2);
+
+        // One millisecond past the boundary
+        tracker.prune_old(1_001_001);
         assert_eq!(tracker.events.len(), 1); // Only the second event remains
+        assert_eq!(tracker.events().next().unwrap().0, 1_000_500);
+    }
+
+    #[test]
+    fn test_snapshot_timing_fills_its_histograms() {
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+
+        let _ = DebuggingRecorder::per_thread().install();
+        // Sample counts per histogram recorded since the last call, which drains them
+        let recorded = || -> HashMap<String, usize> {
+            // No snapshot at all until this thread has recorded something
+            Snapshotter::current_thread_snapshot()
+                .map(|snapshot| snapshot.into_vec())
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|(key, _, _, value)| match value {
+                    DebugValue::Histogram(samples) => Some((key.key().name().to_string(), samples.len())),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        let mut book = OrderBook::new();
+        book.apply_snapshot(
+            (0..200).map(|i| (dec!(100) - Decimal::from(i) / dec!(10), dec!(1))).collect(),
+            (0..200).map(|i| (dec!(100.1) + Decimal::from(i) / dec!(10), dec!(1))).collect(),
+        );
+        let untimed = book.get_snapshot();
+        assert_eq!(recorded().get("orderbook_snapshot_us"), None);
+
+        book.set_snapshot_timing(true);
+        let timed = book.get_snapshot();
+        book.get_snapshot();
+        let counts = recorded();
+        for name in [
+            "orderbook_snapshot_us",
+            "orderbook_snapshot_pwi_us",
+            "orderbook_snapshot_slope_us",
+            "orderbook_snapshot_depth_profile_us",
+        ] {
+            assert_eq!(counts.get(name), Some(&2), "{}", name);
+        }
+        // Timing doesn't change what is computed
+        assert_eq!(serde_json::to_value(&timed).unwrap(), serde_json::to_value(&untimed).unwrap());
+
+        book.set_snapshot_timing(false);
+        book.get_snapshot();
+        assert_eq!(recorded().get("orderbook_snapshot_us").copied().unwrap_or(0), 0);
+    }
+
+    #[test]
+    fn test_level_at_and_cumulative_to_level() {
+        let mut book = OrderBook::new();
+        book.apply_snapshot(
+            vec![(dec!(100), dec!(1)), (dec!(99), dec!(2)), (dec!(98), dec!(3)), (dec!(97), dec!(4))],
+            vec![(dec!(101), dec!(5)), (dec!(102), dec!(6)), (dec!(103), dec!(7))],
+        );
+
+        assert_eq!(book.level_at(true, 0), Some((dec!(100), dec!(1))));
+        assert_eq!(book.level_at(true, 2), Some((dec!(98), dec!(3))));
+        assert_eq!(book.cumulative_to_level(true, 2), dec!(6));
+        assert_eq!(book.level_at(false, 2), Some((dec!(103), dec!(7))));
+        assert_eq!(book.cumulative_to_level(false, 2), dec!(18));
+
+        // Past the last level
+        assert_eq!(book.level_at(false, 3), None);
+        assert_eq!(book.cumulative_to_level(false, 10), dec!(18));
+    }
+
+    #[test]
+    fn test_checksum_interleaves_levels() {
+        let mut book = OrderBook::new();
+        book.apply_snapshot(
+            vec![(dec!(3366.1), dec!(7)), (dec!(3366), dec!(6))],
+            vec![(dec!(3366.8), dec!(9)), (dec!(3368), dec!(8))],
+        );
+        // 3366.1:7:3366.8:9:3366:6:3368:8
+        assert_eq!(book.checksum(ChecksumFormat::Interleaved { depth: 25 }), -1881014294);
+
+        // Uneven sides: 3366.1:7:3366.8:9:3368:8:3372:8
+        book.apply_snapshot(
+            vec![(dec!(3366.1), dec!(7))],
+            vec![(dec!(3366.8), dec!(9)), (dec!(3368), dec!(8)), (dec!(3372), dec!(8))],
+        );
+        assert_eq!(book.checksum(ChecksumFormat::Interleaved { depth: 25 }), 831078360);
+    }
+
+    #[test]
+    fn test_checksum_concatenated_strips_points_and_leading_zeros() {
+        let mut book = OrderBook::new();
+        book.apply_snapshot(
+            vec![(dec!(0.05005), dec!(0.00000500)), (dec!(0.05004), dec!(1.00000000))],
+            vec![(dec!(0.05010), dec!(0.50000000)), (dec!(0.05011), dec!(12.00000000))],
+        );
+        // Asks then bids: 5010|50000000 5011|1200000000 5005|500 5004|100000000
+        assert_eq!(
+            book.checksum(ChecksumFormat::Concatenated { depth: 10 }),
+            crc32fast::hash(b"5010500000005011120000000050055005004100000000") as i32
+        );
+    }
+
+    #[test]
+    fn test_truncate_keeps_best_levels() {
+        let mut book = OrderBook::new();
+        book.apply_snapshot(
+            vec![(dec!(100), dec!(1)), (dec!(99), dec!(2)), (dec!(98), dec!(3))],
+            vec![(dec!(101), dec!(1)), (dec!(102), dec!(2)), (dec!(103), dec!(3))],
+        );
+        book.truncate(2);
+        assert_eq!(book.top_bids(10), vec![(dec!(100), dec!(1)), (dec!(99), dec!(2))]);
+        assert_eq!(book.top_asks(10), vec![(dec!(101), dec!(1)), (dec!(102), dec!(2))]);
+        assert_eq!(book.level_age(dec!(98), true), None);
+    }
+
+    #[test]
+    fn test_flow_weights_use_event_timestamps() {
+        let mut tracker = RollingFlowTracker::new(10);
+        tracker.add_event_at(0, OrderFlowEvent::BidOrder(dec!(10.0)));
+        tracker.add_event_at(5_000, OrderFlowEvent::AskOrder(dec!(10.0)));
+
+        // Bid is half a window old, ask is fresh
+        let (imbalance, pressure) = tracker.imbalance_at(5_000);
+        assert_eq!(pressure, dec!(15));
+        assert!((imbalance.unwrap() - dec!(-5) / dec!(15)).abs() < dec!(0.0001));
+    }
+
+    #[test]
+    fn test_side_pressures_add_up_to_the_total() {
+        let mut tracker = RollingFlowTracker::new(10);
+        tracker.add_event_at(0, OrderFlowEvent::BidOrder(dec!(10.0)));
+        tracker.add_event_at(4_000, OrderFlowEvent::AskOrder(dec!(3.0)));
+        tracker.add_event_at(5_000, OrderFlowEvent::AskOrder(dec!(2.0)));
+        tracker.add_event_at(5_000, OrderFlowEvent::BidCancel);
+
+        let (bid_pressure, ask_pressure, imbalance) = tracker.side_pressures_at(5_000);
+        assert_eq!(bid_pressure, dec!(5));
+        assert_eq!(ask_pressure, dec!(4.7));
+        assert_eq!(tracker.imbalance_at(5_000), (imbalance, bid_pressure + ask_pressure));
+        // The cancel tips the imbalance to the asks, though the bids carry more pressure
+        assert!(imbalance.unwrap() < Decimal::ZERO);
+
+        // The sides still add up below the pressure an imbalance needs
+        let (bid_pressure, ask_pressure, imbalance) = tracker.side_pressures_at(9_900);
+        assert_eq!(imbalance, None);
+        assert_eq!(tracker.imbalance_at(9_900).1, bid_pressure + ask_pressure);
     }
 
     #[test]
@@ -662,4 +1565,345 @@ mod tests {
         // Test volume imbalance
         assert_eq!(book.volume_imbalance(), Some(dec!(0.5))); // 6 bids vs 6 asks
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_last_update_advances_with_each_update() {
+        let clock = ManualClock::new(1_000);
+        let mut book = OrderBook::with_clock(Arc::new(clock.clone()));
+        assert_eq!(book.get_snapshot().last_update, None);
+
+        book.apply_snapshot(vec![(dec!(100), dec!(1))], vec![(dec!(101), dec!(1))]);
+        assert_eq!(book.get_snapshot().last_update, Some(1_000));
+
+        clock.advance(Duration::from_millis(300));
+        book.apply_deltas(vec![(dec!(100), dec!(2))], vec![]);
+        assert_eq!(book.get_snapshot().last_update, Some(1_300));
+
+        // Reading the book doesn't count as an update
+        clock.advance(Duration::from_millis(200));
+        book.get_snapshot();
+        assert_eq!(book.last_update(), Some(1_300));
+
+        book.apply_best_quotes(dec!(100), dec!(1), dec!(100.5), dec!(1));
+        assert_eq!(book.last_update(), Some(1_500));
+
+        book.clear();
+        assert_eq!(book.get_snapshot().last_update, None);
+    }
+
+    #[test]
+    fn test_level_age_grows_and_resets() {
+        let clock = ManualClock::new(1_000);
+        let mut book = OrderBook::with_clock(Arc::new(clock.clone()));
+
+        book.apply_deltas(vec![(dec!(100), dec!(1))], vec![(dec!(101), dec!(1))]);
+        assert_eq!(book.level_age(dec!(100), true), Some(Duration::from_millis(0)));
+
+        clock.advance(Duration::from_millis(500));
+        // Quantity changes don't reset the level's age
+        book.apply_deltas(vec![(dec!(100), dec!(3))], vec![]);
+        clock.advance(Duration::from_millis(250));
+        assert_eq!(book.level_age(dec!(100), true), Some(Duration::from_millis(750)));
+
+        book.apply_deltas(vec![(dec!(100), dec!(0))], vec![]);
+        assert_eq!(book.level_age(dec!(100), true), None);
+
+        clock.advance(Duration::from_millis(100));
+        book.apply_deltas(vec![(dec!(100), dec!(2))], vec![]);
+        assert_eq!(book.level_age(dec!(100), true), Some(Duration::from_millis(0)));
+        assert_eq!(book.level_age(dec!(101), false), Some(Duration::from_millis(850)));
+    }
+
+    #[test]
+    fn test_avg_level_age() {
+        let clock = ManualClock::new(0);
+        let mut book = OrderBook::with_clock(Arc::new(clock.clone()));
+
+        book.apply_deltas(vec![(dec!(99), dec!(1))], vec![]);
+        clock.advance(Duration::from_millis(400));
+        book.apply_deltas(vec![(dec!(100), dec!(1))], vec![]);
+        clock.advance(Duration::from_millis(200));
+
+        // Ages 600ms and 200ms
+        assert_eq!(book.avg_level_age(2, true), Some(Duration::from_millis(400)));
+        assert_eq!(book.avg_level_age(1, true), Some(Duration::from_millis(200)));
+        assert_eq!(book.avg_level_age(5, false), None);
+    }
+
+    #[test]
+    fn test_pressure_score_components_agree() {
+        let mut book = OrderBook::new();
+        book.apply_deltas(
+            vec![(dec!(100), dec!(10)), (dec!(99), dec!(10)), (dec!(98), dec!(10))],
+            vec![(dec!(101), dec!(1))],
+        );
+
+        let score = book.pressure_score(PressureWeights::default()).unwrap();
+        assert!(score > dec!(0.8), "Expected strongly positive score, got {}", score);
+        assert!(score <= dec!(1));
+    }
+
+    #[test]
+    fn test_pressure_score_components_disagree() {
+        let mut book = OrderBook::new();
+        // Heavy touch bid (+0.8) against a heavier ask ladder (-1/3 depth, -1/3 flow)
+        book.apply_deltas(
+            vec![(dec!(100), dec!(9))],
+            vec![(dec!(101), dec!(1)), (dec!(102), dec!(8)), (dec!(103), dec!(9))],
+        );
+
+        let score = book.pressure_score(PressureWeights::default()).unwrap();
+        assert!(score.abs() < dec!(0.1), "Expected near-zero score, got {}", score);
+    }
+
+    #[test]
+    fn test_pressure_score_depth_uses_the_best_five_bids() {
+        let mut book = OrderBook::new();
+        // The asks outweigh the best five bids; only the two deep bids below them favour the bids
+        let bids = [(100, 1), (99, 1), (98, 1), (97, 1), (96, 1), (95, 50), (94, 50)];
+        let asks = [(101, 2), (102, 2), (103, 2), (104, 2), (105, 2)];
+        book.apply_deltas(
+            bids.iter().map(|&(price, qty)| (Decimal::from(price), Decimal::from(qty))).collect(),
+            asks.iter().map(|&(price, qty)| (Decimal::from(price), Decimal::from(qty))).collect(),
+        );
+
+        assert_eq!(book.volume_imbalance(), Some(dec!(5) / dec!(15)));
+        let depth_only = PressureWeights {
+            top_of_book: dec!(0),
+            depth: dec!(1),
+            order_flow: dec!(0),
+        };
+        let score = book.pressure_score(depth_only).unwrap();
+        assert!(score < dec!(0), "Expected the asks to dominate, got {}", score);
+    }
+
+    #[test]
+    fn test_pressure_score_empty_book() {
+        let book = OrderBook::new();
+        assert!(book.pressure_score(PressureWeights::default()).is_none());
+    }
+
+    fn ladder_book() -> OrderBook {
+        let mut book = OrderBook::new();
+        book.apply_snapshot(
+            vec![(dec!(100), dec!(1)), (dec!(99), dec!(2)), (dec!(98), dec!(3))],
+            vec![(dec!(101), dec!(1)), (dec!(102), dec!(2)), (dec!(103), dec!(3))],
+        );
+        book
+    }
+
+    #[test]
+    fn test_best_quotes_overwrite_only_the_touch() {
+        let mut book = ladder_book();
+        book.apply_best_quotes(dec!(100), dec!(5), dec!(101), dec!(0.5));
+
+        assert_eq!(book.best_bid(), Some((dec!(100), dec!(5))));
+        assert_eq!(book.best_ask(), Some((dec!(101), dec!(0.5))));
+        assert_eq!(book.top_bids(10)[1..], [(dec!(99), dec!(2)), (dec!(98), dec!(3))]);
+        assert_eq!(book.top_asks(10)[1..], [(dec!(102), dec!(2)), (dec!(103), dec!(3))]);
+        // Ticker updates are not order flow
+        assert_eq!(book.flow_tracker.events().count(), 0);
+    }
+
+    #[test]
+    fn test_best_quotes_never_cross_existing_depth() {
+        let mut book = ladder_book();
+        let quotes = [
+            // Market drops through two bid levels
+            (dec!(98.5), dec!(1), dec!(99.5), dec!(1)),
+            // ...and rallies through the asks it left behind
+            (dec!(101.5), dec!(2), dec!(102.5), dec!(1)),
+            // Tightens inside the spread
+            (dec!(101.6), dec!(1), dec!(101.7), dec!(1)),
+        ];
+        for (bid_price, bid_qty, ask_price, ask_qty) in quotes {
+            book.apply_best_quotes(bid_price, bid_qty, ask_price, ask_qty);
+            let (best_bid, _) = book.best_bid().unwrap();
+            let (best_ask, _) = book.best_ask().unwrap();
+            assert_eq!((best_bid, best_ask), (bid_price, ask_price));
+            assert!(book.bids.keys().all(|price| *price < best_ask));
+            assert!(book.asks.keys().all(|price| *price > best_bid));
+        }
+
+        // Stale levels on the crossed side are gone, deeper ones on the other side stay
+        assert_eq!(
+            book.top_bids(10),
+            vec![(dec!(101.6), dec!(1)), (dec!(101.5), dec!(2)), (dec!(98.5), dec!(1)), (dec!(98), dec!(3))]
+        );
+        assert_eq!(book.top_asks(10), vec![(dec!(101.7), dec!(1)), (dec!(102.5), dec!(1)), (dec!(103), dec!(3))]);
+        assert_eq!(book.bid_level_created.len(), book.bids.len());
+        assert_eq!(book.ask_level_created.len(), book.asks.len());
+    }
+
+    #[test]
+    fn test_crossed_or_empty_quotes() {
+        let mut book = ladder_book();
+        book.apply_best_quotes(dec!(102), dec!(1), dec!(101), dec!(1));
+        assert_eq!(book.best_bid(), Some((dec!(100), dec!(1))));
+        assert_eq!(book.best_ask(), Some((dec!(101), dec!(1))));
+
+        // An emptied touch leaves the next level as best
+        book.apply_best_quotes(dec!(100), dec!(0), dec!(101), dec!(1));
+        assert_eq!(book.best_bid(), Some((dec!(99), dec!(2))));
+    }
+
+    /// Fills the flow window with `events` bid orders spread over 500 levels, all within one second.
+    fn flood(book: &mut OrderBook, clock: &ManualClock, events: usize) {
+        for i in 0..events {
+            let price = Decimal::from(10_000 - (i % 500) as i64);
+            book.apply_deltas(vec![(price, dec!(1))], vec![]);
+            if i % 10 == 0 {
+                clock.advance(Duration::from_millis(1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_presized_flow_buffer_does_not_reallocate_below_capacity() {
+        let clock = ManualClock::new(1_000_000);
+        let mut book = OrderBook::with_capacity(1_000, 5_000, Arc::new(clock.clone()));
+        let flow_capacity = book.flow_tracker.events.capacity();
+        let level_capacity = book.bid_level_created.capacity();
+        assert!(flow_capacity >= 5_000);
+        assert!(level_capacity >= 1_000);
+
+        flood(&mut book, &clock, 4_900);
+        assert_eq!(book.flow_tracker.events.len(), 4_900);
+        assert_eq!(book.flow_tracker.events.capacity(), flow_capacity);
+        assert_eq!(book.bid_level_created.capacity(), level_capacity);
+
+        // The default buffer has to grow under the same load
+        let clock = ManualClock::new(1_000_000);
+        let mut book = OrderBook::with_clock(Arc::new(clock.clone()));
+        let default_capacity = book.flow_tracker.events.capacity();
+        flood(&mut book, &clock, 4_900);
+        assert!(book.flow_tracker.events.capacity() > default_capacity);
+    }
+
+    #[test]
+    fn test_trust_follows_snapshots() {
+        let mut book = OrderBook::new();
+        assert!(!book.get_snapshot().trusted);
+
+        book.apply_snapshot(vec![(dec!(100), dec!(1))], vec![(dec!(101), dec!(1))]);
+        book.apply_deltas(vec![(dec!(100), dec!(2))], vec![]);
+        assert!(book.get_snapshot().trusted);
+
+        book.mark_untrusted();
+        assert!(!book.is_trusted());
+        // The levels stay for inspection, flagged
+        assert_eq!(book.best_bid(), Some((dec!(100), dec!(2))));
+
+        book.apply_snapshot(vec![(dec!(100), dec!(1))], vec![(dec!(101), dec!(1))]);
+        assert!(book.is_trusted());
+        book.clear();
+        assert!(!book.is_trusted());
+    }
+
+    #[test]
+    fn test_depth_profile_is_cumulative() {
+        let mut book = OrderBook::new();
+        book.apply_snapshot(
+            vec![(dec!(99.99), dec!(1)), (dec!(99.95), dec!(2)), (dec!(99.90), dec!(3)), (dec!(99.50), dec!(4)), (dec!(90), dec!(5))],
+            vec![(dec!(100.01), dec!(1)), (dec!(100.04), dec!(2)), (dec!(100.10), dec!(3)), (dec!(101), dec!(4))],
+        );
+
+        // Bands come back in the order asked for
+        let percents = [dec!(0.5), dec!(0.01), dec!(0.1), dec!(0.05)];
+        let profile = book.depth_profile(&percents);
+        assert_eq!(
+            profile,
+            vec![
+                (dec!(0.5), dec!(10), dec!(6)),
+                (dec!(0.01), dec!(1), dec!(1)),
+                (dec!(0.1), dec!(6), dec!(6)),
+                (dec!(0.05), dec!(3), dec!(3)),
+            ]
+        );
+        for &(percent, bid, ask) in &profile {
+            assert_eq!(book.volume_within_percent_range(percent), Some((bid, ask)));
+        }
+
+        let mut sorted = profile.clone();
+        sorted.sort_by_key(|&(percent, _, _)| percent);
+        for pair in sorted.windows(2) {
+            assert!(pair[1].1 >= pair[0].1 && pair[1].2 >= pair[0].2, "{:?}", pair);
+        }
+
+        let snapshot = book.get_snapshot();
+        assert_eq!((snapshot.bid_volume_005, snapshot.ask_volume_050), (Some(dec!(3)), Some(dec!(6))));
+        assert!(OrderBook::new().depth_profile(&percents).is_empty());
+    }
+
+    #[test]
+    fn test_mid_moves_fire_only_on_tick_changes() {
+        use tokio::sync::broadcast::error::TryRecvError;
+
+        let mut book = OrderBook::new();
+        book.apply_snapshot(
+            vec![(dec!(100.0), dec!(1)), (dec!(99.0), dec!(1))],
+            vec![(dec!(101.0), dec!(1)), (dec!(102.0), dec!(1))],
+        );
+        let mut moves = book.mid_moves(dec!(1));
+
+        // Size changes, deeper levels and a better bid that keeps the mid within 100..101
+        book.apply_deltas(vec![(dec!(100.0), dec!(3))], vec![(dec!(101.0), dec!(0.5))]);
+        book.apply_deltas(vec![(dec!(98.0), dec!(2)), (dec!(99.0), dec!(0))], vec![(dec!(103.0), dec!(4))]);
+        book.apply_deltas(vec![(dec!(100.4), dec!(1))], vec![]);
+        assert_eq!(book.mid_price(), Some(dec!(100.7)));
+        assert_eq!(moves.try_recv(), Err(TryRecvError::Empty));
+
+        // Taking out the best ask lifts the mid into the next tick
+        book.apply_deltas(vec![], vec![(dec!(101.0), dec!(0))]);
+        assert_eq!(
+            moves.try_recv(),
+            Ok(MidMoved { old: dec!(100.7), new: dec!(101.2), direction: MidDirection::Up })
+        );
+        assert_eq!(moves.try_recv(), Err(TryRecvError::Empty));
+
+        // A resync lower down is reported against the mid from before the book was cleared
+        book.clear();
+        book.apply_snapshot(vec![(dec!(97.0), dec!(1))], vec![(dec!(98.0), dec!(1))]);
+        assert_eq!(
+            moves.try_recv(),
+            Ok(MidMoved { old: dec!(101.2), new: dec!(97.5), direction: MidDirection::Down })
+        );
+        assert_eq!(moves.try_recv(), Err(TryRecvError::Empty));
+    }
+
+    #[test]
+    fn test_sub_tick_prices_merge_into_one_level() {
+        let mut book = OrderBook::new();
+        book.set_tick_size(Some(dec!(0.01)));
+        book.apply_snapshot(
+            vec![(dec!(100.004), dec!(1.5)), (dec!(99.996), dec!(0.5)), (dec!(99.98), dec!(2))],
+            vec![(dec!(100.012), dec!(0.3)), (dec!(100.0149), dec!(0.2)), (dec!(100.015), dec!(1))],
+        );
+        // 100.004 and 99.996 are both 100.00; halfway prices round up
+        assert_eq!(book.top_bids(5), vec![(dec!(100.00), dec!(2.0)), (dec!(99.98), dec!(2))]);
+        assert_eq!(book.top_asks(5), vec![(dec!(100.01), dec!(0.5)), (dec!(100.02), dec!(1))]);
+        assert_eq!(book.best_bid(), Some((dec!(100.00), dec!(2.0))));
+        assert_eq!(book.best_ask(), Some((dec!(100.01), dec!(0.5))));
+        assert_eq!(book.spread(), Some(dec!(0.01)));
+        assert_eq!(book.cumulative_to_level(true, 1), dec!(4.0));
+        assert_eq!(book.volume_at_price(dec!(100.00), true), dec!(2.0));
+
+        // A diff to one of the merged prices only changes its share of the level
+        book.apply_deltas(vec![(dec!(99.996), dec!(0.25))], vec![(dec!(100.012), dec!(0))]);
+        assert_eq!(book.best_bid(), Some((dec!(100.00), dec!(1.75))));
+        assert_eq!(book.best_ask(), Some((dec!(100.01), dec!(0.2))));
+        book.apply_deltas(vec![], vec![(dec!(100.0149), dec!(0))]);
+        assert_eq!(book.best_ask(), Some((dec!(100.02), dec!(1))));
+
+        // A quote drops the levels better than it and stands in for its tick
+        book.apply_best_quotes(dec!(99.981), dec!(3), dec!(100.018), dec!(0.4));
+        assert_eq!(book.top_bids(5), vec![(dec!(99.98), dec!(5))]);
+        // 100.015 was better than the quoted ask, though in the same tick
+        assert_eq!(book.top_asks(5), vec![(dec!(100.02), dec!(0.4))]);
+
+        // Without a tick size the exchange's prices come back as sent
+        book.set_tick_size(None);
+        assert_eq!(book.top_bids(5), vec![(dec!(99.981), dec!(3)), (dec!(99.98), dec!(2))]);
+        assert_eq!(book.top_asks(5), vec![(dec!(100.018), dec!(0.4))]);
+    }
+}
@@ -1,14 +1,85 @@
 use anyhow::{Context, Result};
 use polars::prelude::*;
 use serde_json;
-use crate::analytics::FeaturesSnapshot;
-use rust_decimal::prelude::ToPrimitive;
+use crate::analytics::{FeaturesSnapshot, LaggedFeature};
+use crate::session_stats::SessionStats;
+use crate::trade_sink::RawTrade;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+use log::warn;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Only every this many non-finite values is logged.
+const NON_FINITE_LOG_EVERY: u64 = 1000;
+static NON_FINITE_VALUES: AtomicU64 = AtomicU64::new(0);
+
+/// Maps NaN and infinities to null so one bad value cannot poison a column's statistics.
+fn finite_or_null(value: Option<f64>) -> Option<f64> {
+    match value {
+        Some(v) if !v.is_finite() => {
+            let seen = NON_FINITE_VALUES.fetch_add(1, Ordering::Relaxed);
+            if seen.is_multiple_of(NON_FINITE_LOG_EVERY) {
+                warn!("Storing non-finite feature value {} as null ({} so far)", v, seen + 1);
+            }
+            None
+        }
+        other => other,
+    }
+}
+
+/// How feature batches are laid out on disk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PersistenceConfig {
+    /// Stores price and quantity columns (best bid and ask, mid, microprice, spread, volumes
+    /// near the touch, trade prices, VWAPs, future mids) as decimal strings, exactly as held,
+    /// instead of f64. Ratios and other derived features stay f64 either way.
+    pub exact_decimals: bool,
+}
+
+/// A column stored exactly under `exact_decimals`, and how to read it off a snapshot.
+type ExactColumn = (&'static str, fn(&FeaturesSnapshot) -> Option<Decimal>);
+
+/// Columns holding prices or quantities, stored exactly under `exact_decimals`.
+const EXACT_COLUMNS: [ExactColumn; 22] = [
+    ("best_bid", |f| f.best_bid),
+    ("best_ask", |f| f.best_ask),
+    ("mid_price", |f| f.mid_price),
+    ("microprice", |f| f.microprice),
+    ("spread", |f| f.spread),
+    ("bid_volume_001", |f| f.bid_volume_001),
+    ("ask_volume_001", |f| f.ask_volume_001),
+    ("bid_volume_005", |f| f.bid_volume_005),
+    ("ask_volume_005", |f| f.ask_volume_005),
+    ("bid_volume_010", |f| f.bid_volume_010),
+    ("ask_volume_010", |f| f.ask_volume_010),
+    ("bid_volume_050", |f| f.bid_volume_050),
+    ("ask_volume_050", |f| f.ask_volume_050),
+    ("last_trade_price", |f| f.last_trade_price),
+    ("vwap_total", |f| f.vwap_total),
+    ("avg_trade_size", |f| f.avg_trade_size),
+    ("vwap_10", |f| f.vwap_10),
+    ("vwap_50", |f| f.vwap_50),
+    ("vwap_100", |f| f.vwap_100),
+    ("vwap_1000", |f| f.vwap_1000),
+    ("future_mid_100ms", |f| f.future_mid_100ms),
+    ("future_mid_1s", |f| f.future_mid_1s),
+];
 
 /// Save a batch of features to Parquet with comprehensive error handling
 pub fn save_feature_as_parquet(features: &[FeaturesSnapshot], filepath: &str) -> Result<()> {
+    save_features_with_config(features, filepath, &PersistenceConfig::default())
+}
+
+/// `save_feature_as_parquet`, laid out as `config` says.
+pub fn save_features_with_config(
+    features: &[FeaturesSnapshot],
+    filepath: &str,
+    config: &PersistenceConfig,
+) -> Result<()> {
     // Convert Decimal fields to f64 with proper error handling
     fn decimal_to_f64(d: Option<rust_decimal::Decimal>) -> Option<f64> {
-        d.and_then(|d| d.to_f64())
+        finite_or_null(d.and_then(|d| d.to_f64()))
     }
 
     // Serialize complex fields to JSON strings
@@ -24,6 +95,9 @@ pub fn save_feature_as_parquet(features: &[FeaturesSnapshot], filepath: &str) ->
         "microprice" => features.iter().map(|f| decimal_to_f64(f.microprice)).collect::<Vec<_>>(),
         "spread" => features.iter().map(|f| decimal_to_f64(f.spread)).collect::<Vec<_>>(),
         "imbalance" => features.iter().map(|f| decimal_to_f64(f.imbalance)).collect::<Vec<_>>(),
+        "book_imbalance_ema" => features.iter().map(|f| decimal_to_f64(f.book_imbalance_ema)).collect::<Vec<_>>(),
+        "spread_mean_100" => features.iter().map(|f| decimal_to_f64(f.spread_mean_100)).collect::<Vec<_>>(),
+        "spread_std_100" => features.iter().map(|f| decimal_to_f64(f.spread_std_100)).collect::<Vec<_>>(),
         "top_bids" => features.iter().map(|f| serialize_complex(&f.top_bids)).collect::<Vec<_>>(),
         "top_asks" => features.iter().map(|f| serialize_complex(&f.top_asks)).collect::<Vec<_>>(),
         "pwi_1" => features.iter().map(|f| decimal_to_f64(f.pwi_1)).collect::<Vec<_>>(),
@@ -37,18 +111,31 @@ pub fn save_feature_as_parquet(features: &[FeaturesSnapshot], filepath: &str) ->
         "ask_depth_ratio" => features.iter().map(|f| decimal_to_f64(f.ask_depth_ratio)).collect::<Vec<_>>(),
         "bid_volume_001" => features.iter().map(|f| decimal_to_f64(f.bid_volume_001)).collect::<Vec<_>>(),
         "ask_volume_001" => features.iter().map(|f| decimal_to_f64(f.ask_volume_001)).collect::<Vec<_>>(),
+        "bid_volume_005" => features.iter().map(|f| decimal_to_f64(f.bid_volume_005)).collect::<Vec<_>>(),
+        "ask_volume_005" => features.iter().map(|f| decimal_to_f64(f.ask_volume_005)).collect::<Vec<_>>(),
+        "bid_volume_010" => features.iter().map(|f| decimal_to_f64(f.bid_volume_010)).collect::<Vec<_>>(),
+        "ask_volume_010" => features.iter().map(|f| decimal_to_f64(f.ask_volume_010)).collect::<Vec<_>>(),
+        "bid_volume_050" => features.iter().map(|f| decimal_to_f64(f.bid_volume_050)).collect::<Vec<_>>(),
+        "ask_volume_050" => features.iter().map(|f| decimal_to_f64(f.ask_volume_050)).collect::<Vec<_>>(),
         "bid_avg_distance" => features.iter().map(|f| decimal_to_f64(f.bid_avg_distance)).collect::<Vec<_>>(),
         "ask_avg_distance" => features.iter().map(|f| decimal_to_f64(f.ask_avg_distance)).collect::<Vec<_>>(),
         "last_trade_price" => features.iter().map(|f| decimal_to_f64(f.last_trade_price)).collect::<Vec<_>>(),
         "trade_imbalance" => features.iter().map(|f| decimal_to_f64(f.trade_imbalance)).collect::<Vec<_>>(),
+        "trade_imbalance_ema" => features.iter().map(|f| decimal_to_f64(f.trade_imbalance_ema)).collect::<Vec<_>>(),
         "vwap_total" => features.iter().map(|f| decimal_to_f64(f.vwap_total)).collect::<Vec<_>>(),
         "price_change" => features.iter().map(|f| decimal_to_f64(f.price_change)).collect::<Vec<_>>(),
         "avg_trade_size" => features.iter().map(|f| decimal_to_f64(f.avg_trade_size)).collect::<Vec<_>>(),
         "signed_count_momentum" => features.iter().map(|f| f.signed_count_momentum).collect::<Vec<_>>(),
-        "trade_rate_10s" => features.iter().map(|f| f.trade_rate_10s.unwrap_or(f64::NAN)).collect::<Vec<_>>(),
+        "trade_rate_1s" => features.iter().map(|f| finite_or_null(f.trade_rate_1s)).collect::<Vec<_>>(),
+        "trade_rate_10s" => features.iter().map(|f| finite_or_null(f.trade_rate_10s)).collect::<Vec<_>>(),
+        "trade_rate_60s" => features.iter().map(|f| finite_or_null(f.trade_rate_60s)).collect::<Vec<_>>(),
         "order_flow_imbalance" => features.iter().map(|f| decimal_to_f64(f.order_flow_imbalance)).collect::<Vec<_>>(),
         "order_flow_pressure" => features.iter().map(|f| decimal_to_f64(Some(f.order_flow_pressure))).collect::<Vec<_>>(),
+        "bid_flow_pressure" => features.iter().map(|f| decimal_to_f64(Some(f.bid_flow_pressure))).collect::<Vec<_>>(),
+        "ask_flow_pressure" => features.iter().map(|f| decimal_to_f64(Some(f.ask_flow_pressure))).collect::<Vec<_>>(),
         "order_flow_significance" => features.iter().map(|f| f.order_flow_significance).collect::<Vec<_>>(),
+        "flow_price_corr" => features.iter().map(|f| decimal_to_f64(f.flow_price_corr)).collect::<Vec<_>>(),
+        "composite_pressure" => features.iter().map(|f| decimal_to_f64(f.composite_pressure)).collect::<Vec<_>>(),
         "vwap_10" => features.iter().map(|f| decimal_to_f64(f.vwap_10)).collect::<Vec<_>>(),
         "vwap_50" => features.iter().map(|f| decimal_to_f64(f.vwap_50)).collect::<Vec<_>>(),
         "vwap_100" => features.iter().map(|f| decimal_to_f64(f.vwap_100)).collect::<Vec<_>>(),
@@ -57,8 +144,44 @@ pub fn save_feature_as_parquet(features: &[FeaturesSnapshot], filepath: &str) ->
         "aggr_ratio_50" => features.iter().map(|f| decimal_to_f64(f.aggr_ratio_50)).collect::<Vec<_>>(),
         "aggr_ratio_100" => features.iter().map(|f| decimal_to_f64(f.aggr_ratio_100)).collect::<Vec<_>>(),
         "aggr_ratio_1000" => features.iter().map(|f| decimal_to_f64(f.aggr_ratio_1000)).collect::<Vec<_>>(),
+        "aggr_ratio_10s" => features.iter().map(|f| decimal_to_f64(f.aggr_ratio_10s)).collect::<Vec<_>>(),
+        "large_trade_share_100" => features.iter().map(|f| decimal_to_f64(f.large_trade_share_100)).collect::<Vec<_>>(),
+        "net_flow" => features.iter().map(|f| decimal_to_f64(f.net_flow)).collect::<Vec<_>>(),
+        "price_dislocation" => features.iter().map(|f| decimal_to_f64(f.price_dislocation)).collect::<Vec<_>>(),
+        "price_dislocated" => features.iter().map(|f| f.price_dislocated).collect::<Vec<_>>(),
+        "feed_lag_ms" => features.iter().map(|f| finite_or_null(f.feed_lag_ms)).collect::<Vec<_>>(),
+        "trade_ingest_lag_ms" => features.iter().map(|f| finite_or_null(f.trade_ingest_lag_ms)).collect::<Vec<_>>(),
+        "depth_feed_healthy" => features.iter().map(|f| f.depth_feed_healthy).collect::<Vec<_>>(),
+        "trade_feed_healthy" => features.iter().map(|f| f.trade_feed_healthy).collect::<Vec<_>>(),
+        "trade_feed_gap_detected" => features.iter().map(|f| f.trade_feed_gap_detected).collect::<Vec<_>>(),
+        "depth_msg_age_ms" => features.iter().map(|f| f.depth_msg_age_ms).collect::<Vec<_>>(),
+        "trade_msg_age_ms" => features.iter().map(|f| f.trade_msg_age_ms).collect::<Vec<_>>(),
+        "book_trusted" => features.iter().map(|f| f.book_trusted).collect::<Vec<_>>(),
+        "book_last_update" => features.iter().map(|f| f.book_last_update).collect::<Vec<_>>(),
+        "future_mid_100ms" => features.iter().map(|f| decimal_to_f64(f.future_mid_100ms)).collect::<Vec<_>>(),
+        "future_mid_1s" => features.iter().map(|f| decimal_to_f64(f.future_mid_1s)).collect::<Vec<_>>(),
     ].context("Failed to create DataFrame")?;
 
+    if config.exact_decimals {
+        for (column, value) in EXACT_COLUMNS {
+            let exact: Vec<Option<String>> = features.iter().map(|f| value(f).map(|d| d.to_string())).collect();
+            df.with_column(Series::new(column, exact)).context("Failed to store exact column")?;
+        }
+    }
+
+    // Lagged features go in a column each, stored like the feature they lag
+    for name in features.first().map(|f| f.lagged.keys()).into_iter().flatten() {
+        let values = features.iter().map(|f| f.lagged.get(name).copied().flatten());
+        let lagged_column = name.rsplit_once("_lag").map(|(column, _)| column);
+        let exact = config.exact_decimals && EXACT_COLUMNS.iter().any(|(column, _)| Some(*column) == lagged_column);
+        let series = if exact {
+            Series::new(name, values.map(|v| v.map(|d| d.to_string())).collect::<Vec<_>>())
+        } else {
+            Series::new(name, values.map(decimal_to_f64).collect::<Vec<_>>())
+        };
+        df.with_column(series).context("Failed to store lagged column")?;
+    }
+
     // Create parent directories if they don't exist
     if let Some(parent) = std::path::Path::new(filepath).parent() {
         std::fs::create_dir_all(parent).context("Failed to create output directory")?;
@@ -73,6 +196,333 @@ pub fn save_feature_as_parquet(features: &[FeaturesSnapshot], filepath: &str) ->
     Ok(())
 }
 
+/// Reads a batch written by `save_feature_as_parquet` back into snapshots.
+///
+/// Decimals stored as f64 come back through it, so they equal what was saved only to f64
+/// precision; those stored exactly (`PersistenceConfig::exact_decimals`) come back as saved.
+/// Null cells, and columns missing from files written before a feature existed, read as `None`.
+pub fn load_features_from_parquet(filepath: &str) -> Result<Vec<FeaturesSnapshot>> {
+    let file = std::fs::File::open(filepath).context("Failed to open Parquet file")?;
+    let df = ParquetReader::new(file).finish().context("Failed to read Parquet file")?;
+
+    let floats = |name: &str| float_column(&df, name);
+    let decimals = |name: &str| decimal_column(&df, name);
+    let strings = |name: &str| -> Result<Vec<Option<String>>> {
+        match df.column(name) {
+            Ok(column) => Ok(column.utf8()?.into_iter().map(|v| v.map(str::to_string)).collect()),
+            Err(_) => Ok(vec![None; df.height()]),
+        }
+    };
+    let bools = |name: &str| -> Result<Vec<Option<bool>>> {
+        match df.column(name) {
+            Ok(column) => Ok(column.bool()?.into_iter().collect()),
+            Err(_) => Ok(vec![None; df.height()]),
+        }
+    };
+    let levels = |name: &str| -> Result<Vec<Vec<(Decimal, Decimal)>>> {
+        strings(name)?
+            .into_iter()
+            .map(|json| match json {
+                Some(json) => serde_json::from_str(&json).with_context(|| format!("Malformed {} levels", name)),
+                None => Ok(Vec::new()),
+            })
+            .collect()
+    };
+
+    let timestamp = strings("timestamp")?;
+    let top_bids = levels("top_bids")?;
+    let top_asks = levels("top_asks")?;
+    let best_bid = decimals("best_bid")?;
+    let best_ask = decimals("best_ask")?;
+    let mid_price = decimals("mid_price")?;
+    let microprice = decimals("microprice")?;
+    let spread = decimals("spread")?;
+    let imbalance = decimals("imbalance")?;
+    let book_imbalance_ema = decimals("book_imbalance_ema")?;
+    let spread_mean_100 = decimals("spread_mean_100")?;
+    let spread_std_100 = decimals("spread_std_100")?;
+    let pwi_1 = decimals("pwi_1")?;
+    let pwi_5 = decimals("pwi_5")?;
+    let pwi_25 = decimals("pwi_25")?;
+    let pwi_50 = decimals("pwi_50")?;
+    let bid_slope = decimals("bid_slope")?;
+    let ask_slope = decimals("ask_slope")?;
+    let volume_imbalance_top5 = decimals("volume_imbalance_top5")?;
+    let bid_depth_ratio = decimals("bid_depth_ratio")?;
+    let ask_depth_ratio = decimals("ask_depth_ratio")?;
+    let bid_volume_001 = decimals("bid_volume_001")?;
+    let ask_volume_001 = decimals("ask_volume_001")?;
+    let bid_volume_005 = decimals("bid_volume_005")?;
+    let ask_volume_005 = decimals("ask_volume_005")?;
+    let bid_volume_010 = decimals("bid_volume_010")?;
+    let ask_volume_010 = decimals("ask_volume_010")?;
+    let bid_volume_050 = decimals("bid_volume_050")?;
+    let ask_volume_050 = decimals("ask_volume_050")?;
+    let bid_avg_distance = decimals("bid_avg_distance")?;
+    let ask_avg_distance = decimals("ask_avg_distance")?;
+    let last_trade_price = decimals("last_trade_price")?;
+    let trade_imbalance = decimals("trade_imbalance")?;
+    let trade_imbalance_ema = decimals("trade_imbalance_ema")?;
+    let vwap_total = decimals("vwap_total")?;
+    let price_change = decimals("price_change")?;
+    let avg_trade_size = decimals("avg_trade_size")?;
+    let order_flow_imbalance = decimals("order_flow_imbalance")?;
+    let order_flow_pressure = decimals("order_flow_pressure")?;
+    let bid_flow_pressure = decimals("bid_flow_pressure")?;
+    let ask_flow_pressure = decimals("ask_flow_pressure")?;
+    let flow_price_corr = decimals("flow_price_corr")?;
+    let composite_pressure = decimals("composite_pressure")?;
+    let vwap_10 = decimals("vwap_10")?;
+    let vwap_50 = decimals("vwap_50")?;
+    let vwap_100 = decimals("vwap_100")?;
+    let vwap_1000 = decimals("vwap_1000")?;
+    let aggr_ratio_10 = decimals("aggr_ratio_10")?;
+    let aggr_ratio_50 = decimals("aggr_ratio_50")?;
+    let aggr_ratio_100 = decimals("aggr_ratio_100")?;
+    let aggr_ratio_1000 = decimals("aggr_ratio_1000")?;
+    let aggr_ratio_10s = decimals("aggr_ratio_10s")?;
+    let large_trade_share_100 = decimals("large_trade_share_100")?;
+    let net_flow = decimals("net_flow")?;
+    let price_dislocation = decimals("price_dislocation")?;
+    let future_mid_100ms = decimals("future_mid_100ms")?;
+    let future_mid_1s = decimals("future_mid_1s")?;
+    let trade_rate_1s = floats("trade_rate_1s")?;
+    let trade_rate_10s = floats("trade_rate_10s")?;
+    let trade_rate_60s = floats("trade_rate_60s")?;
+    let feed_lag_ms = floats("feed_lag_ms")?;
+    let trade_ingest_lag_ms = floats("trade_ingest_lag_ms")?;
+    let signed_count_momentum: Vec<Option<i64>> = match df.column("signed_count_momentum") {
+        Ok(column) => column.cast(&DataType::Int64)?.i64()?.into_iter().collect(),
+        Err(_) => vec![None; df.height()],
+    };
+    let u64s = |name: &str| -> Result<Vec<Option<u64>>> {
+        match df.column(name) {
+            Ok(column) => Ok(column.cast(&DataType::UInt64)?.u64()?.into_iter().collect()),
+            Err(_) => Ok(vec![None; df.height()]),
+        }
+    };
+    let book_last_update = u64s("book_last_update")?;
+    let depth_msg_age_ms = u64s("depth_msg_age_ms")?;
+    let trade_msg_age_ms = u64s("trade_msg_age_ms")?;
+    let order_flow_significance = bools("order_flow_significance")?;
+    let book_trusted = bools("book_trusted")?;
+    let depth_feed_healthy = bools("depth_feed_healthy")?;
+    let trade_feed_healthy = bools("trade_feed_healthy")?;
+    let trade_feed_gap_detected = bools("trade_feed_gap_detected")?;
+    let price_dislocated = bools("price_dislocated")?;
+    let lagged = df
+        .get_column_names()
+        .into_iter()
+        .filter(|name| name.parse::<LaggedFeature>().is_ok())
+        .map(|name| Ok((name.to_string(), decimals(name)?)))
+        .collect::<Result<Vec<_>>>()?;
+
+    let snapshots = (0..df.height())
+        .map(|row| FeaturesSnapshot {
+            timestamp: timestamp[row].clone().unwrap_or_default(),
+            best_bid: best_bid[row],
+            best_ask: best_ask[row],
+            mid_price: mid_price[row],
+            microprice: microprice[row],
+            spread: spread[row],
+            imbalance: imbalance[row],
+            book_imbalance_ema: book_imbalance_ema[row],
+            spread_mean_100: spread_mean_100[row],
+            spread_std_100: spread_std_100[row],
+            top_bids: top_bids[row].clone(),
+            top_asks: top_asks[row].clone(),
+            pwi_1: pwi_1[row],
+            pwi_5: pwi_5[row],
+            pwi_25: pwi_25[row],
+            pwi_50: pwi_50[row],
+            bid_slope: bid_slope[row],
+            ask_slope: ask_slope[row],
+            volume_imbalance_top5: volume_imbalance_top5[row],
+            bid_depth_ratio: bid_depth_ratio[row],
+            ask_depth_ratio: ask_depth_ratio[row],
+            bid_volume_001: bid_volume_001[row],
+            ask_volume_001: ask_volume_001[row],
+            bid_volume_005: bid_volume_005[row],
+            ask_volume_005: ask_volume_005[row],
+            bid_volume_010: bid_volume_010[row],
+            ask_volume_010: ask_volume_010[row],
+            bid_volume_050: bid_volume_050[row],
+            ask_volume_050: ask_volume_050[row],
+            bid_avg_distance: bid_avg_distance[row],
+            ask_avg_distance: ask_avg_distance[row],
+            last_trade_price: last_trade_price[row],
+            trade_imbalance: trade_imbalance[row],
+            trade_imbalance_ema: trade_imbalance_ema[row],
+            vwap_total: vwap_total[row],
+            price_change: price_change[row],
+            avg_trade_size: avg_trade_size[row],
+            signed_count_momentum: signed_count_momentum[row].unwrap_or(0),
+            trade_rate_1s: trade_rate_1s[row],
+            trade_rate_10s: trade_rate_10s[row],
+            trade_rate_60s: trade_rate_60s[row],
+            order_flow_imbalance: order_flow_imbalance[row],
+            order_flow_pressure: order_flow_pressure[row].unwrap_or(Decimal::ZERO),
+            bid_flow_pressure: bid_flow_pressure[row].unwrap_or(Decimal::ZERO),
+            ask_flow_pressure: ask_flow_pressure[row].unwrap_or(Decimal::ZERO),
+            order_flow_significance: order_flow_significance[row].unwrap_or(false),
+            flow_price_corr: flow_price_corr[row],
+            composite_pressure: composite_pressure[row],
+            vwap_10: vwap_10[row],
+            vwap_50: vwap_50[row],
+            vwap_100: vwap_100[row],
+            vwap_1000: vwap_1000[row],
+            aggr_ratio_10: aggr_ratio_10[row],
+            aggr_ratio_50: aggr_ratio_50[row],
+            aggr_ratio_100: aggr_ratio_100[row],
+            aggr_ratio_1000: aggr_ratio_1000[row],
+            aggr_ratio_10s: aggr_ratio_10s[row],
+            large_trade_share_100: large_trade_share_100[row],
+            net_flow: net_flow[row],
+            price_dislocation: price_dislocation[row],
+            price_dislocated: price_dislocated[row].unwrap_or(false),
+            feed_lag_ms: feed_lag_ms[row],
+            trade_ingest_lag_ms: trade_ingest_lag_ms[row],
+            depth_feed_healthy: depth_feed_healthy[row].unwrap_or(true),
+            trade_feed_healthy: trade_feed_healthy[row].unwrap_or(true),
+            trade_feed_gap_detected: trade_feed_gap_detected[row].unwrap_or(false),
+            depth_msg_age_ms: depth_msg_age_ms[row],
+            trade_msg_age_ms: trade_msg_age_ms[row],
+            // Batches from before the flag existed carry no verdict either way
+            book_trusted: book_trusted[row].unwrap_or(true),
+            book_last_update: book_last_update[row],
+            future_mid_100ms: future_mid_100ms[row],
+            future_mid_1s: future_mid_1s[row],
+            lagged: lagged.iter().map(|(name, values)| (name.clone(), values[row])).collect(),
+        })
+        .collect();
+    Ok(snapshots)
+}
+
+/// Columns of a raw trades file: `timestamp`, `price`, `qty`, `is_buyer_maker`, `trade_id`
+/// and `symbol`, with prices and quantities stored exactly as decimal strings.
+fn raw_trades_frame(trades: &[RawTrade]) -> Result<DataFrame> {
+    let df = df! [
+        "timestamp" => trades.iter().map(|t| t.timestamp).collect::<Vec<_>>(),
+        "price" => trades.iter().map(|t| t.price.to_string()).collect::<Vec<_>>(),
+        "qty" => trades.iter().map(|t| t.quantity.to_string()).collect::<Vec<_>>(),
+        "is_buyer_maker" => trades.iter().map(|t| t.is_buyer_maker).collect::<Vec<_>>(),
+        "trade_id" => trades.iter().map(|t| t.trade_id).collect::<Vec<_>>(),
+        "symbol" => trades.iter().map(|t| t.symbol.clone()).collect::<Vec<_>>(),
+    ]
+    .context("Failed to create DataFrame")?;
+    Ok(df)
+}
+
+/// Streams raw trades into one Parquet file, each `write` as its own row group.
+///
+/// The footer only goes on in `finish`; a file that never gets there can't be read.
+pub struct TradeParquetWriter {
+    writer: polars::io::parquet::BatchedWriter<std::fs::File>,
+}
+
+impl TradeParquetWriter {
+    /// Creates the file at `path`, and its parent directories.
+    pub fn create(path: &std::path::Path) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).context("Failed to create output directory")?;
+        }
+        let file = std::fs::File::create(path).context("Failed to create output file")?;
+        let writer = ParquetWriter::new(file)
+            .with_compression(ParquetCompression::Snappy)
+            .batched(&raw_trades_frame(&[])?.schema())
+            .context("Failed to start Parquet file")?;
+        Ok(Self { writer })
+    }
+
+    pub fn write(&mut self, trades: &[RawTrade]) -> Result<()> {
+        let df = raw_trades_frame(trades)?;
+        self.writer.write_batch(&df).context("Failed to write row group")
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.writer.finish().context("Failed to finish Parquet file")?;
+        Ok(())
+    }
+}
+
+/// Reads a file written by `TradeParquetWriter` back into trades, prices and quantities
+/// exactly as written; files that stored them as f64 come back to f64 precision.
+pub fn load_raw_trades_from_parquet(filepath: &str) -> Result<Vec<RawTrade>> {
+    let file = std::fs::File::open(filepath).context("Failed to open Parquet file")?;
+    let df = ParquetReader::new(file).finish().context("Failed to read Parquet file")?;
+
+    let unsigned = |name: &str| -> Result<Vec<Option<u64>>> {
+        Ok(df.column(name)?.cast(&DataType::UInt64)?.u64()?.into_iter().collect())
+    };
+    let timestamp = unsigned("timestamp")?;
+    let trade_id = unsigned("trade_id")?;
+    let price = decimal_column(&df, "price")?;
+    let qty = decimal_column(&df, "qty")?;
+    let is_buyer_maker: Vec<Option<bool>> = df.column("is_buyer_maker")?.bool()?.into_iter().collect();
+    let symbol: Vec<Option<String>> = df.column("symbol")?.utf8()?.into_iter().map(|v| v.map(str::to_string)).collect();
+
+    (0..df.height())
+        .map(|row| {
+            let decimal = |value: Option<Decimal>, name: &str| {
+                value.with_context(|| format!("Missing {} in row {}", name, row))
+            };
+            Ok(RawTrade {
+                timestamp: timestamp[row].with_context(|| format!("Missing timestamp in row {}", row))?,
+                price: decimal(price[row], "price")?,
+                quantity: decimal(qty[row], "qty")?,
+                is_buyer_maker: is_buyer_maker[row].unwrap_or(false),
+                trade_id: trade_id[row],
+                symbol: symbol[row].clone(),
+            })
+        })
+        .collect()
+}
+
+/// `path`, or the first of `<stem>_1.<ext>`, `<stem>_2.<ext>`, ... next to it that doesn't
+/// exist yet, so nothing already written is overwritten.
+pub fn unused_path(path: &std::path::Path) -> std::path::PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().map(|ext| format!(".{}", ext.to_string_lossy())).unwrap_or_default();
+    (0..)
+        .map(|n| match n {
+            0 => path.to_path_buf(),
+            n => path.with_file_name(format!("{}_{}{}", stem, n, extension)),
+        })
+        .find(|path| !path.exists())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Writes `stats`' summary up to now to `path` as pretty-printed JSON, creating its parent
+/// directories.
+pub fn write_session_summary(stats: &SessionStats, path: &std::path::Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).context("Failed to create output directory")?;
+    }
+    let file = std::fs::File::create(path).context("Failed to create summary file")?;
+    serde_json::to_writer_pretty(file, &stats.summary()).context("Failed to write session summary")
+}
+
+/// An f64 column as options, all `None` when the file doesn't have it.
+fn float_column(df: &DataFrame, name: &str) -> Result<Vec<Option<f64>>> {
+    match df.column(name) {
+        // An all-null column may not have been written as f64
+        Ok(column) => Ok(column.cast(&DataType::Float64)?.f64()?.into_iter().collect()),
+        Err(_) => Ok(vec![None; df.height()]),
+    }
+}
+
+/// A decimal column stored either exactly, as strings, or as f64.
+fn decimal_column(df: &DataFrame, name: &str) -> Result<Vec<Option<Decimal>>> {
+    match df.column(name) {
+        Ok(column) if column.dtype() == &DataType::Utf8 => column
+            .utf8()?
+            .into_iter()
+            .map(|v| v.map(Decimal::from_str).transpose().with_context(|| format!("Malformed {}", name)))
+            .collect(),
+        _ => Ok(float_column(df, name)?.into_iter().map(|v| v.and_then(Decimal::from_f64)).collect()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -90,6 +540,9 @@ mod tests {
             microprice: Some(dec!(100.60)),
             spread: Some(dec!(0.50)),
             imbalance: Some(dec!(0.33)),
+            book_imbalance_ema: Some(dec!(0.29)),
+            spread_mean_100: Some(dec!(0.45)),
+            spread_std_100: Some(dec!(0.05)),
             top_bids: vec![(dec!(100.50), dec!(10.0)), (dec!(100.25), dec!(15.0))],
             top_asks: vec![(dec!(101.00), dec!(8.0)), (dec!(101.25), dec!(12.0))],
             // ... populate all other fields with test values ...
@@ -104,18 +557,31 @@ mod tests {
             ask_depth_ratio: Some(dec!(0.40)),
             bid_volume_001: Some(dec!(8.0)),
             ask_volume_001: Some(dec!(4.0)),
+            bid_volume_005: Some(dec!(18.0)),
+            ask_volume_005: Some(dec!(12.0)),
+            bid_volume_010: Some(dec!(25.0)),
+            ask_volume_010: Some(dec!(20.0)),
+            bid_volume_050: None,
+            ask_volume_050: None,
             bid_avg_distance: Some(dec!(0.25)),
             ask_avg_distance: Some(dec!(0.25)),
             last_trade_price: Some(dec!(100.25)),
             trade_imbalance: Some(dec!(0.60)),
+            trade_imbalance_ema: Some(dec!(0.57)),
             vwap_total: Some(dec!(100.30)),
             price_change: Some(dec!(0.20)),
             avg_trade_size: Some(dec!(1.50)),
             signed_count_momentum: 5,
+            trade_rate_1s: Some(6.0),
             trade_rate_10s: Some(2.5),
+            trade_rate_60s: Some(1.75),
             order_flow_imbalance: Some(dec!(0.30)),
             order_flow_pressure: dec!(7.50),
+            bid_flow_pressure: dec!(4.75),
+            ask_flow_pressure: dec!(2.75),
             order_flow_significance: false,
+            flow_price_corr: Some(dec!(0.12)),
+            composite_pressure: Some(dec!(0.25)),
             vwap_10: Some(dec!(100.35)),
             vwap_50: Some(dec!(100.32)),
             vwap_100: Some(dec!(100.31)),
@@ -124,6 +590,23 @@ mod tests {
             aggr_ratio_50: Some(dec!(0.55)),
             aggr_ratio_100: Some(dec!(0.52)),
             aggr_ratio_1000: Some(dec!(0.50)),
+            aggr_ratio_10s: Some(dec!(0.58)),
+            large_trade_share_100: Some(dec!(0.35)),
+            net_flow: Some(dec!(0.23)),
+            price_dislocation: Some(dec!(0.05)),
+            price_dislocated: false,
+            feed_lag_ms: Some(42.5),
+            trade_ingest_lag_ms: Some(37.0),
+            depth_feed_healthy: true,
+            trade_feed_healthy: true,
+            trade_feed_gap_detected: false,
+            depth_msg_age_ms: Some(12),
+            trade_msg_age_ms: Some(850),
+            book_trusted: true,
+            book_last_update: Some(1_712_745_600_000),
+            future_mid_100ms: Some(dec!(100.33)),
+            future_mid_1s: None,
+            lagged: Default::default(),
         }
     }
 
@@ -214,6 +697,27 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_non_finite_values_are_stored_as_null() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("non_finite.parquet");
+
+        let mut infinite = create_test_snapshot();
+        infinite.trade_rate_10s = Some(f64::INFINITY);
+        let mut nan = create_test_snapshot();
+        nan.trade_rate_10s = Some(f64::NAN);
+        let mut missing = create_test_snapshot();
+        missing.trade_rate_10s = None;
+        save_feature_as_parquet(&[infinite, nan, missing, create_test_snapshot()], path.to_str().unwrap())?;
+
+        let df = ParquetReader::new(fs::File::open(path)?).finish()?;
+        let col = df.column("trade_rate_10s")?.f64()?;
+        assert_eq!(col.null_count(), 3);
+        assert_eq!(col.get(3), Some(2.5));
+        assert_eq!(finite_or_null(Some(f64::NEG_INFINITY)), None);
+        Ok(())
+    }
+
     #[test]
     fn test_complex_field_serialization() -> Result<()> {
         let dir = tempdir()?;
@@ -231,4 +735,158 @@ mod tests {
         
         Ok(())
     }
+
+    /// Asserts two serialized snapshots match, numbers (and decimal strings) to within 1e-9.
+    fn assert_close(expected: &serde_json::Value, actual: &serde_json::Value, path: &str) {
+        use serde_json::Value;
+
+        let number = |value: &Value| match value {
+            Value::Number(n) => n.as_f64(),
+            Value::String(s) => s.parse::<f64>().ok(),
+            _ => None,
+        };
+        match (expected, actual) {
+            (Value::Object(expected), Value::Object(actual)) => {
+                assert_eq!(expected.len(), actual.len(), "{}", path);
+                for (key, value) in expected {
+                    assert_close(value, &actual[key], &format!("{}.{}", path, key));
+                }
+            }
+            (Value::Array(expected), Value::Array(actual)) => {
+                assert_eq!(expected.len(), actual.len(), "{}", path);
+                for (i, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                    assert_close(expected, actual, &format!("{}[{}]", path, i));
+                }
+            }
+            _ => match (number(expected), number(actual)) {
+                (Some(e), Some(a)) => assert!((e - a).abs() < 1e-9, "{}: {} != {}", path, e, a),
+                _ => assert_eq!(expected, actual, "{}", path),
+            },
+        }
+    }
+
+    #[test]
+    fn test_load_round_trips_saved_snapshots() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("load.parquet");
+
+        let full = create_test_snapshot();
+        let mut sparse = create_test_snapshot();
+        sparse.best_bid = None;
+        sparse.top_asks = Vec::new();
+        sparse.trade_rate_10s = None;
+        sparse.trade_rate_60s = None;
+        sparse.net_flow = None;
+        sparse.price_dislocation = None;
+        sparse.price_dislocated = true;
+        sparse.feed_lag_ms = None;
+        sparse.trade_ingest_lag_ms = None;
+        sparse.order_flow_significance = true;
+        sparse.signed_count_momentum = -3;
+        sparse.book_trusted = false;
+        sparse.book_last_update = None;
+        sparse.trade_feed_healthy = false;
+        sparse.trade_feed_gap_detected = true;
+        sparse.trade_msg_age_ms = None;
+        let original = vec![full, sparse];
+        save_feature_as_parquet(&original, path.to_str().unwrap())?;
+
+        let loaded = load_features_from_parquet(path.to_str().unwrap())?;
+        assert_eq!(loaded.len(), 2);
+        for (row, (expected, actual)) in original.iter().zip(&loaded).enumerate() {
+            assert_close(&serde_json::to_value(expected)?, &serde_json::to_value(actual)?, &format!("row {}", row));
+        }
+        assert_eq!(loaded[1].best_bid, None);
+        assert_eq!(loaded[0].future_mid_1s, None);
+        assert_eq!(loaded[1].top_bids[0], (dec!(100.50), dec!(10.0)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_exact_decimals_round_trip_unchanged() -> Result<()> {
+        let dir = tempdir()?;
+        let mut snapshot = create_test_snapshot();
+        // More significant digits than an f64 holds
+        snapshot.best_bid = Some(dec!(69420.123456789012345678));
+        snapshot.bid_volume_001 = Some(dec!(0.000000012345678901234567));
+        snapshot.vwap_1000 = None;
+        let features = vec![snapshot.clone()];
+
+        let exact = dir.path().join("exact.parquet");
+        let config = PersistenceConfig { exact_decimals: true };
+        save_features_with_config(&features, exact.to_str().unwrap(), &config)?;
+        let loaded = load_features_from_parquet(exact.to_str().unwrap())?;
+        assert_eq!(loaded[0].best_bid, snapshot.best_bid);
+        assert_eq!(loaded[0].bid_volume_001, snapshot.bid_volume_001);
+        assert_eq!(loaded[0].vwap_1000, None);
+
+        // Ratios stay f64
+        let df = ParquetReader::new(fs::File::open(&exact)?).finish()?;
+        assert_eq!(df.column("best_bid")?.dtype(), &DataType::Utf8);
+        assert_eq!(df.column("imbalance")?.dtype(), &DataType::Float64);
+
+        let lossy = dir.path().join("lossy.parquet");
+        save_feature_as_parquet(&features, lossy.to_str().unwrap())?;
+        let loaded = load_features_from_parquet(lossy.to_str().unwrap())?;
+        assert_ne!(loaded[0].best_bid, snapshot.best_bid);
+        Ok(())
+    }
+
+    #[test]
+    fn test_raw_trades_round_trip_exactly() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("trades.parquet");
+        let trade = RawTrade {
+            timestamp: 1_712_746_799_900,
+            price: dec!(69420.123456789012345678),
+            quantity: dec!(0.000000012345678901234567),
+            is_buyer_maker: true,
+            trade_id: Some(7),
+            symbol: Some("btcusdt".to_string()),
+        };
+        let mut writer = TradeParquetWriter::create(&path)?;
+        writer.write(std::slice::from_ref(&trade))?;
+        writer.finish()?;
+
+        assert_eq!(load_raw_trades_from_parquet(path.to_str().unwrap())?, vec![trade]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_lagged_features_round_trip_as_columns() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("lagged.parquet");
+        let mut first = create_test_snapshot();
+        first.lagged.insert("mid_price_lag1".to_string(), None);
+        first.lagged.insert("imbalance_lag5".to_string(), None);
+        let mut second = create_test_snapshot();
+        second.lagged.insert("mid_price_lag1".to_string(), Some(dec!(100.625)));
+        second.lagged.insert("imbalance_lag5".to_string(), Some(dec!(0.25)));
+        save_feature_as_parquet(&[first, second.clone()], path.to_str().unwrap())?;
+
+        let df = ParquetReader::new(fs::File::open(&path)?).finish()?;
+        assert_eq!(df.column("mid_price_lag1")?.dtype(), &DataType::Float64);
+        let loaded = load_features_from_parquet(path.to_str().unwrap())?;
+        assert_eq!(loaded[0].lagged.get("mid_price_lag1"), Some(&None));
+        assert_eq!(loaded[1].lagged, second.lagged);
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_fills_missing_columns_with_none() -> Result<()> {
+        let dir = tempdir()?;
+        let path = dir.path().join("old.parquet");
+        save_feature_as_parquet(&[create_test_snapshot()], path.to_str().unwrap())?;
+
+        // A file written before the label columns existed
+        let df = ParquetReader::new(fs::File::open(&path)?).finish()?;
+        let mut df = df.drop_many(&["future_mid_100ms", "future_mid_1s", "feed_lag_ms"]);
+        ParquetWriter::new(fs::File::create(&path)?).finish(&mut df)?;
+
+        let loaded = load_features_from_parquet(path.to_str().unwrap())?;
+        assert_eq!(loaded[0].future_mid_100ms, None);
+        assert_eq!(loaded[0].feed_lag_ms, None);
+        assert_eq!(loaded[0].best_bid, Some(dec!(100.5)));
+        Ok(())
+    }
 }
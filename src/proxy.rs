@@ -0,0 +1,291 @@
+use std::fmt;
+use std::io;
+use std::net::IpAddr;
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::http::Uri;
+
+/// Longest CONNECT response head read before giving up on an HTTP proxy.
+const MAX_RESPONSE_HEAD: usize = 8 * 1024;
+
+const SOCKS_VERSION: u8 = 5;
+const SOCKS_NO_AUTH: u8 = 0;
+const SOCKS_USER_PASS: u8 = 2;
+const SOCKS_CONNECT: u8 = 1;
+const SOCKS_IPV4: u8 = 1;
+const SOCKS_DOMAIN: u8 = 3;
+const SOCKS_IPV6: u8 = 4;
+
+#[derive(Debug, Error)]
+pub enum ProxyError {
+    #[error("Invalid proxy URL {0}")]
+    InvalidUrl(String),
+    #[error("No host to tunnel to in {0}")]
+    InvalidTarget(String),
+    #[error("Proxy connection failed: {0}")]
+    Io(#[from] io::Error),
+    #[error("Proxy refused the tunnel: {0}")]
+    Refused(String),
+}
+
+/// Credentials sent as HTTP Basic auth or SOCKS5 username/password.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ProxyAuth {
+    pub username: String,
+    pub password: String,
+}
+
+impl fmt::Debug for ProxyAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyAuth")
+            .field("username", &self.username)
+            .field("password", &"<redacted>")
+            .finish()
+    }
+}
+
+/// Proxy that feed connections tunnel through before the TLS and WebSocket handshakes.
+///
+/// `http://` proxies are asked for a `CONNECT` tunnel; any other scheme, typically
+/// `socks5://`, gets a SOCKS5 handshake. Either way the proxy resolves the exchange host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyConfig {
+    pub url: String,
+    pub auth: Option<ProxyAuth>,
+}
+
+impl ProxyConfig {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            auth: None,
+        }
+    }
+
+    pub fn with_auth(mut self, username: &str, password: &str) -> Self {
+        self.auth = Some(ProxyAuth {
+            username: username.to_string(),
+            password: password.to_string(),
+        });
+        self
+    }
+
+    fn is_http(&self) -> bool {
+        self.url.starts_with("http://")
+    }
+
+    /// Connects to the proxy and has it open a tunnel to the host and port of `target`,
+    /// a `ws://` or `wss://` URI. The returned stream carries the target's bytes.
+    pub async fn tunnel(&self, target: &str) -> Result<TcpStream, ProxyError> {
+        let (host, port) = target_addr(target)?;
+        let proxy: Uri = self.url.parse().map_err(|_| ProxyError::InvalidUrl(self.url.clone()))?;
+        let authority = proxy
+            .authority()
+            .ok_or_else(|| ProxyError::InvalidUrl(self.url.clone()))?;
+        let default_port = if self.is_http() { 8080 } else { 1080 };
+        let mut stream = TcpStream::connect((
+            unbracket(authority.host()),
+            authority.port_u16().unwrap_or(default_port),
+        ))
+        .await?;
+
+        if self.is_http() {
+            self.http_connect(&mut stream, &host, port).await?;
+        } else {
+            self.socks5_connect(&mut stream, &host, port).await?;
+        }
+        Ok(stream)
+    }
+
+    async fn http_connect(&self, stream: &mut TcpStream, host: &str, port: u16) -> Result<(), ProxyError> {
+        let target = match host.parse::<IpAddr>() {
+            Ok(IpAddr::V6(_)) => format!("[{}]:{}", host, port),
+            _ => format!("{}:{}", host, port),
+        };
+        let mut request = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n");
+        if let Some(auth) = &self.auth {
+            let credentials = base64::encode(format!("{}:{}", auth.username, auth.password));
+            request.push_str(&format!("Proxy-Authorization: Basic {}\r\n", credentials));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).await?;
+
+        // Byte by byte, so nothing the target sends after the head is swallowed here
+        let mut head = Vec::new();
+        while !head.ends_with(b"\r\n\r\n") {
+            if head.len() >= MAX_RESPONSE_HEAD {
+                return Err(ProxyError::Refused("CONNECT response head too long".to_string()));
+            }
+            head.push(stream.read_u8().await?);
+        }
+        let head = String::from_utf8_lossy(&head);
+        let status_line = head.lines().next().unwrap_or_default();
+        if status_line.split_whitespace().nth(1) != Some("200") {
+            return Err(ProxyError::Refused(status_line.to_string()));
+        }
+        Ok(())
+    }
+
+    async fn socks5_connect(&self, stream: &mut TcpStream, host: &str, port: u16) -> Result<(), ProxyError> {
+        let greeting: &[u8] = match self.auth {
+            Some(_) => &[SOCKS_VERSION, 2, SOCKS_NO_AUTH, SOCKS_USER_PASS],
+            None => &[SOCKS_VERSION, 1, SOCKS_NO_AUTH],
+        };
+        stream.write_all(greeting).await?;
+        let mut choice = [0u8; 2];
+        stream.read_exact(&mut choice).await?;
+        if choice[0] != SOCKS_VERSION {
+            return Err(ProxyError::Refused(format!("Not a SOCKS5 proxy (version {})", choice[0])));
+        }
+
+        match (choice[1], &self.auth) {
+            (SOCKS_NO_AUTH, _) => {}
+            (SOCKS_USER_PASS, Some(auth)) => {
+                let mut request = vec![1];
+                push_field(&mut request, &auth.username)?;
+                push_field(&mut request, &auth.password)?;
+                stream.write_all(&request).await?;
+                let mut status = [0u8; 2];
+                stream.read_exact(&mut status).await?;
+                if status[1] != 0 {
+                    return Err(ProxyError::Refused("SOCKS5 authentication failed".to_string()));
+                }
+            }
+            _ => {
+                return Err(ProxyError::Refused("No acceptable SOCKS5 authentication method".to_string()));
+            }
+        }
+
+        let mut request = vec![SOCKS_VERSION, SOCKS_CONNECT, 0];
+        match host.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) => {
+                request.push(SOCKS_IPV4);
+                request.extend_from_slice(&ip.octets());
+            }
+            Ok(IpAddr::V6(ip)) => {
+                request.push(SOCKS_IPV6);
+                request.extend_from_slice(&ip.octets());
+            }
+            Err(_) => {
+                request.push(SOCKS_DOMAIN);
+                push_field(&mut request, host)?;
+            }
+        }
+        request.extend_from_slice(&port.to_be_bytes());
+        stream.write_all(&request).await?;
+
+        let mut reply = [0u8; 4];
+        stream.read_exact(&mut reply).await?;
+        if reply[1] != 0 {
+            return Err(ProxyError::Refused(format!(
+                "SOCKS5 CONNECT to {}:{} failed with reply {}",
+                host, port, reply[1]
+            )));
+        }
+        // The address the proxy bound for us is of no use; skip it and the port
+        let bound_len = match reply[3] {
+            SOCKS_IPV4 => 4,
+            SOCKS_IPV6 => 16,
+            SOCKS_DOMAIN => stream.read_u8().await? as usize,
+            other => return Err(ProxyError::Refused(format!("Unknown SOCKS5 address type {}", other))),
+        };
+        let mut bound = vec![0u8; bound_len + 2];
+        stream.read_exact(&mut bound).await?;
+        Ok(())
+    }
+}
+
+/// Host and port a WebSocket URI connects to, with the scheme's default port.
+pub(crate) fn target_addr(target: &str) -> Result<(String, u16), ProxyError> {
+    let uri: Uri = target.parse().map_err(|_| ProxyError::InvalidTarget(target.to_string()))?;
+    let host = uri.host().ok_or_else(|| ProxyError::InvalidTarget(target.to_string()))?;
+    let port = match (uri.port_u16(), uri.scheme_str()) {
+        (Some(port), _) => port,
+        (None, Some("wss")) | (None, Some("https")) => 443,
+        (None, _) => 80,
+    };
+    Ok((unbracket(host).to_string(), port))
+}
+
+fn unbracket(host: &str) -> &str {
+    host.trim_start_matches('[').trim_end_matches(']')
+}
+
+/// Appends a SOCKS5 length-prefixed field, which can be at most 255 bytes long.
+fn push_field(buf: &mut Vec<u8>, field: &str) -> Result<(), ProxyError> {
+    let len = u8::try_from(field.len())
+        .map_err(|_| ProxyError::Refused(format!("{} bytes is too long for a SOCKS5 field", field.len())))?;
+    buf.push(len);
+    buf.extend_from_slice(field.as_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// HTTP proxy that answers one CONNECT with `status` and then echoes whatever it reads.
+    async fn http_proxy(status: &'static str) -> (String, tokio::task::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handle = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                head.push(socket.read_u8().await.unwrap());
+            }
+            socket
+                .write_all(format!("HTTP/1.1 {}\r\nVia: test\r\n\r\n", status).as_bytes())
+                .await
+                .unwrap();
+            let mut byte = [0u8; 1];
+            if socket.read_exact(&mut byte).await.is_ok() {
+                socket.write_all(&byte).await.unwrap();
+            }
+            String::from_utf8(head).unwrap()
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_target_defaults_to_the_scheme_port() {
+        assert_eq!(
+            target_addr("wss://stream.binance.com:9443/ws/btcusdt@depth").unwrap(),
+            ("stream.binance.com".to_string(), 9443)
+        );
+        assert_eq!(target_addr("wss://ws.kraken.com").unwrap(), ("ws.kraken.com".to_string(), 443));
+        assert_eq!(target_addr("ws://[::1]/feed").unwrap(), ("::1".to_string(), 80));
+    }
+
+    #[tokio::test]
+    async fn test_http_connect_sends_basic_auth() {
+        let (url, proxy) = http_proxy("200 Connection established").await;
+        let config = ProxyConfig::new(&url).with_auth("alice", "s3cret");
+
+        let mut stream = config.tunnel("wss://stream.binance.com:9443/ws").await.unwrap();
+        stream.write_all(b"x").await.unwrap();
+        assert_eq!(stream.read_u8().await.unwrap(), b'x');
+
+        let head = proxy.await.unwrap();
+        assert!(head.starts_with("CONNECT stream.binance.com:9443 HTTP/1.1\r\n"), "{}", head);
+        // base64("alice:s3cret")
+        assert!(head.contains("Proxy-Authorization: Basic YWxpY2U6czNjcmV0\r\n"), "{}", head);
+    }
+
+    #[tokio::test]
+    async fn test_http_proxy_refusal_is_an_error() {
+        let (url, _proxy) = http_proxy("407 Proxy Authentication Required").await;
+        match ProxyConfig::new(&url).tunnel("wss://ws.kraken.com").await {
+            Err(ProxyError::Refused(status)) => assert!(status.contains("407"), "{}", status),
+            other => panic!("Expected a refusal, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_debug_hides_the_password() {
+        let config = ProxyConfig::new("socks5://proxy:1080").with_auth("alice", "s3cret");
+        assert!(!format!("{:?}", config).contains("s3cret"));
+    }
+}
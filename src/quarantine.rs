@@ -0,0 +1,181 @@
+use crate::clock::{Clock, SystemClock};
+use chrono::{TimeZone, Utc};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Payloads written per UTC hour before further ones are only counted.
+const DEFAULT_PER_HOUR: usize = 100;
+/// Longest prefix of a payload kept on file; oversized frames are cut to it.
+pub const MAX_QUARANTINED_PAYLOAD: usize = 4096;
+const HOUR_MS: u64 = 3_600_000;
+
+/// One rejected payload, one JSON object per line on file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantineEntry {
+    pub recv_ts_ms: u64,
+    pub stream: String,
+    /// Why the payload was rejected.
+    pub error: String,
+    pub payload: String,
+}
+
+/// Keeps malformed or oversized payloads for postmortems, in hourly newline-delimited
+/// JSON files under one directory.
+///
+/// Only the first `per_hour` payloads of each UTC hour are written, so a feed that turns
+/// to garbage can't fill the disk; every one is still counted under
+/// `messages_quarantined`. Writes are synchronous, which the cap keeps cheap. Clones
+/// share the hourly budget.
+#[derive(Debug, Clone)]
+pub struct Quarantine {
+    dir: PathBuf,
+    stream: String,
+    per_hour: usize,
+    clock: Arc<dyn Clock>,
+    /// Hour (ms since epoch / 1h) the budget applies to, and payloads written in it.
+    written: Arc<Mutex<(u64, usize)>>,
+}
+
+impl Quarantine {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self::with_clock(dir, DEFAULT_PER_HOUR, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(dir: impl Into<PathBuf>, per_hour: usize, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            dir: dir.into(),
+            stream: String::new(),
+            per_hour,
+            clock,
+            written: Arc::new(Mutex::new((0, 0))),
+        }
+    }
+
+    /// Quarantine writing to the same files and budget under another `stream` label.
+    pub fn for_stream(&self, stream: &str) -> Self {
+        Self {
+            stream: stream.to_string(),
+            ..self.clone()
+        }
+    }
+
+    /// Counts a rejected `payload` and writes it with `error` if this hour's budget allows.
+    /// Returns whether it was written.
+    pub fn record(&self, payload: &str, error: &dyn fmt::Display) -> bool {
+        metrics::increment_counter!("messages_quarantined", "stream" => self.stream.clone());
+        let recv_ts_ms = self.clock.now_ms();
+
+        let mut written = self.written.lock().unwrap();
+        let hour = recv_ts_ms / HOUR_MS;
+        if written.0 != hour {
+            *written = (hour, 0);
+        }
+        if written.1 >= self.per_hour {
+            return false;
+        }
+        written.1 += 1;
+        if written.1 == self.per_hour {
+            warn!("Quarantined {} payloads this hour; only counting the rest", self.per_hour);
+        }
+
+        let entry = QuarantineEntry {
+            recv_ts_ms,
+            stream: self.stream.clone(),
+            error: error.to_string(),
+            payload: truncate(payload, MAX_QUARANTINED_PAYLOAD).to_string(),
+        };
+        match self.write(&entry) {
+            Ok(()) => true,
+            Err(e) => {
+                error!("Failed to quarantine payload under {}: {}", self.dir.display(), e);
+                false
+            }
+        }
+    }
+
+    fn write(&self, entry: &QuarantineEntry) -> io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(quarantine_file_name(entry.recv_ts_ms));
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        file.write_all(&line)
+    }
+}
+
+/// `quarantine_<YYYYMMDD>_<HH>.ndjson`, by the UTC hour the payload was received in.
+pub fn quarantine_file_name(recv_ts_ms: u64) -> String {
+    let hour = Utc
+        .timestamp_millis_opt(recv_ts_ms as i64)
+        .single()
+        .unwrap_or_else(Utc::now);
+    format!("quarantine_{}.ndjson", hour.format("%Y%m%d_%H"))
+}
+
+/// Reads back every entry of one quarantine file.
+pub fn read_quarantine(path: impl AsRef<Path>) -> io::Result<Vec<QuarantineEntry>> {
+    let reader = BufReader::new(File::open(path)?);
+    reader
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+/// Longest prefix of `text` that fits in `max` bytes without splitting a character.
+fn truncate(text: &str, max: usize) -> &str {
+    if text.len() <= max {
+        return text;
+    }
+    let mut end = max;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    // 2024-04-10 10:59:59.900 UTC
+    const BEFORE_HOUR_MS: u64 = 1_712_746_799_900;
+
+    #[test]
+    fn test_budget_is_per_hour() {
+        let dir = tempdir().unwrap();
+        let clock = ManualClock::new(BEFORE_HOUR_MS);
+        let quarantine = Quarantine::with_clock(dir.path(), 2, Arc::new(clock.clone())).for_stream("depth");
+
+        assert!(quarantine.record("a", &"bad a"));
+        assert!(quarantine.clone().record("b", &"bad b"));
+        assert!(!quarantine.record("c", &"bad c"));
+        clock.advance(Duration::from_millis(200));
+        assert!(quarantine.record("d", &"bad d"));
+
+        let first = read_quarantine(dir.path().join("quarantine_20240410_10.ndjson")).unwrap();
+        let payloads: Vec<_> = first.iter().map(|entry| entry.payload.as_str()).collect();
+        assert_eq!(payloads, vec!["a", "b"]);
+        assert_eq!(first[1].error, "bad b");
+        assert_eq!(first[1].stream, "depth");
+
+        let second = read_quarantine(dir.path().join("quarantine_20240410_11.ndjson")).unwrap();
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].recv_ts_ms, BEFORE_HOUR_MS + 200);
+    }
+
+    #[test]
+    fn test_truncate_keeps_whole_characters() {
+        assert_eq!(truncate("abc", 5), "abc");
+        assert_eq!(truncate("a\u{2713}b", 2), "a");
+        assert_eq!(truncate("a\u{2713}b", 4), "a\u{2713}");
+    }
+}
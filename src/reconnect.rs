@@ -0,0 +1,222 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::fmt;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ReconnectError {
+    #[error("Gave up on {uri} after {attempts} reconnect attempts")]
+    GaveUp { uri: String, attempts: u32 },
+    #[error("Gave up on {uri} after {failures} failed connects in a row")]
+    TooManyFailures { uri: String, failures: usize },
+}
+
+/// Raised once a connection has failed `failures` connects in a row without a stable
+/// connection in between, while it carries on reconnecting.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PersistentFailure {
+    pub uri: String,
+    pub failures: usize,
+    /// The error of the connect that crossed the threshold.
+    pub error: String,
+}
+
+/// Where a transport reports that it keeps failing to connect, e.g. for the application to
+/// page someone or exit non-zero.
+///
+/// Fires once each time the run of failed connects reaches `after`, then not again until a
+/// connection has stayed up past the policy's `stable_after`.
+#[derive(Debug, Clone)]
+pub struct FailureEscalation {
+    after: usize,
+    tx: mpsc::UnboundedSender<PersistentFailure>,
+}
+
+impl FailureEscalation {
+    pub fn new(after: usize, tx: mpsc::UnboundedSender<PersistentFailure>) -> Self {
+        assert!(after > 0, "escalating after no failures would fire on every connect");
+        Self { after, tx }
+    }
+
+    /// Reports the failed connect that made `failures` in a row, if that reached the threshold.
+    pub(crate) fn on_failure(&self, uri: &str, failures: usize, error: &dyn fmt::Display) {
+        if failures != self.after {
+            return;
+        }
+        // Nobody listening any more just means nobody wants to be told
+        let _ = self.tx.send(PersistentFailure {
+            uri: uri.to_string(),
+            failures,
+            error: error.to_string(),
+        });
+    }
+}
+
+/// How a connection loop paces its reconnect attempts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReconnectPolicy {
+    pub initial: Duration,
+    pub max: Duration,
+    pub multiplier: f64,
+    /// Each delay is moved up or down by a random share of at most this much, so feeds
+    /// dropped together don't all come back at the same instant.
+    pub jitter_fraction: f64,
+    /// Attempts without a stable connection before giving up; `None` retries forever.
+    pub max_attempts: Option<u32>,
+    /// Failed connects in a row before giving up, counting each endpoint tried; any
+    /// successful connect starts the count over. Unlike `max_attempts`, connections that
+    /// come up and drop straight away never trip it. `None` keeps trying.
+    pub max_consecutive_failures: Option<usize>,
+    /// A connection that stayed up this long resets the backoff.
+    pub stable_after: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter_fraction: 0.2,
+            max_attempts: None,
+            max_consecutive_failures: None,
+            stable_after: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Delay sequence for one connection loop under a `ReconnectPolicy`.
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    policy: ReconnectPolicy,
+    attempts: u32,
+    next_delay: Duration,
+    rng: StdRng,
+}
+
+impl Backoff {
+    pub fn new(policy: ReconnectPolicy) -> Self {
+        Self::with_rng(policy, StdRng::from_entropy())
+    }
+
+    /// Backoff with a reproducible jitter sequence.
+    pub fn with_seed(policy: ReconnectPolicy, seed: u64) -> Self {
+        Self::with_rng(policy, StdRng::seed_from_u64(seed))
+    }
+
+    fn with_rng(policy: ReconnectPolicy, rng: StdRng) -> Self {
+        Self {
+            policy,
+            attempts: 0,
+            next_delay: policy.initial,
+            rng,
+        }
+    }
+
+    /// Delay before the next attempt, or `None` once `max_attempts` is used up.
+    pub fn next_delay(&mut self) -> Option<Duration> {
+        if matches!(self.policy.max_attempts, Some(max) if self.attempts >= max) {
+            return None;
+        }
+        self.attempts += 1;
+
+        let base = self.next_delay;
+        self.next_delay = base.mul_f64(self.policy.multiplier).min(self.policy.max);
+
+        let jitter = self.policy.jitter_fraction;
+        if jitter <= 0.0 {
+            return Some(base);
+        }
+        let factor = 1.0 + self.rng.gen_range(-jitter..=jitter);
+        Some(base.mul_f64(factor).min(self.policy.max))
+    }
+
+    /// Called when a connection ends; one that stayed up past `stable_after` starts the sequence over.
+    pub fn on_disconnect(&mut self, uptime: Duration) {
+        if uptime >= self.policy.stable_after {
+            self.reset();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.attempts = 0;
+        self.next_delay = self.policy.initial;
+    }
+
+    pub fn policy(&self) -> &ReconnectPolicy {
+        &self.policy
+    }
+
+    /// Attempts made since the last reset.
+    pub fn attempts(&self) -> u32 {
+        self.attempts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial: Duration::from_millis(100),
+            max: Duration::from_millis(1_000),
+            multiplier: 2.0,
+            jitter_fraction: 0.0,
+            max_attempts: None,
+            max_consecutive_failures: None,
+            stable_after: Duration::from_secs(10),
+        }
+    }
+
+    fn take(backoff: &mut Backoff, n: usize) -> Vec<Option<Duration>> {
+        (0..n).map(|_| backoff.next_delay()).collect()
+    }
+
+    #[test]
+    fn test_delays_grow_geometrically_up_to_max() {
+        let mut backoff = Backoff::with_seed(policy(), 7);
+        let millis: Vec<_> = take(&mut backoff, 6).into_iter().map(|d| d.unwrap().as_millis()).collect();
+        assert_eq!(millis, vec![100, 200, 400, 800, 1_000, 1_000]);
+    }
+
+    #[test]
+    fn test_jitter_is_bounded_and_reproducible() {
+        let jittered = ReconnectPolicy { jitter_fraction: 0.25, ..policy() };
+        let first = take(&mut Backoff::with_seed(jittered, 42), 6);
+        assert_eq!(first, take(&mut Backoff::with_seed(jittered, 42), 6));
+        assert_ne!(first, take(&mut Backoff::with_seed(jittered, 43), 6));
+
+        for (delay, base) in first.into_iter().zip([100u64, 200, 400, 800, 1_000, 1_000]) {
+            let delay = delay.unwrap();
+            assert!(delay >= Duration::from_millis(base * 3 / 4), "{:?} below {}ms", delay, base);
+            assert!(delay <= Duration::from_millis(base * 5 / 4).min(Duration::from_millis(1_000)));
+        }
+    }
+
+    #[test]
+    fn test_gives_up_after_max_attempts() {
+        let mut backoff = Backoff::with_seed(ReconnectPolicy { max_attempts: Some(3), ..policy() }, 1);
+        assert!(take(&mut backoff, 3).iter().all(Option::is_some));
+        assert_eq!(backoff.next_delay(), None);
+        assert_eq!(backoff.attempts(), 3);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn test_only_stable_connections_reset_the_backoff() {
+        let mut backoff = Backoff::with_seed(policy(), 1);
+        take(&mut backoff, 3);
+
+        backoff.on_disconnect(Duration::from_secs(9));
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(800)));
+
+        backoff.on_disconnect(Duration::from_secs(10));
+        assert_eq!(backoff.attempts(), 0);
+        assert_eq!(backoff.next_delay(), Some(Duration::from_millis(100)));
+    }
+}
@@ -0,0 +1,479 @@
+use crate::exchange::{BinanceAdapter, ExchangeAdapter};
+use crate::lob_feed_manager::{LobFeedMetrics, SymbolSync};
+use crate::orderbook::ConcurrentOrderBook;
+use crate::persistence::load_raw_trades_from_parquet;
+use crate::tape::{TapeEntry, DEPTH_SNAPSHOT_STREAM, DEPTH_STREAM, TRADE_STREAM};
+use crate::tradeslog::{ConcurrentTradesLog, Trade};
+use anyhow::Context;
+use log::{debug, info};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::fs::File;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::sync::watch;
+use tokio::time::{sleep_until, Instant};
+
+/// Trades a `TradeReplayer` inserts between progress updates.
+const PROGRESS_EVERY_TRADES: u64 = 10_000;
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error("Failed to read {}: {source}", .path.display())]
+    Io { path: PathBuf, source: io::Error },
+    #[error("Malformed tape entry at {}:{line}: {source}", .path.display())]
+    Entry {
+        path: PathBuf,
+        line: usize,
+        source: serde_json::Error,
+    },
+    #[error("Failed to load trades from {}: {source:#}", .path.display())]
+    Trades { path: PathBuf, source: anyhow::Error },
+}
+
+/// How fast recorded frames are fed back in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// Back to back, without waiting between frames.
+    AsFastAsPossible,
+    /// Recorded inter-arrival times divided by the multiplier; `Paced(1.0)` is real time.
+    /// Trades replayed from Parquet go by their exchange timestamps instead.
+    Paced(f64),
+}
+
+/// Counts for one pass over a tape.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReplayStats {
+    pub frames: u64,
+    pub depth_events: u64,
+    pub trades: u64,
+    pub parse_failures: u64,
+    /// Times the book was dropped to wait for a snapshot after a gap or checksum mismatch.
+    pub resyncs: u64,
+}
+
+/// Drives a book and a trades log from a recorded tape instead of the network.
+///
+/// Frames go through the adapter parsing and depth sequencing the live feed managers use,
+/// minus the coalescing. Meant for single-symbol tapes: every depth frame lands in the one
+/// book, seeded by the REST snapshots recorded under `depth_snapshot`.
+pub struct ReplayFeedManager {
+    tape_path: PathBuf,
+    order_book: ConcurrentOrderBook,
+    trades_log: ConcurrentTradesLog,
+    speed: ReplaySpeed,
+    adapter: Arc<dyn ExchangeAdapter>,
+}
+
+impl ReplayFeedManager {
+    /// `tape_path` is a tape file, or a directory whose `tape_*.ndjson` files play in name order.
+    pub fn new(
+        tape_path: impl Into<PathBuf>,
+        order_book: ConcurrentOrderBook,
+        trades_log: ConcurrentTradesLog,
+        speed: ReplaySpeed,
+    ) -> Self {
+        Self {
+            tape_path: tape_path.into(),
+            order_book,
+            trades_log,
+            speed,
+            adapter: Arc::new(BinanceAdapter::new()),
+        }
+    }
+
+    /// Parses frames as the exchange the tape was recorded from; Binance by default.
+    pub fn with_adapter(mut self, adapter: Arc<dyn ExchangeAdapter>) -> Self {
+        self.adapter = adapter;
+        self
+    }
+
+    /// Plays the whole tape, returning once the last frame has been applied.
+    pub async fn run(&self) -> Result<ReplayStats, ReplayError> {
+        let mut stats = ReplayStats::default();
+        let mut feed = SymbolSync::new(
+            self.order_book.clone(),
+            self.adapter.as_ref(),
+            None,
+            LobFeedMetrics::register(),
+        );
+        let mut origin: Option<(u64, Instant)> = None;
+
+        for path in recorded_files(&self.tape_path, "tape_", ".ndjson")? {
+            let io_error = |source| ReplayError::Io { path: path.clone(), source };
+            let file = File::open(&path).await.map_err(io_error)?;
+            let mut lines = BufReader::new(file).lines();
+            let mut line = 0;
+
+            while let Some(text) = lines.next_line().await.map_err(io_error)? {
+                line += 1;
+                if text.is_empty() {
+                    continue;
+                }
+                let entry: TapeEntry = serde_json::from_str(&text).map_err(|source| ReplayError::Entry {
+                    path: path.clone(),
+                    line,
+                    source,
+                })?;
+                pace(self.speed, &mut origin, entry.recv_ts_ms).await;
+                stats.frames += 1;
+                self.replay_entry(&entry, &mut feed, &mut stats).await;
+            }
+        }
+
+        info!("Replayed {} frames from {}", stats.frames, self.tape_path.display());
+        Ok(stats)
+    }
+
+    async fn replay_entry(&self, entry: &TapeEntry, feed: &mut SymbolSync, stats: &mut ReplayStats) {
+        let parsed = match entry.stream.as_str() {
+            TRADE_STREAM => {
+                match self.adapter.parse_trades(&entry.payload) {
+                    Ok(trades) => {
+                        for trade in trades {
+                            self.trades_log.insert_trade(trade).await;
+                            stats.trades += 1;
+                        }
+                    }
+                    Err(e) => {
+                        stats.parse_failures += 1;
+                        debug!("Skipping trade frame {}: {}", entry.payload, e);
+                    }
+                }
+                return;
+            }
+            DEPTH_STREAM => self.adapter.parse_depth(&entry.payload),
+            DEPTH_SNAPSHOT_STREAM => self.adapter.parse_snapshot(&entry.payload),
+            other => {
+                debug!("Skipping frame from unknown stream {}", other);
+                return;
+            }
+        };
+
+        // Subscription acks are on tape too; they fail here just as they do live
+        let event = match parsed {
+            Ok(event) => event,
+            Err(e) => {
+                stats.parse_failures += 1;
+                debug!("Skipping depth frame {}: {}", entry.payload, e);
+                return;
+            }
+        };
+        stats.depth_events += 1;
+        let (outcome, checksum) = feed.on_event(event).await;
+        if !feed.apply_now(outcome, checksum).await {
+            stats.resyncs += 1;
+        }
+    }
+}
+
+/// Where a `TradeReplayer` has got to.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TradeReplayProgress {
+    pub replayed: u64,
+    pub total: u64,
+    /// Trades inserted per second of wall-clock time since the replay started.
+    pub trades_per_sec: f64,
+    /// The exchange's id for the last trade inserted, where its file recorded one.
+    pub last_trade_id: Option<u64>,
+}
+
+impl TradeReplayProgress {
+    /// Share of the trades inserted so far, from 0 to 1; 1 for a replay with no trades.
+    pub fn completion(&self) -> f64 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        self.replayed as f64 / self.total as f64
+    }
+}
+
+/// Feeds trades persisted by `TradeSink` (or written with `TradeParquetWriter`) into a
+/// trades log, so the analytics task can run over a historical session.
+///
+/// Trades go in exactly in file order, even where their timestamps step back, and keep
+/// their symbols, trade ids, and prices and quantities as the loader reads them.
+pub struct TradeReplayer {
+    path: PathBuf,
+    trades: Vec<Trade>,
+    speed: ReplaySpeed,
+    progress: watch::Sender<TradeReplayProgress>,
+}
+
+impl TradeReplayer {
+    /// Loads `path`, a raw trades file or a directory whose `trades_*.parquet` files play
+    /// in name order, i.e. by hour.
+    pub fn from_parquet(path: impl Into<PathBuf>) -> Result<Self, ReplayError> {
+        let path = path.into();
+        let mut trades = Vec::new();
+        for file in recorded_files(&path, "trades_", ".parquet")? {
+            let load_error = |source| ReplayError::Trades { path: file.clone(), source };
+            let raw_trades = load_raw_trades_from_parquet(&file.to_string_lossy()).map_err(load_error)?;
+            for (row, raw) in raw_trades.into_iter().enumerate() {
+                let trade = Trade::new(raw.price, raw.quantity, raw.timestamp, raw.is_buyer_maker)
+                    .with_context(|| format!("Invalid trade in row {}", row))
+                    .map_err(load_error)?;
+                let trade = match &raw.symbol {
+                    Some(symbol) => trade.with_symbol(symbol),
+                    None => trade,
+                };
+                trades.push(Trade { trade_id: raw.trade_id, ..trade });
+            }
+        }
+        let (progress, _) = watch::channel(TradeReplayProgress {
+            total: trades.len() as u64,
+            ..TradeReplayProgress::default()
+        });
+        Ok(Self {
+            path,
+            trades,
+            speed: ReplaySpeed::AsFastAsPossible,
+            progress,
+        })
+    }
+
+    /// As fast as possible by default.
+    pub fn with_speed(mut self, speed: ReplaySpeed) -> Self {
+        self.speed = speed;
+        self
+    }
+
+    /// Trades loaded, and so to be replayed.
+    pub fn len(&self) -> usize {
+        self.trades.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trades.is_empty()
+    }
+
+    /// Progress updates, every few thousand trades and once more at the end.
+    pub fn progress(&self) -> watch::Receiver<TradeReplayProgress> {
+        self.progress.subscribe()
+    }
+
+    /// Inserts every trade into `trades_log`, returning once the last one is in.
+    pub async fn run(&self, trades_log: &ConcurrentTradesLog) -> TradeReplayProgress {
+        let started = Instant::now();
+        let mut origin: Option<(u64, Instant)> = None;
+        let mut next_report = PROGRESS_EVERY_TRADES;
+        let mut progress = TradeReplayProgress {
+            total: self.trades.len() as u64,
+            ..TradeReplayProgress::default()
+        };
+
+        for trade in &self.trades {
+            pace(self.speed, &mut origin, trade.timestamp).await;
+            trades_log.insert_trade(trade.clone()).await;
+            progress.replayed += 1;
+            progress.last_trade_id = trade.trade_id;
+            if progress.replayed == next_report {
+                next_report += PROGRESS_EVERY_TRADES;
+                self.report(&mut progress, started);
+                debug!("Replayed {} of {} trades", progress.replayed, progress.total);
+                // Lets the analytics task look at the log between bursts
+                tokio::task::yield_now().await;
+            }
+        }
+
+        self.report(&mut progress, started);
+        info!(
+            "Replayed {} trades from {} at {:.0} trades/s",
+            progress.replayed,
+            self.path.display(),
+            progress.trades_per_sec
+        );
+        progress
+    }
+
+    fn report(&self, progress: &mut TradeReplayProgress, started: Instant) {
+        let elapsed = started.elapsed().as_secs_f64();
+        progress.trades_per_sec = if elapsed > 0.0 {
+            progress.replayed as f64 / elapsed
+        } else {
+            0.0
+        };
+        self.progress.send_replace(*progress);
+    }
+}
+
+/// Waits until whatever is stamped `ts_ms` is due at `speed`, measured from the first
+/// thing paced.
+async fn pace(speed: ReplaySpeed, origin: &mut Option<(u64, Instant)>, ts_ms: u64) {
+    let multiplier = match speed {
+        ReplaySpeed::Paced(multiplier) if multiplier > 0.0 => multiplier,
+        _ => return,
+    };
+    let (first_ts_ms, started) = *origin.get_or_insert((ts_ms, Instant::now()));
+    let offset = Duration::from_millis(ts_ms.saturating_sub(first_ts_ms));
+    sleep_until(started + offset.div_f64(multiplier)).await;
+}
+
+/// `path` itself, or the files in it named `<prefix>...<suffix>` in name order.
+fn recorded_files(path: &Path, prefix: &str, suffix: &str) -> Result<Vec<PathBuf>, ReplayError> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let io_error = |source| ReplayError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(path).map_err(io_error)? {
+        let file = entry.map_err(io_error)?.path();
+        let name = file.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+        if name.starts_with(prefix) && name.ends_with(suffix) {
+            files.push(file);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tape::tape_file_name;
+    use rust_decimal_macros::dec;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    // 2024-04-10 11:00:00 UTC
+    const HOUR_MS: u64 = 1_712_746_800_000;
+
+    fn write_tape(dir: &Path, entries: &[(u64, &str, &str)]) {
+        for &(recv_ts_ms, stream, payload) in entries {
+            let entry = TapeEntry {
+                recv_ts_ms,
+                stream: stream.to_string(),
+                payload: payload.to_string(),
+            };
+            let mut file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(dir.join(tape_file_name(recv_ts_ms)))
+                .unwrap();
+            writeln!(file, "{}", serde_json::to_string(&entry).unwrap()).unwrap();
+        }
+    }
+
+    fn trade(price: &str, time: u64) -> String {
+        format!(
+            r#"{{"e":"trade","E":{time},"s":"BTCUSDT","t":1,"p":"{price}","q":"1.0","b":0,"a":0,"T":{time},"m":false,"M":true}}"#
+        )
+    }
+
+    #[tokio::test]
+    async fn test_directory_plays_hourly_files_in_order() {
+        let dir = tempdir().unwrap();
+        write_tape(dir.path(), &[
+            (HOUR_MS + 3_600_000, TRADE_STREAM, &trade("102.0", 3)),
+            (HOUR_MS - 1, TRADE_STREAM, &trade("100.0", 1)),
+            (HOUR_MS, TRADE_STREAM, &trade("101.0", 2)),
+        ]);
+        std::fs::write(dir.path().join("notes.txt"), "not a tape").unwrap();
+
+        let trades_log = ConcurrentTradesLog::new(10);
+        let replay = ReplayFeedManager::new(
+            dir.path(),
+            ConcurrentOrderBook::new(),
+            trades_log.clone(),
+            ReplaySpeed::AsFastAsPossible,
+        );
+        let stats = replay.run().await.unwrap();
+
+        assert_eq!(stats.frames, 3);
+        assert_eq!(stats.trades, 3);
+        let prices: Vec<_> = trades_log.last_n_trades(3).await.into_iter().map(|t| t.price).collect();
+        assert_eq!(prices, vec![dec!(102.0), dec!(101.0), dec!(100.0)]);
+    }
+
+    #[tokio::test]
+    async fn test_paced_replay_scales_recorded_gaps() {
+        let dir = tempdir().unwrap();
+        write_tape(dir.path(), &[
+            (HOUR_MS, TRADE_STREAM, &trade("100.0", 1)),
+            (HOUR_MS + 400, TRADE_STREAM, &trade("101.0", 2)),
+        ]);
+        let path = dir.path().join(tape_file_name(HOUR_MS));
+
+        let started = Instant::now();
+        let replay = ReplayFeedManager::new(
+            &path,
+            ConcurrentOrderBook::new(),
+            ConcurrentTradesLog::new(10),
+            ReplaySpeed::Paced(4.0),
+        );
+        replay.run().await.unwrap();
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(100), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(400), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_entry_reports_its_line() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("tape_20240410_11.ndjson");
+        std::fs::write(&path, "\n{\"recv_ts_ms\":1,\"stream\":\"trades\",\"payload\":\"{}\"}\nnot json\n").unwrap();
+
+        let replay = ReplayFeedManager::new(
+            &path,
+            ConcurrentOrderBook::new(),
+            ConcurrentTradesLog::new(10),
+            ReplaySpeed::AsFastAsPossible,
+        );
+        match replay.run().await {
+            Err(ReplayError::Entry { line, .. }) => assert_eq!(line, 3),
+            other => panic!("Expected a malformed entry error, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trades_replay_keeps_file_order_when_paced() {
+        use crate::persistence::TradeParquetWriter;
+        use crate::trade_sink::RawTrade;
+        use crate::tradeslog::Trade;
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("trades_20240410_11.parquet");
+        // The last trade is stamped before the one ahead of it, as exchanges sometimes do
+        let stamped = [(HOUR_MS, dec!(100), 7), (HOUR_MS + 400, dec!(101), 8), (HOUR_MS + 300, dec!(102), 9)];
+        let trades: Vec<RawTrade> = stamped
+            .into_iter()
+            .map(|(timestamp, price, id)| {
+                RawTrade::new(&Trade::new(price, dec!(1), timestamp, false).unwrap().with_trade_id(id))
+            })
+            .collect();
+        let mut writer = TradeParquetWriter::create(&path).unwrap();
+        writer.write(&trades).unwrap();
+        writer.finish().unwrap();
+
+        let replayer = TradeReplayer::from_parquet(&path).unwrap().with_speed(ReplaySpeed::Paced(4.0));
+        let progress_rx = replayer.progress();
+        assert_eq!(progress_rx.borrow().completion(), 0.0);
+        let trades_log = ConcurrentTradesLog::new(10);
+        let started = Instant::now();
+        replayer.run(&trades_log).await;
+        let elapsed = started.elapsed();
+
+        assert!(elapsed >= Duration::from_millis(100), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(400), "{:?}", elapsed);
+        let replayed: Vec<_> = trades_log.last_n_trades(3).await.into_iter().map(|t| (t.price, t.trade_id)).collect();
+        assert_eq!(replayed, vec![(dec!(102), Some(9)), (dec!(101), Some(8)), (dec!(100), Some(7))]);
+        let progress = *progress_rx.borrow();
+        assert_eq!((progress.replayed, progress.last_trade_id), (3, Some(9)));
+        assert_eq!(progress.completion(), 1.0);
+    }
+
+    #[test]
+    fn test_missing_trades_file_names_it() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("trades_20240410_11.parquet");
+        match TradeReplayer::from_parquet(&path) {
+            Err(ReplayError::Trades { path: failed, .. }) => assert_eq!(failed, path),
+            other => panic!("Expected a load error, got {:?}", other.map(|replayer| replayer.len())),
+        }
+    }
+}
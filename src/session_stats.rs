@@ -0,0 +1,187 @@
+use crate::clock::{Clock, SystemClock};
+use crate::feed_health::ReconnectCounts;
+use crate::tradeslog::{MakerSemantics, Trade};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+
+/// Totals over one run of the ingestor, for the summary written on shutdown with
+/// `persistence::write_session_summary`. Clones share the totals.
+#[derive(Debug, Clone)]
+pub struct SessionStats {
+    clock: Arc<dyn Clock>,
+    started_ms: u64,
+    totals: Arc<Mutex<Totals>>,
+}
+
+#[derive(Debug, Default)]
+struct Totals {
+    trades: u64,
+    buy_trades: u64,
+    volume: Decimal,
+    buy_volume: Decimal,
+    mid_min: Option<Decimal>,
+    mid_max: Option<Decimal>,
+    mid_sum: Decimal,
+    mids: u64,
+    reconnects: ReconnectCounts,
+    failure: Option<String>,
+}
+
+/// What `SessionStats` adds up to at one point in the run.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SessionSummary {
+    /// Epoch ms.
+    pub started_at_ms: u64,
+    pub ended_at_ms: u64,
+    pub uptime_ms: u64,
+    pub trades: u64,
+    /// Trades the taker bought in, and their volume; the rest were taker sells.
+    pub buy_trades: u64,
+    pub sell_trades: u64,
+    /// In the base asset.
+    pub volume: Decimal,
+    pub buy_volume: Decimal,
+    pub sell_volume: Decimal,
+    /// Over the mids recorded, one per analytics tick; `None` if the book never had one.
+    pub min_mid: Option<Decimal>,
+    pub max_mid: Option<Decimal>,
+    pub mean_mid: Option<Decimal>,
+    pub reconnects: ReconnectCounts,
+    /// What ended the session early, if a feed or the analytics task failed.
+    pub failure: Option<String>,
+}
+
+impl SessionStats {
+    /// A session starting now.
+    pub fn new() -> Self {
+        Self::with_clock(Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            started_ms: clock.now_ms(),
+            clock,
+            totals: Arc::new(Mutex::new(Totals::default())),
+        }
+    }
+
+    /// Counts a trade, as a taker buy or sell by Binance's flag semantics, which every
+    /// adapter normalizes to.
+    pub fn record_trade(&self, trade: &Trade) {
+        let mut totals = self.totals.lock().unwrap();
+        totals.trades += 1;
+        totals.volume += trade.quantity;
+        if MakerSemantics::default().is_taker_buy(trade) {
+            totals.buy_trades += 1;
+            totals.buy_volume += trade.quantity;
+        }
+    }
+
+    pub fn record_mid(&self, mid: Decimal) {
+        let mut totals = self.totals.lock().unwrap();
+        totals.mid_min = Some(totals.mid_min.map_or(mid, |min| min.min(mid)));
+        totals.mid_max = Some(totals.mid_max.map_or(mid, |max| max.max(mid)));
+        totals.mid_sum += mid;
+        totals.mids += 1;
+    }
+
+    /// Sets the reconnects so far, e.g. `FeedHealth::reconnect_counts`.
+    pub fn record_reconnects(&self, reconnects: ReconnectCounts) {
+        self.totals.lock().unwrap().reconnects = reconnects;
+    }
+
+    /// Notes that `failure` is ending the session early.
+    pub fn record_failure(&self, failure: impl Into<String>) {
+        self.totals.lock().unwrap().failure = Some(failure.into());
+    }
+
+    /// The session from its start until now.
+    pub fn summary(&self) -> SessionSummary {
+        let totals = self.totals.lock().unwrap();
+        let ended_at_ms = self.clock.now_ms();
+        SessionSummary {
+            started_at_ms: self.started_ms,
+            ended_at_ms,
+            uptime_ms: ended_at_ms.saturating_sub(self.started_ms),
+            trades: totals.trades,
+            buy_trades: totals.buy_trades,
+            sell_trades: totals.trades - totals.buy_trades,
+            volume: totals.volume,
+            buy_volume: totals.buy_volume,
+            sell_volume: totals.volume - totals.buy_volume,
+            min_mid: totals.mid_min,
+            max_mid: totals.mid_max,
+            mean_mid: (totals.mids > 0).then(|| totals.mid_sum / Decimal::from(totals.mids)),
+            reconnects: totals.reconnects,
+            failure: totals.failure.clone(),
+        }
+    }
+}
+
+impl Default for SessionStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::persistence::write_session_summary;
+    use rust_decimal_macros::dec;
+    use serde_json::{json, Value};
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_summary_json_adds_up_the_session() {
+        let clock = ManualClock::new(1_712_745_600_000);
+        let stats = SessionStats::with_clock(Arc::new(clock.clone()));
+        let trades = [(dec!(100), dec!(2), false), (dec!(101), dec!(0.5), true), (dec!(99), dec!(1.5), false)];
+        for (price, quantity, is_buyer_maker) in trades {
+            stats.record_trade(&Trade::new(price, quantity, 1_712_745_600_000, is_buyer_maker).unwrap());
+        }
+        for mid in [dec!(100.5), dec!(99.5), dec!(101)] {
+            stats.record_mid(mid);
+        }
+        stats.record_reconnects(ReconnectCounts { depth: 1, trade: 2 });
+        clock.advance(Duration::from_secs(90));
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("summaries").join("session.json");
+        write_session_summary(&stats, &path).unwrap();
+        let summary: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+
+        assert_eq!(summary["started_at_ms"], json!(1_712_745_600_000u64));
+        assert_eq!(summary["ended_at_ms"], json!(1_712_745_690_000u64));
+        assert_eq!(summary["uptime_ms"], json!(90_000));
+        assert_eq!(summary["trades"], json!(3));
+        assert_eq!(summary["buy_trades"], json!(2));
+        assert_eq!(summary["sell_trades"], json!(1));
+        // Decimals keep their exact digits as strings
+        assert_eq!(summary["volume"], json!("4.0"));
+        assert_eq!(summary["buy_volume"], json!("3.5"));
+        assert_eq!(summary["sell_volume"], json!("0.5"));
+        assert_eq!(summary["min_mid"], json!("99.5"));
+        assert_eq!(summary["max_mid"], json!("101"));
+        assert_eq!(summary["mean_mid"], json!("100.33333333333333333333333333"));
+        assert_eq!(summary["reconnects"], json!({"depth": 1, "trade": 2}));
+        assert_eq!(summary["failure"], Value::Null);
+    }
+
+    #[test]
+    fn test_summary_says_what_ended_a_failed_session() {
+        let stats = SessionStats::with_clock(Arc::new(ManualClock::new(5)));
+        stats.record_failure("Trade feed crashed");
+        assert_eq!(stats.summary().failure.as_deref(), Some("Trade feed crashed"));
+    }
+
+    #[test]
+    fn test_empty_session_has_no_mids() {
+        let summary = SessionStats::with_clock(Arc::new(ManualClock::new(5))).summary();
+        assert_eq!((summary.trades, summary.volume, summary.uptime_ms), (0, Decimal::ZERO, 0));
+        assert_eq!((summary.min_mid, summary.mean_mid), (None, None));
+    }
+}
@@ -0,0 +1,346 @@
+use anyhow::Context;
+use futures_util::future::BoxFuture;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tokio::time::{sleep_until, Instant};
+
+const DEFAULT_MIN_INTERVAL: Duration = Duration::from_secs(1);
+/// Binance spot's request weight allowance per minute.
+const DEFAULT_WEIGHT_LIMIT: u32 = 6000;
+const DEFAULT_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+const WEIGHT_WINDOW_MS: u128 = 60_000;
+
+#[derive(Debug, Error)]
+pub enum SnapshotFetchError {
+    #[error("Depth snapshot rate limited with HTTP {status}; backing off for {backoff:?}")]
+    RateLimited { status: u16, backoff: Duration },
+    #[error("Depth snapshot request rejected with HTTP {0}")]
+    Status(u16),
+    #[error(transparent)]
+    Request(#[from] anyhow::Error),
+}
+
+/// The parts of a snapshot response the fetcher acts on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotResponse {
+    pub status: u16,
+    /// Request weight used in the current minute, from `X-MBX-USED-WEIGHT-1M`.
+    pub used_weight: Option<u32>,
+    pub retry_after: Option<Duration>,
+    pub body: String,
+}
+
+/// One HTTP GET; a trait so tests can script responses.
+pub trait SnapshotHttp: Send + Sync {
+    fn get<'a>(&'a self, uri: &'a str) -> BoxFuture<'a, anyhow::Result<SnapshotResponse>>;
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReqwestHttp {
+    client: reqwest::Client,
+}
+
+impl SnapshotHttp for ReqwestHttp {
+    fn get<'a>(&'a self, uri: &'a str) -> BoxFuture<'a, anyhow::Result<SnapshotResponse>> {
+        Box::pin(async move {
+            let response = self.client.get(uri).send().await.context("Depth snapshot request failed")?;
+            let header = |name: &str| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.trim().parse::<u32>().ok())
+            };
+            let used_weight = header("x-mbx-used-weight-1m").or_else(|| header("x-mbx-used-weight"));
+            let retry_after = header("retry-after").map(|secs| Duration::from_secs(secs.into()));
+            let status = response.status().as_u16();
+            let body = response.text().await.context("Failed to read depth snapshot")?;
+            Ok(SnapshotResponse { status, used_weight, retry_after, body })
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct UriState {
+    last_started: Option<Instant>,
+    /// Body of the last successful fetch and when it completed.
+    last: Option<(Instant, String)>,
+}
+
+#[derive(Debug)]
+struct Limits {
+    /// No request goes out before this, whatever its URI.
+    paused_until: Option<Instant>,
+    next_backoff: Duration,
+    used_weight: Option<u32>,
+}
+
+/// Fetches REST depth snapshots without running into the exchange's rate limits.
+///
+/// Fetches of one URI start at least `min_interval` apart, and callers that ask while a
+/// fetch is in flight share its body instead of sending their own. HTTP 429 and 418
+/// pause every URI for the longer of `Retry-After` and a backoff that doubles up to
+/// its maximum; reported request weight reaching the limit pauses them until the next
+/// minute. Clones share their limits.
+#[derive(Clone)]
+pub struct SnapshotFetcher {
+    http: Arc<dyn SnapshotHttp>,
+    min_interval: Duration,
+    cache_ttl: Duration,
+    weight_limit: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    uris: Arc<StdMutex<HashMap<String, Arc<Mutex<UriState>>>>>,
+    limits: Arc<StdMutex<Limits>>,
+}
+
+impl SnapshotFetcher {
+    pub fn new() -> Self {
+        Self::with_http(Arc::new(ReqwestHttp::default()))
+    }
+
+    pub fn with_http(http: Arc<dyn SnapshotHttp>) -> Self {
+        Self {
+            http,
+            min_interval: DEFAULT_MIN_INTERVAL,
+            cache_ttl: Duration::ZERO,
+            weight_limit: DEFAULT_WEIGHT_LIMIT,
+            initial_backoff: DEFAULT_INITIAL_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            uris: Arc::new(StdMutex::new(HashMap::new())),
+            limits: Arc::new(StdMutex::new(Limits {
+                paused_until: None,
+                next_backoff: DEFAULT_INITIAL_BACKOFF,
+                used_weight: None,
+            })),
+        }
+    }
+
+    pub fn with_min_interval(mut self, min_interval: Duration) -> Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    /// Also serves a body fetched up to `ttl` before it was asked for. A resync needs a
+    /// snapshot newer than the gap that caused it, so keep this short.
+    pub fn with_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    pub fn with_weight_limit(mut self, weight_limit: u32) -> Self {
+        self.weight_limit = weight_limit;
+        self
+    }
+
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self.limits.lock().unwrap().next_backoff = initial;
+        self
+    }
+
+    /// Request weight the exchange last reported as used this minute.
+    pub fn used_weight(&self) -> Option<u32> {
+        self.limits.lock().unwrap().used_weight
+    }
+
+    /// Body of a snapshot of `uri` fetched no earlier than this call, or within the cache TTL.
+    pub async fn fetch(&self, uri: &str) -> Result<String, SnapshotFetchError> {
+        let requested_at = Instant::now();
+        let entry = self.uris.lock().unwrap().entry(uri.to_string()).or_default().clone();
+        // One fetch per URI at a time; whoever queues behind it gets its body
+        let mut state = entry.lock().await;
+        if let Some((completed_at, body)) = &state.last {
+            if *completed_at + self.cache_ttl >= requested_at {
+                metrics::increment_counter!("snapshot_fetches_shared");
+                return Ok(body.clone());
+            }
+        }
+
+        let paused_until = self.limits.lock().unwrap().paused_until;
+        let ready_at = state.last_started.map(|started| started + self.min_interval).max(paused_until);
+        if let Some(ready_at) = ready_at.filter(|ready_at| *ready_at > Instant::now()) {
+            metrics::increment_counter!("snapshot_fetches_throttled");
+            debug!("Holding snapshot fetch of {} for {:?}", uri, ready_at - Instant::now());
+            sleep_until(ready_at).await;
+        }
+
+        state.last_started = Some(Instant::now());
+        metrics::increment_counter!("snapshot_fetches");
+        let response = self.http.get(uri).await?;
+        self.check(&response)?;
+        state.last = Some((Instant::now(), response.body.clone()));
+        Ok(response.body)
+    }
+
+    /// Accounts for the weight a response reports and turns rate limits into backoff.
+    fn check(&self, response: &SnapshotResponse) -> Result<(), SnapshotFetchError> {
+        let mut limits = self.limits.lock().unwrap();
+        if let Some(used_weight) = response.used_weight {
+            metrics::gauge!("snapshot_used_weight", used_weight as f64);
+            limits.used_weight = Some(used_weight);
+            if used_weight >= self.weight_limit {
+                let rest_of_minute = Duration::from_millis((WEIGHT_WINDOW_MS - now_ms() % WEIGHT_WINDOW_MS) as u64);
+                warn!(
+                    "Used {} of {} request weight; pausing snapshot fetches for {:?}",
+                    used_weight, self.weight_limit, rest_of_minute
+                );
+                limits.paused_until = Some(Instant::now() + rest_of_minute);
+            }
+        }
+
+        match response.status {
+            429 | 418 => {
+                let backoff = response.retry_after.unwrap_or_default().max(limits.next_backoff);
+                limits.next_backoff = (limits.next_backoff * 2).min(self.max_backoff);
+                limits.paused_until = Some(Instant::now() + backoff);
+                metrics::increment_counter!("snapshot_rate_limited");
+                warn!("Snapshot fetches rate limited with HTTP {}; backing off for {:?}", response.status, backoff);
+                Err(SnapshotFetchError::RateLimited { status: response.status, backoff })
+            }
+            200..=299 => {
+                limits.next_backoff = self.initial_backoff;
+                Ok(())
+            }
+            status => Err(SnapshotFetchError::Status(status)),
+        }
+    }
+}
+
+impl Default for SnapshotFetcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn now_ms() -> u128 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    /// Answers with scripted responses, after `delay`, and records when each request arrived.
+    struct ScriptedHttp {
+        responses: StdMutex<VecDeque<SnapshotResponse>>,
+        requests: StdMutex<Vec<Instant>>,
+        delay: Duration,
+    }
+
+    impl ScriptedHttp {
+        fn new(statuses: &[u16], delay: Duration) -> Arc<Self> {
+            let responses = statuses
+                .iter()
+                .enumerate()
+                .map(|(i, &status)| SnapshotResponse {
+                    status,
+                    used_weight: Some(10 * (i as u32 + 1)),
+                    retry_after: None,
+                    body: format!("snapshot {}", i),
+                })
+                .collect();
+            Arc::new(Self {
+                responses: StdMutex::new(responses),
+                requests: StdMutex::new(Vec::new()),
+                delay,
+            })
+        }
+
+        fn requests(&self) -> Vec<Instant> {
+            self.requests.lock().unwrap().clone()
+        }
+    }
+
+    impl SnapshotHttp for ScriptedHttp {
+        fn get<'a>(&'a self, _uri: &'a str) -> BoxFuture<'a, anyhow::Result<SnapshotResponse>> {
+            Box::pin(async move {
+                self.requests.lock().unwrap().push(Instant::now());
+                tokio::time::sleep(self.delay).await;
+                let response = self.responses.lock().unwrap().pop_front();
+                response.ok_or_else(|| anyhow::anyhow!("no more scripted responses"))
+            })
+        }
+    }
+
+    const URI: &str = "https://api.binance.com/api/v3/depth?symbol=BTCUSDT&limit=1000";
+
+    #[tokio::test]
+    async fn test_fetches_of_one_uri_are_spaced_out() {
+        let http = ScriptedHttp::new(&[200, 200, 200], Duration::ZERO);
+        let fetcher = SnapshotFetcher::with_http(http.clone()).with_min_interval(Duration::from_millis(100));
+
+        assert_eq!(fetcher.fetch(URI).await.unwrap(), "snapshot 0");
+        assert_eq!(fetcher.fetch(URI).await.unwrap(), "snapshot 1");
+        // Another URI has its own interval
+        assert_eq!(fetcher.fetch("https://api.binance.com/api/v3/depth?symbol=ETHUSDT").await.unwrap(), "snapshot 2");
+
+        let requests = http.requests();
+        assert!(requests[1] - requests[0] >= Duration::from_millis(100));
+        assert!(requests[2] - requests[1] < Duration::from_millis(100));
+        assert_eq!(fetcher.used_weight(), Some(30));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_fetches_share_one_request() {
+        let http = ScriptedHttp::new(&[200, 200], Duration::from_millis(50));
+        let fetcher = SnapshotFetcher::with_http(http.clone()).with_min_interval(Duration::ZERO);
+
+        let (first, second) = tokio::join!(fetcher.fetch(URI), fetcher.fetch(URI));
+        assert_eq!(first.unwrap(), "snapshot 0");
+        assert_eq!(second.unwrap(), "snapshot 0");
+        assert_eq!(http.requests().len(), 1);
+
+        // Asked for after it landed, so the shared body is too old
+        assert_eq!(fetcher.fetch(URI).await.unwrap(), "snapshot 1");
+    }
+
+    #[tokio::test]
+    async fn test_rate_limits_back_off_exponentially() {
+        let http = ScriptedHttp::new(&[429, 418, 200, 200], Duration::ZERO);
+        let fetcher = SnapshotFetcher::with_http(http.clone())
+            .with_min_interval(Duration::ZERO)
+            .with_backoff(Duration::from_millis(50), Duration::from_secs(1));
+
+        match fetcher.fetch(URI).await {
+            Err(SnapshotFetchError::RateLimited { status: 429, backoff }) => {
+                assert_eq!(backoff, Duration::from_millis(50))
+            }
+            other => panic!("Expected a rate limit, got {:?}", other),
+        }
+        match fetcher.fetch(URI).await {
+            Err(SnapshotFetchError::RateLimited { status: 418, backoff }) => {
+                assert_eq!(backoff, Duration::from_millis(100))
+            }
+            other => panic!("Expected a rate limit, got {:?}", other),
+        }
+        assert_eq!(fetcher.fetch(URI).await.unwrap(), "snapshot 2");
+
+        let requests = http.requests();
+        assert!(requests[1] - requests[0] >= Duration::from_millis(50));
+        assert!(requests[2] - requests[1] >= Duration::from_millis(100));
+
+        // Success resets the backoff, and nothing is paused any more
+        fetcher.fetch(URI).await.unwrap();
+        assert!(http.requests()[3] - requests[2] < Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn test_reaching_the_weight_limit_pauses_fetches() {
+        let http = ScriptedHttp::new(&[200], Duration::ZERO);
+        let fetcher = SnapshotFetcher::with_http(http.clone()).with_weight_limit(10);
+
+        fetcher.fetch(URI).await.unwrap();
+        assert_eq!(fetcher.used_weight(), Some(10));
+        // Until the exchange's minute rolls over
+        let paused_until = fetcher.limits.lock().unwrap().paused_until.expect("not paused");
+        assert!(paused_until <= Instant::now() + Duration::from_secs(60));
+    }
+}
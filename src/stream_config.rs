@@ -0,0 +1,411 @@
+use crate::exchange::binance::BINANCE_REST_BASE;
+use crate::proxy::ProxyConfig;
+use crate::tls::TlsConfig;
+use serde::Serialize;
+use tokio_tungstenite::tungstenite::http::HeaderMap;
+
+pub const BINANCE_WS_BASE: &str = "wss://stream.binance.com:9443";
+pub const COINBASE_WS_BASE: &str = "wss://ws-feed.exchange.coinbase.com";
+pub const OKX_WS_BASE: &str = "wss://ws.okx.com:8443/ws/v5/public";
+pub const BYBIT_WS_BASE: &str = "wss://stream.bybit.com/v5/public/spot";
+pub const KRAKEN_WS_BASE: &str = "wss://ws.kraken.com";
+pub const BITSTAMP_WS_BASE: &str = "wss://ws.bitstamp.net";
+/// Other hosts serving Binance's public streams, tried in turn when the primary misbehaves.
+pub const BINANCE_WS_FALLBACKS: [&str; 2] = ["wss://stream.binance.com:443", "wss://data-stream.binance.vision"];
+pub const BINANCE_TESTNET_WS_BASE: &str = "wss://stream.testnet.binance.vision";
+pub const BINANCE_TESTNET_REST_BASE: &str = "https://testnet.binance.vision";
+/// Binance's market-data-only hosts, serving public streams and REST without the trading API.
+pub const BINANCE_VISION_WS_BASE: &str = "wss://data-stream.binance.vision";
+pub const BINANCE_VISION_REST_BASE: &str = "https://data-api.binance.vision";
+
+/// Venue the feeds connect to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize)]
+pub enum Exchange {
+    #[default]
+    Binance,
+    Coinbase,
+    Okx,
+    Bybit,
+    Kraken,
+    Bitstamp,
+}
+
+impl Exchange {
+    pub fn default_ws_base(&self) -> &'static str {
+        match self {
+            Exchange::Binance => BINANCE_WS_BASE,
+            Exchange::Coinbase => COINBASE_WS_BASE,
+            Exchange::Okx => OKX_WS_BASE,
+            Exchange::Bybit => BYBIT_WS_BASE,
+            Exchange::Kraken => KRAKEN_WS_BASE,
+            Exchange::Bitstamp => BITSTAMP_WS_BASE,
+        }
+    }
+
+    /// Hosts to rotate to when the default one fails, in order.
+    pub fn default_ws_fallbacks(&self) -> Vec<String> {
+        match self {
+            Exchange::Binance => BINANCE_WS_FALLBACKS.iter().map(|base| base.to_string()).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Which Binance deployment the feeds and REST snapshots talk to.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Environment {
+    #[default]
+    Prod,
+    Testnet,
+    /// The market data hosts under `binance.vision`.
+    Vision,
+    /// A gateway serving both streams and REST at this base URL. `http(s)://` and
+    /// `ws(s)://` are interchangeable; each side gets the scheme it needs.
+    Custom(String),
+}
+
+impl Environment {
+    /// Reads `prod`, `testnet`, `vision`, or a base URL for a custom gateway.
+    pub fn from_arg(arg: &str) -> Option<Self> {
+        match arg {
+            "prod" => Some(Environment::Prod),
+            "testnet" => Some(Environment::Testnet),
+            "vision" => Some(Environment::Vision),
+            url if url.contains("://") => Some(Environment::Custom(url.trim_end_matches('/').to_string())),
+            _ => None,
+        }
+    }
+
+    pub fn ws_base(&self) -> String {
+        match self {
+            Environment::Prod => BINANCE_WS_BASE.to_string(),
+            Environment::Testnet => BINANCE_TESTNET_WS_BASE.to_string(),
+            Environment::Vision => BINANCE_VISION_WS_BASE.to_string(),
+            Environment::Custom(url) => with_scheme(url, "ws", "wss"),
+        }
+    }
+
+    pub fn rest_base(&self) -> String {
+        match self {
+            Environment::Prod => BINANCE_REST_BASE.to_string(),
+            Environment::Testnet => BINANCE_TESTNET_REST_BASE.to_string(),
+            Environment::Vision => BINANCE_VISION_REST_BASE.to_string(),
+            Environment::Custom(url) => with_scheme(url, "http", "https"),
+        }
+    }
+
+    /// Other hosts to rotate to; only production has any.
+    fn ws_fallbacks(&self) -> Vec<String> {
+        match self {
+            Environment::Prod => Exchange::Binance.default_ws_fallbacks(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// `url` with its scheme swapped for `plain` or `secure`, keeping it encrypted or not.
+fn with_scheme(url: &str, plain: &str, secure: &str) -> String {
+    match url.split_once("://") {
+        Some((scheme, rest)) if scheme == "https" || scheme == "wss" => format!("{}://{}", secure, rest),
+        Some((_, rest)) => format!("{}://{}", plain, rest),
+        None => url.to_string(),
+    }
+}
+
+/// Depth stream flavour; diff streams need snapshot sync, partial streams are full top-N books.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthSpeed {
+    Diff100ms,
+    Diff1000ms,
+    Partial5,
+    Partial10,
+    Partial20,
+}
+
+impl DepthSpeed {
+    fn stream_suffix(&self) -> &'static str {
+        match self {
+            DepthSpeed::Diff100ms => "depth@100ms",
+            DepthSpeed::Diff1000ms => "depth",
+            DepthSpeed::Partial5 => "depth5@100ms",
+            DepthSpeed::Partial10 => "depth10@100ms",
+            DepthSpeed::Partial20 => "depth20@100ms",
+        }
+    }
+
+    /// Diff streams only make sense on top of a REST snapshot.
+    pub fn is_diff(&self) -> bool {
+        matches!(self, DepthSpeed::Diff100ms | DepthSpeed::Diff1000ms)
+    }
+}
+
+/// How a depth feed keeps its book current; either way it takes one socket per symbol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthMode {
+    /// Diff stream sequenced against a REST snapshot.
+    DiffWithRestSnapshot,
+    /// Partial-depth stream of the top `levels`, each message replacing the book.
+    PartialBook(usize),
+}
+
+impl DepthMode {
+    /// Partial books round up to the nearest depth Binance serves (5, 10 or 20 levels).
+    pub fn depth_speed(&self) -> DepthSpeed {
+        match *self {
+            DepthMode::DiffWithRestSnapshot => DepthSpeed::Diff100ms,
+            DepthMode::PartialBook(levels) if levels <= 5 => DepthSpeed::Partial5,
+            DepthMode::PartialBook(levels) if levels <= 10 => DepthSpeed::Partial10,
+            DepthMode::PartialBook(_) => DepthSpeed::Partial20,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeStreamKind {
+    Trade,
+    AggTrade,
+}
+
+impl TradeStreamKind {
+    fn stream_suffix(&self) -> &'static str {
+        match self {
+            TradeStreamKind::Trade => "trade",
+            TradeStreamKind::AggTrade => "aggTrade",
+        }
+    }
+}
+
+/// Describes which market data streams to subscribe to for one symbol.
+#[derive(Debug, Clone)]
+pub struct StreamConfig {
+    pub exchange: Exchange,
+    pub symbol: String,
+    pub depth_speed: DepthSpeed,
+    pub trade_stream: TradeStreamKind,
+    pub base_url: String,
+    /// Binance deployment `base_url` and the REST snapshots point at.
+    pub environment: Environment,
+    /// Hosts serving the same streams as `base_url`, rotated through on reconnect.
+    pub fallback_base_urls: Vec<String>,
+    /// Proxy both feeds tunnel their connections through, if any.
+    pub proxy: Option<ProxyConfig>,
+    /// TLS settings both feeds' `wss://` connections use instead of the platform defaults.
+    pub tls: Option<TlsConfig>,
+    /// Extra headers both feeds send with every WebSocket handshake.
+    pub headers: HeaderMap,
+}
+
+impl StreamConfig {
+    pub fn new(symbol: &str) -> Self {
+        Self {
+            exchange: Exchange::Binance,
+            symbol: symbol.to_string(),
+            depth_speed: DepthSpeed::Diff100ms,
+            trade_stream: TradeStreamKind::Trade,
+            base_url: BINANCE_WS_BASE.to_string(),
+            environment: Environment::Prod,
+            fallback_base_urls: Exchange::Binance.default_ws_fallbacks(),
+            proxy: None,
+            tls: None,
+            headers: HeaderMap::new(),
+        }
+    }
+
+    /// Switches venue, pointing `base_url` and its fallbacks at that exchange's public feed.
+    pub fn with_exchange(mut self, exchange: Exchange) -> Self {
+        self.exchange = exchange;
+        self.environment = Environment::Prod;
+        self.base_url = exchange.default_ws_base().to_string();
+        self.fallback_base_urls = exchange.default_ws_fallbacks();
+        self
+    }
+
+    /// Points `base_url`, its fallbacks and the REST snapshots at a Binance deployment.
+    pub fn with_environment(mut self, environment: Environment) -> Self {
+        self.base_url = environment.ws_base();
+        self.fallback_base_urls = environment.ws_fallbacks();
+        self.environment = environment;
+        self
+    }
+
+    /// Host REST depth snapshots are fetched from.
+    pub fn rest_base_url(&self) -> String {
+        self.environment.rest_base()
+    }
+
+    pub fn with_depth_mode(mut self, mode: DepthMode) -> Self {
+        self.depth_speed = mode.depth_speed();
+        self
+    }
+
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    pub fn with_tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    pub fn build_depth_url(&self) -> String {
+        self.build_url(self.depth_speed.stream_suffix())
+    }
+
+    pub fn build_trade_url(&self) -> String {
+        self.build_url(self.trade_stream.stream_suffix())
+    }
+
+    pub fn build_book_ticker_url(&self) -> String {
+        self.build_url("bookTicker")
+    }
+
+    /// `url` followed by the same path on each fallback host; just `url` if it isn't on `base_url`.
+    pub fn endpoints_for(&self, url: &str) -> Vec<String> {
+        let path = match url.strip_prefix(self.base_url.trim_end_matches('/')) {
+            Some(path) => path,
+            None => return vec![url.to_string()],
+        };
+        std::iter::once(url.to_string())
+            .chain(
+                self.fallback_base_urls
+                    .iter()
+                    .map(|base| format!("{}{}", base.trim_end_matches('/'), path)),
+            )
+            .collect()
+    }
+
+    fn build_url(&self, stream: &str) -> String {
+        format!(
+            "{}/ws/{}@{}",
+            self.base_url.trim_end_matches('/'),
+            self.symbol.to_lowercase(),
+            stream
+        )
+    }
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self::new("btcusdt")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_urls() {
+        let config = StreamConfig::default();
+        assert_eq!(config.build_depth_url(), "wss://stream.binance.com:9443/ws/btcusdt@depth@100ms");
+        assert_eq!(config.build_trade_url(), "wss://stream.binance.com:9443/ws/btcusdt@trade");
+        assert_eq!(config.build_book_ticker_url(), "wss://stream.binance.com:9443/ws/btcusdt@bookTicker");
+    }
+
+    #[test]
+    fn test_depth_variants() {
+        let mut config = StreamConfig::new("ETHUSDT");
+
+        config.depth_speed = DepthSpeed::Diff1000ms;
+        assert_eq!(config.build_depth_url(), "wss://stream.binance.com:9443/ws/ethusdt@depth");
+
+        config.depth_speed = DepthSpeed::Partial5;
+        assert_eq!(config.build_depth_url(), "wss://stream.binance.com:9443/ws/ethusdt@depth5@100ms");
+
+        config.depth_speed = DepthSpeed::Partial20;
+        assert_eq!(config.build_depth_url(), "wss://stream.binance.com:9443/ws/ethusdt@depth20@100ms");
+    }
+
+    #[test]
+    fn test_testnet_base_url() {
+        let config = StreamConfig {
+            exchange: Exchange::Binance,
+            symbol: "bnbusdt".to_string(),
+            depth_speed: DepthSpeed::Partial10,
+            trade_stream: TradeStreamKind::AggTrade,
+            base_url: "wss://stream.testnet.binance.vision/".to_string(),
+            environment: Environment::Testnet,
+            fallback_base_urls: Vec::new(),
+            proxy: None,
+            tls: None,
+            headers: HeaderMap::new(),
+        };
+
+        assert_eq!(config.build_depth_url(), "wss://stream.testnet.binance.vision/ws/bnbusdt@depth10@100ms");
+        assert_eq!(config.build_trade_url(), "wss://stream.testnet.binance.vision/ws/bnbusdt@aggTrade");
+    }
+
+    #[test]
+    fn test_environments_switch_stream_and_rest_hosts() {
+        let cases = [
+            (Environment::Prod, "wss://stream.binance.com:9443", "https://api.binance.com"),
+            (Environment::Testnet, "wss://stream.testnet.binance.vision", "https://testnet.binance.vision"),
+            (Environment::Vision, "wss://data-stream.binance.vision", "https://data-api.binance.vision"),
+            (Environment::Custom("http://127.0.0.1:8080".to_string()), "ws://127.0.0.1:8080", "http://127.0.0.1:8080"),
+            (Environment::Custom("wss://gateway.internal".to_string()), "wss://gateway.internal", "https://gateway.internal"),
+        ];
+        for (environment, ws_base, rest_base) in cases {
+            let config = StreamConfig::new("btcusdt").with_environment(environment.clone());
+            assert_eq!(config.build_trade_url(), format!("{}/ws/btcusdt@trade", ws_base), "{:?}", environment);
+            assert_eq!(config.rest_base_url(), rest_base, "{:?}", environment);
+            // Only production has hosts to fall back to
+            assert_eq!(config.fallback_base_urls.is_empty(), environment != Environment::Prod);
+        }
+
+        assert_eq!(Environment::from_arg("testnet"), Some(Environment::Testnet));
+        assert_eq!(
+            Environment::from_arg("http://127.0.0.1:8080/"),
+            Some(Environment::Custom("http://127.0.0.1:8080".to_string()))
+        );
+        assert_eq!(Environment::from_arg("staging"), None);
+    }
+
+    #[test]
+    fn test_depth_modes() {
+        let config = StreamConfig::new("btcusdt").with_depth_mode(DepthMode::DiffWithRestSnapshot);
+        assert_eq!(config.build_depth_url(), "wss://stream.binance.com:9443/ws/btcusdt@depth@100ms");
+        assert!(config.depth_speed.is_diff());
+
+        let speeds: Vec<_> = [1, 5, 7, 10, 20, 50]
+            .into_iter()
+            .map(|levels| DepthMode::PartialBook(levels).depth_speed())
+            .collect();
+        use DepthSpeed::*;
+        assert_eq!(speeds, vec![Partial5, Partial5, Partial10, Partial10, Partial20, Partial20]);
+        assert!(!Partial10.is_diff());
+    }
+
+    #[test]
+    fn test_with_exchange_switches_base_url() {
+        let config = StreamConfig::new("BTC-USD").with_exchange(Exchange::Coinbase);
+        assert_eq!(config.exchange, Exchange::Coinbase);
+        assert_eq!(config.base_url, COINBASE_WS_BASE);
+    }
+
+    #[test]
+    fn test_endpoints_rotate_over_fallback_hosts() {
+        let config = StreamConfig::new("btcusdt");
+        assert_eq!(
+            config.endpoints_for(&config.build_trade_url()),
+            vec![
+                "wss://stream.binance.com:9443/ws/btcusdt@trade".to_string(),
+                "wss://stream.binance.com:443/ws/btcusdt@trade".to_string(),
+                "wss://data-stream.binance.vision/ws/btcusdt@trade".to_string(),
+            ]
+        );
+
+        // Venues without fallbacks, and URLs off the base host, are left alone
+        let config = StreamConfig::new("BTC-USD").with_exchange(Exchange::Coinbase);
+        assert_eq!(config.endpoints_for(COINBASE_WS_BASE), vec![COINBASE_WS_BASE.to_string()]);
+        assert_eq!(
+            StreamConfig::new("btcusdt").endpoints_for("ws://127.0.0.1:9000/ws"),
+            vec!["ws://127.0.0.1:9000/ws".to_string()]
+        );
+    }
+}
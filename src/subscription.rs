@@ -0,0 +1,339 @@
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::{mpsc, oneshot};
+use tokio::time::timeout;
+
+const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error, PartialEq)]
+pub enum SubscriptionError {
+    #[error("Request {id} rejected by exchange ({code}): {msg}")]
+    Rejected { id: u64, code: i64, msg: String },
+    #[error("Connection closed before the request was acknowledged")]
+    ConnectionClosed,
+    #[error("No acknowledgement within {0:?}")]
+    Timeout(Duration),
+}
+
+/// Error the exchange reported in a control frame, for a request of ours or for the
+/// connection as a whole.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExchangeError {
+    /// Request the error answers, when the exchange could tell which.
+    pub id: Option<u64>,
+    pub code: i64,
+    pub msg: String,
+}
+
+impl fmt::Display for ExchangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.id {
+            Some(id) => write!(f, "Exchange error for request {} ({}): {}", id, self.code, self.msg),
+            None => write!(f, "Exchange error ({}): {}", self.code, self.msg),
+        }
+    }
+}
+
+impl std::error::Error for ExchangeError {}
+
+/// A frame answering a request rather than carrying market data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlFrame {
+    /// Resolved the pending request `id`.
+    Ack { id: u64 },
+    /// Acknowledged a request this connection isn't waiting on, e.g. one that already
+    /// timed out.
+    UnknownAck { id: u64 },
+    /// The exchange rejected a request; a pending one with the same id fails with it.
+    Error(ExchangeError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionMethod {
+    Subscribe,
+    Unsubscribe,
+}
+
+impl SubscriptionMethod {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionMethod::Subscribe => "SUBSCRIBE",
+            SubscriptionMethod::Unsubscribe => "UNSUBSCRIBE",
+        }
+    }
+}
+
+/// A SUBSCRIBE/UNSUBSCRIBE waiting to be written to the live connection.
+#[derive(Debug)]
+pub struct SubscriptionRequest {
+    pub id: u64,
+    pub method: SubscriptionMethod,
+    pub streams: Vec<String>,
+    reply: oneshot::Sender<Result<(), SubscriptionError>>,
+}
+
+impl SubscriptionRequest {
+    /// Binance's `{"method":...,"params":[...],"id":N}` frame.
+    pub fn frame(&self) -> String {
+        json!({
+            "method": self.method.as_str(),
+            "params": self.streams,
+            "id": self.id,
+        })
+        .to_string()
+    }
+}
+
+/// Cloneable handle for changing a running feed's streams.
+#[derive(Debug, Clone)]
+pub struct SubscriptionHandle {
+    tx: mpsc::UnboundedSender<SubscriptionRequest>,
+    next_id: Arc<AtomicU64>,
+    ack_timeout: Duration,
+}
+
+impl SubscriptionHandle {
+    /// Creates a handle and the receiver the connection loop drains.
+    pub fn channel() -> (Self, mpsc::UnboundedReceiver<SubscriptionRequest>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let handle = Self {
+            tx,
+            next_id: Arc::new(AtomicU64::new(1)),
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+        };
+        (handle, rx)
+    }
+
+    pub fn with_ack_timeout(mut self, ack_timeout: Duration) -> Self {
+        self.ack_timeout = ack_timeout;
+        self
+    }
+
+    pub async fn subscribe(&self, streams: Vec<String>) -> Result<(), SubscriptionError> {
+        self.request(SubscriptionMethod::Subscribe, streams).await
+    }
+
+    pub async fn unsubscribe(&self, streams: Vec<String>) -> Result<(), SubscriptionError> {
+        self.request(SubscriptionMethod::Unsubscribe, streams).await
+    }
+
+    async fn request(&self, method: SubscriptionMethod, streams: Vec<String>) -> Result<(), SubscriptionError> {
+        let (reply, ack) = oneshot::channel();
+        let request = SubscriptionRequest {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst),
+            method,
+            streams,
+            reply,
+        };
+        self.tx
+            .send(request)
+            .map_err(|_| SubscriptionError::ConnectionClosed)?;
+
+        match timeout(self.ack_timeout, ack).await {
+            Ok(Ok(result)) => result,
+            Ok(Err(_)) => Err(SubscriptionError::ConnectionClosed),
+            Err(_) => Err(SubscriptionError::Timeout(self.ack_timeout)),
+        }
+    }
+}
+
+/// `{"result":null,"id":1}`, `{"error":{"code":2,"msg":"..."},"id":1}`, or the older
+/// `{"code":2,"msg":"...","id":1}`; the id is null or missing when the request was unreadable.
+#[derive(Debug, Deserialize)]
+struct ControlMessage {
+    #[serde(default)]
+    id: Option<u64>,
+    #[serde(default)]
+    error: Option<AckError>,
+    #[serde(default)]
+    code: Option<i64>,
+    #[serde(default)]
+    msg: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AckError {
+    code: i64,
+    msg: String,
+}
+
+/// Requests written on the current connection that are still waiting for their ack.
+///
+/// Dropping it (on disconnect) fails every outstanding request with `ConnectionClosed`.
+#[derive(Debug, Default)]
+pub struct PendingAcks {
+    pending: HashMap<u64, oneshot::Sender<Result<(), SubscriptionError>>>,
+}
+
+impl PendingAcks {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts tracking a request and returns the frame to send for it.
+    pub fn register(&mut self, request: SubscriptionRequest) -> String {
+        let frame = request.frame();
+        self.pending.insert(request.id, request.reply);
+        frame
+    }
+
+    /// Fails a registered request whose frame could not be written.
+    pub fn fail(&mut self, id: u64, error: SubscriptionError) {
+        if let Some(reply) = self.pending.remove(&id) {
+            let _ = reply.send(Err(error));
+        }
+    }
+
+    /// Recognizes acks and error frames, resolving the matching request if one is pending.
+    ///
+    /// Returns `None` for anything else so the caller can treat it as market data.
+    pub fn on_text(&mut self, text: &str) -> Option<ControlFrame> {
+        // Cheap pre-check; market data payloads never carry these top-level keys
+        if !["\"id\"", "\"error\"", "\"code\""].iter().any(|key| text.contains(key)) {
+            return None;
+        }
+        let frame: ControlMessage = serde_json::from_str(text).ok()?;
+        let error = match (frame.error, frame.code, frame.msg) {
+            (Some(error), _, _) => Some(ExchangeError { id: frame.id, code: error.code, msg: error.msg }),
+            (None, Some(code), Some(msg)) => Some(ExchangeError { id: frame.id, code, msg }),
+            _ => None,
+        };
+
+        match (frame.id, error) {
+            (_, Some(error)) => {
+                if let Some((id, reply)) = error.id.and_then(|id| self.pending.remove_entry(&id)) {
+                    let _ = reply.send(Err(SubscriptionError::Rejected {
+                        id,
+                        code: error.code,
+                        msg: error.msg.clone(),
+                    }));
+                }
+                Some(ControlFrame::Error(error))
+            }
+            (Some(id), None) => match self.pending.remove(&id) {
+                Some(reply) => {
+                    let _ = reply.send(Ok(()));
+                    Some(ControlFrame::Ack { id })
+                }
+                None => Some(ControlFrame::UnknownAck { id }),
+            },
+            (None, None) => None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+/// Next queued request, or never if this connection does not take requests.
+pub async fn next_request(
+    requests: Option<&mut mpsc::UnboundedReceiver<SubscriptionRequest>>,
+) -> SubscriptionRequest {
+    match requests {
+        Some(requests) => match requests.recv().await {
+            Some(request) => request,
+            None => std::future::pending().await,
+        },
+        None => std::future::pending().await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frame_format() {
+        let (handle, mut requests) = SubscriptionHandle::channel();
+        let caller = tokio::spawn(async move {
+            handle.subscribe(vec!["btcusdt@trade".to_string(), "ethusdt@trade".to_string()]).await
+        });
+
+        let request = requests.recv().await.unwrap();
+        let frame: serde_json::Value = serde_json::from_str(&request.frame()).unwrap();
+        assert_eq!(
+            frame,
+            json!({"method": "SUBSCRIBE", "params": ["btcusdt@trade", "ethusdt@trade"], "id": 1})
+        );
+
+        let mut pending = PendingAcks::new();
+        pending.register(request);
+        assert_eq!(pending.on_text(r#"{"result":null,"id":1}"#), Some(ControlFrame::Ack { id: 1 }));
+        assert_eq!(caller.await.unwrap(), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_acks_correlate_by_id() {
+        let (handle, mut requests) = SubscriptionHandle::channel();
+        let first = tokio::spawn({
+            let handle = handle.clone();
+            async move { handle.subscribe(vec!["btcusdt@depth".to_string()]).await }
+        });
+        let mut pending = PendingAcks::new();
+        pending.register(requests.recv().await.unwrap());
+
+        let second = tokio::spawn(async move { handle.unsubscribe(vec!["ethusdt@depth".to_string()]).await });
+        let request = requests.recv().await.unwrap();
+        assert_eq!(request.method, SubscriptionMethod::Unsubscribe);
+        pending.register(request);
+
+        // Acks arrive out of order; market data in between is left alone
+        assert!(matches!(
+            pending.on_text(r#"{"error":{"code":2,"msg":"Invalid request"},"id":2}"#),
+            Some(ControlFrame::Error(_))
+        ));
+        assert_eq!(pending.on_text(r#"{"e":"trade","E":1,"s":"BTCUSDT","t":12345}"#), None);
+        assert_eq!(pending.on_text(r#"{"result":null,"id":1}"#), Some(ControlFrame::Ack { id: 1 }));
+        assert!(pending.is_empty());
+
+        assert_eq!(first.await.unwrap(), Ok(()));
+        assert_eq!(
+            second.await.unwrap(),
+            Err(SubscriptionError::Rejected { id: 2, code: 2, msg: "Invalid request".to_string() })
+        );
+    }
+
+    #[test]
+    fn test_control_frames_without_a_pending_request() {
+        let mut pending = PendingAcks::new();
+
+        assert_eq!(pending.on_text(r#"{"result":null,"id":7}"#), Some(ControlFrame::UnknownAck { id: 7 }));
+        assert_eq!(
+            pending.on_text(r#"{"error":{"code":2,"msg":"Invalid request: unknown variant"},"id":null}"#),
+            Some(ControlFrame::Error(ExchangeError {
+                id: None,
+                code: 2,
+                msg: "Invalid request: unknown variant".to_string(),
+            }))
+        );
+        let error = match pending.on_text(r#"{"code":3,"msg":"Invalid JSON: expected value at line 1 column 1"}"#) {
+            Some(ControlFrame::Error(error)) => error,
+            other => panic!("Expected an error frame, got {:?}", other),
+        };
+        assert_eq!(error.to_string(), "Exchange error (3): Invalid JSON: expected value at line 1 column 1");
+        assert_eq!(pending.on_text(r#"{"e":"depthUpdate","E":1,"s":"BTCUSDT","U":1,"u":2,"b":[],"a":[]}"#), None);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_fails_outstanding_requests() {
+        let (handle, mut requests) = SubscriptionHandle::channel();
+        let caller = tokio::spawn(async move { handle.subscribe(vec!["btcusdt@trade".to_string()]).await });
+
+        let mut pending = PendingAcks::new();
+        pending.register(requests.recv().await.unwrap());
+        drop(pending);
+
+        assert_eq!(caller.await.unwrap(), Err(SubscriptionError::ConnectionClosed));
+    }
+}
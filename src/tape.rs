@@ -0,0 +1,207 @@
+use crate::clock::{Clock, SystemClock};
+use chrono::{TimeZone, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::task::{self, JoinHandle};
+
+/// Frames buffered for the writer before new ones are dropped.
+const DEFAULT_TAPE_CAPACITY: usize = 10_000;
+
+/// `stream` of frames recorded by the depth feed.
+pub const DEPTH_STREAM: &str = "depth";
+/// `stream` of depth snapshots the depth feed fetched over REST.
+pub const DEPTH_SNAPSHOT_STREAM: &str = "depth_snapshot";
+/// `stream` of frames recorded by the trade feed.
+pub const TRADE_STREAM: &str = "trades";
+
+/// One raw frame as received, one JSON object per line on tape.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TapeEntry {
+    pub recv_ts_ms: u64,
+    pub stream: String,
+    pub payload: String,
+}
+
+/// Records raw frames to hourly newline-delimited JSON files under one directory.
+///
+/// Recording never blocks: frames go through a bounded channel to a writer on a blocking
+/// task, and are dropped (and counted) when the writer falls behind. Clones share the writer.
+#[derive(Debug, Clone)]
+pub struct TapeRecorder {
+    tx: mpsc::Sender<TapeEntry>,
+    stream: String,
+    clock: Arc<dyn Clock>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl TapeRecorder {
+    /// Starts a writer under `dir`. The handle completes once every recorder is dropped
+    /// and the last frames are on disk.
+    pub fn spawn(dir: impl Into<PathBuf>) -> (Self, JoinHandle<()>) {
+        Self::spawn_with_clock(dir, DEFAULT_TAPE_CAPACITY, Arc::new(SystemClock))
+    }
+
+    pub fn spawn_with_clock(dir: impl Into<PathBuf>, capacity: usize, clock: Arc<dyn Clock>) -> (Self, JoinHandle<()>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        let dir = dir.into();
+        let writer = task::spawn_blocking(move || write_tape(dir, rx));
+        (Self::new(tx, clock), writer)
+    }
+
+    fn new(tx: mpsc::Sender<TapeEntry>, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            tx,
+            stream: String::new(),
+            clock,
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Recorder writing to the same tape under another `stream` label.
+    pub fn for_stream(&self, stream: &str) -> Self {
+        Self {
+            stream: stream.to_string(),
+            ..self.clone()
+        }
+    }
+
+    pub fn record(&self, payload: &str) {
+        let entry = TapeEntry {
+            recv_ts_ms: self.clock.now_ms(),
+            stream: self.stream.clone(),
+            payload: payload.to_string(),
+        };
+        if self.tx.try_send(entry).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            metrics::increment_counter!("tape_frames_dropped");
+        }
+    }
+
+    /// Frames dropped so far across all clones.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// `tape_<YYYYMMDD>_<HH>.ndjson`, by the UTC hour the frame was received in.
+pub fn tape_file_name(recv_ts_ms: u64) -> String {
+    let hour = Utc
+        .timestamp_millis_opt(recv_ts_ms as i64)
+        .single()
+        .unwrap_or_else(Utc::now);
+    format!("tape_{}.ndjson", hour.format("%Y%m%d_%H"))
+}
+
+/// Reads back every entry of one tape file.
+pub fn read_tape(path: impl AsRef<Path>) -> io::Result<Vec<TapeEntry>> {
+    let reader = BufReader::new(File::open(path)?);
+    reader
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.is_empty()))
+        .map(|line| Ok(serde_json::from_str(&line?)?))
+        .collect()
+}
+
+fn write_tape(dir: PathBuf, mut rx: mpsc::Receiver<TapeEntry>) {
+    let mut current: Option<(String, BufWriter<File>)> = None;
+
+    while let Some(entry) = rx.blocking_recv() {
+        let mut next = Some(entry);
+        while let Some(entry) = next {
+            if let Err(e) = write_entry(&dir, &mut current, &entry) {
+                error!("Failed to write tape entry under {}: {}", dir.display(), e);
+            }
+            next = rx.try_recv().ok();
+        }
+        // Caught up: get the burst onto disk before waiting for more
+        if let Some((name, writer)) = current.as_mut() {
+            if let Err(e) = writer.flush() {
+                error!("Failed to flush tape {}: {}", name, e);
+            }
+        }
+    }
+}
+
+fn write_entry(dir: &Path, current: &mut Option<(String, BufWriter<File>)>, entry: &TapeEntry) -> io::Result<()> {
+    let name = tape_file_name(entry.recv_ts_ms);
+    if !matches!(current, Some((open, _)) if *open == name) {
+        if let Some((_, mut previous)) = current.take() {
+            previous.flush()?;
+        }
+        std::fs::create_dir_all(dir)?;
+        let file = OpenOptions::new().create(true).append(true).open(dir.join(&name))?;
+        *current = Some((name, BufWriter::new(file)));
+    }
+
+    let (_, writer) = current.as_mut().unwrap();
+    serde_json::to_writer(&mut *writer, entry)?;
+    writer.write_all(b"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    // 2024-04-10 10:59:59.900 UTC
+    const BEFORE_HOUR_MS: u64 = 1_712_746_799_900;
+
+    #[tokio::test]
+    async fn test_frames_round_trip_and_rotate_hourly() {
+        let dir = tempdir().unwrap();
+        let clock = ManualClock::new(BEFORE_HOUR_MS);
+        let (tape, writer) = TapeRecorder::spawn_with_clock(dir.path(), 16, Arc::new(clock.clone()));
+        let depth = tape.for_stream(DEPTH_STREAM);
+        let trades = tape.for_stream(TRADE_STREAM);
+
+        let awkward = "{\"e\":\"trade\",\"note\":\"line\\nbreak \u{2713}\"}";
+        depth.record(r#"{"lastUpdateId":1,"bids":[],"asks":[]}"#);
+        trades.record(awkward);
+        clock.advance(Duration::from_millis(200));
+        depth.record(r#"{"e":"depthUpdate","U":2,"u":2}"#);
+
+        drop((tape, depth, trades));
+        writer.await.unwrap();
+
+        let first = read_tape(dir.path().join("tape_20240410_10.ndjson")).unwrap();
+        assert_eq!(
+            first,
+            vec![
+                TapeEntry {
+                    recv_ts_ms: BEFORE_HOUR_MS,
+                    stream: "depth".to_string(),
+                    payload: r#"{"lastUpdateId":1,"bids":[],"asks":[]}"#.to_string(),
+                },
+                TapeEntry {
+                    recv_ts_ms: BEFORE_HOUR_MS,
+                    stream: "trades".to_string(),
+                    payload: awkward.to_string(),
+                },
+            ]
+        );
+
+        let second = read_tape(dir.path().join("tape_20240410_11.ndjson")).unwrap();
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].recv_ts_ms, BEFORE_HOUR_MS + 200);
+        assert_eq!(second[0].payload, r#"{"e":"depthUpdate","U":2,"u":2}"#);
+    }
+
+    #[test]
+    fn test_full_channel_drops_and_counts() {
+        // No writer drains this channel
+        let (tx, _rx) = mpsc::channel(2);
+        let tape = TapeRecorder::new(tx, Arc::new(ManualClock::new(0))).for_stream(DEPTH_STREAM);
+        for _ in 0..5 {
+            tape.record("{}");
+        }
+        assert_eq!(tape.dropped(), 3);
+    }
+}
@@ -0,0 +1,113 @@
+use native_tls::{Certificate, TlsConnector};
+use std::io;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tokio::net::TcpStream;
+use tokio_native_tls::TlsStream;
+
+const PEM_CERTIFICATE_END: &str = "-----END CERTIFICATE-----";
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("Cannot read CA bundle {path}: {source}")]
+    Roots { path: PathBuf, source: io::Error },
+    #[error("No certificate in CA bundle {0}")]
+    EmptyRoots(PathBuf),
+    #[error("TLS failed: {0}")]
+    Native(#[from] native_tls::Error),
+}
+
+/// TLS settings for `wss://` feed connections, on top of the platform's trust store.
+///
+/// Without one, connections use the platform defaults as-is.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsConfig {
+    /// PEM bundle of CA certificates trusted alongside the system roots.
+    pub custom_roots: Option<PathBuf>,
+    /// Skips certificate and hostname verification entirely. Only built for tests, and
+    /// with the `insecure-tls` feature.
+    #[cfg(any(test, feature = "insecure-tls"))]
+    pub accept_invalid_certs: bool,
+    /// Name sent as SNI and checked against the certificate instead of the URI's host.
+    pub sni_override: Option<String>,
+}
+
+impl TlsConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_custom_roots(mut self, path: impl Into<PathBuf>) -> Self {
+        self.custom_roots = Some(path.into());
+        self
+    }
+
+    /// For tests against self-signed servers.
+    #[cfg(any(test, feature = "insecure-tls"))]
+    pub fn with_accept_invalid_certs(mut self, accept: bool) -> Self {
+        self.accept_invalid_certs = accept;
+        self
+    }
+
+    pub fn with_sni_override(mut self, server_name: &str) -> Self {
+        self.sni_override = Some(server_name.to_string());
+        self
+    }
+
+    /// Builds a connector from these settings. The CA bundle is read on every call, so a
+    /// reconnect picks up a rotated bundle.
+    pub fn connector(&self) -> Result<TlsConnector, TlsError> {
+        let mut builder = TlsConnector::builder();
+        if let Some(path) = &self.custom_roots {
+            for root in read_roots(path)? {
+                builder.add_root_certificate(root);
+            }
+        }
+        #[cfg(any(test, feature = "insecure-tls"))]
+        builder.danger_accept_invalid_certs(self.accept_invalid_certs);
+        Ok(builder.build()?)
+    }
+
+    /// Runs the TLS handshake with `host` over `stream`.
+    pub async fn handshake(&self, host: &str, stream: TcpStream) -> Result<TlsStream<TcpStream>, TlsError> {
+        let connector = tokio_native_tls::TlsConnector::from(self.connector()?);
+        let server_name = self.sni_override.as_deref().unwrap_or(host);
+        Ok(connector.connect(server_name, stream).await?)
+    }
+}
+
+/// Every certificate in the PEM bundle at `path`.
+fn read_roots(path: &Path) -> Result<Vec<Certificate>, TlsError> {
+    let pem = std::fs::read_to_string(path).map_err(|source| TlsError::Roots {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    let roots = pem
+        .split_inclusive(PEM_CERTIFICATE_END)
+        .filter(|block| block.contains(PEM_CERTIFICATE_END))
+        .map(|block| Certificate::from_pem(block.as_bytes()))
+        .collect::<Result<Vec<_>, _>>()?;
+    if roots.is_empty() {
+        return Err(TlsError::EmptyRoots(path.to_path_buf()));
+    }
+    Ok(roots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_custom_roots_must_hold_a_certificate() {
+        let missing = TlsConfig::new().with_custom_roots("/nonexistent/roots.pem");
+        assert!(matches!(missing.connector(), Err(TlsError::Roots { .. })));
+
+        let mut empty = tempfile::NamedTempFile::new().unwrap();
+        writeln!(empty, "# no certificates here").unwrap();
+        let empty = TlsConfig::new().with_custom_roots(empty.path());
+        assert!(matches!(empty.connector(), Err(TlsError::EmptyRoots(_))));
+
+        assert!(TlsConfig::new().connector().is_ok());
+    }
+}
@@ -0,0 +1,312 @@
+use crate::clock::{Clock, SystemClock};
+use crate::persistence::{self, TradeParquetWriter};
+use crate::tradeslog::Trade;
+use chrono::{TimeZone, Utc};
+use log::error;
+use rust_decimal::Decimal;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+use tokio::task::{self, JoinHandle};
+use tokio::time::{self, Instant};
+
+/// Trades buffered for the writer before new ones are dropped.
+const DEFAULT_SINK_CAPACITY: usize = 100_000;
+/// Trades written to a file as one row group.
+const ROW_GROUP_TRADES: usize = 50_000;
+/// Longest a trade waits in memory before being written out as part of a row group.
+const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+const HOUR_MS: u64 = 3_600_000;
+
+/// One parsed trade as persisted by `TradeSink`, one row per trade.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawTrade {
+    pub timestamp: u64,
+    pub price: Decimal,
+    pub quantity: Decimal,
+    pub is_buyer_maker: bool,
+    /// The exchange's id for the trade, on feeds whose trades carry one.
+    pub trade_id: Option<u64>,
+    pub symbol: Option<String>,
+}
+
+impl RawTrade {
+    pub fn new(trade: &Trade) -> Self {
+        Self {
+            timestamp: trade.timestamp,
+            price: trade.price,
+            quantity: trade.quantity,
+            is_buyer_maker: trade.is_buyer_maker,
+            trade_id: trade.trade_id,
+            symbol: trade.symbol.clone(),
+        }
+    }
+}
+
+/// Persists every trade the feed parses to hourly Parquet files under one directory.
+///
+/// Recording never blocks: trades go through a bounded channel to a writer on a blocking
+/// task, and are dropped (and counted) when the writer falls behind. Each file is written
+/// in row groups, at the latest every flush interval, and gets its footer, becoming
+/// readable, at the end of its hour or when the writer stops. Clones share the writer.
+#[derive(Debug, Clone)]
+pub struct TradeSink {
+    tx: mpsc::Sender<(u64, RawTrade)>,
+    clock: Arc<dyn Clock>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl TradeSink {
+    /// Starts a writer under `dir`. The handle completes once every sink is dropped and
+    /// the last file is finished.
+    pub fn spawn(dir: impl Into<PathBuf>) -> (Self, JoinHandle<()>) {
+        Self::spawn_with_clock(dir, DEFAULT_SINK_CAPACITY, DEFAULT_FLUSH_INTERVAL, Arc::new(SystemClock))
+    }
+
+    /// Like `spawn`, filing trades by the hour `clock` received them in.
+    pub fn spawn_with_clock(
+        dir: impl Into<PathBuf>,
+        capacity: usize,
+        flush_interval: Duration,
+        clock: Arc<dyn Clock>,
+    ) -> (Self, JoinHandle<()>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        let dir = dir.into();
+        let runtime = Handle::current();
+        let writer_clock = clock.clone();
+        let writer = task::spawn_blocking(move || write_trades(dir, rx, flush_interval, writer_clock, runtime));
+        (Self::new(tx, clock), writer)
+    }
+
+    fn new(tx: mpsc::Sender<(u64, RawTrade)>, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            tx,
+            clock,
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn record(&self, trade: RawTrade) {
+        if self.tx.try_send((self.clock.now_ms(), trade)).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            metrics::increment_counter!("trade_sink_dropped");
+        }
+    }
+
+    /// Trades dropped so far across all clones.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// `trades_<YYYYMMDD>_<HH>.parquet`, by the UTC hour the trade was received in.
+pub fn trade_file_name(recv_ts_ms: u64) -> String {
+    let hour = Utc
+        .timestamp_millis_opt(recv_ts_ms as i64)
+        .single()
+        .unwrap_or_else(Utc::now);
+    format!("trades_{}.parquet", hour.format("%Y%m%d_%H"))
+}
+
+fn write_trades(
+    dir: PathBuf,
+    mut rx: mpsc::Receiver<(u64, RawTrade)>,
+    flush_interval: Duration,
+    clock: Arc<dyn Clock>,
+    runtime: Handle,
+) {
+    let mut current: Option<HourFile> = None;
+    let mut next_flush = Instant::now() + flush_interval;
+    loop {
+        // Wake up by the end of the hour too, so its file is finished without waiting for a later trade
+        let now_ms = clock.now_ms();
+        let deadline = next_flush.min(Instant::now() + Duration::from_millis(HOUR_MS - now_ms % HOUR_MS));
+        match runtime.block_on(time::timeout_at(deadline, rx.recv())) {
+            Ok(Some((recv_ts_ms, trade))) => {
+                let name = trade_file_name(recv_ts_ms);
+                if !matches!(&current, Some(file) if file.name == name) {
+                    if let Some(previous) = current.take() {
+                        previous.finish();
+                    }
+                    current = Some(HourFile::new(&dir, name));
+                }
+                if let Some(file) = current.as_mut() {
+                    file.push(trade);
+                }
+            }
+            Ok(None) => break,
+            // Nothing is queued, so no more trades of an hour that is over can be on their way
+            Err(_) => {
+                if matches!(&current, Some(file) if file.name != trade_file_name(clock.now_ms())) {
+                    if let Some(previous) = current.take() {
+                        previous.finish();
+                    }
+                }
+            }
+        }
+        if Instant::now() >= next_flush {
+            if let Some(file) = current.as_mut() {
+                file.write_pending();
+            }
+            next_flush = Instant::now() + flush_interval;
+        }
+    }
+    if let Some(file) = current {
+        file.finish();
+    }
+}
+
+/// The file of one hour, with the trades not yet written out as a row group.
+struct HourFile {
+    name: String,
+    path: PathBuf,
+    writer: Option<TradeParquetWriter>,
+    pending: Vec<RawTrade>,
+}
+
+impl HourFile {
+    /// A file for the hour `name`, next to any left by an earlier run in the same hour
+    /// rather than over it.
+    fn new(dir: &Path, name: String) -> Self {
+        let path = persistence::unused_path(&dir.join(&name));
+        Self {
+            name,
+            path,
+            writer: None,
+            pending: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, trade: RawTrade) {
+        self.pending.push(trade);
+        if self.pending.len() >= ROW_GROUP_TRADES {
+            self.write_pending();
+        }
+    }
+
+    /// Writes the pending trades as a row group, opening the file first if need be.
+    fn write_pending(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        if self.writer.is_none() {
+            match TradeParquetWriter::create(&self.path) {
+                Ok(writer) => self.writer = Some(writer),
+                Err(e) => error!("Failed to create trades file {}: {:#}", self.path.display(), e),
+            }
+        }
+        if let Some(writer) = self.writer.as_mut() {
+            if let Err(e) = writer.write(&self.pending) {
+                error!("Failed to write {} trades to {}: {:#}", self.pending.len(), self.path.display(), e);
+            }
+        }
+        self.pending.clear();
+    }
+
+    fn finish(mut self) {
+        self.write_pending();
+        if let Some(writer) = self.writer.take() {
+            if let Err(e) = writer.finish() {
+                error!("Failed to finish trades file {}: {:#}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::persistence::load_raw_trades_from_parquet;
+    use rust_decimal_macros::dec;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    // 2024-04-10 10:59:59.900 UTC
+    const BEFORE_HOUR_MS: u64 = 1_712_746_799_900;
+    const FLUSH_INTERVAL: Duration = Duration::from_millis(20);
+
+    fn raw_trade(price: Decimal, trade_id: Option<u64>) -> RawTrade {
+        let trade = Trade::new(price, dec!(0.25), BEFORE_HOUR_MS - 5, true).unwrap().with_symbol("BTCUSDT");
+        RawTrade::new(&Trade { trade_id, ..trade })
+    }
+
+    #[tokio::test]
+    async fn test_trades_round_trip_and_rotate_hourly() {
+        let dir = tempdir().unwrap();
+        let clock = ManualClock::new(BEFORE_HOUR_MS);
+        let (sink, writer) = TradeSink::spawn_with_clock(dir.path(), 16, FLUSH_INTERVAL, Arc::new(clock.clone()));
+
+        let before = vec![raw_trade(dec!(69000.5), Some(1)), raw_trade(dec!(69001), None)];
+        for trade in &before {
+            sink.record(trade.clone());
+        }
+        clock.advance(Duration::from_millis(200));
+        let after = raw_trade(dec!(69002.25), Some(3));
+        sink.record(after.clone());
+
+        drop(sink);
+        writer.await.unwrap();
+
+        let first = dir.path().join("trades_20240410_10.parquet");
+        assert_eq!(load_raw_trades_from_parquet(&first.to_string_lossy()).unwrap(), before);
+        assert_eq!(before[0].symbol.as_deref(), Some("btcusdt"));
+        let second = dir.path().join("trades_20240410_11.parquet");
+        assert_eq!(load_raw_trades_from_parquet(&second.to_string_lossy()).unwrap(), vec![after]);
+    }
+
+    #[tokio::test]
+    async fn test_restart_within_the_hour_keeps_the_earlier_file() {
+        let dir = tempdir().unwrap();
+        for price in [dec!(1), dec!(2)] {
+            let (sink, writer) = TradeSink::spawn_with_clock(dir.path(), 16, FLUSH_INTERVAL, Arc::new(ManualClock::new(BEFORE_HOUR_MS)));
+            sink.record(raw_trade(price, None));
+            drop(sink);
+            writer.await.unwrap();
+        }
+
+        let load = |name: &str| load_raw_trades_from_parquet(&dir.path().join(name).to_string_lossy()).unwrap();
+        assert_eq!(load("trades_20240410_10.parquet")[0].price, dec!(1));
+        assert_eq!(load("trades_20240410_10_1.parquet")[0].price, dec!(2));
+    }
+
+    #[tokio::test]
+    async fn test_finishes_the_hour_without_a_later_trade() {
+        let dir = tempdir().unwrap();
+        let clock = ManualClock::new(BEFORE_HOUR_MS);
+        let (sink, writer) = TradeSink::spawn_with_clock(dir.path(), 16, FLUSH_INTERVAL, Arc::new(clock.clone()));
+        let trade = raw_trade(dec!(69000.5), Some(1));
+        sink.record(trade.clone());
+        clock.advance(Duration::from_millis(200));
+
+        // The sink is still open and nothing else arrives
+        let path = dir.path().join("trades_20240410_10.parquet");
+        let mut loaded = None;
+        for _ in 0..100 {
+            tokio::time::sleep(FLUSH_INTERVAL).await;
+            loaded = load_raw_trades_from_parquet(&path.to_string_lossy()).ok();
+            if loaded.is_some() {
+                break;
+            }
+        }
+        assert_eq!(loaded, Some(vec![trade]));
+
+        drop(sink);
+        writer.await.unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_full_channel_drops_and_counts() {
+        // No writer drains this channel
+        let (tx, _rx) = mpsc::channel(2);
+        let sink = TradeSink::new(tx, Arc::new(ManualClock::new(0)));
+        for _ in 0..5 {
+            sink.record(raw_trade(dec!(1), None));
+        }
+        assert_eq!(sink.dropped(), 3);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::clock::{Clock, SystemClock};
 use std::collections::VecDeque;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -6,14 +7,90 @@ use rust_decimal_macros::dec;
 use thiserror::Error;
 use serde::Serialize;
 
-#[derive(Debug, Clone)]
+/// One executed trade. The fields stay public for deserialization, but `Trade::new`
+/// is the way to build one from exchange data.
+#[derive(Debug, Clone, PartialEq)]
 pub struct Trade {
     pub price: Decimal,
     pub quantity: Decimal,
     pub timestamp: u64,
     pub is_buyer_maker: bool,
+    /// Lowercase symbol the trade was in, for payloads that name it; see `with_symbol`.
+    pub symbol: Option<String>,
+    /// Exchange trade id, for feeds that number their trades consecutively per symbol.
+    pub trade_id: Option<u64>,
+    /// First and last exchange trade ids of the trades this one aggregates, e.g. a Binance
+    /// aggTrade's `f` and `l`; `trade_id` is then the aggregate's own id.
+    pub aggregated_trade_ids: Option<(u64, u64)>,
+}
+
+/// How a feed's `is_buyer_maker` flag maps to the side that took liquidity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MakerSemantics {
+    /// The flag is set when the buyer was the maker, so the taker sold. Binance's meaning,
+    /// and the one every adapter here normalizes to.
+    #[default]
+    BuyerMaker,
+    /// The flag is set when the seller was the maker, so the taker bought.
+    SellerMaker,
+}
+
+impl MakerSemantics {
+    /// Whether `trade` was a taker buy under these semantics.
+    pub fn is_taker_buy(self, trade: &Trade) -> bool {
+        match self {
+            MakerSemantics::BuyerMaker => !trade.is_buyer_maker,
+            MakerSemantics::SellerMaker => trade.is_buyer_maker,
+        }
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TradeError {
+    #[error("Negative trade price {0}")]
+    NegativePrice(Decimal),
+    #[error("Negative trade quantity {0}")]
+    NegativeQuantity(Decimal),
 }
 
+impl Trade {
+    /// Trade with a non-negative price and quantity.
+    pub fn new(price: Decimal, quantity: Decimal, timestamp: u64, is_buyer_maker: bool) -> Result<Self, TradeError> {
+        if price < Decimal::ZERO {
+            return Err(TradeError::NegativePrice(price));
+        }
+        if quantity < Decimal::ZERO {
+            return Err(TradeError::NegativeQuantity(quantity));
+        }
+        Ok(Self {
+            price,
+            quantity,
+            timestamp,
+            is_buyer_maker,
+            symbol: None,
+            trade_id: None,
+            aggregated_trade_ids: None,
+        })
+    }
+
+    /// Tags the trade with the symbol it was in, lowercased, so a feed carrying several
+    /// symbols can route it to that symbol's log.
+    pub fn with_symbol(mut self, symbol: &str) -> Self {
+        self.symbol = Some(symbol.to_lowercase());
+        self
+    }
+
+    pub fn with_trade_id(mut self, trade_id: u64) -> Self {
+        self.trade_id = Some(trade_id);
+        self
+    }
+}
+
+/// Rolling log of recent trades.
+///
+/// Duration windows end at the last trade's timestamp, so they only move when trades
+/// arrive; methods ending in `_now` end their window at the injected clock's time instead.
+/// Running totals are kept on insert and evict, so every read works from `&self`.
 #[derive(Debug, Clone)]
 pub struct TradesLog {
     trades: VecDeque<Trade>,
@@ -21,19 +98,29 @@ pub struct TradesLog {
     trade_count: usize,
     buy_volume: Decimal,
     sell_volume: Decimal,
-    stats_dirty: bool,
-    cached_stats: CachedStats,
+    signed_count_momentum: i64,
+    /// Price of the trade before the last one, kept past its eviction.
+    previous_price: Option<Decimal>,
+    imbalance_alpha: Decimal,
+    imbalance_ema: Option<Decimal>,
+    maker_semantics: MakerSemantics,
+    clock: Arc<dyn Clock>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct TradeLogSnapshot {
     pub last_price: Option<Decimal>,
     pub trade_imbalance: Option<Decimal>,
+    pub trade_imbalance_ema: Option<Decimal>,
     pub vwap_total: Option<Decimal>,
     pub price_change: Option<Decimal>,
     pub avg_trade_size: Option<Decimal>,
     pub signed_count_momentum: i64,
+    /// Trades per second over the 1s, 10s and 60s ending at the last trade. The longer
+    /// windows only see as far back as the log holds.
+    pub trade_rate_1s: Option<f64>,
     pub trade_rate_10s: Option<f64>,
+    pub trade_rate_60s: Option<f64>,
     pub vwap_10: Option<Decimal>,
     pub vwap_50: Option<Decimal>,
     pub vwap_100: Option<Decimal>,
@@ -42,16 +129,9 @@ pub struct TradeLogSnapshot {
     pub aggr_ratio_50: Option<Decimal>,
     pub aggr_ratio_100: Option<Decimal>,
     pub aggr_ratio_1000: Option<Decimal>,
-}
-
-#[derive(Debug, Clone, Default)]
-struct CachedStats {
-    trade_imbalance: Option<Decimal>,
-    vwap_total: Option<Decimal>,
-    price_change: Option<Decimal>,
-    last_price: Option<Decimal>,
-    avg_trade_size: Option<Decimal>,
-    signed_count_momentum: i64,
+    pub aggr_ratio_10s: Option<Decimal>,
+    /// Share of the last 100 trades' volume in trades of at least 1 (base asset).
+    pub large_trade_share_100: Option<Decimal>,
 }
 
 #[derive(Debug, Error)]
@@ -64,93 +144,164 @@ pub enum TradesLogError {
     InvalidWindowSize,
 }
 
+const DEFAULT_IMBALANCE_ALPHA: Decimal = dec!(0.1);
+/// Trade size bucket bounds for `large_trade_share_100`, sized for BTC.
+const SIZE_THRESHOLDS: [Decimal; 3] = [dec!(0.01), dec!(0.1), dec!(1)];
+
 impl TradesLog {
     pub fn new(max_len: usize) -> Self {
+        Self::with_clock(max_len, Arc::new(SystemClock))
+    }
+
+    /// Creates an empty log whose `_now` windows read time from `clock`.
+    pub fn with_clock(max_len: usize, clock: Arc<dyn Clock>) -> Self {
         Self {
             trades: VecDeque::with_capacity(max_len),
             max_len,
             trade_count: 0,
             buy_volume: dec!(0),
             sell_volume: dec!(0),
-            stats_dirty: true,
-            cached_stats: CachedStats::default(),
+            signed_count_momentum: 0,
+            previous_price: None,
+            imbalance_alpha: DEFAULT_IMBALANCE_ALPHA,
+            imbalance_ema: None,
+            maker_semantics: MakerSemantics::default(),
+            clock,
         }
     }
 
-    fn update_cached_stats(&mut self) {
-        if !self.stats_dirty {
-            return;
-        }
-
-        let total_volume = self.buy_volume + self.sell_volume;
-
-        self.cached_stats.trade_imbalance = if total_volume > dec!(0) {
-            Some(self.buy_volume / total_volume)
-        } else {
-            None
-        };
-
-        self.cached_stats.vwap_total = if total_volume > dec!(0) {
-            let last_price = self.trades.back().map(|t| t.price).unwrap_or(dec!(0));
-            Some((self.buy_volume + self.sell_volume) * last_price / total_volume)
-        } else {
-            None
-        };
-
-        self.cached_stats.price_change = match (self.trades.len(), self.cached_stats.last_price) {
-            (_, None) => None,
-            (0, _) => None,
-            (_, Some(prev)) => {
-                let current = self.trades.back().unwrap().price;
-                Some(current - prev)
-            }
-        };
+    /// Weight of the newest imbalance reading in `trade_imbalance_ema`, in (0, 1].
+    pub fn with_imbalance_alpha(mut self, alpha: Decimal) -> Self {
+        self.imbalance_alpha = alpha;
+        self
+    }
 
-        self.cached_stats.last_price = self.trades.back().map(|t| t.price);
+    /// How `is_buyer_maker` is read when splitting volume into taker buys and sells.
+    pub fn with_maker_semantics(mut self, semantics: MakerSemantics) -> Self {
+        self.maker_semantics = semantics;
+        self
+    }
 
-        self.cached_stats.avg_trade_size = if self.trade_count > 0 {
-            Some(total_volume / Decimal::from(self.trade_count))
+    /// Adjusts volumes and momentum for a trade leaving the buffer.
+    fn evict(&mut self, removed: &Trade) {
+        if self.maker_semantics.is_taker_buy(removed) {
+            self.buy_volume -= removed.quantity;
+            // When removing a buy trade, we need to decrement momentum
+            // because we're removing a +1 that was previously added
+            self.signed_count_momentum -= 1;
         } else {
-            None
-        };
-
-        self.stats_dirty = false;
+            self.sell_volume -= removed.quantity;
+            // When removing a sell trade, we need to increment momentum
+            // because we're removing a -1 that was previously added
+            self.signed_count_momentum += 1;
+        }
     }
 
     pub fn insert_trade(&mut self, trade: Trade) {
         // Handle trade eviction if buffer is full
         if self.trades.len() == self.max_len {
             let removed = self.trades.pop_front().unwrap();
-            
-            // Adjust volumes and momentum for removed trade
-            if removed.is_buyer_maker {
-                self.sell_volume -= removed.quantity;
-                // When removing a sell trade, we need to increment momentum
-                // because we're removing a -1 that was previously added
-                self.cached_stats.signed_count_momentum += 1;
-            } else {
-                self.buy_volume -= removed.quantity;
-                // When removing a buy trade, we need to decrement momentum
-                // because we're removing a +1 that was previously added
-                self.cached_stats.signed_count_momentum -= 1;
-            }
+            self.evict(&removed);
         } else {
             self.trade_count += 1;
         }
 
         // Add new trade
-        if trade.is_buyer_maker {
-            self.sell_volume += trade.quantity;
-            // Sell trades (maker) decrease momentum
-            self.cached_stats.signed_count_momentum -= 1;
-        } else {
+        if self.maker_semantics.is_taker_buy(&trade) {
             self.buy_volume += trade.quantity;
             // Buy trades (taker) increase momentum
-            self.cached_stats.signed_count_momentum += 1;
+            self.signed_count_momentum += 1;
+        } else {
+            self.sell_volume += trade.quantity;
+            // Sell trades (maker) decrease momentum
+            self.signed_count_momentum -= 1;
         }
 
-        self.stats_dirty = true;
+        self.previous_price = self.last_price();
         self.trades.push_back(trade);
+        self.update_imbalance_ema();
+    }
+
+    /// Inserts `trades` oldest first, leaving the log as that many `insert_trade` calls
+    /// would, evictions and running totals included.
+    pub fn insert_trades(&mut self, trades: Vec<Trade>) {
+        for trade in trades {
+            self.insert_trade(trade);
+        }
+    }
+
+    /// Folds the current windowed buy fraction into the EMA, seeding it on the first reading.
+    fn update_imbalance_ema(&mut self) {
+        let total_volume = self.buy_volume + self.sell_volume;
+        if total_volume == dec!(0) {
+            return;
+        }
+        let current = self.buy_volume / total_volume;
+        self.imbalance_ema = Some(match self.imbalance_ema {
+            Some(ema) => self.imbalance_alpha * current + (dec!(1) - self.imbalance_alpha) * ema,
+            None => current,
+        });
+    }
+
+    /// Resizes the history window, evicting the oldest trades when shrinking. A window of
+    /// no trades is rejected, leaving the log as it was.
+    pub fn set_max_len(&mut self, new_len: usize) -> Result<(), TradesLogError> {
+        if new_len == 0 {
+            return Err(TradesLogError::InvalidWindowSize);
+        }
+        while self.trades.len() > new_len {
+            let removed = self.trades.pop_front().unwrap();
+            self.evict(&removed);
+            self.trade_count -= 1;
+        }
+
+        if new_len > self.trades.capacity() {
+            self.trades.reserve(new_len - self.trades.len());
+        }
+        self.max_len = new_len;
+        Ok(())
+    }
+
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Verifies the running aggregates against the trades currently buffered.
+    ///
+    /// Momentum and volumes are maintained incrementally on insert/evict, so
+    /// they must always equal a from-scratch recount of the buffer.
+    pub fn check_invariants(&self) -> Result<(), String> {
+        let (mut buy_volume, mut sell_volume, mut momentum) = (dec!(0), dec!(0), 0i64);
+        for trade in &self.trades {
+            if self.maker_semantics.is_taker_buy(trade) {
+                buy_volume += trade.quantity;
+                momentum += 1;
+            } else {
+                sell_volume += trade.quantity;
+                momentum -= 1;
+            }
+        }
+
+        if self.signed_count_momentum != momentum {
+            return Err(format!(
+                "momentum {} != signed count {} of buffered trades",
+                self.signed_count_momentum, momentum
+            ));
+        }
+        if self.buy_volume != buy_volume || self.sell_volume != sell_volume {
+            return Err(format!(
+                "volumes {}/{} != buffered {}/{}",
+                self.buy_volume, self.sell_volume, buy_volume, sell_volume
+            ));
+        }
+        if self.trade_count != self.trades.len() {
+            return Err(format!(
+                "trade_count {} != buffered {}",
+                self.trade_count,
+                self.trades.len()
+            ));
+        }
+        Ok(())
     }
 
     pub fn last_n_trades(&self, n: usize) -> Vec<Trade> {
@@ -161,6 +312,19 @@ impl TradesLog {
         self.trades.iter().rev().take(n)
     }
 
+    /// Copies of the trades stamped after `timestamp` (epoch ms), oldest first, for a
+    /// consumer polling for what's new since the last trade it saw. Nothing is removed.
+    ///
+    /// Like the duration windows, this walks back from the newest trade and stops at the
+    /// first one not after `timestamp`. Trades evicted before the poll are gone, and ones
+    /// sharing `timestamp` that arrive after it are not returned by the next poll either.
+    pub fn trades_since(&self, timestamp: u64) -> Vec<Trade> {
+        let mut trades: Vec<Trade> =
+            self.trades.iter().rev().take_while(|t| t.timestamp > timestamp).cloned().collect();
+        trades.reverse();
+        trades
+    }
+
     pub fn vwap(&self, window: usize) -> Result<Decimal, TradesLogError> {
         if window == 0 {
             return Err(TradesLogError::InvalidWindowSize);
@@ -185,6 +349,7 @@ impl TradesLog {
         }
     }
 
+    /// Trades per second over the `window_ms` ending at the last trade.
     pub fn trade_rate(&self, window_ms: u64) -> Result<f64, TradesLogError> {
         if self.trades.len() < 2 {
             return Err(TradesLogError::InsufficientTrades);
@@ -200,20 +365,63 @@ impl TradesLog {
         Ok(count as f64 / (window_ms as f64 / 1000.0))
     }
 
+    /// Trades per second over the `window_ms` ending now; falls to zero when trading stops.
+    ///
+    /// The window includes its start, like `trade_rate`. Trades stamped after the clock's
+    /// time (exchange clock ahead of ours) are not counted.
+    pub fn trade_rate_now(&self, window_ms: u64) -> Result<f64, TradesLogError> {
+        if window_ms == 0 {
+            return Err(TradesLogError::InvalidWindowSize);
+        }
+        let now = self.clock.now_ms();
+        let start_time = now.saturating_sub(window_ms);
+
+        let first = self.trades.partition_point(|t| t.timestamp < start_time);
+        let end = self.trades.partition_point(|t| t.timestamp <= now);
+        let count = end.saturating_sub(first);
+
+        Ok(count as f64 / (window_ms as f64 / 1000.0))
+    }
+
+    /// Milliseconds between the last trade and now, per the injected clock.
+    pub fn ms_since_last_trade(&self) -> Option<u64> {
+        let last = self.trades.back()?;
+        Some(self.clock.now_ms().saturating_sub(last.timestamp))
+    }
+
+    /// Taker-buy fraction of volume over the last `n` trades; like `vwap`, an error until
+    /// `n` trades have been seen.
     pub fn aggressor_volume_ratio(&self, n: usize) -> Result<Decimal, TradesLogError> {
         if n == 0 {
             return Err(TradesLogError::InvalidWindowSize);
         }
-        if self.trades.is_empty() {  
+        if self.trades.len() < n {
             return Err(TradesLogError::InsufficientTrades);
         }
 
-        let (buyer_volume, seller_volume) = self.last_n_trades_ref(n)
+        self.taker_buy_fraction(self.last_n_trades_ref(n))
+    }
+
+    /// Taker-buy fraction of volume over trades within `window_ms` of the last trade.
+    pub fn aggressor_volume_ratio_duration(&self, window_ms: u64) -> Result<Decimal, TradesLogError> {
+        if window_ms == 0 {
+            return Err(TradesLogError::InvalidWindowSize);
+        }
+        let last = self.trades.back().ok_or(TradesLogError::InsufficientTrades)?;
+        let start_time = last.timestamp.saturating_sub(window_ms);
+
+        self.taker_buy_fraction(
+            self.trades.iter().rev().take_while(|t| t.timestamp >= start_time)
+        )
+    }
+
+    fn taker_buy_fraction<'a>(&self, trades: impl Iterator<Item = &'a Trade>) -> Result<Decimal, TradesLogError> {
+        let (buyer_volume, seller_volume) = trades
             .fold((dec!(0), dec!(0)), |(buy, sell), t| {
-                if t.is_buyer_maker {
-                    (buy, sell + t.quantity)
-                } else {
+                if self.maker_semantics.is_taker_buy(t) {
                     (buy + t.quantity, sell)
+                } else {
+                    (buy, sell + t.quantity)
                 }
             });
 
@@ -225,45 +433,86 @@ impl TradesLog {
         }
     }
 
-    pub fn trade_imbalance(&mut self) -> Option<Decimal> {
-        self.update_cached_stats();
-        self.cached_stats.trade_imbalance
+    /// Volume share of each trade size bucket over the last `n` trades; like `vwap`, an
+    /// error until `n` trades have been seen.
+    ///
+    /// `thresholds` are ascending bucket bounds: bucket 0 holds trades below the first,
+    /// bucket `i` those from `thresholds[i - 1]` up to `thresholds[i]`, and the last those
+    /// at or above the last bound, so there is one more bucket than bounds.
+    pub fn size_distribution(&self, n: usize, thresholds: &[Decimal]) -> Result<Vec<Decimal>, TradesLogError> {
+        if n == 0 {
+            return Err(TradesLogError::InvalidWindowSize);
+        }
+        if self.trades.len() < n {
+            return Err(TradesLogError::InsufficientTrades);
+        }
+
+        let mut volumes = vec![dec!(0); thresholds.len() + 1];
+        for trade in self.last_n_trades_ref(n) {
+            let bucket = thresholds.partition_point(|&bound| bound <= trade.quantity);
+            volumes[bucket] += trade.quantity;
+        }
+        let total: Decimal = volumes.iter().sum();
+        if total == dec!(0) {
+            return Err(TradesLogError::ZeroVolume);
+        }
+        Ok(volumes.into_iter().map(|volume| volume / total).collect())
     }
 
-    pub fn vwap_total(&mut self) -> Option<Decimal> {
-        self.update_cached_stats();
-        self.cached_stats.vwap_total
+    /// Taker-buy fraction of the buffered volume.
+    pub fn trade_imbalance(&self) -> Option<Decimal> {
+        let total_volume = self.buy_volume + self.sell_volume;
+        (total_volume > dec!(0)).then(|| self.buy_volume / total_volume)
     }
 
-    pub fn price_change(&mut self) -> Option<Decimal> {
-        self.update_cached_stats();
-        self.cached_stats.price_change
+    /// Exponential moving average of `trade_imbalance`, updated on every trade.
+    pub fn trade_imbalance_ema(&self) -> Option<Decimal> {
+        self.imbalance_ema
+    }
+
+    pub fn vwap_total(&self) -> Option<Decimal> {
+        let total_volume = self.buy_volume + self.sell_volume;
+        if total_volume > dec!(0) {
+            let last_price = self.last_price().unwrap_or(dec!(0));
+            Some((self.buy_volume + self.sell_volume) * last_price / total_volume)
+        } else {
+            None
+        }
+    }
+
+    /// Difference between the last trade's price and the one before it.
+    pub fn price_change(&self) -> Option<Decimal> {
+        Some(self.last_price()? - self.previous_price?)
     }
 
     pub fn last_price(&self) -> Option<Decimal> {
         self.trades.back().map(|t| t.price)
     }
 
-    pub fn avg_trade_size(&mut self) -> Option<Decimal> {
-        self.update_cached_stats();
-        self.cached_stats.avg_trade_size
+    pub fn avg_trade_size(&self) -> Option<Decimal> {
+        if self.trade_count > 0 {
+            Some((self.buy_volume + self.sell_volume) / Decimal::from(self.trade_count))
+        } else {
+            None
+        }
     }
 
     pub fn signed_count_momentum(&self) -> i64 {
-        self.cached_stats.signed_count_momentum
+        self.signed_count_momentum
     }
 
-    pub fn get_snapshot(&mut self) -> TradeLogSnapshot {
-        self.update_cached_stats();
-        
+    pub fn get_snapshot(&self) -> TradeLogSnapshot {
         TradeLogSnapshot {
             last_price: self.last_price(),
             trade_imbalance: self.trade_imbalance(),
+            trade_imbalance_ema: self.trade_imbalance_ema(),
             vwap_total: self.vwap_total(),
             price_change: self.price_change(),
             avg_trade_size: self.avg_trade_size(),
             signed_count_momentum: self.signed_count_momentum(),
+            trade_rate_1s: self.trade_rate(1_000).ok(),
             trade_rate_10s: self.trade_rate(10_000).ok(),
+            trade_rate_60s: self.trade_rate(60_000).ok(),
             vwap_10: self.vwap(10).ok(),  
             vwap_50: self.vwap(50).ok(),
             vwap_100: self.vwap(100).ok(),
@@ -272,6 +521,11 @@ impl TradesLog {
             aggr_ratio_50: self.aggressor_volume_ratio(50).ok(),
             aggr_ratio_100: self.aggressor_volume_ratio(100).ok(),
             aggr_ratio_1000: self.aggressor_volume_ratio(1000).ok(),
+            aggr_ratio_10s: self.aggressor_volume_ratio_duration(10_000).ok(),
+            large_trade_share_100: self
+                .size_distribution(100, &SIZE_THRESHOLDS)
+                .ok()
+                .and_then(|shares| shares.last().copied()),
         }
     }
 }
@@ -288,16 +542,45 @@ impl ConcurrentTradesLog {
         }
     }
 
+    pub fn with_clock(max_len: usize, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(TradesLog::with_clock(max_len, clock))),
+        }
+    }
+
+    /// Shares a log configured through its own builders, e.g. `with_maker_semantics`.
+    pub fn from_log(log: TradesLog) -> Self {
+        Self {
+            inner: Arc::new(RwLock::new(log)),
+        }
+    }
+
     pub async fn insert_trade(&self, trade: Trade) {
         let mut log = self.inner.write().await;
         log.insert_trade(trade);
     }
 
+    /// See `TradesLog::insert_trades`; the lock is taken once for the whole batch.
+    pub async fn insert_trades(&self, trades: Vec<Trade>) {
+        let mut log = self.inner.write().await;
+        log.insert_trades(trades);
+    }
+
+    pub async fn set_max_len(&self, new_len: usize) -> Result<(), TradesLogError> {
+        let mut log = self.inner.write().await;
+        log.set_max_len(new_len)
+    }
+
     pub async fn last_n_trades(&self, n: usize) -> Vec<Trade> {
         let log = self.inner.read().await;
         log.last_n_trades(n)
     }
 
+    pub async fn trades_since(&self, timestamp: u64) -> Vec<Trade> {
+        let log = self.inner.read().await;
+        log.trades_since(timestamp)
+    }
+
     pub async fn vwap(&self, n: usize) -> Result<Decimal, TradesLogError> {
         let log = self.inner.read().await;  
         log.vwap(n)
@@ -308,23 +591,43 @@ impl ConcurrentTradesLog {
         log.trade_rate(window_ms)
     }
 
+    pub async fn trade_rate_now(&self, window_ms: u64) -> Result<f64, TradesLogError> {
+        let log = self.inner.read().await;
+        log.trade_rate_now(window_ms)
+    }
+
+    pub async fn ms_since_last_trade(&self) -> Option<u64> {
+        let log = self.inner.read().await;
+        log.ms_since_last_trade()
+    }
+
     pub async fn aggressor_volume_ratio(&self, n: usize) -> Result<Decimal, TradesLogError> {
         let log = self.inner.read().await;
         log.aggressor_volume_ratio(n)
     }
 
+    pub async fn aggressor_volume_ratio_duration(&self, window_ms: u64) -> Result<Decimal, TradesLogError> {
+        let log = self.inner.read().await;
+        log.aggressor_volume_ratio_duration(window_ms)
+    }
+
     pub async fn trade_imbalance(&self) -> Option<Decimal> {
-        let mut log = self.inner.write().await;
+        let log = self.inner.read().await;
         log.trade_imbalance()
     }
 
+    pub async fn trade_imbalance_ema(&self) -> Option<Decimal> {
+        let log = self.inner.read().await;
+        log.trade_imbalance_ema()
+    }
+
     pub async fn vwap_total(&self) -> Option<Decimal> {
-        let mut log = self.inner.write().await;
+        let log = self.inner.read().await;
         log.vwap_total()
     }
 
     pub async fn price_change(&self) -> Option<Decimal> {
-        let mut log = self.inner.write().await;
+        let log = self.inner.read().await;
         log.price_change()
     }
 
@@ -334,7 +637,7 @@ impl ConcurrentTradesLog {
     }
 
     pub async fn avg_trade_size(&self) -> Option<Decimal> {
-        let mut log = self.inner.write().await;
+        let log = self.inner.read().await;
         log.avg_trade_size()
     }
 
@@ -343,23 +646,36 @@ impl ConcurrentTradesLog {
         log.signed_count_momentum()
     }
 
+    /// Snapshot taken under a read lock, so it doesn't hold up other readers.
     pub async fn get_snapshot(&self) -> TradeLogSnapshot {
-        let mut log = self.inner.write().await;
+        let log = self.inner.read().await;
         log.get_snapshot()
     }
 }
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::clock::ManualClock;
     use rust_decimal_macros::dec;
+    use std::time::Duration;
 
     fn create_test_trade(price: Decimal, quantity: Decimal, is_buyer_maker: bool) -> Trade {
-        Trade {
-            price,
-            quantity,
-            timestamp: 0,
-            is_buyer_maker,
-        }
+        Trade::new(price, quantity, 0, is_buyer_maker).unwrap()
+    }
+
+    #[test]
+    fn test_trade_new_rejects_negative_values() {
+        assert_eq!(
+            Trade::new(dec!(100), dec!(-0.5), 0, false).unwrap_err(),
+            TradeError::NegativeQuantity(dec!(-0.5))
+        );
+        assert_eq!(
+            Trade::new(dec!(-1), dec!(1), 0, false).unwrap_err(),
+            TradeError::NegativePrice(dec!(-1))
+        );
+        // Zero is not negative
+        let trade = Trade::new(dec!(100), dec!(0), 42, true).unwrap();
+        assert_eq!((trade.quantity, trade.timestamp), (dec!(0), 42));
     }
 
     #[test]
@@ -397,6 +713,57 @@ mod tests {
         assert_eq!(log.sell_volume, dec!(1), "Sell volume should be 1");
     }
 
+    #[tokio::test]
+    async fn test_batch_insert_matches_sequential_inserts() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut timestamp = 1_000;
+        let trades: Vec<Trade> = (0..500)
+            .map(|_| {
+                timestamp += rng.gen_range(0..20);
+                let price = Decimal::new(rng.gen_range(99_000..101_000), 2);
+                let quantity = Decimal::new(rng.gen_range(0..5_000), 3);
+                Trade::new(price, quantity, timestamp, rng.gen_bool(0.5)).unwrap()
+            })
+            .collect();
+
+        let sequential = ConcurrentTradesLog::new(100);
+        for trade in trades.clone() {
+            sequential.insert_trade(trade).await;
+        }
+        // Batches of uneven sizes, some larger than the log itself
+        let batched = ConcurrentTradesLog::new(100);
+        let mut rest = trades.as_slice();
+        while !rest.is_empty() {
+            let (batch, tail) = rest.split_at(rng.gen_range(1..=150).min(rest.len()));
+            batched.insert_trades(batch.to_vec()).await;
+            rest = tail;
+        }
+
+        assert_eq!(batched.get_snapshot().await, sequential.get_snapshot().await);
+        assert_eq!(batched.last_n_trades(100).await, sequential.last_n_trades(100).await);
+        let log = batched.inner.read().await;
+        log.check_invariants().unwrap();
+        assert_eq!(log.trades.len(), 100);
+    }
+
+    #[tokio::test]
+    async fn test_trades_since_returns_only_newer_trades() {
+        let log = ConcurrentTradesLog::new(10);
+        for (price, timestamp) in [(dec!(100), 1_000), (dec!(101), 1_000), (dec!(102), 2_000), (dec!(103), 2_500)] {
+            log.insert_trade(Trade::new(price, dec!(1), timestamp, false).unwrap()).await;
+        }
+
+        let newer: Vec<_> = log.trades_since(1_000).await.iter().map(|t| (t.price, t.timestamp)).collect();
+        assert_eq!(newer, vec![(dec!(102), 2_000), (dec!(103), 2_500)]);
+        assert_eq!(log.trades_since(0).await.len(), 4);
+        assert!(log.trades_since(2_500).await.is_empty());
+        // Reading leaves the trades in place
+        assert_eq!(log.last_n_trades(10).await.len(), 4);
+    }
+
     #[test]
     fn test_vwap_calculation() {
         let mut log = TradesLog::new(10);
@@ -443,18 +810,27 @@ mod tests {
             quantity: dec!(1),
             timestamp: now - 5000,
             is_buyer_maker: false,
+            symbol: None,
+            trade_id: None,
+            aggregated_trade_ids: None,
         });
         log.insert_trade(Trade {
             price: dec!(101),
             quantity: dec!(2),
             timestamp: now - 3000,
             is_buyer_maker: true,
+            symbol: None,
+            trade_id: None,
+            aggregated_trade_ids: None,
         });
         log.insert_trade(Trade {
             price: dec!(102),
             quantity: dec!(3),
             timestamp: now,
             is_buyer_maker: false,
+            symbol: None,
+            trade_id: None,
+            aggregated_trade_ids: None,
         });
         
         // Test trade rate with approximate comparison
@@ -462,6 +838,59 @@ mod tests {
         assert!((rate - 0.6).abs() < 0.0001); // 3 trades / 5 seconds
     }
 
+    #[test]
+    fn test_burst_shows_in_short_trade_rates_first() {
+        let mut log = TradesLog::new(1000);
+        // A trade every 5s for a minute, then 20 trades within the last second
+        for i in 0..12 {
+            log.insert_trade(Trade { timestamp: 100_000 + i * 5_000, ..create_test_trade(dec!(100), dec!(1), false) });
+        }
+        for i in 0..20 {
+            log.insert_trade(Trade { timestamp: 160_000 + i * 50, ..create_test_trade(dec!(100), dec!(1), false) });
+        }
+
+        let snapshot = log.get_snapshot();
+        let rate_1s = snapshot.trade_rate_1s.unwrap();
+        let rate_10s = snapshot.trade_rate_10s.unwrap();
+        let rate_60s = snapshot.trade_rate_60s.unwrap();
+        assert_eq!(rate_1s, 20.0);
+        // Window [150_950, 160_950] catches the burst and the quiet trade at 155_000
+        assert_eq!(rate_10s, 2.1);
+        // Window [100_950, 160_950] drops only the very first quiet trade
+        assert_eq!(rate_60s, 31.0 / 60.0);
+        assert!(rate_1s > rate_10s && rate_10s > rate_60s);
+    }
+
+    #[test]
+    fn test_trade_rate_now_uses_pinned_clock() {
+        let clock = ManualClock::new(3_500);
+        let mut log = TradesLog::with_clock(10, Arc::new(clock.clone()));
+        assert_eq!(log.ms_since_last_trade(), None);
+        assert_eq!(log.trade_rate_now(1_000).unwrap(), 0.0);
+
+        for timestamp in [1_000, 2_000, 3_000] {
+            log.insert_trade(Trade { timestamp, ..create_test_trade(dec!(100), dec!(1), false) });
+        }
+
+        // Window [2000, 3500] includes the trade exactly on its start
+        assert_eq!(log.trade_rate_now(1_500).unwrap(), 2.0 / 1.5);
+        // Window [2001, 3500] does not
+        assert_eq!(log.trade_rate_now(1_499).unwrap(), 1.0 / 1.499);
+        assert_eq!(log.ms_since_last_trade(), Some(500));
+
+        clock.advance(Duration::from_millis(6_500));
+        assert_eq!(log.trade_rate_now(5_000).unwrap(), 0.0);
+        assert_eq!(log.ms_since_last_trade(), Some(7_000));
+        // Anchored at the last trade, so the quiet spell doesn't show
+        assert_eq!(log.trade_rate(1_000).unwrap(), 2.0);
+
+        // A trade stamped ahead of our clock is not counted yet
+        log.insert_trade(Trade { timestamp: 10_100, ..create_test_trade(dec!(100), dec!(1), false) });
+        assert_eq!(log.trade_rate_now(1_000).unwrap(), 0.0);
+        assert_eq!(log.ms_since_last_trade(), Some(0));
+        assert!(matches!(log.trade_rate_now(0), Err(TradesLogError::InvalidWindowSize)));
+    }
+
     #[test]
     fn test_aggressor_volume_ratio() {
         let mut log = TradesLog::new(10);
@@ -479,6 +908,50 @@ mod tests {
         // Use approximate comparison for decimal values
         let ratio = log.aggressor_volume_ratio(2).unwrap();
         assert!((ratio - dec!(0.3333333333333333333333333)).abs() < dec!(0.0000001));
+
+        // Fewer trades than the window
+        assert!(matches!(
+            log.aggressor_volume_ratio(3),
+            Err(TradesLogError::InsufficientTrades)
+        ));
+    }
+
+    #[test]
+    fn test_aggressor_volume_ratio_duration() {
+        let mut log = TradesLog::new(10);
+
+        assert!(matches!(
+            log.aggressor_volume_ratio_duration(1000),
+            Err(TradesLogError::InsufficientTrades)
+        ));
+
+        let now = 100_000;
+        for (offset, qty, is_buyer_maker) in [
+            (12_000, dec!(5), false), // outside a 10s window
+            (8_000, dec!(1), false),
+            (4_000, dec!(3), true),
+            (0, dec!(1), false),
+        ] {
+            log.insert_trade(Trade {
+                price: dec!(100),
+                quantity: qty,
+                timestamp: now - offset,
+                is_buyer_maker,
+                symbol: None,
+                trade_id: None,
+                aggregated_trade_ids: None,
+            });
+        }
+
+        // Buy volume 1 + 1 = 2 of 5 inside the last 10s
+        assert_eq!(log.aggressor_volume_ratio_duration(10_000).unwrap(), dec!(0.4));
+        // Only the last trade inside 1s
+        assert_eq!(log.aggressor_volume_ratio_duration(1_000).unwrap(), dec!(1));
+        assert!(matches!(
+            log.aggressor_volume_ratio_duration(0),
+            Err(TradesLogError::InvalidWindowSize)
+        ));
+        assert_eq!(log.get_snapshot().aggr_ratio_10s, Some(dec!(0.4)));
     }
 
     #[test]
@@ -494,6 +967,93 @@ mod tests {
         assert_eq!(snapshot.last_price, Some(dec!(101)));
         assert!((snapshot.trade_imbalance.unwrap() - dec!(0.3333333333333333333333333)).abs() < dec!(0.0000001));
         assert_eq!(snapshot.vwap_10, log.vwap(10).ok());
+        assert_eq!(snapshot.aggr_ratio_10, None);
+    }
+
+    #[test]
+    fn test_snapshot_windows_fill_only_when_populated() {
+        let mut log = TradesLog::new(100);
+        for i in 0..50 {
+            log.insert_trade(create_test_trade(dec!(100), dec!(1), i % 2 == 0));
+        }
+
+        let snapshot = log.get_snapshot();
+        assert_eq!(snapshot.aggr_ratio_10, Some(dec!(0.5)));
+        assert_eq!(snapshot.aggr_ratio_50, Some(dec!(0.5)));
+        assert_eq!(snapshot.aggr_ratio_100, None);
+        assert_eq!(snapshot.aggr_ratio_1000, None);
+        assert_eq!(snapshot.vwap_50, Some(dec!(100)));
+        assert_eq!(snapshot.vwap_100, None);
+    }
+
+    #[test]
+    fn test_trade_imbalance_ema_follows_step_change() {
+        let mut log = TradesLog::new(10).with_imbalance_alpha(dec!(0.5));
+        assert_eq!(log.trade_imbalance_ema(), None);
+
+        // Seeded by the first reading
+        log.insert_trade(create_test_trade(dec!(100), dec!(1), true));
+        assert_eq!(log.trade_imbalance_ema(), Some(dec!(0)));
+
+        // Flow flips to buying: the window saturates after 10 trades, the EMA lags behind it
+        let mut previous = dec!(0);
+        for _ in 0..10 {
+            log.insert_trade(create_test_trade(dec!(100), dec!(1), false));
+            let ema = log.trade_imbalance_ema().unwrap();
+            assert!(ema > previous);
+            assert!(ema < log.trade_imbalance().unwrap());
+            previous = ema;
+        }
+        assert_eq!(log.trade_imbalance(), Some(dec!(1)));
+        assert!(previous > dec!(0.9));
+        assert_eq!(log.get_snapshot().trade_imbalance_ema, Some(previous));
+    }
+
+    #[test]
+    fn test_seller_maker_semantics_flip_the_imbalance() {
+        let trades = [(dec!(3), false), (dec!(1), true)];
+        let mut binance = TradesLog::new(10);
+        let mut flipped = TradesLog::new(10).with_maker_semantics(MakerSemantics::SellerMaker);
+        for (quantity, is_buyer_maker) in trades {
+            binance.insert_trade(create_test_trade(dec!(100), quantity, is_buyer_maker));
+            flipped.insert_trade(create_test_trade(dec!(100), quantity, is_buyer_maker));
+        }
+
+        // 3 of 4 bought by takers, or sold: the imbalance mirrors around one half
+        assert_eq!(binance.trade_imbalance(), Some(dec!(0.75)));
+        assert_eq!(flipped.trade_imbalance(), Some(dec!(0.25)));
+        assert_eq!(binance.aggressor_volume_ratio(2).unwrap(), dec!(0.75));
+        assert_eq!(flipped.aggressor_volume_ratio(2).unwrap(), dec!(0.25));
+        assert_eq!(binance.signed_count_momentum(), 0);
+        assert!(flipped.check_invariants().is_ok());
+
+        // Evicting under flipped semantics unwinds the same side it was counted on
+        flipped.set_max_len(1).unwrap();
+        assert_eq!(flipped.trade_imbalance(), Some(dec!(1)));
+        assert_eq!(flipped.signed_count_momentum(), 1);
+        assert!(flipped.check_invariants().is_ok());
+    }
+
+    #[test]
+    fn test_size_distribution_splits_volume_by_trade_size() {
+        let mut log = TradesLog::new(10);
+        let thresholds = [dec!(0.01), dec!(0.1), dec!(1)];
+        assert!(matches!(log.size_distribution(4, &thresholds), Err(TradesLogError::InsufficientTrades)));
+
+        // Retail clicks, one mid-sized order and a block; bounds belong to the bucket above
+        for quantity in [dec!(0.005), dec!(0.005), dec!(0.09), dec!(0.1), dec!(1.0), dec!(3.8)] {
+            log.insert_trade(create_test_trade(dec!(100), quantity, false));
+        }
+        assert_eq!(
+            log.size_distribution(6, &thresholds).unwrap(),
+            vec![dec!(0.002), dec!(0.018), dec!(0.02), dec!(0.96)]
+        );
+        // Only the window counts
+        assert_eq!(
+            log.size_distribution(2, &thresholds).unwrap(),
+            vec![dec!(0), dec!(0), dec!(0), dec!(1)]
+        );
+        assert_eq!(log.size_distribution(3, &[]).unwrap(), vec![dec!(1)]);
     }
 
     #[test]
@@ -528,6 +1088,84 @@ mod tests {
         assert_eq!(log.signed_count_momentum(), 1); // Evicted buy (-1), added buy (+1)
     }
 
+    #[test]
+    fn test_set_max_len_shrink() {
+        let mut log = TradesLog::new(100);
+        log.insert_trade(create_test_trade(dec!(100), dec!(1), false));
+        log.insert_trade(create_test_trade(dec!(101), dec!(2), true));
+        log.insert_trade(create_test_trade(dec!(102), dec!(3), false));
+        log.insert_trade(create_test_trade(dec!(103), dec!(4), true));
+        log.insert_trade(create_test_trade(dec!(104), dec!(5), false));
+
+        log.set_max_len(2).unwrap();
+
+        // Survivors: sell 4 @ 103, buy 5 @ 104
+        assert_eq!(log.max_len(), 2);
+        assert_eq!(log.trades.len(), 2);
+        assert_eq!(log.trade_count, 2);
+        assert_eq!(log.buy_volume, dec!(5));
+        assert_eq!(log.sell_volume, dec!(4));
+        assert_eq!(log.signed_count_momentum(), 0);
+        assert_eq!(log.avg_trade_size(), Some(dec!(4.5)));
+
+        // Further inserts respect the new bound
+        log.insert_trade(create_test_trade(dec!(105), dec!(1), false));
+        assert_eq!(log.trades.len(), 2);
+        assert_eq!(log.sell_volume, dec!(0));
+        assert_eq!(log.signed_count_momentum(), 2);
+    }
+
+    #[test]
+    fn test_set_max_len_grow() {
+        let mut log = TradesLog::new(2);
+        log.insert_trade(create_test_trade(dec!(100), dec!(1), false));
+        log.insert_trade(create_test_trade(dec!(101), dec!(1), false));
+
+        log.set_max_len(4).unwrap();
+        log.insert_trade(create_test_trade(dec!(102), dec!(1), true));
+
+        assert_eq!(log.trades.len(), 3);
+        assert_eq!(log.buy_volume, dec!(2));
+        assert_eq!(log.sell_volume, dec!(1));
+    }
+
+    #[test]
+    fn test_set_max_len_rejects_zero() {
+        let mut log = TradesLog::new(2);
+        log.insert_trade(create_test_trade(dec!(100), dec!(1), false));
+
+        assert!(matches!(log.set_max_len(0), Err(TradesLogError::InvalidWindowSize)));
+        assert_eq!(log.max_len(), 2);
+        assert_eq!(log.trades.len(), 1);
+
+        // Still inserts without anything to evict into
+        log.insert_trade(create_test_trade(dec!(101), dec!(1), true));
+        assert_eq!(log.trades.len(), 2);
+    }
+
+    #[test]
+    fn test_momentum_invariant_randomized() {
+        // Small LCG so the sequences are reproducible without extra dependencies
+        let mut state: u64 = 0x2545_F491_4F6C_DD1D;
+        let mut next = || {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            state >> 33
+        };
+
+        for max_len in 1..=5 {
+            let mut log = TradesLog::new(max_len);
+            for step in 0..500 {
+                let is_buyer_maker = next() % 2 == 0;
+                let quantity = Decimal::from(next() % 10);
+                log.insert_trade(create_test_trade(dec!(100), quantity, is_buyer_maker));
+
+                if let Err(e) = log.check_invariants() {
+                    panic!("max_len {} step {}: {}", max_len, step, e);
+                }
+            }
+        }
+    }
+
     #[test] 
     fn test_aggressor_ratio_edge_cases() {
         let mut log = TradesLog::new(10);
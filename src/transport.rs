@@ -0,0 +1,687 @@
+use crate::connector::{ConnectorFsm, ConnectorState};
+use crate::heartbeat::{Heartbeat, HeartbeatAction, KeepAliveConfig};
+use crate::proxy::{self, ProxyConfig};
+use crate::quarantine::{Quarantine, MAX_QUARANTINED_PAYLOAD};
+use crate::reconnect::{Backoff, FailureEscalation, ReconnectError, ReconnectPolicy};
+use crate::tape::TapeRecorder;
+use crate::tls::TlsConfig;
+use crate::subscription::{next_request, ControlFrame, PendingAcks, SubscriptionError, SubscriptionRequest};
+use futures_util::stream::{SplitSink, SplitStream};
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, info, warn};
+use std::fmt;
+use std::io;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, sleep_until, Instant};
+use tokio_tungstenite::{
+    client_async_tls_with_config, client_async_with_config, connect_async,
+    tungstenite::{client::IntoClientRequest, http::HeaderMap, protocol::Message, Error as WsError},
+    MaybeTlsStream, WebSocketStream,
+};
+
+pub type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Gauge set to 1 for the endpoint a transport is connected through, 0 for its others.
+const ACTIVE_ENDPOINT_GAUGE: &str = "ws_endpoint_active";
+/// Frames above this many bytes are dropped unless `with_max_message_size` says otherwise.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 1 << 20;
+
+/// Connects to one of a list of equivalent endpoints and paces reconnect attempts.
+///
+/// Every failed attempt or dropped connection moves on to the next endpoint. A rotation
+/// starts at the endpoint that last worked, and the reconnect backoff only kicks in once
+/// a whole rotation has failed.
+#[derive(Debug)]
+pub struct Transport {
+    endpoints: Vec<String>,
+    active: usize,
+    last_good: usize,
+    /// Endpoints tried since the current rotation started.
+    tried: usize,
+    /// Connects that failed since the last one that didn't.
+    failures: usize,
+    /// Connects that failed since the last connection that stayed up past `stable_after`.
+    unstable_failures: usize,
+    escalation: Option<FailureEscalation>,
+    keepalive: KeepAliveConfig,
+    backoff: Backoff,
+    connected_at: Option<Instant>,
+    tape: Option<TapeRecorder>,
+    proxy: Option<ProxyConfig>,
+    tls: Option<TlsConfig>,
+    headers: HeaderMap,
+    connector: ConnectorFsm,
+    max_message_size: usize,
+    quarantine: Option<Quarantine>,
+}
+
+impl Transport {
+    pub fn new(uri: String, keepalive: KeepAliveConfig) -> Self {
+        Self::with_policy(uri, keepalive, ReconnectPolicy::default())
+    }
+
+    pub fn with_policy(uri: String, keepalive: KeepAliveConfig, policy: ReconnectPolicy) -> Self {
+        Self::with_endpoints(vec![uri], keepalive, policy)
+    }
+
+    /// Rotates through `endpoints`, the first being the preferred one.
+    pub fn with_endpoints(endpoints: Vec<String>, keepalive: KeepAliveConfig, policy: ReconnectPolicy) -> Self {
+        assert!(!endpoints.is_empty(), "a transport needs at least one endpoint");
+        Self {
+            endpoints,
+            active: 0,
+            last_good: 0,
+            tried: 0,
+            failures: 0,
+            unstable_failures: 0,
+            escalation: None,
+            keepalive,
+            backoff: Backoff::new(policy),
+            connected_at: None,
+            tape: None,
+            proxy: None,
+            tls: None,
+            headers: HeaderMap::new(),
+            connector: ConnectorFsm::new(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            quarantine: None,
+        }
+    }
+
+    /// Records every text frame received on connections made from here on.
+    pub fn with_tape(mut self, tape: TapeRecorder) -> Self {
+        self.tape = Some(tape);
+        self
+    }
+
+    /// Tunnels every connection through `proxy` before the TLS and WebSocket handshakes.
+    pub fn with_proxy(mut self, proxy: ProxyConfig) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    /// Runs the TLS handshake of `wss://` connections with `tls` instead of the platform defaults.
+    pub fn with_tls(mut self, tls: TlsConfig) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Sends `headers` with every handshake, on top of the WebSocket ones, e.g. an API key
+    /// for authenticated streams. Their values are marked sensitive, so `Debug` output
+    /// doesn't show them.
+    pub fn with_headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        for value in self.headers.values_mut() {
+            value.set_sensitive(true);
+        }
+        self
+    }
+
+    /// Publishes this transport's connection state through `connector`.
+    pub fn with_connector(mut self, connector: ConnectorFsm) -> Self {
+        self.connector = connector;
+        self
+    }
+
+    /// Drops, counts and quarantines data frames larger than `bytes`.
+    pub fn with_max_message_size(mut self, bytes: usize) -> Self {
+        self.max_message_size = bytes;
+        self
+    }
+
+    /// Keeps oversized and undecodable frames in `quarantine`, and lets feeds put the
+    /// payloads they fail to parse there through `FeedConnection::quarantine`.
+    pub fn with_quarantine(mut self, quarantine: Quarantine) -> Self {
+        self.quarantine = Some(quarantine);
+        self
+    }
+
+    /// Reports through `escalation` when connects keep failing; unlike
+    /// `max_consecutive_failures`, connections that come up and drop straight away don't
+    /// start the count over.
+    pub fn with_escalation(mut self, escalation: FailureEscalation) -> Self {
+        self.escalation = Some(escalation);
+        self
+    }
+
+    pub fn connector(&self) -> &ConnectorFsm {
+        &self.connector
+    }
+
+    /// Endpoint the current or next connection goes to.
+    pub fn uri(&self) -> &str {
+        &self.endpoints[self.active]
+    }
+
+    /// Opens a connection; subscription requests are only served if `requests` is given.
+    pub async fn connect<'a>(
+        &mut self,
+        requests: Option<&'a mut mpsc::UnboundedReceiver<SubscriptionRequest>>,
+    ) -> Result<FeedConnection<'a>, WsError> {
+        let uri = self.uri().to_string();
+        self.connector.transition(ConnectorState::Connecting);
+        let ws_stream = match self.open(&uri).await {
+            Ok(ws_stream) => ws_stream,
+            Err(e) => {
+                self.failures += 1;
+                self.unstable_failures += 1;
+                if let Some(escalation) = &self.escalation {
+                    escalation.on_failure(&uri, self.unstable_failures, &e);
+                }
+                self.connector.transition(ConnectorState::Idle);
+                return Err(e);
+            }
+        };
+        self.failures = 0;
+        self.connected_at = Some(Instant::now());
+        self.on_connected();
+        Ok(self.feed_connection(uri, ws_stream, requests, true))
+    }
+
+    /// Opens a second connection to the current endpoint while one is still live, to roll
+    /// over to with `FeedConnection::take_over`. The feed never went down, so connection
+    /// state is left alone, and a failure here doesn't count towards giving up.
+    pub async fn connect_alongside<'a>(&mut self) -> Result<FeedConnection<'a>, WsError> {
+        let uri = self.uri().to_string();
+        let ws_stream = self.open(&uri).await?;
+        Ok(self.feed_connection(uri, ws_stream, None, false))
+    }
+
+    fn feed_connection<'a>(
+        &self,
+        uri: String,
+        ws_stream: WsStream,
+        requests: Option<&'a mut mpsc::UnboundedReceiver<SubscriptionRequest>>,
+        current: bool,
+    ) -> FeedConnection<'a> {
+        let (write, read) = ws_stream.split();
+        FeedConnection {
+            uri,
+            write,
+            read,
+            heartbeat: Heartbeat::new(self.keepalive, Instant::now()),
+            pending_acks: PendingAcks::new(),
+            requests,
+            tape: self.tape.clone(),
+            connector: self.connector.clone(),
+            max_message_size: self.max_message_size,
+            quarantine: self.quarantine.clone(),
+            current,
+        }
+    }
+
+    async fn open(&self, uri: &str) -> Result<WsStream, WsError> {
+        let mut request = uri.into_client_request()?;
+        request.headers_mut().extend(self.headers.clone());
+        let tls = self.tls.as_ref().filter(|_| uri.starts_with("wss://"));
+        if self.proxy.is_none() && tls.is_none() {
+            return Ok(connect_async(request).await?.0);
+        }
+        let (host, port) = proxy::target_addr(uri).map_err(|e| WsError::Io(io::Error::other(e)))?;
+        let stream = match &self.proxy {
+            Some(proxy) => proxy
+                .tunnel(uri)
+                .await
+                .map_err(|e| WsError::Io(io::Error::other(e)))?,
+            None => TcpStream::connect((host.as_str(), port)).await?,
+        };
+        let (ws_stream, _) = match tls {
+            Some(tls) => {
+                let stream = tls
+                    .handshake(&host, stream)
+                    .await
+                    .map_err(|e| WsError::Io(io::Error::other(e)))?;
+                client_async_with_config(request, MaybeTlsStream::NativeTls(stream), None).await?
+            }
+            None => client_async_tls_with_config(request, stream, None, None).await?,
+        };
+        Ok(ws_stream)
+    }
+
+    fn on_connected(&mut self) {
+        if self.endpoints.len() > 1 {
+            info!("Endpoint {}/{} active: {}", self.active + 1, self.endpoints.len(), self.uri());
+        }
+        self.last_good = self.active;
+        self.tried = 0;
+        self.connector.transition(ConnectorState::Connected);
+        for (i, endpoint) in self.endpoints.iter().enumerate() {
+            let active = if i == self.active { 1.0 } else { 0.0 };
+            metrics::gauge!(ACTIVE_ENDPOINT_GAUGE, active, "endpoint" => endpoint.clone());
+        }
+    }
+
+    /// Moves on to the next endpoint, waiting first if that completes a rotation; fails,
+    /// leaving the connector in `Error`, once the policy's attempts or consecutive failed
+    /// connects are used up.
+    pub async fn backoff(&mut self) -> Result<(), ReconnectError> {
+        if let Some(connected_at) = self.connected_at.take() {
+            let uptime = connected_at.elapsed();
+            if uptime >= self.backoff.policy().stable_after {
+                self.unstable_failures = 0;
+            }
+            self.backoff.on_disconnect(uptime);
+        }
+        if matches!(self.backoff.policy().max_consecutive_failures, Some(max) if self.failures >= max) {
+            return Err(self.give_up(ReconnectError::TooManyFailures {
+                uri: self.uri().to_string(),
+                failures: self.failures,
+            }));
+        }
+        self.tried += 1;
+        if self.tried < self.endpoints.len() {
+            let failed = self.active;
+            self.active = (self.active + 1) % self.endpoints.len();
+            warn!("Rotating from {} to {}", self.endpoints[failed], self.uri());
+            return Ok(());
+        }
+
+        self.tried = 0;
+        self.active = self.last_good;
+        let delay = match self.backoff.next_delay() {
+            Some(delay) => delay,
+            None => {
+                return Err(self.give_up(ReconnectError::GaveUp {
+                    uri: self.uri().to_string(),
+                    attempts: self.backoff.attempts(),
+                }))
+            }
+        };
+        warn!("Reconnecting to {} in {:?}...", self.uri(), delay);
+        self.connector.transition(ConnectorState::Backoff);
+        sleep(delay).await;
+        Ok(())
+    }
+
+    fn give_up(&self, error: ReconnectError) -> ReconnectError {
+        self.connector.transition(ConnectorState::Error);
+        error
+    }
+}
+
+impl Drop for Transport {
+    // Dropped once its feed stops, whatever it was doing at the time; a transport that
+    // gave up leaves that for watchers to see
+    fn drop(&mut self) {
+        if self.connector.state() != ConnectorState::Error {
+            self.connector.transition(ConnectorState::Idle);
+        }
+    }
+}
+
+/// A data payload, in the frame type the exchange sent it as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Frame {
+    Text(String),
+    /// Passed to `ExchangeAdapter::parse_binary`; by default that reads it as UTF-8 text.
+    Binary(Vec<u8>),
+}
+
+/// A live connection that surfaces data payloads and handles everything else itself:
+/// answering pings, idle keepalive, and subscription requests and their acks.
+pub struct FeedConnection<'a> {
+    uri: String,
+    write: SplitSink<WsStream, Message>,
+    read: SplitStream<WsStream>,
+    heartbeat: Heartbeat,
+    pending_acks: PendingAcks,
+    requests: Option<&'a mut mpsc::UnboundedReceiver<SubscriptionRequest>>,
+    tape: Option<TapeRecorder>,
+    connector: ConnectorFsm,
+    max_message_size: usize,
+    quarantine: Option<Quarantine>,
+    /// Whether this is the connection the connector reports on; one opened alongside it
+    /// only becomes so once it takes over.
+    current: bool,
+}
+
+impl<'a> FeedConnection<'a> {
+    /// Makes this connection, opened with `Transport::connect_alongside`, the current one:
+    /// subscription requests move over to it, then `previous` is closed.
+    pub async fn take_over(&mut self, mut previous: FeedConnection<'a>) {
+        self.requests = previous.requests.take();
+        self.current = true;
+        previous.current = false;
+        previous.close().await;
+    }
+}
+
+impl FeedConnection<'_> {
+    /// Next text payload, or `Ok(None)` once the connection closed or stopped responding.
+    /// Binary frames are read as UTF-8 text; any that aren't are dropped.
+    pub async fn next_text(&mut self) -> Result<Option<String>, WsError> {
+        loop {
+            match self.next_frame().await? {
+                Some(Frame::Text(text)) => return Ok(Some(text)),
+                Some(Frame::Binary(bin)) => match String::from_utf8(bin) {
+                    Ok(text) => return Ok(Some(text)),
+                    Err(e) => {
+                        warn!("Dropping non UTF-8 binary frame from {}", self.uri);
+                        self.quarantine(&String::from_utf8_lossy(e.as_bytes()), &e.utf8_error());
+                    }
+                },
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// Next data payload as the exchange framed it, or `Ok(None)` once the connection
+    /// closed or stopped responding. Binary frames go on the tape only if they are UTF-8.
+    pub async fn next_frame(&mut self) -> Result<Option<Frame>, WsError> {
+        loop {
+            let deadline = self.heartbeat.deadline();
+            tokio::select! {
+                msg = self.read.next() => {
+                    let msg = match msg {
+                        Some(msg) => msg?,
+                        None => return Ok(None),
+                    };
+                    self.heartbeat.on_message(Instant::now());
+
+                    match msg {
+                        Message::Text(_) | Message::Binary(_) if msg.len() > self.max_message_size => {
+                            self.reject_oversized(&msg);
+                        }
+                        Message::Text(text) => {
+                            self.record(&text);
+                            match self.pending_acks.on_text(&text) {
+                                Some(frame) => self.on_control_frame(frame),
+                                None => return Ok(Some(Frame::Text(text))),
+                            }
+                        }
+                        Message::Binary(bin) => {
+                            if let Ok(text) = std::str::from_utf8(&bin) {
+                                self.record(text);
+                            }
+                            return Ok(Some(Frame::Binary(bin)));
+                        }
+                        Message::Ping(payload) => self.write.send(Message::Pong(payload)).await?,
+                        _ => {}
+                    }
+                }
+                request = next_request(self.requests.as_deref_mut()) => {
+                    let id = request.id;
+                    let frame = self.pending_acks.register(request);
+                    if let Err(e) = self.write.send(Message::Text(frame)).await {
+                        self.pending_acks.fail(id, SubscriptionError::ConnectionClosed);
+                        return Err(e);
+                    }
+                }
+                _ = sleep_until(deadline) => {
+                    match self.heartbeat.on_deadline(Instant::now()) {
+                        HeartbeatAction::SendPing => {
+                            debug!("Stream idle, pinging {}", self.uri);
+                            self.write.send(Message::Ping(Vec::new())).await?;
+                        }
+                        HeartbeatAction::Reconnect => {
+                            warn!("No response from {} after ping, forcing reconnect", self.uri);
+                            return Ok(None);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn record(&self, text: &str) {
+        if let Some(tape) = &self.tape {
+            tape.record(text);
+        }
+    }
+
+    fn on_control_frame(&self, frame: ControlFrame) {
+        match frame {
+            ControlFrame::Ack { id } => debug!("Request {} acknowledged on {}", id, self.uri),
+            ControlFrame::UnknownAck { id } => debug!("Ignoring ack for unknown request {} on {}", id, self.uri),
+            ControlFrame::Error(e) => {
+                metrics::increment_counter!("ws_exchange_errors");
+                warn!("{} on {}", e, self.uri);
+            }
+        }
+    }
+
+    fn reject_oversized(&self, msg: &Message) {
+        metrics::increment_counter!("ws_oversized_messages");
+        warn!(
+            "Dropping {} byte frame from {}, over the {} byte limit",
+            msg.len(),
+            self.uri,
+            self.max_message_size
+        );
+        let data: &[u8] = match msg {
+            Message::Text(text) => text.as_bytes(),
+            Message::Binary(bin) => bin,
+            _ => &[],
+        };
+        let prefix = String::from_utf8_lossy(&data[..data.len().min(MAX_QUARANTINED_PAYLOAD)]);
+        let error = format!("{} byte frame over the {} byte limit", msg.len(), self.max_message_size);
+        self.quarantine(&prefix, &error);
+    }
+
+    /// Keeps a payload the feed could not use, with the reason, if a quarantine is set.
+    pub fn quarantine(&self, payload: &str, error: &dyn fmt::Display) {
+        if let Some(quarantine) = &self.quarantine {
+            quarantine.record(payload, error);
+        }
+    }
+
+    /// Sends an application frame, e.g. an exchange specific subscribe message.
+    pub async fn send_text(&mut self, text: String) -> Result<(), WsError> {
+        self.write.send(Message::Text(text)).await
+    }
+
+    /// Sends a close frame so the exchange sees a clean disconnect rather than a dropped socket.
+    pub async fn close(&mut self) {
+        if let Err(e) = self.write.close().await {
+            debug!("Failed to close connection to {}: {}", self.uri, e);
+        }
+    }
+}
+
+impl Drop for FeedConnection<'_> {
+    fn drop(&mut self) {
+        if self.current {
+            self.connector.transition(ConnectorState::Idle);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+    use tokio::time::{timeout, Duration};
+    use ConnectorState::*;
+
+    #[tokio::test]
+    async fn test_connector_follows_the_connection_lifecycle() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}", listener.local_addr().unwrap());
+        let (release_tx, release_rx) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            // Hold the handshake until the test has seen the attempt in flight
+            let _ = release_rx.await;
+            let _ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            sleep(Duration::from_secs(5)).await;
+        });
+
+        let policy = ReconnectPolicy {
+            initial: Duration::from_secs(10),
+            jitter_fraction: 0.0,
+            ..ReconnectPolicy::default()
+        };
+        let mut transport = Transport::with_policy(uri, KeepAliveConfig::default(), policy);
+        let connector = transport.connector().clone();
+        let mut seen = vec![connector.state()];
+
+        let (conn, _) = tokio::join!(transport.connect(None), async {
+            let mut states = connector.subscribe();
+            states.wait_for(|state| *state == Connecting).await.unwrap();
+            seen.push(Connecting);
+            release_tx.send(()).unwrap();
+        });
+        let conn = conn.unwrap();
+        seen.push(connector.state());
+
+        drop(conn);
+        seen.push(connector.state());
+
+        assert!(timeout(Duration::from_millis(50), transport.backoff()).await.is_err());
+        seen.push(connector.state());
+
+        drop(transport);
+        seen.push(connector.state());
+
+        assert_eq!(seen, vec![Idle, Connecting, Connected, Idle, Backoff, Idle]);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_and_undecodable_frames_are_quarantined() {
+        use crate::quarantine::{quarantine_file_name, read_quarantine};
+        use crate::clock::ManualClock;
+        use std::sync::Arc;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            ws.send(Message::Text("x".repeat(100))).await.unwrap();
+            ws.send(Message::Binary(vec![b'{', 0xff, b'}'])).await.unwrap();
+            ws.send(Message::Text("fits".to_string())).await.unwrap();
+            sleep(Duration::from_secs(5)).await;
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let quarantine = Quarantine::with_clock(dir.path(), 10, Arc::new(ManualClock::new(0)));
+        let mut transport = Transport::new(uri, KeepAliveConfig::default())
+            .with_max_message_size(64)
+            .with_quarantine(quarantine);
+        let mut conn = transport.connect(None).await.unwrap();
+        let text = timeout(Duration::from_secs(1), conn.next_text()).await.unwrap().unwrap();
+        assert_eq!(text.as_deref(), Some("fits"));
+
+        let entries = read_quarantine(dir.path().join(quarantine_file_name(0))).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].error, "100 byte frame over the 64 byte limit");
+        assert_eq!(entries[0].payload, "x".repeat(100));
+        assert!(entries[1].error.contains("invalid utf-8"), "{}", entries[1].error);
+        assert_eq!(entries[1].payload, "{\u{fffd}}");
+    }
+
+    #[tokio::test]
+    async fn test_control_frames_never_reach_the_feed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            for frame in [
+                r#"{"result":null,"id":42}"#,
+                r#"{"error":{"code":2,"msg":"Invalid request: unknown variant"},"id":null}"#,
+                r#"{"e":"trade","E":1,"s":"BTCUSDT"}"#,
+            ] {
+                ws.send(Message::Text(frame.to_string())).await.unwrap();
+            }
+            sleep(Duration::from_secs(5)).await;
+        });
+
+        let mut transport = Transport::new(uri, KeepAliveConfig::default());
+        let mut conn = transport.connect(None).await.unwrap();
+        let text = timeout(Duration::from_secs(1), conn.next_text()).await.unwrap().unwrap();
+        assert_eq!(text.as_deref(), Some(r#"{"e":"trade","E":1,"s":"BTCUSDT"}"#));
+    }
+
+    #[tokio::test]
+    #[allow(clippy::result_large_err)] // The handshake callback's signature is tungstenite's
+    async fn test_headers_go_with_every_endpoint_handshake() {
+        use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+
+        // Nothing listens on the first endpoint, so the transport rotates to the second
+        let refused = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let dead = format!("ws://{}", refused.local_addr().unwrap());
+        drop(refused);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live = format!("ws://{}", listener.local_addr().unwrap());
+        let (headers_tx, headers_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let _ws = tokio_tungstenite::accept_hdr_async(tcp, |request: &Request, response: Response| {
+                let _ = headers_tx.send(request.headers().clone());
+                Ok(response)
+            })
+            .await
+            .unwrap();
+            sleep(Duration::from_secs(5)).await;
+        });
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-mbx-apikey", "secret-key".parse().unwrap());
+        let mut transport =
+            Transport::with_endpoints(vec![dead, live], KeepAliveConfig::default(), ReconnectPolicy::default())
+                .with_headers(headers);
+        assert!(transport.connect(None).await.is_err());
+        transport.backoff().await.unwrap();
+        let _conn = transport.connect(None).await.unwrap();
+
+        let received = timeout(Duration::from_secs(1), headers_rx).await.unwrap().unwrap();
+        assert_eq!(received["x-mbx-apikey"], "secret-key");
+        // The WebSocket handshake headers are still there
+        assert_eq!(received["upgrade"], "websocket");
+        assert!(!format!("{:?}", transport).contains("secret-key"));
+    }
+
+    #[tokio::test]
+    async fn test_escalates_once_per_run_of_failures_until_a_stable_connection() {
+        use crate::reconnect::{FailureEscalation, PersistentFailure};
+
+        // Hangs up on every handshake but the fifth, which it serves for 100ms
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let uri = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            for accepted in 1.. {
+                let (tcp, _) = listener.accept().await.unwrap();
+                if accepted == 5 {
+                    let _ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                    sleep(Duration::from_millis(100)).await;
+                }
+            }
+        });
+
+        let policy = ReconnectPolicy {
+            initial: Duration::from_millis(1),
+            max: Duration::from_millis(1),
+            jitter_fraction: 0.0,
+            stable_after: Duration::from_millis(50),
+            ..ReconnectPolicy::default()
+        };
+        let (escalations_tx, mut escalations) = mpsc::unbounded_channel();
+        let mut transport = Transport::with_policy(uri.clone(), KeepAliveConfig::default(), policy)
+            .with_escalation(FailureEscalation::new(2, escalations_tx));
+        let mut fired = Vec::new();
+        for _ in 0..4 {
+            assert!(transport.connect(None).await.is_err());
+            transport.backoff().await.unwrap();
+            fired.push(escalations.try_recv().is_ok());
+        }
+        // Only reaching the threshold fires, not every failure past it
+        assert_eq!(fired, vec![false, true, false, false]);
+
+        let mut conn = transport.connect(None).await.unwrap();
+        assert_eq!(timeout(Duration::from_secs(1), conn.next_text()).await.unwrap().ok().flatten(), None);
+        drop(conn);
+        transport.backoff().await.unwrap();
+
+        for _ in 0..2 {
+            assert!(transport.connect(None).await.is_err());
+            transport.backoff().await.unwrap();
+        }
+        let again = escalations.try_recv().unwrap();
+        assert_eq!((again.uri.as_str(), again.failures), (uri.as_str(), 2));
+        assert!(!again.error.is_empty());
+        assert_eq!(escalations.try_recv().ok(), None::<PersistentFailure>);
+    }
+}
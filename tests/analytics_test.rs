@@ -1,5 +1,5 @@
 use ingestor::{
-    analytics::run_analytics_task,
+    analytics::{run_analytics_task, AnalyticsConfig},
     orderbook::ConcurrentOrderBook,
     tradeslog::{ConcurrentTradesLog, Trade},
 };
@@ -19,11 +19,20 @@ async fn test_full_analytics_pipeline() {
         quantity: dec!(2.0),
         timestamp: 1000,
         is_buyer_maker: false,
+        symbol: None,
+        trade_id: None,
+        aggregated_trade_ids: None,
     }).await;
 
+    let dir = tempfile::tempdir().unwrap();
+    let config = AnalyticsConfig {
+        output_dir: dir.path().to_path_buf(),
+        ..AnalyticsConfig::default()
+    };
     let handle = tokio::spawn(run_analytics_task(
         order_book,
         trades_log.clone(),
+        config,
         shutdown_rx,
     ));
 
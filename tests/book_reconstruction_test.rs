@@ -0,0 +1,63 @@
+mod common;
+
+use common::{fixture, load_session, rebuild_book, ExpectedBook};
+use ingestor::{
+    orderbook::ConcurrentOrderBook,
+    replay::{ReplayFeedManager, ReplaySpeed},
+    tradeslog::ConcurrentTradesLog,
+};
+use rust_decimal_macros::dec;
+
+const SESSION: &str = "book_session.ndjson";
+
+/// State after the captured BTCUSDT session: a snapshot at update 1000, then five diffs
+/// that update, add and remove levels on both sides. A diff recorded before the snapshot
+/// would have set 69420.00 to 9.9 had it not been dropped.
+fn expected_book() -> ExpectedBook {
+    ExpectedBook {
+        bids: vec![
+            (dec!(69420.00), dec!(1.200)),
+            (dec!(69419.50), dec!(0.800)),
+            (dec!(69418.00), dec!(3.000)),
+            (dec!(69416.00), dec!(0.900)),
+            (dec!(69415.00), dec!(5.250)),
+        ],
+        asks: vec![
+            (dec!(69420.75), dec!(0.200)),
+            (dec!(69421.50), dec!(0.750)),
+            (dec!(69422.00), dec!(0.500)),
+            (dec!(69423.00), dec!(2.400)),
+            (dec!(69425.00), dec!(4.000)),
+        ],
+    }
+}
+
+#[tokio::test]
+async fn test_session_rebuilds_the_verified_book() {
+    let session = load_session(fixture(SESSION));
+    assert_eq!(session.len(), 7);
+
+    let book = rebuild_book(&session).await;
+    expected_book().assert_matches(&book).await;
+    assert_eq!(book.spread().await, Some(dec!(0.75)));
+    assert_eq!(book.mid_price().await, Some(dec!(69420.375)));
+    // Nothing beyond the five levels each side
+    assert_eq!(book.top_bids(10).await.len(), 5);
+    assert_eq!(book.top_asks(10).await.len(), 5);
+}
+
+#[tokio::test]
+async fn test_replay_reaches_the_same_book() {
+    let order_book = ConcurrentOrderBook::new();
+    let replay = ReplayFeedManager::new(
+        fixture(SESSION),
+        order_book.clone(),
+        ConcurrentTradesLog::new(10),
+        ReplaySpeed::AsFastAsPossible,
+    );
+
+    let stats = replay.run().await.unwrap();
+    assert_eq!(stats.depth_events, 7);
+    assert_eq!(stats.resyncs, 0);
+    expected_book().assert_matches(&order_book).await;
+}
@@ -63,4 +63,31 @@ async fn test_flow_tracker_threadsafe() {
         "Cancel should reduce imbalance, got {}",
         imbalance_value
     );
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn test_batch_matches_sequential_application() {
+    let diffs = vec![
+        (vec![(dec!(100.0), dec!(1.0)), (dec!(99.5), dec!(2.0))], vec![(dec!(101.0), dec!(1.5))]),
+        (vec![(dec!(100.0), dec!(0.0))], vec![(dec!(101.5), dec!(3.0))]),
+        (vec![(dec!(100.2), dec!(0.7))], vec![(dec!(101.0), dec!(0.0)), (dec!(100.8), dec!(1.0))]),
+        (vec![(dec!(98.0), dec!(5.0))], vec![]),
+    ];
+
+    let sequential = ConcurrentOrderBook::new();
+    for (bids, asks) in diffs.clone() {
+        sequential.apply_deltas(bids, asks).await;
+    }
+
+    let batched = ConcurrentOrderBook::new();
+    batched.apply_delta_batch(diffs).await;
+
+    assert_eq!(batched.top_bids(10).await, sequential.top_bids(10).await);
+    assert_eq!(batched.top_asks(10).await, sequential.top_asks(10).await);
+    assert_eq!(batched.best_bid().await, Some((dec!(100.2), dec!(0.7))));
+    assert_eq!(batched.best_ask().await, Some((dec!(100.8), dec!(1.0))));
+
+    let (_, sequential_pressure) = sequential.get_flow_imbalance().await;
+    let (_, batched_pressure) = batched.get_flow_imbalance().await;
+    assert!((sequential_pressure - batched_pressure).abs() < dec!(0.01));
+}
@@ -0,0 +1,144 @@
+use futures_util::{SinkExt, StreamExt};
+use ingestor::{
+    connector::ConnectorState,
+    feed_handles::FeedHandles,
+    lob_feed_manager::LobFeedManager,
+    log_feed_manager::LogFeedManager,
+    stream_config::{DepthMode, StreamConfig},
+    tradeslog::ConcurrentTradesLog,
+};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{sleep, timeout, Duration};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+#[derive(Debug, PartialEq)]
+enum ServerEvent {
+    Accepted,
+    Closed,
+}
+
+/// WebSocket server sending `frame` on every connection it accepts, reporting each
+/// connection and each close frame it gets.
+fn feed_server(listener: TcpListener, frame: &'static str) -> mpsc::UnboundedReceiver<ServerEvent> {
+    let (events_tx, events_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((tcp, _)) = listener.accept().await {
+            let events_tx = events_tx.clone();
+            tokio::spawn(async move {
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                let _ = events_tx.send(ServerEvent::Accepted);
+                let _ = ws.send(Message::Text(frame.to_string())).await;
+                while let Some(Ok(msg)) = ws.next().await {
+                    if let Message::Close(_) = msg {
+                        let _ = events_tx.send(ServerEvent::Closed);
+                        break;
+                    }
+                }
+            });
+        }
+    });
+    events_rx
+}
+
+async fn next_event(events: &mut mpsc::UnboundedReceiver<ServerEvent>) -> ServerEvent {
+    timeout(Duration::from_secs(2), events.recv()).await.expect("Timed out waiting for the server").unwrap()
+}
+
+async fn wait_for_state(mut states: watch::Receiver<ConnectorState>, state: ConnectorState) {
+    timeout(Duration::from_secs(2), async {
+        while *states.borrow_and_update() != state {
+            states.changed().await.unwrap();
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("Timed out waiting for {:?}", state));
+}
+
+/// Stops the feeds and checks nothing of them is left running: the connection was
+/// closed, every task returned, and no reconnect follows.
+async fn stop_and_check(mut feeds: FeedHandles, events: &mut mpsc::UnboundedReceiver<ServerEvent>) {
+    assert!(!feeds.is_finished());
+    feeds.stop();
+    let stopped = timeout(Duration::from_secs(1), feeds.join()).await.expect("feeds did not stop");
+    assert_eq!(stopped, Ok(()));
+    assert!(feeds.tasks().is_empty());
+    assert_eq!(next_event(events).await, ServerEvent::Closed);
+
+    sleep(Duration::from_millis(200)).await;
+    assert!(events.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn test_order_book_feed_stops_and_restarts() {
+    const PARTIAL_BOOK: &str = r#"{"lastUpdateId":1000,"bids":[["69420.00","1.500"]],"asks":[["69420.50","0.600"]]}"#;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut config = StreamConfig::new("btcusdt").with_depth_mode(DepthMode::PartialBook(5));
+    config.base_url = format!("ws://{}", listener.local_addr().unwrap());
+    let manager = LobFeedManager::from_config(&config);
+    let book = manager.get_order_book();
+    let mut events = feed_server(listener, PARTIAL_BOOK);
+
+    for _ in 0..2 {
+        book.clear().await;
+        let feeds = manager.spawn();
+        assert_eq!(next_event(&mut events).await, ServerEvent::Accepted);
+        wait_for_state(manager.watch_connection_state(), ConnectorState::Connected).await;
+        timeout(Duration::from_secs(2), async {
+            while book.best_bid().await.is_none() {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        stop_and_check(feeds, &mut events).await;
+        assert_eq!(manager.connection_state(), ConnectorState::Idle);
+    }
+}
+
+#[tokio::test]
+async fn test_trade_feed_stops_and_restarts() {
+    const TRADE: &str =
+        r#"{"e":"trade","E":1,"s":"BTCUSDT","t":1,"p":"69000.00","q":"0.5","T":1712745600000,"m":true}"#;
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let uri = format!("ws://{}", listener.local_addr().unwrap());
+    let trades_log = ConcurrentTradesLog::new(10);
+    let manager = Arc::new(LogFeedManager::new(uri, trades_log.clone()));
+    let mut events = feed_server(listener, TRADE);
+
+    for round in 1..=2 {
+        let feeds = manager.spawn();
+        assert_eq!(next_event(&mut events).await, ServerEvent::Accepted);
+        timeout(Duration::from_secs(2), async {
+            while trades_log.last_n_trades(10).await.len() < round {
+                sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+
+        stop_and_check(feeds, &mut events).await;
+        assert_eq!(manager.connection_state(), ConnectorState::Idle);
+    }
+    // Only the manager itself is left holding the log
+    assert_eq!(Arc::strong_count(&manager), 1);
+}
+
+#[tokio::test]
+async fn test_dropping_the_handles_stops_the_feed() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let uri = format!("ws://{}", listener.local_addr().unwrap());
+    let manager = Arc::new(LogFeedManager::new(uri, ConcurrentTradesLog::new(10)));
+    let mut events = feed_server(listener, "{}");
+
+    let feeds = manager.spawn();
+    assert_eq!(next_event(&mut events).await, ServerEvent::Accepted);
+    // Dropped mid-handshake, the feed would give up the socket without a close frame
+    wait_for_state(manager.watch_connection_state(), ConnectorState::Connected).await;
+    drop(feeds);
+    assert_eq!(next_event(&mut events).await, ServerEvent::Closed);
+}
@@ -0,0 +1,54 @@
+use ingestor::{
+    lob_feed_manager::LobFeedManager,
+    log_feed_manager::LogFeedManager,
+    metrics_server::MetricsServer,
+    stream_config::DepthMode,
+    tradeslog::ConcurrentTradesLog,
+};
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tokio::time::{timeout, Duration};
+
+async fn get(addr: SocketAddr, path: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, addr);
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    timeout(Duration::from_secs(1), stream.read_to_string(&mut response)).await.unwrap().unwrap();
+    response
+}
+
+// The recorder is process-wide, so everything that needs it lives in this one test
+#[tokio::test]
+async fn test_scrape_shows_the_feed_metrics() {
+    let server = MetricsServer::bind(SocketAddr::from(([127, 0, 0, 1], 0))).await.unwrap();
+    let addr = server.local_addr().unwrap();
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let serving = tokio::spawn(server.serve(shutdown_rx));
+
+    // Built after the recorder went in, so their metrics register with it
+    let _lob = LobFeedManager::with_depth_mode("BTCUSDT", DepthMode::DiffWithRestSnapshot);
+    let _log = LogFeedManager::new("ws://127.0.0.1:1".to_string(), ConcurrentTradesLog::new(10));
+    for _ in 0..3 {
+        metrics::increment_counter!("log_feed_messages_received");
+    }
+    metrics::gauge!("lob_feed_current_connections", 1.0);
+    metrics::histogram!("trade_process_latency_us", 250.0);
+
+    let response = get(addr, "/metrics").await;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.contains("Content-Type: text/plain; version=0.0.4"));
+    assert!(response.contains("\nlog_feed_messages_received 3\n"), "{}", response);
+    assert!(response.contains("\nlob_feed_current_connections 1\n"), "{}", response);
+    assert!(response.contains("trade_process_latency_us_count 1\n"), "{}", response);
+    // Registered by the managers but never touched
+    assert!(response.contains("\nlob_feed_sequence_gaps 0\n"), "{}", response);
+    assert!(response.contains("\nlog_feed_trade_gaps 0\n"), "{}", response);
+
+    assert!(get(addr, "/").await.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+    shutdown_tx.send(true).unwrap();
+    timeout(Duration::from_secs(1), serving).await.expect("server did not stop").unwrap();
+}
@@ -0,0 +1,381 @@
+mod support;
+
+use ingestor::{
+    connector::ConnectorState,
+    exchange::{
+        binance::{BinanceRestSnapshot, DepthUpdateEvent},
+        relay::{encode_frame, relay_levels, RelayRecord, RelayTrade},
+        RelayAdapter,
+    },
+    heartbeat::KeepAliveConfig,
+    lob_feed_manager::LobFeedManager,
+    log_feed_manager::LogFeedManager,
+    orderbook::ConcurrentOrderBook,
+    reconnect::ReconnectPolicy,
+    stream_config::{Exchange, StreamConfig},
+    tradeslog::{ConcurrentTradesLog, Trade},
+};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use support::mock_exchange::{MockExchange, ServerEvent};
+use tokio::sync::watch;
+use tokio::time::{sleep, timeout, Duration, Instant};
+
+const SNAPSHOT: &str = r#"{"type":"snapshot","product_id":"BTC-USD","bids":[["67012.45","0.50"],["67012.01","1.20"]],"asks":[["67013.10","0.005"],["67013.40","2.10"]]}"#;
+const UPDATES: [&str; 2] = [
+    r#"{"type":"l2update","product_id":"BTC-USD","changes":[["buy","67012.50","0.25"]],"time":"2024-04-10T10:40:00.100Z"}"#,
+    r#"{"type":"l2update","product_id":"BTC-USD","changes":[["sell","67013.10","0"]],"time":"2024-04-10T10:40:00.200Z"}"#,
+];
+/// The market moved on while the feed was down.
+const SNAPSHOT_AFTER_DROP: &str = r#"{"type":"snapshot","product_id":"BTC-USD","bids":[["66990.00","3.00"]],"asks":[["66991.00","1.00"]]}"#;
+
+fn trade(id: u64, price: &str) -> String {
+    format!(
+        r#"{{"e":"trade","E":1,"s":"BTCUSDT","t":{},"p":"{}","q":"0.5","T":1712745600000,"m":true}}"#,
+        id, price
+    )
+}
+
+fn relay_diff(first: u64, last: u64, bids: &[(Decimal, Decimal)], asks: &[(Decimal, Decimal)]) -> Vec<u8> {
+    encode_frame(&RelayRecord::Depth(DepthUpdateEvent {
+        event_type: "depthUpdate".to_string(),
+        event_time: 1712745600000 + last,
+        symbol: "BTCUSDT".to_string(),
+        first_update_id: first,
+        final_update_id: last,
+        bids: relay_levels(bids),
+        asks: relay_levels(asks),
+    }))
+}
+
+fn fast_reconnect() -> ReconnectPolicy {
+    ReconnectPolicy {
+        initial: Duration::from_millis(10),
+        ..ReconnectPolicy::default()
+    }
+}
+
+/// Coinbase book feed against `url`, which carries its snapshots in-band and subscribes on
+/// every connection.
+fn coinbase_book_feed(url: String) -> LobFeedManager {
+    let mut config = StreamConfig::new("BTC-USD").with_exchange(Exchange::Coinbase);
+    config.base_url = url;
+    LobFeedManager::from_config(&config).with_reconnect_policy(fast_reconnect())
+}
+
+async fn wait_for_touch(book: &ConcurrentOrderBook, bid: (Decimal, Decimal), ask: (Decimal, Decimal)) {
+    timeout(Duration::from_secs(2), async {
+        while book.best_bid().await != Some(bid) || book.best_ask().await != Some(ask) {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("Timed out waiting for {:?} / {:?} at the touch", bid, ask));
+}
+
+async fn wait_for_trades(trades_log: &ConcurrentTradesLog, count: usize) {
+    timeout(Duration::from_secs(2), async {
+        while trades_log.last_n_trades(count).await.len() < count {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("Timed out waiting for {} trades", count));
+}
+
+#[tokio::test]
+async fn test_order_book_feed_subscribes_and_applies_the_script() {
+    let mut exchange = MockExchange::new()
+        .with_session(&[SNAPSHOT, UPDATES[0], UPDATES[1]])
+        .with_pacing(Duration::from_millis(20))
+        .start()
+        .await;
+    let manager = coinbase_book_feed(exchange.url());
+    let book = manager.get_order_book();
+    let feeds = manager.spawn();
+
+    exchange.expect_connection(0).await;
+    assert_eq!(
+        exchange.expect_subscribe().await,
+        json!({"type": "subscribe", "product_ids": ["BTC-USD"], "channels": ["level2_batch"]})
+    );
+    wait_for_touch(&book, (dec!(67012.50), dec!(0.25)), (dec!(67013.40), dec!(2.10))).await;
+    assert_eq!(book.top_bids(3).await.len(), 3);
+    assert!(book.is_trusted().await);
+    assert_eq!(manager.connection_state(), ConnectorState::Connected);
+
+    assert_eq!(feeds.shutdown().await, Ok(()));
+    assert_eq!(exchange.next_event().await, ServerEvent::Closed(0));
+}
+
+#[tokio::test]
+async fn test_order_book_feed_resubscribes_and_resyncs_after_a_drop() {
+    let mut exchange = MockExchange::new()
+        .with_session(&[SNAPSHOT, UPDATES[0]])
+        .with_session(&[SNAPSHOT_AFTER_DROP])
+        .start()
+        .await;
+    let manager = coinbase_book_feed(exchange.url());
+    let book = manager.get_order_book();
+    let feeds = manager.spawn();
+
+    exchange.expect_subscribe().await;
+    wait_for_touch(&book, (dec!(67012.50), dec!(0.25)), (dec!(67013.10), dec!(0.005))).await;
+
+    exchange.drop_connections();
+    exchange.expect_connection(1).await;
+    exchange.expect_subscribe().await;
+    // Nothing of the first connection's book survives the drop
+    wait_for_touch(&book, (dec!(66990.00), dec!(3.00)), (dec!(66991.00), dec!(1.00))).await;
+    assert_eq!(book.top_bids(10).await.len(), 1);
+    assert_eq!(book.top_asks(10).await.len(), 1);
+    assert_eq!(manager.health().health().reconnect_counts.depth, 1);
+
+    assert_eq!(feeds.shutdown().await, Ok(()));
+    assert_eq!(exchange.connections(), 2);
+}
+
+#[tokio::test]
+async fn test_trade_feed_receives_paced_trades_and_subscribes_at_runtime() {
+    let pacing = Duration::from_millis(100);
+    let trades = [trade(1, "69000.00"), trade(2, "69000.50"), trade(3, "69001.00")];
+    let mut exchange = MockExchange::new()
+        .with_session(&trades.iter().map(String::as_str).collect::<Vec<_>>())
+        .with_pacing(pacing)
+        .start()
+        .await;
+    let trades_log = ConcurrentTradesLog::new(10);
+    let manager = Arc::new(LogFeedManager::new(exchange.url(), trades_log.clone()));
+    let (_shutdown_tx, shutdown_rx) = watch::channel(false);
+    let feed = tokio::spawn({
+        let manager = manager.clone();
+        async move { manager.start(shutdown_rx).await }
+    });
+
+    exchange.expect_connection(0).await;
+    let connected_at = Instant::now();
+    wait_for_trades(&trades_log, 3).await;
+    // Each trade waited out the pacing before it was sent
+    assert!(connected_at.elapsed() >= pacing * 3 - Duration::from_millis(20));
+    let prices: Vec<_> = trades_log.last_n_trades(3).await.iter().map(|trade| trade.price).collect();
+    assert!(prices.contains(&dec!(69000.00)) && prices.contains(&dec!(69001.00)));
+
+    // The mock acknowledges the request, so the subscription resolves
+    let subscribed = timeout(Duration::from_secs(2), manager.subscribe(vec!["ethusdt@trade".to_string()]))
+        .await
+        .unwrap();
+    assert_eq!(subscribed, Ok(()));
+    assert_eq!(
+        exchange.expect_subscribe().await,
+        json!({"method": "SUBSCRIBE", "params": ["ethusdt@trade"], "id": 1})
+    );
+
+    exchange.send(&trade(4, "3500.10"));
+    wait_for_trades(&trades_log, 4).await;
+    feed.abort();
+}
+
+#[tokio::test]
+async fn test_trade_feed_reconnects_and_keeps_ingesting_after_a_drop() {
+    let first = trade(1, "69000.00");
+    let second = trade(2, "69010.00");
+    let mut exchange = MockExchange::new().with_session(&[&first]).with_session(&[&second]).start().await;
+    let trades_log = ConcurrentTradesLog::new(10);
+    let manager = Arc::new(LogFeedManager::new(exchange.url(), trades_log.clone()).with_reconnect_policy(fast_reconnect()));
+    let feeds = manager.spawn();
+
+    exchange.expect_connection(0).await;
+    wait_for_trades(&trades_log, 1).await;
+    exchange.drop_connections();
+
+    exchange.expect_connection(1).await;
+    wait_for_trades(&trades_log, 2).await;
+    let prices: Vec<_> = trades_log.last_n_trades(2).await.iter().map(|trade| trade.price).collect();
+    assert!(prices.contains(&dec!(69000.00)) && prices.contains(&dec!(69010.00)));
+    assert_eq!(manager.health().health().reconnect_counts.trade, 1);
+
+    assert_eq!(feeds.shutdown().await, Ok(()));
+    assert_eq!(exchange.next_event().await, ServerEvent::Closed(1));
+}
+
+#[tokio::test]
+async fn test_trade_feed_rolls_over_without_losing_or_doubling_trades() {
+    // The second connection starts at trade 2, a frame ahead of the first, so the two
+    // overlap until the first delivers trade 2 as well
+    let first: Vec<_> = (1..=4).map(|id| trade(id, &format!("69000.{}", id))).collect();
+    let second: Vec<_> = (2..=6).map(|id| trade(id, &format!("69000.{}", id))).collect();
+    let mut exchange = MockExchange::new()
+        .with_session(&first.iter().map(String::as_str).collect::<Vec<_>>())
+        .with_session(&second.iter().map(String::as_str).collect::<Vec<_>>())
+        .with_pacing(Duration::from_millis(200))
+        .start()
+        .await;
+    let trades_log = ConcurrentTradesLog::new(20);
+    let manager = Arc::new(
+        LogFeedManager::new(exchange.url(), trades_log.clone()).with_rollover_after(Duration::from_millis(100)),
+    );
+    let feeds = manager.spawn();
+
+    exchange.expect_connection(0).await;
+    exchange.expect_connection(1).await;
+    // The first connection is closed cleanly once the second has caught up
+    assert_eq!(exchange.next_event().await, ServerEvent::Closed(0));
+
+    wait_for_trades(&trades_log, 6).await;
+    sleep(Duration::from_millis(300)).await;
+    let mut prices: Vec<_> = trades_log.last_n_trades(20).await.iter().map(|trade| trade.price).collect();
+    prices.sort();
+    let expected: Vec<_> = (1..=6).map(|id| Decimal::new(690000 + id, 1)).collect();
+    assert_eq!(prices, expected);
+    assert_eq!(manager.connection_state(), ConnectorState::Connected);
+    assert_eq!(manager.health().health().reconnect_counts.trade, 0);
+    // Each connection's ids ran on from its own first trade
+    assert!(!manager.health().health().trade_feed_gap_detected);
+
+    assert_eq!(feeds.shutdown().await, Ok(()));
+}
+
+#[tokio::test]
+async fn test_trade_feed_flags_skipped_trade_ids_until_it_reconnects() {
+    let first = [trade(1, "69000.01"), trade(2, "69000.02"), trade(5, "69000.05")];
+    // The exchange moved on while the feed was down; that alone is no gap
+    let second = [trade(40, "69000.40"), trade(41, "69000.41")];
+    let mut exchange = MockExchange::new()
+        .with_session(&first.iter().map(String::as_str).collect::<Vec<_>>())
+        .with_session(&second.iter().map(String::as_str).collect::<Vec<_>>())
+        .start()
+        .await;
+    let trades_log = ConcurrentTradesLog::new(10);
+    let manager = Arc::new(LogFeedManager::new(exchange.url(), trades_log.clone()).with_reconnect_policy(fast_reconnect()));
+    let health = manager.health();
+    let feeds = manager.spawn();
+
+    exchange.expect_connection(0).await;
+    wait_for_trades(&trades_log, 3).await;
+    assert!(health.health().trade_feed_gap_detected);
+    exchange.drop_connections();
+
+    exchange.expect_connection(1).await;
+    wait_for_trades(&trades_log, 5).await;
+    assert!(!health.health().trade_feed_gap_detected);
+
+    exchange.send(&trade(43, "69000.43"));
+    wait_for_trades(&trades_log, 6).await;
+    assert!(health.health().trade_feed_gap_detected);
+
+    assert_eq!(feeds.shutdown().await, Ok(()));
+}
+
+#[tokio::test]
+async fn test_combined_trade_stream_fills_each_symbols_log() {
+    let combined = |symbol: &str, id: u64, price: &str| {
+        format!(
+            r#"{{"stream":"{}@trade","data":{{"e":"trade","E":1,"s":"{}","t":{},"p":"{}","q":"0.5","T":1712745600000,"m":true}}}}"#,
+            symbol,
+            symbol.to_uppercase(),
+            id,
+            price
+        )
+    };
+    // Both symbols number their trades from 1, so the same ids arrive for each
+    let frames = [
+        combined("btcusdt", 1, "69000.00"),
+        combined("ethusdt", 1, "3500.00"),
+        combined("btcusdt", 2, "69000.50"),
+        combined("ethusdt", 2, "3500.50"),
+        combined("ethusdt", 3, "3501.00"),
+    ];
+    let mut exchange = MockExchange::new()
+        .with_session(&frames.iter().map(String::as_str).collect::<Vec<_>>())
+        .start()
+        .await;
+    let (btc, eth) = (ConcurrentTradesLog::new(10), ConcurrentTradesLog::new(10));
+    let logs = HashMap::from([("btcusdt".to_string(), btc.clone()), ("ethusdt".to_string(), eth.clone())]);
+    let manager = Arc::new(LogFeedManager::with_symbols(logs).with_endpoints(vec![exchange.url()]));
+    let feeds = manager.spawn();
+
+    exchange.expect_connection(0).await;
+    wait_for_trades(&eth, 3).await;
+    wait_for_trades(&btc, 2).await;
+    let prices = |trades: Vec<Trade>| trades.iter().map(|trade| trade.price).collect::<Vec<_>>();
+    assert_eq!(prices(btc.last_n_trades(10).await), vec![dec!(69000.50), dec!(69000.00)]);
+    assert_eq!(prices(eth.last_n_trades(10).await), vec![dec!(3501.00), dec!(3500.50), dec!(3500.00)]);
+
+    assert_eq!(feeds.shutdown().await, Ok(()));
+}
+
+#[tokio::test]
+async fn test_keepalive_pings_on_a_quiet_feed_are_answered() {
+    let mut exchange = MockExchange::new().start().await;
+    let keepalive = KeepAliveConfig {
+        idle_interval: Duration::from_millis(100),
+        pong_timeout: Duration::from_millis(200),
+    };
+    let manager = Arc::new(LogFeedManager::new(exchange.url(), ConcurrentTradesLog::new(10)).with_keepalive(keepalive));
+    let feeds = manager.spawn();
+
+    exchange.expect_connection(0).await;
+    sleep(Duration::from_millis(700)).await;
+
+    // Every ping was answered in time, so the feed never gave up on the connection
+    let pings = exchange.drain_events().iter().filter(|event| **event == ServerEvent::Ping(0)).count();
+    assert!(pings >= 3, "Only {} pings", pings);
+    assert_eq!(exchange.connections(), 1);
+    assert_eq!(manager.connection_state(), ConnectorState::Connected);
+
+    assert_eq!(feeds.shutdown().await, Ok(()));
+}
+
+#[tokio::test]
+async fn test_binary_depth_frames_sync_the_book() {
+    let mut exchange = MockExchange::new().start().await;
+    let manager = LobFeedManager::with_adapter(Arc::new(RelayAdapter::new(exchange.url())), "btcusdt");
+    let book = manager.get_order_book();
+    let feeds = manager.spawn();
+
+    exchange.expect_connection(0).await;
+    exchange.send_binary(&encode_frame(&RelayRecord::Snapshot(BinanceRestSnapshot {
+        last_update_id: 100,
+        bids: relay_levels(&[(dec!(69000.00), dec!(1.0))]),
+        asks: relay_levels(&[(dec!(69001.00), dec!(2.0)), (dec!(69002.00), dec!(1.5))]),
+    })));
+    exchange.send_binary(&relay_diff(99, 101, &[(dec!(69000.50), dec!(0.3))], &[]));
+    // Undecodable frames are dropped without losing the connection or the book
+    exchange.send_binary(&[0xff, 0x00, 0x13]);
+    exchange.send_binary(&relay_diff(102, 102, &[], &[(dec!(69001.00), dec!(0))]));
+
+    wait_for_touch(&book, (dec!(69000.50), dec!(0.3)), (dec!(69002.00), dec!(1.5))).await;
+    assert_eq!(book.top_bids(10).await.len(), 2);
+    assert!(book.is_trusted().await);
+    assert_eq!(exchange.connections(), 1);
+
+    assert_eq!(feeds.shutdown().await, Ok(()));
+}
+
+#[tokio::test]
+async fn test_binary_trade_frames_fill_the_log() {
+    let mut exchange = MockExchange::new().start().await;
+    let trades_log = ConcurrentTradesLog::new(10);
+    let adapter = Arc::new(RelayAdapter::new(exchange.url()));
+    let manager = Arc::new(LogFeedManager::with_adapter(adapter, "btcusdt", trades_log.clone()));
+    let feeds = manager.spawn();
+
+    exchange.expect_connection(0).await;
+    let sent = [
+        Trade::new(dec!(69000.50), dec!(0.25), 1712745600100, true).unwrap(),
+        Trade::new(dec!(69001.00), dec!(1.5), 1712745600200, false).unwrap(),
+    ];
+    for trade in &sent {
+        exchange.send_binary(&encode_frame(&RelayRecord::Trade(RelayTrade::from(trade))));
+    }
+
+    wait_for_trades(&trades_log, 2).await;
+    let mut received = trades_log.last_n_trades(2).await;
+    received.sort_by_key(|trade| trade.timestamp);
+    assert_eq!(received, sent);
+
+    assert_eq!(feeds.shutdown().await, Ok(()));
+}
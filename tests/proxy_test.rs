@@ -0,0 +1,150 @@
+use ingestor::{
+    heartbeat::KeepAliveConfig,
+    log_feed_manager::LogFeedManager,
+    proxy::ProxyConfig,
+    tradeslog::ConcurrentTradesLog,
+    transport::Transport,
+};
+
+use futures_util::SinkExt;
+use rust_decimal_macros::dec;
+use std::io;
+use std::net::Ipv4Addr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio::time::{sleep, timeout, Duration};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+/// Minimal SOCKS5 proxy: checks credentials when `auth` is given and relays each client to
+/// the address it asked for. Every target it connected to is reported on the channel.
+async fn socks5_proxy(auth: Option<(&'static str, &'static str)>) -> (String, mpsc::UnboundedReceiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("socks5://{}", listener.local_addr().unwrap());
+    let (target_tx, target_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        loop {
+            let (client, _) = listener.accept().await.unwrap();
+            let target_tx = target_tx.clone();
+            tokio::spawn(async move {
+                let _ = relay(client, auth, target_tx).await;
+            });
+        }
+    });
+    (url, target_rx)
+}
+
+async fn relay(
+    mut client: TcpStream,
+    auth: Option<(&str, &str)>,
+    target_tx: mpsc::UnboundedSender<String>,
+) -> io::Result<()> {
+    let mut greeting = [0u8; 2];
+    client.read_exact(&mut greeting).await?;
+    let mut methods = vec![0u8; greeting[1] as usize];
+    client.read_exact(&mut methods).await?;
+
+    match auth {
+        Some((username, password)) => {
+            if !methods.contains(&2) {
+                return client.write_all(&[5, 0xff]).await;
+            }
+            client.write_all(&[5, 2]).await?;
+            client.read_u8().await?;
+            let offered = (read_field(&mut client).await?, read_field(&mut client).await?);
+            let accepted = offered.0 == username && offered.1 == password;
+            client.write_all(&[1, if accepted { 0 } else { 1 }]).await?;
+            if !accepted {
+                return Ok(());
+            }
+        }
+        None => client.write_all(&[5, 0]).await?,
+    }
+
+    let mut request = [0u8; 4];
+    client.read_exact(&mut request).await?;
+    let host = match request[3] {
+        1 => {
+            let mut ip = [0u8; 4];
+            client.read_exact(&mut ip).await?;
+            Ipv4Addr::from(ip).to_string()
+        }
+        3 => read_field(&mut client).await?,
+        _ => return client.write_all(&[5, 8, 0, 1, 0, 0, 0, 0, 0, 0]).await,
+    };
+    let port = client.read_u16().await?;
+    let target = format!("{}:{}", host, port);
+    let mut upstream = TcpStream::connect(&target).await?;
+    let _ = target_tx.send(target);
+
+    client.write_all(&[5, 0, 0, 1, 0, 0, 0, 0, 0, 0]).await?;
+    tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+    Ok(())
+}
+
+async fn read_field(stream: &mut TcpStream) -> io::Result<String> {
+    let mut field = vec![0u8; stream.read_u8().await? as usize];
+    stream.read_exact(&mut field).await?;
+    Ok(String::from_utf8_lossy(&field).into_owned())
+}
+
+/// WebSocket server that sends `frame` to every client and then keeps the socket open.
+async fn ws_server(frame: String) -> (String, u16) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        loop {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let frame = frame.clone();
+            tokio::spawn(async move {
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                ws.send(Message::Text(frame)).await.unwrap();
+                sleep(Duration::from_secs(5)).await;
+            });
+        }
+    });
+    (format!("ws://127.0.0.1:{}/ws/btcusdt@trade", port), port)
+}
+
+#[tokio::test]
+async fn test_transport_tunnels_through_socks5_with_credentials() {
+    let (uri, port) = ws_server("hello".to_string()).await;
+    let (proxy_url, mut targets) = socks5_proxy(Some(("alice", "s3cret"))).await;
+
+    let mut rejected = Transport::new(uri.clone(), KeepAliveConfig::default())
+        .with_proxy(ProxyConfig::new(&proxy_url).with_auth("alice", "wrong"));
+    assert!(rejected.connect(None).await.is_err());
+
+    let mut transport = Transport::new(uri, KeepAliveConfig::default())
+        .with_proxy(ProxyConfig::new(&proxy_url).with_auth("alice", "s3cret"));
+    let mut conn = transport.connect(None).await.unwrap();
+    let text = timeout(Duration::from_secs(1), conn.next_text()).await.unwrap().unwrap();
+    assert_eq!(text.as_deref(), Some("hello"));
+
+    assert_eq!(targets.recv().await.unwrap(), format!("127.0.0.1:{}", port));
+}
+
+#[tokio::test]
+async fn test_trade_feed_streams_through_proxy() {
+    let trade = r#"{"e":"trade","E":1,"s":"BTCUSDT","t":1,"p":"100.50","q":"2.0","b":0,"a":0,"T":1,"m":true,"M":true}"#;
+    let (uri, _) = ws_server(trade.to_string()).await;
+    let (proxy_url, mut targets) = socks5_proxy(None).await;
+
+    let trades_log = ConcurrentTradesLog::new(10);
+    let manager = LogFeedManager::new(uri, trades_log.clone()).with_proxy(ProxyConfig::new(&proxy_url));
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let handle = tokio::spawn(async move { manager.start(shutdown_rx).await });
+
+    timeout(Duration::from_secs(1), targets.recv()).await.unwrap().unwrap();
+    timeout(Duration::from_secs(1), async {
+        while trades_log.last_n_trades(1).await.is_empty() {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(trades_log.last_n_trades(1).await[0].price, dec!(100.50));
+
+    shutdown_tx.send(true).unwrap();
+    handle.await.unwrap().unwrap();
+}
@@ -0,0 +1,182 @@
+mod common;
+
+use common::{rebuild_book, ExpectedBook};
+use futures_util::SinkExt;
+use ingestor::{
+    exchange::BinanceAdapter,
+    lob_feed_manager::LobFeedManager,
+    orderbook::ConcurrentOrderBook,
+    reconnect::ReconnectPolicy,
+    snapshot_fetcher::SnapshotFetcher,
+    stream_config::{DepthMode, StreamConfig},
+    tape::{TapeEntry, DEPTH_SNAPSHOT_STREAM, DEPTH_STREAM},
+};
+use metrics_util::debugging::{DebugValue, DebuggingRecorder, Snapshotter};
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::time::{sleep, timeout, Duration};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+const FIRST_SNAPSHOT: &str = r#"{"lastUpdateId":1000,"bids":[["69420.00","1.500"],["69419.50","0.800"]],"asks":[["69420.50","0.600"],["69421.00","1.100"]]}"#;
+const FIRST_DIFFS: [&str; 1] = [
+    r#"{"e":"depthUpdate","E":1712746800109,"s":"BTCUSDT","U":1001,"u":1003,"b":[["69420.00","1.200"]],"a":[]}"#,
+];
+
+/// The market moved on while the feed was down, so nothing from the first connection
+/// survives in this snapshot.
+const SECOND_SNAPSHOT: &str = r#"{"lastUpdateId":2000,"bids":[["69400.00","2.000"],["69399.50","1.000"],["69398.00","0.500"]],"asks":[["69400.50","0.700"],["69401.00","1.400"],["69402.00","3.000"]]}"#;
+const SECOND_DIFFS: [&str; 3] = [
+    // Already covered by the snapshot, so dropped
+    r#"{"e":"depthUpdate","E":1712746860009,"s":"BTCUSDT","U":1995,"u":1999,"b":[["69400.00","9.900"]],"a":[]}"#,
+    r#"{"e":"depthUpdate","E":1712746860109,"s":"BTCUSDT","U":2000,"u":2003,"b":[["69400.25","0.300"]],"a":[["69400.50","0.000"]]}"#,
+    r#"{"e":"depthUpdate","E":1712746860209,"s":"BTCUSDT","U":2004,"u":2006,"b":[["69398.00","0.000"]],"a":[["69401.50","0.900"]]}"#,
+];
+
+/// REST server answering each snapshot request with the next body sent on the returned
+/// channel, and reporting every request as it arrives.
+async fn snapshot_server(listener: TcpListener) -> (mpsc::UnboundedSender<&'static str>, mpsc::UnboundedReceiver<()>) {
+    let (body_tx, mut body_rx) = mpsc::unbounded_channel::<&'static str>();
+    let (request_tx, request_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((mut tcp, _)) = listener.accept().await {
+            let mut request = [0u8; 1024];
+            let _ = tcp.read(&mut request).await;
+            let _ = request_tx.send(());
+            let body = match body_rx.recv().await {
+                Some(body) => body,
+                None => return,
+            };
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            let _ = tcp.write_all(response.as_bytes()).await;
+        }
+    });
+    (body_tx, request_rx)
+}
+
+/// Waits for `level` to become the touch on the `bid` or ask side of `book`.
+async fn wait_for_touch(book: &ConcurrentOrderBook, bid: bool, level: (Decimal, Decimal)) {
+    timeout(Duration::from_secs(2), async {
+        loop {
+            let touch = if bid { book.best_bid().await } else { book.best_ask().await };
+            if touch == Some(level) {
+                break;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap_or_else(|_| panic!("Timed out waiting for {:?} at the touch", level));
+}
+
+fn entry(stream: &str, payload: &str) -> TapeEntry {
+    TapeEntry {
+        recv_ts_ms: 0,
+        stream: stream.to_string(),
+        payload: payload.to_string(),
+    }
+}
+
+fn counter(name: &str) -> u64 {
+    Snapshotter::current_thread_snapshot()
+        .unwrap()
+        .into_vec()
+        .into_iter()
+        .find_map(|(key, _, _, value)| match value {
+            DebugValue::Counter(n) if key.key().name() == name => Some(n),
+            _ => None,
+        })
+        .unwrap_or(0)
+}
+
+#[tokio::test]
+async fn test_dropped_connection_resyncs_from_a_fresh_snapshot() {
+    // Handles are bound at registration, so the recorder must be in place before the manager exists
+    let _ = DebuggingRecorder::per_thread().install();
+
+    let ws_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let http_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let mut config = StreamConfig::new("btcusdt").with_depth_mode(DepthMode::DiffWithRestSnapshot);
+    config.base_url = format!("ws://{}", ws_listener.local_addr().unwrap());
+    let adapter =
+        BinanceAdapter::from_config(&config).with_rest_base(format!("http://{}", http_listener.local_addr().unwrap()));
+    let manager = LobFeedManager::with_adapter(Arc::new(adapter), "btcusdt")
+        .with_reconnect_policy(ReconnectPolicy {
+            initial: Duration::from_millis(10),
+            ..ReconnectPolicy::default()
+        })
+        .with_snapshot_fetcher(SnapshotFetcher::new().with_min_interval(Duration::from_millis(10)));
+    let book = manager.get_order_book();
+
+    let (snapshots, mut snapshot_requests) = snapshot_server(http_listener).await;
+    snapshots.send(FIRST_SNAPSHOT).unwrap();
+    let (drop_tx, drop_rx) = oneshot::channel::<()>();
+    tokio::spawn(async move {
+        // First connection: a few diffs, then the socket drops without a close frame
+        let (tcp, _) = ws_listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+        for frame in FIRST_DIFFS {
+            ws.send(Message::Text(frame.to_string())).await.unwrap();
+        }
+        let _ = drop_rx.await;
+        drop(ws);
+
+        let (tcp, _) = ws_listener.accept().await.unwrap();
+        let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+        for frame in SECOND_DIFFS {
+            ws.send(Message::Text(frame.to_string())).await.unwrap();
+        }
+        let _ = futures_util::StreamExt::next(&mut ws).await;
+    });
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+
+    wait_for_touch(&book, true, (dec!(69420.00), dec!(1.200))).await;
+    assert_eq!(counter("lob_feed_resyncs_after_reconnect"), 0);
+    drop_tx.send(()).unwrap();
+
+    // Between the drop and the fresh snapshot the old levels must not be served
+    timeout(Duration::from_secs(2), snapshot_requests.recv()).await.unwrap().unwrap();
+    timeout(Duration::from_secs(2), snapshot_requests.recv()).await.unwrap().unwrap();
+    sleep(Duration::from_millis(100)).await;
+    assert!(!book.is_trusted().await);
+    assert_eq!(book.best_bid().await, None);
+    assert_eq!(book.best_ask().await, None);
+
+    snapshots.send(SECOND_SNAPSHOT).unwrap();
+    wait_for_touch(&book, false, (dec!(69401.00), dec!(1.400))).await;
+    sleep(Duration::from_millis(100)).await;
+    shutdown_tx.send(true).unwrap();
+    feed.await.unwrap().unwrap();
+
+    let mut reference = vec![entry(DEPTH_SNAPSHOT_STREAM, SECOND_SNAPSHOT)];
+    reference.extend(SECOND_DIFFS.iter().map(|frame| entry(DEPTH_STREAM, frame)));
+    let reference = rebuild_book(&reference).await;
+    assert_eq!(book.top_bids(10).await, reference.top_bids(10).await);
+    assert_eq!(book.top_asks(10).await, reference.top_asks(10).await);
+
+    ExpectedBook {
+        bids: vec![
+            (dec!(69400.25), dec!(0.300)),
+            (dec!(69400.00), dec!(2.000)),
+            (dec!(69399.50), dec!(1.000)),
+        ],
+        asks: vec![
+            (dec!(69401.00), dec!(1.400)),
+            (dec!(69401.50), dec!(0.900)),
+            (dec!(69402.00), dec!(3.000)),
+        ],
+    }
+    .assert_matches(&book)
+    .await;
+    assert!(book.is_trusted().await);
+    assert_eq!(counter("lob_feed_resyncs_after_reconnect"), 1);
+}
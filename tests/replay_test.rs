@@ -0,0 +1,159 @@
+use ingestor::{
+    analytics::{run_analytics_task, AnalyticsConfig, OutputSink},
+    orderbook::ConcurrentOrderBook,
+    persistence::TradeParquetWriter,
+    replay::{ReplayFeedManager, ReplaySpeed, ReplayStats, TradeReplayer},
+    trade_sink::RawTrade,
+    tradeslog::{ConcurrentTradesLog, Trade, TradeLogSnapshot},
+};
+
+use rust_decimal::Decimal;
+use rust_decimal_macros::dec;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{timeout, Duration};
+
+const TAPE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/replay_tape.ndjson");
+
+#[tokio::test]
+async fn test_replay_rebuilds_book_and_trades_offline() {
+    let order_book = ConcurrentOrderBook::new();
+    let trades_log = ConcurrentTradesLog::new(100);
+    let replay = ReplayFeedManager::new(TAPE, order_book.clone(), trades_log.clone(), ReplaySpeed::AsFastAsPossible);
+
+    let stats = replay.run().await.unwrap();
+    assert_eq!(
+        stats,
+        ReplayStats {
+            frames: 7,
+            depth_events: 4,
+            trades: 2,
+            parse_failures: 1,
+            resyncs: 0,
+        }
+    );
+
+    // The diff recorded before the snapshot is released on top of it
+    assert_eq!(order_book.top_bids(10).await, vec![(dec!(100.00), dec!(1.2))]);
+    assert_eq!(
+        order_book.top_asks(10).await,
+        vec![(dec!(100.25), dec!(0.4)), (dec!(100.50), dec!(1.5))]
+    );
+
+    let trades = trades_log.last_n_trades(10).await;
+    assert_eq!(trades.len(), 2);
+    assert_eq!(trades[0].price, dec!(100.25));
+    assert!(trades[0].is_buyer_maker);
+}
+
+#[tokio::test]
+async fn test_replayed_state_feeds_analytics() {
+    let order_book = ConcurrentOrderBook::new();
+    let trades_log = ConcurrentTradesLog::new(100);
+    ReplayFeedManager::new(TAPE, order_book.clone(), trades_log.clone(), ReplaySpeed::Paced(10.0))
+        .run()
+        .await
+        .unwrap();
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let config = AnalyticsConfig {
+        sinks: vec![OutputSink::Channel(tx)],
+        snapshot_interval: Duration::from_millis(10),
+        ..AnalyticsConfig::default()
+    };
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let handle = tokio::spawn(run_analytics_task(
+        Arc::new(order_book),
+        Arc::new(trades_log),
+        config,
+        shutdown_rx,
+    ));
+
+    let snapshot = timeout(Duration::from_secs(1), rx.recv()).await.unwrap().unwrap();
+    shutdown_tx.send(true).unwrap();
+    handle.await.unwrap();
+
+    assert_eq!(snapshot.best_bid, Some(dec!(100.00)));
+    assert_eq!(snapshot.best_ask, Some(dec!(100.25)));
+    assert_eq!(snapshot.last_trade_price, Some(dec!(100.25)));
+}
+
+// 2024-04-10 10:59:59 UTC
+const TRADES_T0: u64 = 1_712_746_799_000;
+
+fn raw_trade(
+    offset_ms: u64,
+    price: Decimal,
+    quantity: Decimal,
+    is_buyer_maker: bool,
+    trade_id: Option<u64>,
+) -> RawTrade {
+    let trade = Trade::new(price, quantity, TRADES_T0 + offset_ms, is_buyer_maker).unwrap().with_symbol("BTCUSDT");
+    RawTrade::new(&Trade { trade_id, ..trade })
+}
+
+fn write_trades_file(path: &Path, row_groups: &[Vec<RawTrade>]) {
+    let mut writer = TradeParquetWriter::create(path).unwrap();
+    for trades in row_groups {
+        writer.write(trades).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+#[tokio::test]
+async fn test_trades_replay_rebuilds_the_log_snapshot() {
+    // Two hours of a trade sink's output, the first file in two row groups
+    let dir = tempfile::tempdir().unwrap();
+    write_trades_file(&dir.path().join("trades_20240410_10.parquet"), &[
+        vec![
+            raw_trade(0, dec!(100.0), dec!(1.0), false, Some(101)),
+            raw_trade(400, dec!(100.5), dec!(0.5), true, Some(102)),
+        ],
+        vec![raw_trade(800, dec!(101.0), dec!(2.0), false, Some(103))],
+    ]);
+    write_trades_file(&dir.path().join("trades_20240410_11.parquet"), &[vec![
+        raw_trade(1_100, dec!(100.5), dec!(1.5), true, Some(104)),
+        raw_trade(1_200, dec!(100.0), dec!(1.0), true, Some(105)),
+    ]]);
+
+    let replayer = TradeReplayer::from_parquet(dir.path()).unwrap();
+    assert_eq!(replayer.len(), 5);
+    // Room for four, so the first trade is evicted by the last
+    let trades_log = ConcurrentTradesLog::new(4);
+    let progress = replayer.run(&trades_log).await;
+
+    assert_eq!((progress.replayed, progress.total, progress.completion()), (5, 5, 1.0));
+    assert_eq!(progress.last_trade_id, Some(105));
+    assert_eq!(
+        trades_log.get_snapshot().await,
+        TradeLogSnapshot {
+            last_price: Some(dec!(100.0)),
+            // One taker buy of 2.0 against sells of 0.5, 1.5 and 1.0
+            trade_imbalance: Some(dec!(0.4)),
+            trade_imbalance_ema: Some(dec!(0.8681285714285714285714285714)),
+            vwap_total: Some(dec!(100.0)),
+            price_change: Some(dec!(-0.5)),
+            avg_trade_size: Some(dec!(1.25)),
+            signed_count_momentum: -2,
+            trade_rate_1s: Some(4.0),
+            trade_rate_10s: Some(0.4),
+            trade_rate_60s: Some(4.0 / 60.0),
+            vwap_10: None,
+            vwap_50: None,
+            vwap_100: None,
+            vwap_1000: None,
+            aggr_ratio_10: None,
+            aggr_ratio_50: None,
+            aggr_ratio_100: None,
+            aggr_ratio_1000: None,
+            aggr_ratio_10s: Some(dec!(0.4)),
+            large_trade_share_100: None,
+        }
+    );
+
+    let trades = trades_log.last_n_trades(4).await;
+    let prices: Vec<_> = trades.iter().map(|t| t.price).collect();
+    assert_eq!(prices, vec![dec!(100.0), dec!(100.5), dec!(101.0), dec!(100.5)]);
+    assert!(trades.iter().all(|t| t.symbol.as_deref() == Some("btcusdt")));
+}
@@ -0,0 +1,233 @@
+//! Local stand-in for an exchange's WebSocket feed: plays scripted frames to each
+//! connection, drops connections on command and reports what clients send it.
+
+use futures_util::{SinkExt, StreamExt};
+use serde_json::Value;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::{sleep_until, timeout, Duration, Instant};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+const EVENT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Something a client did, tagged with the connection it did it on (0 for the first).
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerEvent {
+    Connected(usize),
+    /// A text frame, parsed when it is JSON and kept as a string otherwise.
+    Received(usize, Value),
+    /// A ping, already answered.
+    Ping(usize),
+    /// The client closed the connection with a close frame.
+    Closed(usize),
+}
+
+#[derive(Debug, Clone)]
+enum Command {
+    Send(Message),
+    Drop,
+}
+
+/// What the server plays to its clients; `start` binds it to a local port.
+#[derive(Debug, Clone, Default)]
+pub struct MockExchange {
+    sessions: Vec<Vec<String>>,
+    pacing: Duration,
+}
+
+impl MockExchange {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Frames played to the next connection, in order. The first session goes to the
+    /// first connection, the second to the next; connections past the last get none.
+    pub fn with_session(mut self, frames: &[&str]) -> Self {
+        self.sessions.push(frames.iter().map(|frame| frame.to_string()).collect());
+        self
+    }
+
+    /// Wait before each scripted frame; none by default.
+    pub fn with_pacing(mut self, pacing: Duration) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    pub async fn start(self) -> MockServer {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (events_tx, events) = mpsc::unbounded_channel();
+        let (commands, _) = broadcast::channel(64);
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        tokio::spawn({
+            let commands = commands.clone();
+            let connections = connections.clone();
+            async move {
+                while let Ok((tcp, _)) = listener.accept().await {
+                    let id = connections.fetch_add(1, Ordering::SeqCst);
+                    let session = self.sessions.get(id).cloned().unwrap_or_default();
+                    let connection = Connection {
+                        id,
+                        pacing: self.pacing,
+                        events: events_tx.clone(),
+                        commands: commands.subscribe(),
+                    };
+                    tokio::spawn(async move {
+                        if let Ok(ws) = tokio_tungstenite::accept_async(tcp).await {
+                            connection.serve(ws, session).await;
+                        }
+                    });
+                }
+            }
+        });
+        MockServer { url, events, commands, connections }
+    }
+}
+
+struct Connection {
+    id: usize,
+    pacing: Duration,
+    events: mpsc::UnboundedSender<ServerEvent>,
+    commands: broadcast::Receiver<Command>,
+}
+
+impl Connection {
+    async fn serve(mut self, mut ws: tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>, session: Vec<String>) {
+        let _ = self.events.send(ServerEvent::Connected(self.id));
+        let mut script = session.into_iter().peekable();
+        let mut next_frame_at = Instant::now() + self.pacing;
+
+        loop {
+            tokio::select! {
+                _ = sleep_until(next_frame_at), if script.peek().is_some() => {
+                    let frame = script.next().unwrap();
+                    if ws.send(Message::Text(frame)).await.is_err() {
+                        return;
+                    }
+                    next_frame_at = Instant::now() + self.pacing;
+                }
+                command = self.commands.recv() => match command {
+                    Ok(Command::Send(frame)) => {
+                        if ws.send(frame).await.is_err() {
+                            return;
+                        }
+                    }
+                    // Gone without a close frame, like a connection lost in transit
+                    Ok(Command::Drop) | Err(_) => return,
+                },
+                msg = ws.next() => match msg {
+                    Some(Ok(Message::Text(text))) => {
+                        let frame = serde_json::from_str(&text).unwrap_or(Value::String(text));
+                        if let Some(ack) = ack_for(&frame) {
+                            let _ = ws.send(Message::Text(ack)).await;
+                        }
+                        let _ = self.events.send(ServerEvent::Received(self.id, frame));
+                    }
+                    // tungstenite queues the pong itself and writes it on the next poll
+                    Some(Ok(Message::Ping(_))) => {
+                        let _ = self.events.send(ServerEvent::Ping(self.id));
+                    }
+                    Some(Ok(Message::Close(_))) => {
+                        let _ = self.events.send(ServerEvent::Closed(self.id));
+                        return;
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(_)) | None => return,
+                },
+            }
+        }
+    }
+}
+
+/// Binance-style acknowledgement of a request carrying an `id`, as the subscription
+/// handle waits for. Venues that don't number their requests get none.
+fn ack_for(frame: &Value) -> Option<String> {
+    match (frame["method"].as_str(), frame["id"].as_u64()) {
+        (Some(_), Some(id)) => Some(format!(r#"{{"result":null,"id":{}}}"#, id)),
+        _ => None,
+    }
+}
+
+/// Whether `frame` subscribes to something, in any of the supported venues' dialects.
+pub fn is_subscribe(frame: &Value) -> bool {
+    [("method", "SUBSCRIBE"), ("type", "subscribe"), ("op", "subscribe"), ("event", "subscribe")]
+        .iter()
+        .any(|(key, value)| frame[*key] == *value)
+}
+
+/// A running `MockExchange`, serving until it is dropped.
+pub struct MockServer {
+    url: String,
+    events: mpsc::UnboundedReceiver<ServerEvent>,
+    commands: broadcast::Sender<Command>,
+    connections: Arc<AtomicUsize>,
+}
+
+impl MockServer {
+    /// `ws://` address of the server; any path is accepted.
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    /// Connections accepted so far.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    /// Sends `frame` on every open connection now, between scripted frames if a script
+    /// is still playing.
+    pub fn send(&self, frame: &str) {
+        let _ = self.commands.send(Command::Send(Message::Text(frame.to_string())));
+    }
+
+    /// `send` for a binary frame.
+    pub fn send_binary(&self, frame: &[u8]) {
+        let _ = self.commands.send(Command::Send(Message::Binary(frame.to_vec())));
+    }
+
+    /// Drops every open connection without a close frame.
+    pub fn drop_connections(&self) {
+        let _ = self.commands.send(Command::Drop);
+    }
+
+    pub async fn next_event(&mut self) -> ServerEvent {
+        timeout(EVENT_TIMEOUT, self.events.recv())
+            .await
+            .expect("Timed out waiting for the mock exchange")
+            .unwrap()
+    }
+
+    /// Waits for connection `id` to be accepted, skipping pings.
+    pub async fn expect_connection(&mut self, id: usize) {
+        loop {
+            match self.next_event().await {
+                ServerEvent::Connected(connected) if connected == id => return,
+                ServerEvent::Ping(_) => {}
+                other => panic!("Expected connection {}, got {:?}", id, other),
+            }
+        }
+    }
+
+    /// Waits for the next text frame, which must be a subscribe, and returns it. Pings
+    /// and new connections before it are skipped.
+    pub async fn expect_subscribe(&mut self) -> Value {
+        loop {
+            match self.next_event().await {
+                ServerEvent::Received(_, frame) => {
+                    assert!(is_subscribe(&frame), "Expected a subscribe frame, got {}", frame);
+                    return frame;
+                }
+                ServerEvent::Ping(_) | ServerEvent::Connected(_) => {}
+                other => panic!("Expected a subscribe frame, got {:?}", other),
+            }
+        }
+    }
+
+    /// Events received so far, without waiting for more.
+    pub fn drain_events(&mut self) -> Vec<ServerEvent> {
+        std::iter::from_fn(|| self.events.try_recv().ok()).collect()
+    }
+}
@@ -0,0 +1,4 @@
+//! Helpers shared by the integration tests that talk to a local exchange.
+#![allow(dead_code)]
+
+pub mod mock_exchange;
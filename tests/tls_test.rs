@@ -0,0 +1,114 @@
+use futures_util::{SinkExt, StreamExt};
+use ingestor::{
+    heartbeat::KeepAliveConfig,
+    log_feed_manager::LogFeedManager,
+    stream_config::StreamConfig,
+    tls::TlsConfig,
+    tradeslog::ConcurrentTradesLog,
+    transport::Transport,
+};
+use rust_decimal_macros::dec;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use tempfile::TempDir;
+use tokio::net::TcpListener;
+use tokio::sync::watch;
+use tokio::time::{sleep, timeout, Duration};
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+const TRADE: &str = r#"{"e":"trade","E":1,"s":"BTCUSDT","t":1,"p":"69000.00","q":"0.5","T":1712745600000,"m":true}"#;
+
+/// Certificate for `localhost` signed by its own key, written as a PEM file into `dir`,
+/// and the acceptor serving it.
+fn self_signed(dir: &TempDir) -> (PathBuf, tokio_native_tls::TlsAcceptor) {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let cert_pem = cert.serialize_pem().unwrap();
+    let path = dir.path().join("roots.pem");
+    std::fs::write(&path, &cert_pem).unwrap();
+
+    let identity =
+        native_tls::Identity::from_pkcs8(cert_pem.as_bytes(), cert.serialize_private_key_pem().as_bytes()).unwrap();
+    let acceptor = native_tls::TlsAcceptor::new(identity).unwrap();
+    (path, acceptor.into())
+}
+
+/// TLS WebSocket server sending `frame` on every connection that gets through the
+/// handshake.
+async fn tls_feed_server(acceptor: tokio_native_tls::TlsAcceptor, frame: &'static str) -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((tcp, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                let tls = match acceptor.accept(tcp).await {
+                    Ok(tls) => tls,
+                    Err(_) => return,
+                };
+                let mut ws = tokio_tungstenite::accept_async(tls).await.unwrap();
+                let _ = ws.send(Message::Text(frame.to_string())).await;
+                while let Some(Ok(_)) = ws.next().await {}
+            });
+        }
+    });
+    addr
+}
+
+async fn connects(uri: String, tls: TlsConfig) -> bool {
+    let mut transport = Transport::new(uri, KeepAliveConfig::default()).with_tls(tls);
+    timeout(Duration::from_secs(2), transport.connect(None)).await.unwrap().is_ok()
+}
+
+#[tokio::test]
+async fn test_custom_roots_trust_a_self_signed_server() {
+    let dir = TempDir::new().unwrap();
+    let (roots, acceptor) = self_signed(&dir);
+    let addr = tls_feed_server(acceptor, TRADE).await;
+    let uri = format!("wss://localhost:{}/ws/btcusdt@trade", addr.port());
+
+    assert!(!connects(uri.clone(), TlsConfig::new()).await);
+    assert!(connects(uri.clone(), TlsConfig::new().with_custom_roots(&roots)).await);
+    #[cfg(feature = "insecure-tls")]
+    assert!(connects(uri, TlsConfig::new().with_accept_invalid_certs(true)).await);
+}
+
+#[tokio::test]
+async fn test_sni_override_is_checked_against_the_certificate() {
+    let dir = TempDir::new().unwrap();
+    let (roots, acceptor) = self_signed(&dir);
+    let addr = tls_feed_server(acceptor, TRADE).await;
+    // The certificate only names localhost, not the address connected to
+    let uri = format!("wss://{}/ws/btcusdt@trade", addr);
+
+    let tls = TlsConfig::new().with_custom_roots(&roots);
+    assert!(!connects(uri.clone(), tls.clone()).await);
+    assert!(connects(uri.clone(), tls.clone().with_sni_override("localhost")).await);
+    assert!(!connects(uri, tls.with_sni_override("stream.binance.com")).await);
+}
+
+#[tokio::test]
+async fn test_trade_feed_streams_over_custom_tls_from_config() {
+    let dir = TempDir::new().unwrap();
+    let (roots, acceptor) = self_signed(&dir);
+    let addr = tls_feed_server(acceptor, TRADE).await;
+
+    let mut config = StreamConfig::new("btcusdt").with_tls(TlsConfig::new().with_custom_roots(&roots));
+    config.base_url = format!("wss://localhost:{}", addr.port());
+    config.fallback_base_urls.clear();
+    let trades_log = ConcurrentTradesLog::new(10);
+    let manager = LogFeedManager::from_config(&config, trades_log.clone());
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let feed = tokio::spawn(async move { manager.start(shutdown_rx).await });
+    timeout(Duration::from_secs(2), async {
+        while trades_log.last_n_trades(1).await.is_empty() {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("no trade came through the TLS feed");
+    assert_eq!(trades_log.last_n_trades(1).await[0].price, dec!(69000.00));
+
+    shutdown_tx.send(true).unwrap();
+    feed.await.unwrap().unwrap();
+}
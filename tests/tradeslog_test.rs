@@ -1,4 +1,5 @@
-use ingestor::tradeslog::{ConcurrentTradesLog, Trade, TradesLogError};
+use ingestor::tradeslog::{ConcurrentTradesLog, MakerSemantics, Trade, TradesLog, TradesLogError};
+use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
 use tokio::time::{sleep, Duration};
 use std::sync::Arc;
@@ -16,6 +17,9 @@ async fn test_concurrent_inserts() {
             quantity: dec!(1),
             timestamp: 1000,
             is_buyer_maker: false,
+            symbol: None,
+            trade_id: None,
+            aggregated_trade_ids: None,
         }).await;
     });
 
@@ -26,6 +30,9 @@ async fn test_concurrent_inserts() {
             quantity: dec!(2),
             timestamp: 2000,
             is_buyer_maker: true,
+            symbol: None,
+            trade_id: None,
+            aggregated_trade_ids: None,
         }).await;
     });
 
@@ -61,6 +68,9 @@ async fn test_vwap_concurrent() {
                 quantity: dec!(1),
                 timestamp: i * 1000,
                 is_buyer_maker: i % 2 == 0,
+                symbol: None,
+                trade_id: None,
+                aggregated_trade_ids: None,
             }).await;
         });
     }
@@ -83,6 +93,9 @@ async fn test_snapshot_concurrent() {
         quantity: dec!(1),
         timestamp: 1000,
         is_buyer_maker: false,
+        symbol: None,
+        trade_id: None,
+        aggregated_trade_ids: None,
     }).await;
 
     // Clone resources for spawned task
@@ -104,6 +117,9 @@ async fn test_snapshot_concurrent() {
         quantity: dec!(2),
         timestamp: 2000,
         is_buyer_maker: true,
+        symbol: None,
+        trade_id: None,
+        aggregated_trade_ids: None,
     }).await;
 
     // Verify snapshot reflects ONLY the first trade
@@ -133,6 +149,9 @@ async fn test_aggressor_ratio_concurrent() {
                 quantity: qty,
                 timestamp: i * 1000,
                 is_buyer_maker: is_buyer,
+                symbol: None,
+                trade_id: None,
+                aggregated_trade_ids: None,
             }).await;
         });
     }
@@ -164,6 +183,9 @@ async fn test_zero_volume_trades() {
         quantity: dec!(0),
         timestamp: 1000,
         is_buyer_maker: false,
+        symbol: None,
+        trade_id: None,
+        aggregated_trade_ids: None,
     }).await;
 
     assert!(matches!(
@@ -171,3 +193,66 @@ async fn test_zero_volume_trades() {
         Err(TradesLogError::ZeroVolume)
     ));
 }
+
+#[tokio::test]
+async fn test_shared_log_keeps_its_maker_semantics() {
+    let log = ConcurrentTradesLog::from_log(TradesLog::new(10).with_maker_semantics(MakerSemantics::SellerMaker));
+
+    log.insert_trade(Trade {
+        price: dec!(100),
+        quantity: dec!(2),
+        timestamp: 1000,
+        is_buyer_maker: true,
+        symbol: None,
+        trade_id: None,
+        aggregated_trade_ids: None,
+    }).await;
+
+    assert_eq!(log.trade_imbalance().await, Some(dec!(1)));
+    assert_eq!(log.signed_count_momentum().await, 1);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_snapshots_are_consistent_alongside_readers_and_a_writer() {
+    const MAX_LEN: usize = 50;
+    let trade = |i: usize| Trade {
+        price: dec!(100),
+        quantity: dec!(1),
+        timestamp: i as u64,
+        is_buyer_maker: i % 3 == 2,
+        symbol: None,
+        trade_id: None,
+        aggregated_trade_ids: None,
+    };
+    // Full from the start, so every snapshot covers exactly MAX_LEN unit trades
+    let log = ConcurrentTradesLog::new(MAX_LEN);
+    for i in 0..MAX_LEN {
+        log.insert_trade(trade(i)).await;
+    }
+
+    let barrier = Arc::new(Barrier::new(5));
+    let mut readers = Vec::new();
+    for _ in 0..4 {
+        let (log, barrier) = (log.clone(), barrier.clone());
+        readers.push(tokio::spawn(async move {
+            barrier.wait().await;
+            for _ in 0..200 {
+                let (snapshot, recent) = tokio::join!(log.get_snapshot(), log.last_n_trades(MAX_LEN));
+                assert_eq!(recent.len(), MAX_LEN);
+                assert_eq!(snapshot.avg_trade_size, Some(dec!(1)));
+                assert_eq!(snapshot.vwap_total, Some(dec!(100)));
+                // buys - sells and buys / total must describe the same window
+                let buys = (MAX_LEN as i64 + snapshot.signed_count_momentum) / 2;
+                assert_eq!(snapshot.trade_imbalance, Some(Decimal::from(buys) / dec!(50)));
+            }
+        }));
+    }
+
+    barrier.wait().await;
+    for i in MAX_LEN..MAX_LEN * 20 {
+        log.insert_trade(trade(i)).await;
+    }
+    for reader in readers {
+        reader.await.unwrap();
+    }
+}